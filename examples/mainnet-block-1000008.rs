@@ -12,14 +12,12 @@ async fn main() {
     let rpc_url = format!("https://eth-mainnet.g.alchemy.com/v2/{}", api_key);
     let provider = Provider::try_from(rpc_url).unwrap();
 
-    let prev_block_number = Some(BlockId::Number(BlockNumber::from(1000007)));
-    let current_block_number = Some(BlockId::Number(BlockNumber::from(1000008)));
+    let prev_block_id = BlockId::Number(BlockNumber::from(1000007));
+    let current_block_id = BlockId::Number(BlockNumber::from(1000008));
+    let prev_block_number = Some(prev_block_id);
+    let current_block_number = Some(current_block_id);
 
-    let prev_block = provider
-        .get_block(prev_block_number.unwrap())
-        .await
-        .unwrap()
-        .unwrap();
+    let prev_block = provider.get_block(prev_block_id).await.unwrap().unwrap();
     let mut state_trie = StateTrie::from_root(prev_block.state_root);
 
     // block 1000008 only has one tx where sender sends funds to receiver.
@@ -88,11 +86,7 @@ async fn main() {
         .unwrap();
 
     // now our trie should have calculated the state root correctly.
-    let current_block = provider
-        .get_block(current_block_number.unwrap())
-        .await
-        .unwrap()
-        .unwrap();
+    let current_block = provider.get_block(current_block_id).await.unwrap().unwrap();
     assert_eq!(state_trie.root().unwrap(), current_block.state_root);
     println!("state root matched!");
 }
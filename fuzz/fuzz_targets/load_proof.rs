@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use partial_mpt::fuzzing::ProofInput;
+
+fuzz_target!(|input: ProofInput| {
+    partial_mpt::fuzzing::load_proof(&input);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use partial_mpt::types::U256;
+use libfuzzer_sys::fuzz_target;
+use partial_mpt::fuzzing::NodeData;
+
+fuzz_target!(|node: NodeData<U256>| {
+    partial_mpt::fuzzing::roundtrip_node(&node);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use partial_mpt::fuzzing::TrieOp;
+
+fuzz_target!(|ops: Vec<TrieOp>| {
+    partial_mpt::fuzzing::run_ops(&ops);
+});
@@ -0,0 +1,31 @@
+// c api of partial-mpt, built with `cargo build --release --features cffi`.
+// functions returning int give PM_OK or a negative code, pm_last_error has the message.
+#ifndef PARTIAL_MPT_H
+#define PARTIAL_MPT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define PM_OK 0
+#define PM_ERR_NULL -1
+#define PM_ERR_TRIE -2
+
+typedef struct StateTrie StateTrie;
+
+// message of the last error on this thread, or NULL. valid until the next failing call.
+const char *pm_last_error(void);
+
+// root is 32 bytes. free with pm_state_trie_free.
+StateTrie *pm_state_trie_new(const uint8_t *root);
+// canonical witness encoding, NULL on error.
+StateTrie *pm_state_trie_from_witness(const uint8_t *witness, size_t len);
+void pm_state_trie_free(StateTrie *trie);
+
+// proof is an eth_getProof result as json.
+int pm_state_trie_load_proof(StateTrie *trie, const uint8_t *proof, size_t len);
+// address is 20 bytes, balance is 32 bytes big endian.
+int pm_state_trie_set_balance(StateTrie *trie, const uint8_t *address, const uint8_t *balance);
+// writes the 32 byte state root to out.
+int pm_state_trie_root(const StateTrie *trie, uint8_t *out);
+
+#endif
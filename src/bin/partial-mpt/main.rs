@@ -0,0 +1,442 @@
+use std::{
+    fmt::Debug,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use clap::{Parser, Subcommand};
+use ethers_core::types::{Address, BlockId, H256, U256};
+use ethers_core::utils::hex;
+use ethers_providers::{Http, Middleware, Provider};
+use partial_mpt::{StateDiff, StateTrie, Witness, WitnessExplorer};
+use serde_json::{json, Map, Value};
+
+#[derive(Parser)]
+#[command(name = "partial-mpt", about = "partial merkle patricia trie tools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch proofs, apply edits and print the state root before and after
+    Root {
+        #[arg(long, env = "RPC_URL")]
+        rpc_url: String,
+        /// Block number, tag or hash
+        #[arg(long, default_value = "latest")]
+        block: BlockId,
+        /// Account to load, with optional slots: 0xaddress[:slot,slot]
+        #[arg(long = "account", value_parser = parse_account)]
+        accounts: Vec<(Address, Vec<U256>)>,
+        /// New balance: 0xaddress=value
+        #[arg(long = "balance", value_parser = parse_account_edit)]
+        balances: Vec<(Address, U256)>,
+        /// New nonce: 0xaddress=value
+        #[arg(long = "nonce", value_parser = parse_account_edit)]
+        nonces: Vec<(Address, U256)>,
+        /// New storage value: 0xaddress:slot=value
+        #[arg(long = "storage", value_parser = parse_storage_edit)]
+        storage: Vec<(Address, U256, U256)>,
+        /// Print the roots and the loaded trie after the edits as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Load the same accounts and slots at two blocks and print what changed
+    Diff {
+        #[arg(long, env = "RPC_URL")]
+        rpc_url: String,
+        /// Block number, tag or hash of the old state
+        #[arg(long)]
+        from: BlockId,
+        /// Block number, tag or hash of the new state
+        #[arg(long, default_value = "latest")]
+        to: BlockId,
+        /// Account to compare, with optional slots: 0xaddress[:slot,slot]
+        #[arg(long = "account", value_parser = parse_account, required = true)]
+        accounts: Vec<(Address, Vec<U256>)>,
+        /// Print the diff as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Walk the nodes of a witness interactively
+    Explore {
+        /// Witness file, raw bytes or 0x hex
+        witness: PathBuf,
+    },
+}
+
+fn fail(err: impl Debug) -> String {
+    format!("{:?}", err)
+}
+
+// decimal, or hex with 0x.
+fn parse_u256(value: &str) -> Result<U256, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).map_err(fail),
+        None => U256::from_dec_str(value).map_err(fail),
+    }
+}
+
+fn parse_address(value: &str) -> Result<Address, String> {
+    value.parse::<Address>().map_err(fail)
+}
+
+fn parse_account(value: &str) -> Result<(Address, Vec<U256>), String> {
+    match value.split_once(':') {
+        Some((address, slots)) => Ok((
+            parse_address(address)?,
+            slots
+                .split(',')
+                .filter(|slot| !slot.is_empty())
+                .map(parse_u256)
+                .collect::<Result<_, _>>()?,
+        )),
+        None => Ok((parse_address(value)?, vec![])),
+    }
+}
+
+fn parse_account_edit(value: &str) -> Result<(Address, U256), String> {
+    let (address, value) = value
+        .split_once('=')
+        .ok_or("expected 0xaddress=value".to_string())?;
+    Ok((parse_address(address)?, parse_u256(value)?))
+}
+
+fn parse_storage_edit(value: &str) -> Result<(Address, U256, U256), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or("expected 0xaddress:slot=value".to_string())?;
+    let (address, slot) = key
+        .split_once(':')
+        .ok_or("expected 0xaddress:slot=value".to_string())?;
+    Ok((
+        parse_address(address)?,
+        parse_u256(slot)?,
+        parse_u256(value)?,
+    ))
+}
+
+// every edited account and slot has to be loaded as well.
+fn keys_to_load(
+    accounts: &[(Address, Vec<U256>)],
+    balances: &[(Address, U256)],
+    nonces: &[(Address, U256)],
+    storage: &[(Address, U256, U256)],
+) -> Vec<(Address, Vec<U256>)> {
+    let mut keys = accounts.to_vec();
+    let mut add = |address: Address, slot: Option<U256>| {
+        let index = match keys.iter().position(|(a, _)| *a == address) {
+            Some(index) => index,
+            None => {
+                keys.push((address, vec![]));
+                keys.len() - 1
+            }
+        };
+        if let Some(slot) = slot {
+            if !keys[index].1.contains(&slot) {
+                keys[index].1.push(slot);
+            }
+        }
+    };
+    for (address, _) in balances.iter().chain(nonces.iter()) {
+        add(*address, None);
+    }
+    for (address, slot, _) in storage {
+        add(*address, Some(*slot));
+    }
+    keys
+}
+
+fn slot_hashes(slots: &[U256]) -> Vec<H256> {
+    slots
+        .iter()
+        .map(|slot| {
+            let mut bytes = [0u8; 32];
+            slot.to_big_endian(&mut bytes);
+            H256::from(bytes)
+        })
+        .collect()
+}
+
+async fn load_state(
+    provider: &Provider<Http>,
+    block: BlockId,
+    keys: &[(Address, Vec<U256>)],
+) -> Result<StateTrie, String> {
+    let header = provider
+        .get_block(block)
+        .await
+        .map_err(fail)?
+        .ok_or("block not found".to_string())?;
+    let mut state_trie = StateTrie::from_root(header.state_root);
+    state_trie
+        .fetch_proofs(
+            provider,
+            keys.iter()
+                .map(|(address, slots)| (*address, slot_hashes(slots)))
+                .collect(),
+            Some(block),
+        )
+        .await
+        .map_err(fail)?;
+    Ok(state_trie)
+}
+
+async fn root(
+    rpc_url: String,
+    block: BlockId,
+    accounts: Vec<(Address, Vec<U256>)>,
+    balances: Vec<(Address, U256)>,
+    nonces: Vec<(Address, U256)>,
+    storage: Vec<(Address, U256, U256)>,
+    json: bool,
+) -> Result<(), String> {
+    let provider = Provider::<Http>::try_from(rpc_url).map_err(fail)?;
+    let keys = keys_to_load(&accounts, &balances, &nonces, &storage);
+    let mut state_trie = load_state(&provider, block, &keys).await?;
+    let root_before = state_trie.root().unwrap();
+
+    for (address, balance) in balances {
+        state_trie
+            .account_trie
+            .set_balance(address, balance)
+            .map_err(fail)?;
+    }
+    for (address, nonce) in nonces {
+        state_trie
+            .account_trie
+            .set_nonce(address, nonce)
+            .map_err(fail)?;
+    }
+    for (address, slot, value) in storage {
+        state_trie
+            .set_storage_value(address, slot, value)
+            .map_err(fail)?;
+    }
+    if json {
+        let out = json!({
+            "rootBefore": root_before,
+            "rootAfter": state_trie.root(),
+            "trie": state_trie.to_json().map_err(fail)?,
+        });
+        println!("{}", serde_json::to_string_pretty(&out).map_err(fail)?);
+    } else {
+        println!("root before: {:?}", root_before);
+        println!("root after: {:?}", state_trie.root().unwrap());
+    }
+    Ok(())
+}
+
+fn state_diff_json(state_diff: &StateDiff) -> Value {
+    fn change<T: Debug>(change: &Option<(T, T)>) -> Option<Value> {
+        change
+            .as_ref()
+            .map(|(from, to)| json!({ "from": format!("{:?}", from), "to": format!("{:?}", to) }))
+    }
+
+    let mut accounts = Map::new();
+    for (address, diff) in state_diff.accounts.iter() {
+        let mut account = Map::new();
+        for (name, value) in [
+            ("balance", change(&diff.balance)),
+            ("nonce", change(&diff.nonce)),
+            ("codeHash", change(&diff.code_hash)),
+        ] {
+            if let Some(value) = value {
+                account.insert(name.to_string(), value);
+            }
+        }
+        let storage = diff
+            .storage
+            .iter()
+            .map(|(slot, values)| (format!("{:#x}", slot), change(&Some(*values)).unwrap()))
+            .collect::<Map<_, _>>();
+        if !storage.is_empty() {
+            account.insert("storage".to_string(), Value::Object(storage));
+        }
+        account.insert("created".to_string(), json!(diff.created));
+        account.insert("destroyed".to_string(), json!(diff.destroyed));
+        accounts.insert(format!("{:?}", address), Value::Object(account));
+    }
+    Value::Object(accounts)
+}
+
+fn print_state_diff(state_diff: &StateDiff) {
+    if state_diff.accounts.is_empty() {
+        println!("no changes");
+    }
+    for (address, diff) in state_diff.accounts.iter() {
+        match (diff.created, diff.destroyed) {
+            (true, _) => println!("{:?} (created)", address),
+            (_, true) => println!("{:?} (destroyed)", address),
+            _ => println!("{:?}", address),
+        }
+        if let Some((from, to)) = diff.balance {
+            println!("  balance: {} -> {}", from, to);
+        }
+        if let Some((from, to)) = diff.nonce {
+            println!("  nonce: {} -> {}", from, to);
+        }
+        if let Some((from, to)) = diff.code_hash {
+            println!("  code hash: {:?} -> {:?}", from, to);
+        }
+        for (slot, (from, to)) in diff.storage.iter() {
+            println!("  storage {:#x}: {:#x} -> {:#x}", slot, from, to);
+        }
+    }
+}
+
+async fn diff(
+    rpc_url: String,
+    from: BlockId,
+    to: BlockId,
+    accounts: Vec<(Address, Vec<U256>)>,
+    json: bool,
+) -> Result<(), String> {
+    let provider = Provider::<Http>::try_from(rpc_url).map_err(fail)?;
+    let old_state = load_state(&provider, from, &accounts).await?;
+    let new_state = load_state(&provider, to, &accounts).await?;
+    let state_diff = new_state.diff(&old_state, &accounts).map_err(fail)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&state_diff_json(&state_diff)).map_err(fail)?
+        );
+    } else {
+        print_state_diff(&state_diff);
+    }
+    Ok(())
+}
+
+fn read_witness(path: &PathBuf) -> Result<Witness, String> {
+    let raw = std::fs::read(path).map_err(fail)?;
+    let raw = match std::str::from_utf8(&raw).map(str::trim) {
+        Ok(text) if text.starts_with("0x") => hex::decode(text).map_err(fail)?,
+        _ => raw,
+    };
+    Witness::decode(raw.into()).map_err(fail)
+}
+
+fn explore(path: PathBuf) -> Result<(), String> {
+    let witness = read_witness(&path)?;
+    let mut explorer = WitnessExplorer::new(&witness);
+    println!(
+        "witness with {} nodes under {:?}, type help for commands",
+        witness.len(),
+        witness.root()
+    );
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{}> ", explorer.pwd());
+        io::stdout().flush().map_err(fail)?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(fail)?,
+            None => return Ok(()),
+        };
+        if matches!(line.trim(), "quit" | "exit") {
+            return Ok(());
+        }
+        match explorer.execute(&line) {
+            Ok(out) => print!("{}", out),
+            Err(err) => println!("error: {:?}", err),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let result = match Cli::parse().command {
+        Command::Root {
+            rpc_url,
+            block,
+            accounts,
+            balances,
+            nonces,
+            storage,
+            json,
+        } => root(rpc_url, block, accounts, balances, nonces, storage, json).await,
+        Command::Diff {
+            rpc_url,
+            from,
+            to,
+            accounts,
+            json,
+        } => diff(rpc_url, from, to, accounts, json).await,
+        Command::Explore { witness } => explore(witness),
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keys_to_load, parse_account, parse_storage_edit, parse_u256, state_diff_json};
+    use ethers_core::types::{Address, U256};
+    use partial_mpt::{AccountDiff, StateDiff};
+
+    #[test]
+    pub fn test_cli_parse_args() {
+        assert_eq!(parse_u256("0x10").unwrap(), U256::from(16));
+        assert_eq!(parse_u256("10").unwrap(), U256::from(10));
+        assert!(parse_u256("ten").is_err());
+
+        let address = Address::from_low_u64_be(1);
+        assert_eq!(
+            parse_account("0x0000000000000000000000000000000000000001:1,0x2").unwrap(),
+            (address, vec![U256::from(1), U256::from(2)])
+        );
+        assert_eq!(
+            parse_account("0x0000000000000000000000000000000000000001").unwrap(),
+            (address, vec![])
+        );
+        assert_eq!(
+            parse_storage_edit("0x0000000000000000000000000000000000000001:3=4").unwrap(),
+            (address, U256::from(3), U256::from(4))
+        );
+        assert!(parse_storage_edit("0x0000000000000000000000000000000000000001=4").is_err());
+    }
+
+    #[test]
+    pub fn test_cli_edits_are_loaded() {
+        let a = Address::from_low_u64_be(1);
+        let b = Address::from_low_u64_be(2);
+        let keys = keys_to_load(
+            &[(a, vec![U256::one()])],
+            &[(b, U256::one())],
+            &[],
+            &[
+                (a, U256::one(), U256::zero()),
+                (b, U256::from(2), U256::zero()),
+            ],
+        );
+        assert_eq!(keys, vec![(a, vec![U256::one()]), (b, vec![U256::from(2)])]);
+    }
+
+    #[test]
+    pub fn test_cli_state_diff_json() {
+        let address = Address::from_low_u64_be(1);
+        let state_diff = StateDiff {
+            accounts: [(
+                address,
+                AccountDiff {
+                    balance: Some((U256::from(1), U256::from(2))),
+                    storage: [(U256::from(10), (U256::zero(), U256::from(255)))].into(),
+                    ..AccountDiff::default()
+                },
+            )]
+            .into(),
+        };
+        let json = state_diff_json(&state_diff);
+        let account = &json[format!("{:?}", address)];
+        assert_eq!(account["balance"]["to"], "2");
+        assert_eq!(account["storage"]["0xa"]["to"], "255");
+        assert!(account.get("nonce").is_none());
+        assert_eq!(account["created"], false);
+    }
+}
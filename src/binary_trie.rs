@@ -0,0 +1,528 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use bytes::BytesMut;
+use ethers_core::{
+    types::{Bytes, H256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+use crate::{Error, KeccakHasher, TrieHasher};
+
+pub const STEM_LEN: usize = 31;
+const STEM_BITS: usize = STEM_LEN * 8;
+const STEM_WIDTH: usize = 256;
+
+pub type Stem = [u8; STEM_LEN];
+
+type Values = Box<[Option<H256>; STEM_WIDTH]>;
+
+fn stem_of(key: &[u8; 32]) -> Stem {
+    let mut stem = [0u8; STEM_LEN];
+    stem.copy_from_slice(&key[..STEM_LEN]);
+    stem
+}
+
+// a stem has only STEM_BITS bits, internal nodes below that can't be, e.g. a proof which
+// keeps going.
+fn bit_at(stem: &Stem, depth: usize) -> Result<usize, Error> {
+    if depth >= STEM_BITS {
+        return Err(Error::InternalError(
+            "internal node is deeper than the stem length",
+        ));
+    }
+    Ok(((stem[depth / 8] >> (7 - depth % 8)) & 1) as usize)
+}
+
+// eip-7864 style binary trie: 31 byte stems are placed on a binary path and every stem
+// holds 256 values, indexed by the last key byte. empty subtrees hash to zero.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BinaryNode {
+    Internal([Option<H256>; 2]),
+    Stem { stem: Stem, values: Values },
+}
+
+fn hash_pair<H: TrieHasher>(left: H256, right: H256) -> H256 {
+    if left.is_zero() && right.is_zero() {
+        return H256::zero();
+    }
+    let mut preimage = [0u8; 64];
+    preimage[..32].copy_from_slice(left.as_bytes());
+    preimage[32..].copy_from_slice(right.as_bytes());
+    H::hash(&preimage)
+}
+
+fn append_optional_hash(rlp_stream: &mut RlpStream, entry: &Option<H256>) {
+    let bm = match entry {
+        Some(hash) => BytesMut::from(hash.as_bytes()),
+        None => BytesMut::new(),
+    };
+    rlp_stream.append(&bm);
+}
+
+fn decode_optional_hash(rlp: &Rlp) -> Result<Option<H256>, Error> {
+    let value = rlp.data()?.to_owned();
+    match value.len() {
+        32 => Ok(Some(H256::from_slice(value.as_slice()))),
+        0 => Ok(None),
+        _ => Err(Error::InternalError("invalid hash length in binary node")),
+    }
+}
+
+impl BinaryNode {
+    pub fn hash<H: TrieHasher>(&self) -> H256 {
+        match self {
+            BinaryNode::Internal([left, right]) => {
+                hash_pair::<H>(left.unwrap_or_default(), right.unwrap_or_default())
+            }
+            BinaryNode::Stem { stem, values } => {
+                // values are merkleized as a binary tree of 256 leaves.
+                let mut level = values
+                    .iter()
+                    .map(|value| value.map(|v| H::hash(v.as_bytes())).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                while level.len() > 1 {
+                    level = level
+                        .chunks(2)
+                        .map(|pair| hash_pair::<H>(pair[0], pair[1]))
+                        .collect();
+                }
+
+                let mut preimage = Vec::with_capacity(STEM_LEN + 1 + 32);
+                preimage.extend_from_slice(stem);
+                preimage.push(0);
+                preimage.extend_from_slice(level[0].as_bytes());
+                H::hash(&preimage)
+            }
+        }
+    }
+
+    pub fn from_raw_rlp(raw: Bytes) -> Result<Self, Error> {
+        let rlp = Rlp::new(&raw);
+        if rlp.item_count()? != 2 {
+            return Err(Error::InternalError("binary node should have two items"));
+        }
+
+        // stem nodes carry their values as a nested list, internal nodes only hashes.
+        let values_rlp = rlp.at(1)?;
+        if !values_rlp.is_list() {
+            return Ok(BinaryNode::Internal([
+                decode_optional_hash(&rlp.at(0)?)?,
+                decode_optional_hash(&values_rlp)?,
+            ]));
+        }
+
+        let stem_bytes = rlp.at(0)?.data()?.to_owned();
+        if stem_bytes.len() != STEM_LEN {
+            return Err(Error::InternalError("invalid stem length in binary node"));
+        }
+        if values_rlp.item_count()? != STEM_WIDTH {
+            return Err(Error::InternalError("stem node should have 256 values"));
+        }
+        let mut stem = [0u8; STEM_LEN];
+        stem.copy_from_slice(&stem_bytes);
+        let mut values: Values = Box::new([None; STEM_WIDTH]);
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = decode_optional_hash(&values_rlp.at(i)?)?;
+        }
+        Ok(BinaryNode::Stem { stem, values })
+    }
+
+    pub fn to_raw_rlp(&self) -> Bytes {
+        let mut rlp_stream = RlpStream::new_list(2);
+        match self {
+            BinaryNode::Internal(children) => {
+                for child in children.iter() {
+                    append_optional_hash(&mut rlp_stream, child);
+                }
+            }
+            BinaryNode::Stem { stem, values } => {
+                rlp_stream.append(&BytesMut::from(stem.as_slice()));
+                rlp_stream.begin_list(STEM_WIDTH);
+                for value in values.iter() {
+                    append_optional_hash(&mut rlp_stream, value);
+                }
+            }
+        }
+        Bytes::from(rlp_stream.out().to_vec())
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct BinaryTrie<H: TrieHasher = KeccakHasher> {
+    root: Option<H256>,
+    nodes: HashMap<H256, BinaryNode>,
+    _marker: PhantomData<H>,
+}
+
+impl<H: TrieHasher> BinaryTrie<H> {
+    pub fn from_root(root: H256) -> Self {
+        BinaryTrie {
+            root: Some(root),
+            nodes: HashMap::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::from_root(H256::zero())
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.root
+    }
+
+    pub fn get(&self, key: H256) -> Result<Option<H256>, Error> {
+        let key = key.to_fixed_bytes();
+        let stem = stem_of(&key);
+        let mut current = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut depth = 0;
+        loop {
+            if current.is_zero() {
+                return Ok(None);
+            }
+            match self
+                .nodes
+                .get(&current)
+                .ok_or(Error::MissingData("node not present, please add a proof"))?
+            {
+                BinaryNode::Internal(children) => match children[bit_at(&stem, depth)?] {
+                    Some(child) => {
+                        current = child;
+                        depth += 1;
+                    }
+                    None => return Ok(None),
+                },
+                BinaryNode::Stem {
+                    stem: node_stem,
+                    values,
+                } => {
+                    if *node_stem == stem {
+                        return Ok(values[key[STEM_LEN] as usize]);
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    pub fn set(&mut self, key: H256, value: H256) -> Result<(), Error> {
+        self.update(key, Some(value))
+    }
+
+    pub fn remove(&mut self, key: H256) -> Result<(), Error> {
+        self.update(key, None)
+    }
+
+    fn update(&mut self, key: H256, value: Option<H256>) -> Result<(), Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let current = if root.is_zero() { None } else { Some(root) };
+        let new_root = self.update_at(current, 0, &key.to_fixed_bytes(), value)?;
+        self.root = Some(new_root.unwrap_or_default());
+        Ok(())
+    }
+
+    // updates the subtree at hash and returns its new hash, None if it is empty now.
+    fn update_at(
+        &mut self,
+        hash: Option<H256>,
+        depth: usize,
+        key: &[u8; 32],
+        value: Option<H256>,
+    ) -> Result<Option<H256>, Error> {
+        let hash = match hash {
+            Some(hash) => hash,
+            None => {
+                return match value {
+                    Some(value) => {
+                        let mut values: Values = Box::new([None; STEM_WIDTH]);
+                        values[key[STEM_LEN] as usize] = Some(value);
+                        Ok(Some(self.store(BinaryNode::Stem {
+                            stem: stem_of(key),
+                            values,
+                        })))
+                    }
+                    None => Ok(None),
+                };
+            }
+        };
+
+        let node = self
+            .nodes
+            .get(&hash)
+            .ok_or(Error::MissingData("node not present, please add a proof"))?
+            .to_owned();
+
+        match node {
+            BinaryNode::Internal(mut children) => {
+                let bit = bit_at(&stem_of(key), depth)?;
+                children[bit] = self.update_at(children[bit], depth + 1, key, value)?;
+                self.nodes.remove(&hash);
+                self.store_internal(children)
+            }
+            BinaryNode::Stem { stem, mut values } => {
+                if stem == stem_of(key) {
+                    values[key[STEM_LEN] as usize] = value;
+                    self.nodes.remove(&hash);
+                    if values.iter().all(Option::is_none) {
+                        return Ok(None);
+                    }
+                    Ok(Some(self.store(BinaryNode::Stem { stem, values })))
+                } else if value.is_none() {
+                    // key is not in the trie, nothing to remove.
+                    Ok(Some(hash))
+                } else {
+                    if depth >= STEM_BITS {
+                        return Err(Error::InternalError("stems are equal at max depth"));
+                    }
+                    // push the existing stem one level down and retry.
+                    let mut children = [None; 2];
+                    children[bit_at(&stem, depth)?] = Some(hash);
+                    let internal = self.store(BinaryNode::Internal(children));
+                    self.update_at(Some(internal), depth, key, value)
+                }
+            }
+        }
+    }
+
+    fn store_internal(&mut self, children: [Option<H256>; 2]) -> Result<Option<H256>, Error> {
+        match children {
+            [None, None] => Ok(None),
+            [Some(only_child), None] | [None, Some(only_child)] => {
+                // an internal node with a single stem below it collapses into that stem.
+                match self.nodes.get(&only_child) {
+                    Some(BinaryNode::Stem { .. }) => Ok(Some(only_child)),
+                    Some(BinaryNode::Internal(_)) => {
+                        Ok(Some(self.store(BinaryNode::Internal(children))))
+                    }
+                    None => Err(Error::MissingData(
+                        "sibling node not present, please add a proof for a neighbouring key",
+                    )),
+                }
+            }
+            _ => Ok(Some(self.store(BinaryNode::Internal(children)))),
+        }
+    }
+
+    fn store(&mut self, node: BinaryNode) -> H256 {
+        let hash = node.hash::<H>();
+        self.nodes.insert(hash, node);
+        hash
+    }
+
+    // proof is the list of serialized nodes from the root down to the key's stem (or to the
+    // point where the key is shown to be absent).
+    pub fn load_proof(
+        &mut self,
+        key: H256,
+        value: Option<H256>,
+        proof: Vec<Bytes>,
+    ) -> Result<(), Error> {
+        if proof.is_empty() {
+            if self.root.is_some() && self.root != Some(H256::zero()) {
+                return Err(Error::MissingData(
+                    "Root is not empty, hence some proof is needed",
+                ));
+            }
+            if value.is_some() {
+                return Err(Error::InternalError(
+                    "Value should be empty, since root is empty",
+                ));
+            }
+            return Ok(());
+        }
+
+        let key = key.to_fixed_bytes();
+        let stem = stem_of(&key);
+        let mut expected = match self.root {
+            Some(root) => root,
+            None => {
+                let root = BinaryNode::from_raw_rlp(proof[0].clone())?.hash::<H>();
+                self.root = Some(root);
+                root
+            }
+        };
+
+        for (depth, proof_entry) in proof.iter().enumerate() {
+            let node = BinaryNode::from_raw_rlp(proof_entry.to_owned())?;
+            if node.hash::<H>() != expected {
+                return Err(Error::InternalError(
+                    "proof entry hash does not match the node hash",
+                ));
+            }
+            self.nodes.entry(expected).or_insert_with(|| node.clone());
+
+            match node {
+                BinaryNode::Internal(children) => match children[bit_at(&stem, depth)?] {
+                    Some(child) => expected = child,
+                    None => {
+                        if value.is_some() {
+                            return Err(Error::InternalError(
+                                "child does not exist and value is not null",
+                            ));
+                        }
+                        return Ok(());
+                    }
+                },
+                BinaryNode::Stem {
+                    stem: node_stem,
+                    values,
+                } => {
+                    let found = if node_stem == stem {
+                        values[key[STEM_LEN] as usize]
+                    } else {
+                        None
+                    };
+                    if found != value {
+                        return Err(Error::InternalError("value in stem does not match input"));
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(Error::InternalError(
+            "proof ended before reaching a stem or an empty child",
+        ))
+    }
+
+    pub fn get_proof(&self, key: H256) -> Result<Vec<Bytes>, Error> {
+        let stem = stem_of(&key.to_fixed_bytes());
+        let mut current = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut proof = Vec::new();
+        let mut depth = 0;
+        while !current.is_zero() {
+            let node = self
+                .nodes
+                .get(&current)
+                .ok_or(Error::MissingData("node not present, please add a proof"))?;
+            proof.push(node.to_raw_rlp());
+            match node {
+                BinaryNode::Internal(children) => match children[bit_at(&stem, depth)?] {
+                    Some(child) => {
+                        current = child;
+                        depth += 1;
+                    }
+                    None => break,
+                },
+                BinaryNode::Stem { .. } => break,
+            }
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryNode, BinaryTrie};
+    use ethers_core::types::H256;
+
+    fn key(prefix: &[u8], suffix: u8) -> H256 {
+        let mut key = [0u8; 32];
+        key[..prefix.len()].copy_from_slice(prefix);
+        key[31] = suffix;
+        H256::from(key)
+    }
+
+    #[test]
+    pub fn test_binary_trie_set_get_remove() {
+        let mut trie = BinaryTrie::<crate::KeccakHasher>::empty();
+        trie.set(key(&[0b1000_0000], 0), H256::from_low_u64_be(1))
+            .unwrap();
+        let root_one = trie.root();
+
+        // shares the first 6 bits with the first key.
+        trie.set(key(&[0b1000_0010], 0), H256::from_low_u64_be(2))
+            .unwrap();
+        trie.set(key(&[0b1000_0010], 9), H256::from_low_u64_be(3))
+            .unwrap();
+        assert_eq!(
+            trie.get(key(&[0b1000_0010], 9)).unwrap(),
+            Some(H256::from_low_u64_be(3))
+        );
+        assert_eq!(trie.get(key(&[0b1000_0011], 0)).unwrap(), None);
+
+        trie.remove(key(&[0b1000_0010], 0)).unwrap();
+        trie.remove(key(&[0b1000_0010], 9)).unwrap();
+        assert_eq!(trie.root(), root_one);
+
+        trie.remove(key(&[0b1000_0000], 0)).unwrap();
+        assert_eq!(trie.root(), Some(H256::zero()));
+    }
+
+    #[test]
+    pub fn test_binary_trie_root_independent_of_order() {
+        let keys = [
+            key(&[1], 0),
+            key(&[1, 2], 5),
+            key(&[1, 3], 5),
+            key(&[255], 7),
+        ];
+
+        let mut trie_a = BinaryTrie::<crate::KeccakHasher>::empty();
+        for (i, k) in keys.iter().enumerate() {
+            trie_a.set(*k, H256::from_low_u64_be(i as u64)).unwrap();
+        }
+        let mut trie_b = BinaryTrie::<crate::KeccakHasher>::empty();
+        for (i, k) in keys.iter().enumerate().rev() {
+            trie_b.set(*k, H256::from_low_u64_be(i as u64)).unwrap();
+        }
+        assert_eq!(trie_a.root(), trie_b.root());
+    }
+
+    #[test]
+    pub fn test_binary_trie_load_proof_and_update() {
+        let mut full = BinaryTrie::<crate::KeccakHasher>::empty();
+        for i in 0..32u8 {
+            full.set(key(&[i * 8], i), H256::from_low_u64_be(i as u64))
+                .unwrap();
+        }
+
+        let target = key(&[40], 5);
+        let mut partial = BinaryTrie::<crate::KeccakHasher>::from_root(full.root().unwrap());
+        partial
+            .load_proof(
+                target,
+                Some(H256::from_low_u64_be(5)),
+                full.get_proof(target).unwrap(),
+            )
+            .unwrap();
+        assert!(partial
+            .load_proof(target, None, full.get_proof(target).unwrap())
+            .is_err());
+
+        full.set(target, H256::from_low_u64_be(500)).unwrap();
+        partial.set(target, H256::from_low_u64_be(500)).unwrap();
+        assert_eq!(partial.root(), full.root());
+
+        // keys whose nodes were not loaded cannot be read.
+        assert!(partial.get(key(&[48], 6)).is_err());
+    }
+
+    #[test]
+    pub fn test_binary_trie_load_proof_too_deep() {
+        // internal nodes all the way down, more of them than the stem has bits.
+        let mut proof = Vec::new();
+        let mut child = H256::repeat_byte(1);
+        for _ in 0..300 {
+            let node = BinaryNode::Internal([Some(child), None]);
+            child = node.hash::<crate::KeccakHasher>();
+            proof.insert(0, node.to_raw_rlp());
+        }
+        let mut trie = BinaryTrie::<crate::KeccakHasher>::from_root(child);
+        assert!(trie.load_proof(H256::zero(), None, proof).is_err());
+    }
+
+    #[test]
+    pub fn test_binary_node_rlp_roundtrip() {
+        let mut trie = BinaryTrie::<crate::KeccakHasher>::empty();
+        trie.set(key(&[1], 0), H256::from_low_u64_be(10)).unwrap();
+        trie.set(key(&[2], 3), H256::from_low_u64_be(20)).unwrap();
+
+        let proof = trie.get_proof(key(&[2], 3)).unwrap();
+        assert!(proof.len() > 1);
+        for raw in proof {
+            let node = BinaryNode::from_raw_rlp(raw.clone()).unwrap();
+            assert_eq!(node.to_raw_rlp(), raw);
+        }
+    }
+}
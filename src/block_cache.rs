@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+
+use ethers_core::types::H256;
+
+struct Entry<T> {
+    number: u64,
+    parent_hash: H256,
+    value: T,
+}
+
+// witnesses or hydrated tries (e.g. Arc<StateTrie>) by block hash, for services following the
+// chain which would fetch the same proofs again otherwise. forks are kept side by side until
+// a new head or finalization says which ones are dead, see set_head and finalize. when it's
+// full the lowest blocks go first.
+pub struct BlockCache<T> {
+    entries: HashMap<H256, Entry<T>>,
+    capacity: usize,
+    finalized: Option<u64>,
+}
+
+impl<T> BlockCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        BlockCache {
+            entries: HashMap::new(),
+            capacity,
+            finalized: None,
+        }
+    }
+
+    // blocks below the finalized one aren't taken.
+    pub fn insert(&mut self, number: u64, hash: H256, parent_hash: H256, value: T) {
+        if self.capacity == 0 || self.finalized.is_some_and(|finalized| number < finalized) {
+            return;
+        }
+        if !self.entries.contains_key(&hash) {
+            while self.entries.len() >= self.capacity {
+                let lowest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.number)
+                    .map(|(hash, _)| *hash)
+                    .unwrap();
+                self.entries.remove(&lowest);
+            }
+        }
+        self.entries.insert(
+            hash,
+            Entry {
+                number,
+                parent_hash,
+                value,
+            },
+        );
+    }
+
+    pub fn get(&self, hash: &H256) -> Option<&T> {
+        self.entries.get(hash).map(|entry| &entry.value)
+    }
+
+    pub fn get_mut(&mut self, hash: &H256) -> Option<&mut T> {
+        self.entries.get_mut(hash).map(|entry| &mut entry.value)
+    }
+
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // a new head, after a reorg or not. the chain back from it is followed through the parents
+    // the cache has, and blocks at those heights which aren't on it are dropped. returns the
+    // hashes of the dropped ones.
+    pub fn set_head(&mut self, number: u64, hash: H256, parent_hash: H256) -> Vec<H256> {
+        let mut canonical = HashMap::from([(number, hash)]);
+        let mut lowest = number;
+        let mut parent = (number.checked_sub(1), parent_hash);
+        while let (Some(parent_number), parent_hash) = parent {
+            canonical.insert(parent_number, parent_hash);
+            lowest = parent_number;
+            match self.entries.get(&parent_hash) {
+                Some(entry) => parent = (parent_number.checked_sub(1), entry.parent_hash),
+                None => break,
+            }
+        }
+        self.remove_where(|hash, entry| {
+            entry.number >= lowest
+                && entry.number <= number
+                && canonical.get(&entry.number) != Some(hash)
+        })
+    }
+
+    // nothing before the finalized block is needed again, and no fork off the chain up to it
+    // can become canonical. drops both, and whatever was built on those forks.
+    pub fn finalize(&mut self, number: u64, hash: H256) -> Vec<H256> {
+        self.finalized = Some(number);
+        let mut heights: Vec<(u64, H256, H256)> = self
+            .entries
+            .iter()
+            .map(|(hash, entry)| (entry.number, *hash, entry.parent_hash))
+            .collect();
+        heights.sort();
+        // blocks on forks off the finalized block, and the ones built on top of them.
+        let mut forks = HashSet::new();
+        for (entry_number, entry_hash, parent_hash) in heights {
+            if (entry_number == number && entry_hash != hash) || forks.contains(&parent_hash) {
+                forks.insert(entry_hash);
+            }
+        }
+        self.remove_where(|hash, entry| entry.number < number || forks.contains(hash))
+    }
+
+    fn remove_where(&mut self, dead: impl Fn(&H256, &Entry<T>) -> bool) -> Vec<H256> {
+        let removed: Vec<H256> = self
+            .entries
+            .iter()
+            .filter(|(hash, entry)| dead(hash, entry))
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in removed.iter() {
+            self.entries.remove(hash);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockCache;
+    use ethers_core::types::H256;
+
+    fn hash(number: u64, fork: u8) -> H256 {
+        let mut hash = H256::from_low_u64_be(number);
+        hash.0[0] = fork;
+        hash
+    }
+
+    #[test]
+    pub fn test_block_cache() {
+        let mut cache = BlockCache::new(100);
+        // 1..=5 on fork 0, and fork 1 off block 2 with 3' and 4'.
+        for number in 1..=5u64 {
+            cache.insert(number, hash(number, 0), hash(number - 1, 0), number);
+        }
+        cache.insert(3, hash(3, 1), hash(2, 0), 30);
+        cache.insert(4, hash(4, 1), hash(3, 1), 40);
+        assert_eq!(cache.len(), 7);
+        assert_eq!(cache.get(&hash(4, 1)), Some(&40));
+
+        // the head on fork 0 drops 3' and 4'.
+        let mut removed = cache.set_head(5, hash(5, 0), hash(4, 0));
+        removed.sort();
+        assert_eq!(removed, vec![hash(3, 1), hash(4, 1)]);
+        assert_eq!(cache.len(), 5);
+
+        // a reorg to fork 2 off block 3 at height 5 drops 4 and 5.
+        cache.insert(4, hash(4, 2), hash(3, 0), 42);
+        let mut removed = cache.set_head(5, hash(5, 2), hash(4, 2));
+        removed.sort();
+        assert_eq!(removed, vec![hash(4, 0), hash(5, 0)]);
+        assert!(cache.contains(&hash(3, 0)));
+
+        // finalizing 3 drops what's before it and forks off it, along with their children.
+        cache.insert(3, hash(3, 3), hash(2, 0), 33);
+        cache.insert(4, hash(4, 3), hash(3, 3), 43);
+        let mut removed = cache.finalize(3, hash(3, 0));
+        removed.sort();
+        assert_eq!(
+            removed,
+            vec![hash(1, 0), hash(2, 0), hash(3, 3), hash(4, 3)]
+        );
+        assert_eq!(cache.len(), 2);
+        cache.insert(2, hash(2, 0), hash(1, 0), 2);
+        assert!(!cache.contains(&hash(2, 0)));
+
+        // full, the lowest block goes.
+        let mut small = BlockCache::new(2);
+        for number in 1..=3u64 {
+            small.insert(number, hash(number, 0), hash(number - 1, 0), ());
+        }
+        assert!(!small.contains(&hash(1, 0)));
+        assert!(small.contains(&hash(3, 0)));
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+use ethers_core::{
+    types::{Address, H160, H256, U256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+use crate::Error;
+
+// BLOCKHASH only reaches this far back.
+pub const BLOCK_HASH_HISTORY: usize = 256;
+
+// eip-4788: the parent beacon block roots are written into this contract before a block's
+// transactions run, into a ring buffer of 8191 timestamps and roots.
+pub const BEACON_ROOTS_ADDRESS: Address = H160([
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22,
+    0xd0, 0xbe, 0xac, 0x02,
+]);
+pub const BEACON_ROOTS_HISTORY: u64 = 8191;
+
+// the slots of the timestamp and the root the block with this timestamp writes.
+pub fn beacon_root_slots(timestamp: u64) -> (U256, U256) {
+    let index = timestamp % BEACON_ROOTS_HISTORY;
+    (U256::from(index), U256::from(index + BEACON_ROOTS_HISTORY))
+}
+
+// the hashes of the blocks before the one being executed, the newest first, for BLOCKHASH
+// in stateless execution. travels with the witness, see Witness::with_block_hashes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockHashes {
+    // number of hashes[0].
+    newest: u64,
+    hashes: VecDeque<H256>,
+}
+
+impl BlockHashes {
+    // `hashes[i]` is the hash of block `newest - i`.
+    pub fn new(newest: u64, hashes: Vec<H256>) -> Result<Self, Error> {
+        if hashes.len() > BLOCK_HASH_HISTORY {
+            return Err(Error::InternalError("more than 256 block hashes"));
+        }
+        if hashes.len() as u64 > newest + 1 {
+            return Err(Error::InternalError("block hashes go back before genesis"));
+        }
+        Ok(BlockHashes {
+            newest,
+            hashes: hashes.into(),
+        })
+    }
+
+    pub fn newest(&self) -> Option<u64> {
+        (!self.hashes.is_empty()).then_some(self.newest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    // the hash of the next block, the oldest goes once there are 256.
+    pub fn push(&mut self, number: u64, hash: H256) -> Result<(), Error> {
+        if !self.hashes.is_empty() && number != self.newest + 1 {
+            return Err(Error::InternalError("block hashes have to be consecutive"));
+        }
+        self.newest = number;
+        self.hashes.push_front(hash);
+        self.hashes.truncate(BLOCK_HASH_HISTORY);
+        Ok(())
+    }
+
+    pub fn get(&self, number: u64) -> Option<H256> {
+        let back = self.newest.checked_sub(number)?;
+        self.hashes.get(back as usize).copied()
+    }
+
+    // what BLOCKHASH gives while executing block `current`: zero for `current` itself, later
+    // blocks and ones more than 256 back. an error when the hash should be there but isn't.
+    pub fn blockhash(&self, current: u64, number: u64) -> Result<H256, Error> {
+        if number >= current || current - number > BLOCK_HASH_HISTORY as u64 {
+            return Ok(H256::zero());
+        }
+        self.get(number)
+            .ok_or(Error::MissingData("block hash not in the store"))
+    }
+
+    // rlp([newest, [hash_newest, ..]]).
+    pub(crate) fn append_to(&self, rlp_stream: &mut RlpStream) {
+        rlp_stream.begin_list(2);
+        rlp_stream.append(&self.newest);
+        rlp_stream.begin_list(self.hashes.len());
+        for hash in self.hashes.iter() {
+            rlp_stream.append(hash);
+        }
+    }
+
+    pub(crate) fn decode(rlp: &Rlp) -> Result<Self, Error> {
+        if rlp.item_count()? != 2 {
+            return Err(Error::InternalError("block hashes should have two fields"));
+        }
+        let hashes = rlp.list_at(1)?;
+        if hashes.is_empty() {
+            return Err(Error::InternalError("block hashes can't be empty"));
+        }
+        Self::new(rlp.val_at(0)?, hashes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{beacon_root_slots, BlockHashes, BEACON_ROOTS_ADDRESS};
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_block_hashes() {
+        let mut block_hashes = BlockHashes::default();
+        assert_eq!(block_hashes.newest(), None);
+        for number in 0..300u64 {
+            block_hashes
+                .push(number, H256::from_low_u64_be(number + 1))
+                .unwrap();
+        }
+        assert_eq!(block_hashes.len(), 256);
+        assert_eq!(block_hashes.newest(), Some(299));
+        assert_eq!(block_hashes.get(299), Some(H256::from_low_u64_be(300)));
+        assert_eq!(block_hashes.get(44), Some(H256::from_low_u64_be(45)));
+        assert_eq!(block_hashes.get(43), None);
+        assert!(block_hashes.push(301, H256::zero()).is_err());
+
+        // executing block 300.
+        assert_eq!(
+            block_hashes.blockhash(300, 299).unwrap(),
+            H256::from_low_u64_be(300)
+        );
+        assert_eq!(block_hashes.blockhash(300, 300).unwrap(), H256::zero());
+        assert_eq!(block_hashes.blockhash(300, 43).unwrap(), H256::zero());
+        // a block the store should have, but only has 10 of.
+        let short = BlockHashes::new(299, vec![H256::zero(); 10]).unwrap();
+        assert!(short.blockhash(300, 100).is_err());
+
+        assert!(BlockHashes::new(300, vec![H256::zero(); 257]).is_err());
+        assert!(BlockHashes::new(3, vec![H256::zero(); 5]).is_err());
+    }
+
+    #[test]
+    pub fn test_beacon_root_slots() {
+        assert_eq!(
+            BEACON_ROOTS_ADDRESS,
+            "0x000F3df6D732807Ef1319fB7B8bB8522d0Beac02"
+                .parse::<Address>()
+                .unwrap()
+        );
+        assert_eq!(beacon_root_slots(8192), (U256::from(1), U256::from(8192)));
+    }
+}
@@ -0,0 +1,252 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use arc_swap::ArcSwap;
+use ethers_core::types::{Address, EIP1186ProofResponse, H256, U256};
+
+use crate::{
+    state_trie::{AccountData, StorageTrie, VerifiedProofs},
+    Error, StateTrie, Witness,
+};
+
+// a state trie shared between threads or tasks, e.g. one hydrated witness answering lookups
+// for a proof server. reads run in parallel, writes wait for them and go one at a time.
+#[derive(Debug, Default)]
+pub struct ConcurrentStateTrie {
+    inner: RwLock<StateTrie>,
+}
+
+pub(crate) fn get_storage(trie: &StateTrie, address: Address, slot: U256) -> Result<U256, Error> {
+    let storage_root = trie.get_storage_root(address)?;
+    match trie.loaded_storage_trie(&storage_root) {
+        Some(storage_trie) => storage_trie.get(slot),
+        // only fine for empty storage, otherwise the node is missing.
+        None => StorageTrie::from_root(storage_root).get(slot),
+    }
+    .map_err(|err| err.at_address(address))
+}
+
+impl ConcurrentStateTrie {
+    pub fn new(state_trie: StateTrie) -> Self {
+        ConcurrentStateTrie {
+            inner: RwLock::new(state_trie),
+        }
+    }
+
+    pub fn from_witness(witness: &Witness) -> Result<Self, Error> {
+        Ok(Self::new(StateTrie::from_witness(witness)?))
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.read(|trie| trie.root())
+    }
+
+    pub fn get_account(&self, address: Address) -> Result<AccountData, Error> {
+        self.read(|trie| trie.account_trie.get(address))
+    }
+
+    // like StateTrie::get_storage_at, without remembering the address and slot.
+    pub fn get_storage(&self, address: Address, slot: U256) -> Result<U256, Error> {
+        self.read(|trie| get_storage(trie, address, slot))
+    }
+
+    pub fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+    ) -> Result<EIP1186ProofResponse, Error> {
+        self.read(|trie| trie.get_proof(address, slots))
+    }
+
+    // anything else that only reads, under a shared lock.
+    pub fn read<R>(&self, f: impl FnOnce(&StateTrie) -> R) -> R {
+        f(&self.inner.read().unwrap())
+    }
+
+    // verifies the proofs on `threads` threads without holding the lock, reads can go on
+    // meanwhile, and then merges them under a single write. fails if the root changed in
+    // between, otherwise there's a result per proof.
+    pub fn load_proofs(
+        &self,
+        proofs: Vec<EIP1186ProofResponse>,
+        threads: usize,
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        let root = self.root().ok_or(Error::Usage("root not set"))?;
+        let verified = VerifiedProofs::verify(root, proofs, threads);
+        self.write(|trie| trie.load_verified(verified))
+    }
+
+    // changes under an exclusive lock, e.g. `write(|trie| trie.load_proof(proof))`.
+    pub fn write<R>(&self, f: impl FnOnce(&mut StateTrie) -> R) -> R {
+        f(&mut self.inner.write().unwrap())
+    }
+
+    pub fn into_inner(self) -> StateTrie {
+        self.inner.into_inner().unwrap()
+    }
+}
+
+// readers see the last published version while a writer works on the next one, so serving
+// proofs doesn't wait for writes and the roots they recompute. publish swaps the pointer to
+// the current version atomically, readers take no lock at all.
+#[derive(Debug, Default)]
+pub struct VersionedStateTrie {
+    published: ArcSwap<StateTrie>,
+    next: Mutex<StateTrie>,
+}
+
+impl VersionedStateTrie {
+    pub fn new(state_trie: StateTrie) -> Self {
+        VersionedStateTrie {
+            published: ArcSwap::from_pointee(state_trie.clone()),
+            next: Mutex::new(state_trie),
+        }
+    }
+
+    pub fn from_witness(witness: &Witness) -> Result<Self, Error> {
+        Ok(Self::new(StateTrie::from_witness(witness)?))
+    }
+
+    // the published version, it stays readable for as long as it's held.
+    pub fn snapshot(&self) -> Arc<StateTrie> {
+        self.published.load_full()
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.snapshot().root()
+    }
+
+    pub fn get_account(&self, address: Address) -> Result<AccountData, Error> {
+        self.snapshot().account_trie.get(address)
+    }
+
+    pub fn get_storage(&self, address: Address, slot: U256) -> Result<U256, Error> {
+        get_storage(&self.snapshot(), address, slot)
+    }
+
+    pub fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+    ) -> Result<EIP1186ProofResponse, Error> {
+        self.snapshot().get_proof(address, slots)
+    }
+
+    // changes the next version, one writer at a time. readers don't see them until publish.
+    pub fn write<R>(&self, f: impl FnOnce(&mut StateTrie) -> R) -> R {
+        f(&mut self.next.lock().unwrap())
+    }
+
+    // makes the next version the one readers see and returns its root. the versions share
+    // their node arenas (see Nodes), the next one copies only the arenas it writes to later.
+    pub fn publish(&self) -> Option<H256> {
+        let next = self.next.lock().unwrap();
+        let version = Arc::new(next.clone());
+        let root = version.root();
+        self.published.store(version);
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConcurrentStateTrie, VersionedStateTrie};
+    use crate::StateTrie;
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_concurrent_state_trie() {
+        let mut full = StateTrie::new();
+        for i in 1..=20u64 {
+            let address = Address::from_low_u64_be(i);
+            full.account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+            full.set_storage_value(address, U256::one(), U256::from(i * 2))
+                .unwrap();
+        }
+        let shared = ConcurrentStateTrie::from_witness(&full.witness().unwrap()).unwrap();
+        assert_eq!(shared.root(), full.root());
+
+        std::thread::scope(|scope| {
+            for thread in 0..4u64 {
+                let shared = &shared;
+                scope.spawn(move || {
+                    for i in 1..=20u64 {
+                        let address = Address::from_low_u64_be(i);
+                        assert_eq!(shared.get_account(address).unwrap().balance, U256::from(i));
+                        if i % 4 == thread {
+                            assert_eq!(
+                                shared.get_storage(address, U256::one()).unwrap(),
+                                U256::from(i * 2)
+                            );
+                        }
+                    }
+                });
+            }
+            scope.spawn(|| {
+                shared
+                    .write(|trie| {
+                        trie.account_trie
+                            .set_nonce(Address::from_low_u64_be(1), U256::one())
+                    })
+                    .unwrap();
+            });
+        });
+
+        full.account_trie
+            .set_nonce(Address::from_low_u64_be(1), U256::one())
+            .unwrap();
+        assert_eq!(shared.root(), full.root());
+        let proof = shared
+            .get_proof(Address::from_low_u64_be(2), vec![H256::from_low_u64_be(1)])
+            .unwrap();
+        assert_eq!(proof.storage_proof[0].value, U256::from(4));
+        assert_eq!(shared.into_inner().root(), full.root());
+    }
+
+    #[test]
+    pub fn test_versioned_state_trie() {
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut state_trie = StateTrie::new();
+        state_trie.account_trie.set_balance(a, U256::one()).unwrap();
+        state_trie.account_trie.set_balance(b, U256::one()).unwrap();
+        let versioned = VersionedStateTrie::new(state_trie);
+        let first = versioned.root();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let versioned = &versioned;
+                scope.spawn(move || {
+                    for _ in 0..200 {
+                        // both balances change in one version, readers never see one of them.
+                        let snapshot = versioned.snapshot();
+                        assert_eq!(
+                            snapshot.account_trie.get(a).unwrap().balance,
+                            snapshot.account_trie.get(b).unwrap().balance
+                        );
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for i in 2..50u64 {
+                    versioned.write(|trie| {
+                        trie.account_trie.set_balance(a, U256::from(i)).unwrap();
+                        trie.account_trie.set_balance(b, U256::from(i)).unwrap();
+                    });
+                    versioned.publish();
+                }
+            });
+        });
+
+        // unpublished writes aren't visible, a held snapshot keeps its version.
+        let held = versioned.snapshot();
+        versioned.write(|trie| trie.account_trie.set_nonce(a, U256::one()).unwrap());
+        assert_eq!(versioned.get_account(a).unwrap().nonce, U256::zero());
+        let root = versioned.publish();
+        assert_ne!(root, first);
+        assert_eq!(versioned.root(), root);
+        assert_eq!(versioned.get_account(a).unwrap().nonce, U256::one());
+        assert_eq!(held.account_trie.get(a).unwrap().nonce, U256::zero());
+        assert_eq!(versioned.get_account(b).unwrap().balance, U256::from(49));
+    }
+}
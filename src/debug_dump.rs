@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use ethers_core::utils::hex;
+
+use crate::{
+    hasher::TrieHasher,
+    nodes::{LeafValue, NodeData},
+    trie::{MptKey, Trie},
+    utils::hex_nibbles,
+    Error, StateTrie,
+};
+
+fn path_name(path: &[u8]) -> String {
+    match path.len() {
+        0 => "root".to_string(),
+        _ => hex_nibbles(path),
+    }
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    // one line per loaded node sorted by nibble path, then the unresolved ones. the same
+    // trie always dumps the same text whatever order it was built or loaded in, unlike
+    // Debug which prints the node map in hash map order. meant for golden file tests.
+    pub fn debug_dump(&self) -> Result<String, Error> {
+        let root = self.root().ok_or(Error::Usage("root not set"))?;
+        let mut loaded = self.loaded_nodes()?;
+        loaded.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut dump = String::new();
+        // writing to a string can't fail.
+        let _ = writeln!(dump, "root {:?}", root);
+        for node in loaded {
+            let path = path_name(&node.path);
+            let _ = match &node.node_data {
+                NodeData::Leaf { key, value } => writeln!(
+                    dump,
+                    "{} {:?} leaf key={} value=0x{}",
+                    path,
+                    node.hash,
+                    hex_nibbles(&key.to_u4_vec()),
+                    hex::encode(value.to_raw_rlp()?)
+                ),
+                NodeData::Branch(children) => writeln!(
+                    dump,
+                    "{} {:?} branch {}",
+                    path,
+                    node.hash,
+                    children
+                        .iter()
+                        .take(16)
+                        .enumerate()
+                        .filter_map(|(nibble, child)| Some(format!("{:x}={:?}", nibble, (*child)?)))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+                NodeData::Extension { key, node: child } => writeln!(
+                    dump,
+                    "{} {:?} extension key={} node={:?}",
+                    path,
+                    node.hash,
+                    hex_nibbles(&key.to_u4_vec()),
+                    child
+                ),
+            };
+        }
+        for (path, hash) in self.unresolved_nodes()? {
+            let _ = writeln!(dump, "{} {:?} unresolved", path_name(&path), hash);
+        }
+        Ok(dump)
+    }
+}
+
+impl StateTrie {
+    // debug_dump of the account trie, followed by the loaded storage tries sorted by the
+    // hashed address of their account.
+    pub fn debug_dump(&self) -> Result<String, Error> {
+        let mut dump = self.account_trie.debug_dump()?;
+        for (address_hash, storage_trie) in self.storage_tries_by_hash()? {
+            let _ = writeln!(dump, "\nstorage of {:?}", address_hash);
+            dump.push_str(&storage_trie.debug_dump()?);
+        }
+        Ok(dump)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_debug_dump_is_stable() {
+        let entries = (1..50u64)
+            .map(|i| (U256::from(i), U256::from(i * 3)))
+            .collect::<Vec<_>>();
+        let mut forwards = StorageTrie::empty();
+        for (slot, value) in entries.iter() {
+            forwards.set(*slot, *value).unwrap();
+        }
+        let mut backwards = StorageTrie::empty();
+        for (slot, value) in entries.iter().rev() {
+            backwards.set(*slot, *value).unwrap();
+        }
+        let dump = forwards.debug_dump().unwrap();
+        assert_eq!(dump, backwards.debug_dump().unwrap());
+        assert_eq!(
+            dump,
+            StorageTrie::from_entries(entries)
+                .unwrap()
+                .debug_dump()
+                .unwrap()
+        );
+        assert!(dump.starts_with(&format!("root {:?}\nroot ", forwards.root().unwrap())));
+
+        // a partial trie lists where it stops.
+        let mut proof_only = StorageTrie::from_root(forwards.root().unwrap());
+        proof_only
+            .load_proof(
+                U256::from(7),
+                U256::from(21),
+                forwards.get_proof(U256::from(7)).unwrap(),
+            )
+            .unwrap();
+        let partial = proof_only.debug_dump().unwrap();
+        assert!(partial.contains(" unresolved\n"));
+        assert!(partial.lines().count() < dump.lines().count());
+    }
+
+    #[test]
+    pub fn test_state_trie_debug_dump() {
+        let address = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::new();
+        state_trie
+            .account_trie
+            .set_balance(address, U256::from(10))
+            .unwrap();
+        state_trie
+            .set_storage_value(address, U256::one(), U256::from(2))
+            .unwrap();
+        let dump = state_trie.debug_dump().unwrap();
+        assert_eq!(dump, state_trie.clone().debug_dump().unwrap());
+        assert_eq!(dump.matches("root ").count(), 4);
+        assert_eq!(dump.matches(" leaf ").count(), 2);
+        assert!(dump.contains("\nstorage of "));
+    }
+}
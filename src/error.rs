@@ -1,13 +1,257 @@
-use ethers::core::utils::rlp;
+use std::fmt;
+
+use ethers_core::{
+    types::{Address, H256, U256},
+    utils::rlp,
+};
 
 #[derive(Debug)]
 pub enum Error {
     RlpDecoderError(rlp::DecoderError),
+    // data which doesn't check out, or a case the trie doesn't handle.
     InternalError(&'static str),
+    // something which isn't loaded yet, e.g. a node without a proof for it.
+    MissingData(&'static str),
+    // the call is wrong, e.g. on a trie without a root or with a path it doesn't have.
+    Usage(&'static str),
+    ProviderError(String),
+    // the provider answered and refused, e.g. a method it doesn't serve or a node it doesn't
+    // have. unlike a ProviderError, asking again gets the same answer.
+    ProviderRejected(String),
+    JsonError(serde_json::Error),
+    // an error from deep in a traversal, with what it was working on. see Error::context.
+    WithContext(Box<(Error, ErrorContext)>),
+}
+
+// what a failed operation was on, as far as it's known where it failed: the account and slot
+// it was reading or writing, and the node the trie walk stopped at.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub address: Option<Address>,
+    pub slot: Option<U256>,
+    // nibbles from the root of the trie (the storage trie for a slot) to the node.
+    pub path: Option<Vec<u8>>,
+    pub node: Option<H256>,
+}
+
+impl ErrorContext {
+    pub fn is_empty(&self) -> bool {
+        *self == ErrorContext::default()
+    }
+
+    // fields which are already set stay, what is closest to the failure is the most precise.
+    fn fill(&mut self, other: ErrorContext) {
+        self.address = self.address.or(other.address);
+        self.slot = self.slot.or(other.slot);
+        self.path = self.path.take().or(other.path);
+        self.node = self.node.or(other.node);
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(address) = self.address {
+            parts.push(format!("address {:?}", address));
+        }
+        if let Some(slot) = self.slot {
+            parts.push(format!("slot {:#x}", slot));
+        }
+        if let Some(path) = &self.path {
+            let nibbles: String = path.iter().map(|nibble| format!("{:x}", nibble)).collect();
+            parts.push(format!("path [{}]", nibbles));
+        }
+        if let Some(node) = self.node {
+            parts.push(format!("node {:?}", node));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
 }
 
+// what went wrong, coarsely, for retry and alerting policies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    // something that isn't loaded yet: a node, a storage trie, code or a block hash. the
+    // operation works once a proof or witness for it is added.
+    MissingData,
+    // the provider failed, e.g. a timeout or a rate limit.
+    Provider,
+    // data which doesn't check out: a proof against another root, a node which doesn't match
+    // its hash, bad rlp or json. trying again with the same data gives the same error.
+    InvalidData,
+    // the call itself is wrong, e.g. a trie without a root or ids that don't exist. a bug on
+    // the caller's side.
+    Usage,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::WithContext(inner) => inner.0.kind(),
+            Error::ProviderError(_) | Error::ProviderRejected(_) => ErrorKind::Provider,
+            Error::RlpDecoderError(_) | Error::JsonError(_) => ErrorKind::InvalidData,
+            Error::MissingData(_) => ErrorKind::MissingData,
+            Error::Usage(_) => ErrorKind::Usage,
+            Error::InternalError(_) => ErrorKind::InvalidData,
+        }
+    }
+
+    // missing data can be fetched and the provider may be back, the rest fails the same way
+    // again.
+    pub fn is_retryable(&self) -> bool {
+        self.kind() == ErrorKind::MissingData || self.is_transient()
+    }
+
+    // a provider failure which can go away by itself, like a timeout or a rate limit. the
+    // errors RetryProvider tries again.
+    pub fn is_transient(&self) -> bool {
+        matches!(self.root_cause(), Error::ProviderError(_))
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::WithContext(inner) => Some(&inner.1),
+            _ => None,
+        }
+    }
+
+    // the error without its context.
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::WithContext(inner) => inner.0.root_cause(),
+            err => err,
+        }
+    }
+
+    // adds what the caller knows to the context, on the way up from where it failed.
+    pub(crate) fn with_context(self, context: ErrorContext) -> Error {
+        match self {
+            Error::WithContext(mut inner) => {
+                inner.1.fill(context);
+                Error::WithContext(inner)
+            }
+            err if context.is_empty() => err,
+            err => Error::WithContext(Box::new((err, context))),
+        }
+    }
+
+    pub(crate) fn at_address(self, address: Address) -> Error {
+        self.with_context(ErrorContext {
+            address: Some(address),
+            ..Default::default()
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RlpDecoderError(err) => write!(f, "rlp: {}", err),
+            Error::InternalError(message) | Error::MissingData(message) | Error::Usage(message) => {
+                write!(f, "{}", message)
+            }
+            Error::ProviderError(message) | Error::ProviderRejected(message) => {
+                write!(f, "provider: {}", message)
+            }
+            Error::JsonError(err) => write!(f, "json: {}", err),
+            Error::WithContext(inner) => write!(f, "{} ({})", inner.0, inner.1),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<rlp::DecoderError> for Error {
     fn from(err: rlp::DecoderError) -> Self {
         Error::RlpDecoderError(err)
     }
 }
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::JsonError(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind};
+    use crate::{StateTrie, WitnessExplorer};
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_error_kind() {
+        // a key the trie only has the root of.
+        let err = StateTrie::from_root(H256::repeat_byte(1))
+            .account_trie
+            .get(Address::zero())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingData);
+        assert!(err.is_retryable());
+
+        assert_eq!(Error::Usage("root not set").kind(), ErrorKind::Usage);
+        // a path the trie doesn't have.
+        let mut trie = StateTrie::new();
+        for i in 1..=2u64 {
+            trie.account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::one())
+                .unwrap();
+        }
+        let explorer = WitnessExplorer::new(&trie.witness().unwrap());
+        let err = (0..16)
+            .find_map(|nibble| explorer.clone().cd(&format!("{:x}", nibble)).err())
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::Usage);
+
+        let err = Error::InternalError("node does not match its hash");
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(!err.is_retryable());
+        assert!(Error::ProviderError("429".to_string()).is_retryable());
+        let err = Error::ProviderRejected("method not found".to_string());
+        assert_eq!(err.kind(), ErrorKind::Provider);
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    pub fn test_error_context() {
+        let address = Address::from_low_u64_be(0x1000);
+        let mut full = StateTrie::new();
+        for slot in 1..=2u64 {
+            full.set_storage_value(address, U256::from(slot), U256::from(slot))
+                .unwrap();
+        }
+        // only the first slot's leaf is loaded, the second one is under the same branch.
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        partial
+            .load_proof(
+                full.get_proof(address, vec![H256::from_low_u64_be(1)])
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let err = partial.get_storage_at(address, U256::from(2)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingData);
+        let context = err.context().unwrap();
+        assert_eq!(context.address, Some(address));
+        assert_eq!(context.slot, Some(U256::from(2)));
+        assert_eq!(context.path.as_ref().map(Vec::len), Some(1));
+        assert!(context.node.is_some());
+        assert!(matches!(
+            err.root_cause(),
+            Error::MissingData("node not present, please add a proof")
+        ));
+        assert!(err.to_string().starts_with(
+            "node not present, please add a proof (address 0x0000000000000000000000000000000000001000, slot 0x2, path ["
+        ));
+
+        // an account the trie has no branch for yet.
+        let err = StateTrie::from_root(full.root().unwrap())
+            .account_trie
+            .set_balance(address, U256::one())
+            .unwrap_err();
+        let context = err.context().unwrap();
+        assert_eq!(context.address, Some(address));
+        assert_eq!(context.path, Some(vec![]));
+        assert_eq!(context.node, full.root());
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use eth_trie::{EthTrie, MemoryDB, DB};
+use ethers_core::types::{Bytes, H256};
+
+use crate::{
+    hasher::TrieHasher,
+    nodes::LeafValue,
+    trie::{MptKey, Trie},
+    Error, StateTrie,
+};
+
+fn db_error(err: impl std::error::Error) -> Error {
+    Error::ProviderError(format!("eth_trie: {}", err))
+}
+
+// eth_trie proofs are plain byte vectors, root first like ours.
+pub fn proof_from_eth_trie(proof: Vec<Vec<u8>>) -> Vec<Bytes> {
+    proof.into_iter().map(Bytes::from).collect()
+}
+
+pub fn proof_to_eth_trie(proof: &[Bytes]) -> Vec<Vec<u8>> {
+    proof.iter().map(|node| node.to_vec()).collect()
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    // writes the loaded nodes into an eth_trie database, which keys nodes by their hash.
+    pub fn export_to_eth_trie_db<D: DB>(&self, db: &D) -> Result<(), Error> {
+        for raw in self.raw_nodes()? {
+            db.insert(H::hash(&raw).as_bytes(), raw.to_vec())
+                .map_err(db_error)?;
+        }
+        db.flush().map_err(db_error)
+    }
+
+    // loads every node reachable from the root which the database has. nodes it doesn't
+    // have stay unresolved, like with a partial witness.
+    pub fn load_from_eth_trie_db<D: DB>(&mut self, db: &D) -> Result<(), Error> {
+        loop {
+            let mut loaded = false;
+            for (_, hash) in self.unresolved_nodes()? {
+                if let Some(raw) = db.get(hash.as_bytes()).map_err(db_error)? {
+                    self.insert_fetched_node(hash, raw.into())?;
+                    loaded = true;
+                }
+            }
+            if !loaded {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<K: MptKey, V: LeafValue> Trie<K, V> {
+    // an EthTrie over the loaded nodes, e.g. to cross check roots or get values through it.
+    // its keys are the hashed keys of ours for secure tries.
+    pub fn to_eth_trie(&self) -> Result<EthTrie<MemoryDB>, Error> {
+        let root = self.root().ok_or(Error::Usage("root not set"))?;
+        let db = Arc::new(MemoryDB::new(false));
+        self.export_to_eth_trie_db(db.as_ref())?;
+        EthTrie::from(db, root.to_fixed_bytes().into()).map_err(db_error)
+    }
+
+    // a partial trie with every node of an EthTrie database reachable from `root`.
+    pub fn from_eth_trie_db<D: DB>(db: &D, root: H256) -> Result<Self, Error> {
+        let mut trie = Self::from_root(root);
+        trie.load_from_eth_trie_db(db)?;
+        Ok(trie)
+    }
+}
+
+impl StateTrie {
+    // the account trie and all loaded storage tries into one database, like geth's hash
+    // based scheme keeps them.
+    pub fn export_to_eth_trie_db<D: DB>(&self, db: &D) -> Result<(), Error> {
+        self.account_trie.export_to_eth_trie_db(db)?;
+        for (_, storage_trie) in self.storage_tries_by_hash()? {
+            storage_trie.export_to_eth_trie_db(db)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{proof_from_eth_trie, proof_to_eth_trie};
+    use crate::state_trie::StorageTrie;
+    use eth_trie::{EthTrie, MemoryDB, Trie as _};
+    use ethers_core::{
+        types::{BigEndianHash, H256, U256},
+        utils::{keccak256, rlp},
+    };
+
+    fn entries() -> Vec<(U256, U256)> {
+        (1..40u64)
+            .map(|i| (U256::from(i), U256::from(i * 1000)))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_eth_trie_export() {
+        let full = StorageTrie::from_entries(entries()).unwrap();
+        let mut eth_trie = full.to_eth_trie().unwrap();
+        assert_eq!(
+            eth_trie.root_hash().unwrap().0,
+            full.root().unwrap().to_fixed_bytes()
+        );
+
+        // secure keys, values are the rlp of the slot value.
+        let key = keccak256(H256::from_uint(&U256::from(7)));
+        assert_eq!(
+            eth_trie.get(&key).unwrap(),
+            Some(rlp::encode(&U256::from(7000)).to_vec())
+        );
+
+        // their proofs load into ours.
+        let proof = proof_from_eth_trie(eth_trie.get_proof(&key).unwrap());
+        assert_eq!(proof_to_eth_trie(&proof), eth_trie.get_proof(&key).unwrap());
+        let mut partial = StorageTrie::from_root(full.root().unwrap());
+        partial
+            .load_proof(U256::from(7), U256::from(7000), proof)
+            .unwrap();
+        assert_eq!(partial.get(U256::from(7)).unwrap(), U256::from(7000));
+    }
+
+    #[test]
+    pub fn test_eth_trie_import() {
+        // a trie built by eth_trie, read by us.
+        let db = Arc::new(MemoryDB::new(false));
+        let mut eth_trie = EthTrie::new(db.clone());
+        for (slot, value) in entries() {
+            eth_trie
+                .insert(&keccak256(H256::from_uint(&slot)), &rlp::encode(&value))
+                .unwrap();
+        }
+        let root = H256::from(eth_trie.root_hash().unwrap().0);
+
+        let trie = StorageTrie::from_eth_trie_db(db.as_ref(), root).unwrap();
+        assert_eq!(
+            trie.root(),
+            StorageTrie::from_entries(entries()).unwrap().root()
+        );
+        for (slot, value) in entries() {
+            assert_eq!(trie.get(slot).unwrap(), value);
+        }
+
+        // an empty database leaves the trie unresolved.
+        let empty = StorageTrie::from_eth_trie_db(&MemoryDB::new(false), root).unwrap();
+        assert!(empty.get(U256::one()).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use ethers_core::types::{Bytes, H256};
+
+use crate::{
+    hasher::TrieHasher,
+    nibbles::Nibbles,
+    nodes::{LeafValue, NodeData},
+    trie::{MptKey, Trie},
+    Error,
+};
+
+// what an expired subtree leaves behind: where it was and its root, which resurrection
+// proofs are checked against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpiredSubtree {
+    pub path: Vec<u8>,
+    pub root: H256,
+}
+
+// the rest of a key below an expired subtree, for checking proofs against its root.
+#[derive(Clone, Debug, PartialEq)]
+struct SuffixKey(Nibbles);
+
+impl MptKey for SuffixKey {
+    fn to_nibbles(&self) -> Result<Nibbles, Error> {
+        Ok(self.0.clone())
+    }
+}
+
+fn suffix(expired: &ExpiredSubtree, path: &[u8]) -> Result<SuffixKey, Error> {
+    if !path.starts_with(&expired.path) {
+        return Err(Error::Usage("key is not in the expired subtree"));
+    }
+    Ok(SuffixKey(Nibbles::from_u4_vec(
+        path[expired.path.len()..].to_vec(),
+    )?))
+}
+
+// experimental, for state expiry prototypes: subtrees are expired out of the trie (their keys
+// read as empty and the root no longer commits to them) and single keys are resurrected with
+// a proof of their value against the expired subtree's root. nothing here is part of any
+// ethereum fork.
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    // expires the subtree at nibble `path`, which has to end at a node, e.g. a branch child.
+    pub fn expire(&mut self, path: &[u8]) -> Result<ExpiredSubtree, Error> {
+        let root = self.remove_subtree(path)?;
+        Ok(ExpiredSubtree {
+            path: path.to_vec(),
+            root,
+        })
+    }
+
+    // what resurrect takes for `key`, from a trie which still has the subtree at `path` (an
+    // archive of the state before it expired): its nodes from the subtree root to the key.
+    pub fn resurrection_proof(&self, path: &[u8], key: K) -> Result<Vec<Bytes>, Error> {
+        let mut proof = self.get_proof(key)?;
+        let mut depth = 0;
+        let mut above = 0;
+        for raw in proof.iter() {
+            if depth >= path.len() {
+                break;
+            }
+            depth += match NodeData::<V>::from_raw_rlp(raw.to_owned())? {
+                NodeData::Branch(_) => 1,
+                NodeData::Extension { key, .. } => key.len(),
+                NodeData::Leaf { .. } => {
+                    return Err(Error::InternalError("path ends inside a leaf"));
+                }
+            };
+            above += 1;
+        }
+        if depth != path.len() {
+            return Err(Error::InternalError("path ends inside an extension"));
+        }
+        Ok(proof.split_off(above))
+    }
+
+    // puts `key` back with its value from before the expiry. `proof` shows the value under
+    // `expired.root`, and the key has to read as empty in the trie now (load an exclusion
+    // proof for it first in a partial trie). so a key comes back at most once, and never over
+    // a value written after the expiry.
+    pub fn resurrect(
+        &mut self,
+        expired: &ExpiredSubtree,
+        key: K,
+        value: V,
+        proof: Vec<Bytes>,
+    ) -> Result<(), Error> {
+        if value == V::default() {
+            return Err(Error::Usage("nothing to resurrect for an empty value"));
+        }
+        let suffix = suffix(expired, &key.to_nibbles()?.to_u4_vec())?;
+        let mut expired_trie = Trie::<SuffixKey, V, H>::from_root(expired.root);
+        expired_trie.load_proof(suffix.clone(), value.clone(), proof)?;
+        if expired_trie.get(suffix)? != value {
+            return Err(Error::InternalError(
+                "proof does not show the value in the expired subtree",
+            ));
+        }
+        if self.get(key.clone())? != V::default() {
+            return Err(Error::Usage("key is not empty in the trie"));
+        }
+        self.set(key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state_trie::StorageTrie, trie::MptKey};
+    use ethers_core::types::U256;
+
+    fn archive() -> (StorageTrie, Vec<(U256, U256)>) {
+        let entries = (1..60u64)
+            .map(|i| (U256::from(i), U256::from(i * 5)))
+            .collect::<Vec<_>>();
+        (StorageTrie::from_entries(entries.clone()).unwrap(), entries)
+    }
+
+    #[test]
+    pub fn test_expire_and_resurrect() {
+        let (archive, entries) = archive();
+        let mut trie = archive.clone();
+        let expired = trie.expire(&[0x3]).unwrap();
+        assert_ne!(trie.root(), archive.root());
+
+        let in_subtree = entries
+            .iter()
+            .filter(|(slot, _)| slot.to_nibbles().unwrap().to_u4_vec()[0] == 0x3)
+            .cloned()
+            .collect::<Vec<_>>();
+        assert!(in_subtree.len() > 1);
+        for (slot, _) in in_subtree.iter() {
+            assert_eq!(trie.get(*slot).unwrap(), U256::zero());
+        }
+        // the other keys are still there, and the expired nodes are gone.
+        let (outside, value) = entries
+            .iter()
+            .find(|(slot, _)| !in_subtree.iter().any(|(other, _)| other == slot))
+            .unwrap();
+        assert_eq!(trie.get(*outside).unwrap(), *value);
+        assert!(trie.get_raw_node(expired.root).unwrap().is_none());
+
+        // bringing every key back gives the old root.
+        for (slot, value) in in_subtree.iter() {
+            let proof = archive.resurrection_proof(&expired.path, *slot).unwrap();
+            trie.resurrect(&expired, *slot, *value, proof).unwrap();
+        }
+        assert_eq!(trie.root(), archive.root());
+    }
+
+    #[test]
+    pub fn test_resurrect_checks() {
+        let (archive, entries) = archive();
+        let mut trie = archive.clone();
+        let expired = trie.expire(&[0x3]).unwrap();
+        let (slot, value) = entries
+            .iter()
+            .find(|(slot, _)| slot.to_nibbles().unwrap().to_u4_vec()[0] == 0x3)
+            .cloned()
+            .unwrap();
+        let proof = archive.resurrection_proof(&expired.path, slot).unwrap();
+
+        // a wrong value, or a key outside the subtree, doesn't come back.
+        assert!(trie
+            .resurrect(&expired, slot, value + 1, proof.clone())
+            .is_err());
+        let (outside, outside_value) = entries
+            .iter()
+            .find(|(slot, _)| slot.to_nibbles().unwrap().to_u4_vec()[0] != 0x3)
+            .cloned()
+            .unwrap();
+        let outside_proof = archive.get_proof(outside).unwrap();
+        assert!(trie
+            .resurrect(&expired, outside, outside_value, outside_proof)
+            .is_err());
+
+        // only once, and not over a newer value.
+        trie.resurrect(&expired, slot, value, proof.clone())
+            .unwrap();
+        assert!(trie
+            .resurrect(&expired, slot, value, proof.clone())
+            .is_err());
+        trie.set(slot, U256::from(1)).unwrap();
+        assert!(trie.resurrect(&expired, slot, value, proof).is_err());
+
+        // paths have to end at a node.
+        let mut trie = archive.clone();
+        assert!(trie.expire(&[0x3, 0x4, 0x5, 0x6, 0x7, 0x8]).is_err());
+        assert_eq!(trie.root(), archive.root());
+    }
+}
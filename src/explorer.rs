@@ -0,0 +1,475 @@
+use std::collections::{HashMap, HashSet};
+
+use ethers_core::{
+    types::{Bytes, H256, U256},
+    utils::hex,
+};
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nodes::NodeData,
+    utils::{hex_nibbles, nibbles_to_hash},
+    AccountData, Error, Witness,
+};
+
+const HELP: &str = "commands:
+  ls              show the current node and its children
+  cd <nibbles>    descend along a nibble path, e.g. cd 3a0
+  cd storage      enter the storage trie of the account leaf
+  cd .. | cd /    go up one node, or back to the state root
+  pwd             print the current path
+  node [hash]     print the raw rlp and the decoded node
+  accounts        list the account leaves in the witness
+  check           list missing, unused and undecodable nodes";
+
+#[derive(Clone, Debug)]
+enum Node {
+    Account(NodeData<AccountData>),
+    Storage(NodeData<U256>),
+}
+
+impl Node {
+    fn children(&self) -> Vec<H256> {
+        match self {
+            Node::Account(NodeData::Branch(arr)) | Node::Storage(NodeData::Branch(arr)) => {
+                arr.iter().flatten().copied().collect()
+            }
+            Node::Account(NodeData::Extension { node, .. })
+            | Node::Storage(NodeData::Extension { node, .. }) => vec![*node],
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    hash: H256,
+    // nibbles taken from the parent to get here, empty for the roots.
+    nibbles: Vec<u8>,
+    storage: bool,
+}
+
+// walks the raw nodes of a witness by hand, for debugging witnesses that don't load.
+// nothing is verified up front, nodes are only decoded when they are looked at.
+#[derive(Clone, Debug)]
+pub struct WitnessExplorer {
+    root: H256,
+    nodes: HashMap<H256, Bytes>,
+    stack: Vec<Step>,
+}
+
+impl WitnessExplorer {
+    pub fn new(witness: &Witness) -> Self {
+        WitnessExplorer {
+            root: witness.root(),
+            nodes: witness.node_map(),
+            stack: vec![Step {
+                hash: witness.root(),
+                nibbles: vec![],
+                storage: false,
+            }],
+        }
+    }
+
+    pub fn current(&self) -> H256 {
+        self.stack.last().unwrap().hash
+    }
+
+    // account nibbles, and the storage nibbles after a / once in a storage trie.
+    pub fn pwd(&self) -> String {
+        let mut path = "/".to_string();
+        for step in self.stack.iter().skip(1) {
+            if step.nibbles.is_empty() && step.storage {
+                path.push('/');
+            }
+            path.extend(step.nibbles.iter().map(|nibble| format!("{:x}", nibble)));
+        }
+        path
+    }
+
+    fn decode(&self, hash: H256, storage: bool) -> Result<Option<Node>, Error> {
+        let raw = match self.nodes.get(&hash) {
+            Some(raw) => raw.to_owned(),
+            None => return Ok(None),
+        };
+        Ok(Some(if storage {
+            Node::Storage(NodeData::from_raw_rlp(raw)?)
+        } else {
+            Node::Account(NodeData::from_raw_rlp(raw)?)
+        }))
+    }
+
+    fn current_node(&self) -> Result<Node, Error> {
+        self.node_at(self.stack.last().unwrap())
+    }
+
+    fn node_at(&self, step: &Step) -> Result<Node, Error> {
+        if step.hash == KeccakHasher::empty_root() {
+            return Err(Error::Usage("trie is empty"));
+        }
+        self.decode(step.hash, step.storage)?
+            .ok_or(Error::MissingData("node is not in the witness"))
+    }
+
+    fn status(&self, hash: &H256) -> &'static str {
+        if self.nodes.contains_key(hash) {
+            ""
+        } else {
+            " (missing)"
+        }
+    }
+
+    pub fn ls(&self) -> Result<String, Error> {
+        let mut out = format!("{} {:?}\n", self.pwd(), self.current());
+        match self.current_node()? {
+            Node::Account(NodeData::Branch(arr)) | Node::Storage(NodeData::Branch(arr)) => {
+                out.push_str("branch\n");
+                for (nibble, child) in arr.iter().enumerate().take(16) {
+                    if let Some(child) = child {
+                        out.push_str(&format!(
+                            "  {:x} {:?}{}\n",
+                            nibble,
+                            child,
+                            self.status(child)
+                        ));
+                    }
+                }
+            }
+            Node::Account(NodeData::Extension { key, node })
+            | Node::Storage(NodeData::Extension { key, node }) => {
+                out.push_str(&format!(
+                    "extension {}\n  -> {:?}{}\n",
+                    hex_nibbles(&key.to_u4_vec()),
+                    node,
+                    self.status(&node)
+                ));
+            }
+            Node::Account(NodeData::Leaf { key, value }) => {
+                out.push_str(&format!(
+                    "account leaf {}\n  nonce {}\n  balance {}\n  storage root {:?}{}\n  code hash {:?}\n",
+                    hex_nibbles(&key.to_u4_vec()),
+                    value.nonce,
+                    value.balance,
+                    value.storage_root,
+                    if value.storage_root == KeccakHasher::empty_root() {
+                        " (empty)"
+                    } else {
+                        self.status(&value.storage_root)
+                    },
+                    value.code_hash
+                ));
+            }
+            Node::Storage(NodeData::Leaf { key, value }) => {
+                out.push_str(&format!(
+                    "storage leaf {}\n  value {:#x}\n",
+                    hex_nibbles(&key.to_u4_vec()),
+                    value
+                ));
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn cd(&mut self, path: &str) -> Result<(), Error> {
+        match path {
+            "/" => {
+                self.stack.truncate(1);
+                return Ok(());
+            }
+            ".." => {
+                if self.stack.len() > 1 {
+                    self.stack.pop();
+                }
+                return Ok(());
+            }
+            "storage" => {
+                let storage_root = match self.current_node()? {
+                    Node::Account(NodeData::Leaf { value, .. }) => value.storage_root,
+                    _ => return Err(Error::Usage("not at an account leaf")),
+                };
+                self.stack.push(Step {
+                    hash: storage_root,
+                    nibbles: vec![],
+                    storage: true,
+                });
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let mut nibbles = path
+            .strip_prefix("0x")
+            .unwrap_or(path)
+            .chars()
+            .map(|c| {
+                c.to_digit(16)
+                    .map(|nibble| nibble as u8)
+                    .ok_or(Error::Usage("path should be hex nibbles"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // descend on a copy, so a bad path leaves the position as it was.
+        let mut stack = self.stack.clone();
+        while !nibbles.is_empty() {
+            let step = stack.last().unwrap();
+            let storage = step.storage;
+            let (hash, taken) = match self.node_at(step)? {
+                Node::Account(NodeData::Branch(arr)) | Node::Storage(NodeData::Branch(arr)) => {
+                    let child = arr[nibbles[0] as usize]
+                        .ok_or(Error::Usage("branch has no child at this nibble"))?;
+                    (child, 1)
+                }
+                Node::Account(NodeData::Extension { key, node })
+                | Node::Storage(NodeData::Extension { key, node }) => {
+                    let key = key.to_u4_vec();
+                    if !nibbles.starts_with(&key) {
+                        return Err(Error::Usage("path does not follow the extension key"));
+                    }
+                    (node, key.len())
+                }
+                _ => return Err(Error::Usage("path ends at a leaf")),
+            };
+            stack.push(Step {
+                hash,
+                nibbles: nibbles.drain(..taken).collect(),
+                storage,
+            });
+        }
+        self.stack = stack;
+        Ok(())
+    }
+
+    pub fn node(&self, hash: Option<H256>) -> Result<String, Error> {
+        let (hash, storage) = match hash {
+            Some(hash) => (hash, self.stack.last().unwrap().storage),
+            None => (self.current(), self.stack.last().unwrap().storage),
+        };
+        let raw = self
+            .nodes
+            .get(&hash)
+            .ok_or(Error::MissingData("node is not in the witness"))?;
+        let decoded = match self.decode(hash, storage) {
+            Ok(Some(Node::Account(node_data))) => format!("{:?}", node_data),
+            Ok(Some(Node::Storage(node_data))) => format!("{:?}", node_data),
+            Ok(None) => unreachable!(),
+            Err(err) => format!("undecodable: {:?}", err),
+        };
+        Ok(format!("{:?}\n0x{}\n{}\n", hash, hex::encode(raw), decoded))
+    }
+
+    // address hash and account of every account leaf reachable from the state root.
+    pub fn accounts(&self) -> Result<Vec<(H256, AccountData)>, Error> {
+        let mut accounts = Vec::new();
+        let mut pending = vec![(self.root, Vec::new())];
+        while let Some((hash, path)) = pending.pop() {
+            let node_data = match self.decode(hash, false)? {
+                Some(Node::Account(node_data)) => node_data,
+                _ => continue,
+            };
+            match node_data {
+                NodeData::Leaf { key, value } => {
+                    let mut full_path = path;
+                    full_path.extend(key.to_u4_vec());
+                    accounts.push((nibbles_to_hash(&full_path)?, value));
+                }
+                NodeData::Branch(arr) => {
+                    for (nibble, child) in arr.iter().enumerate() {
+                        if let Some(child) = child {
+                            let mut child_path = path.clone();
+                            child_path.push(nibble as u8);
+                            pending.push((*child, child_path));
+                        }
+                    }
+                }
+                NodeData::Extension { key, node } => {
+                    let mut child_path = path;
+                    child_path.extend(key.to_u4_vec());
+                    pending.push((node, child_path));
+                }
+            }
+        }
+        accounts.sort_by_key(|(address_hash, _)| *address_hash);
+        Ok(accounts)
+    }
+
+    // referenced nodes that are not in the witness, nodes in it that are never referenced,
+    // and nodes that are referenced but can't be decoded.
+    pub fn check(&self) -> (Vec<H256>, Vec<H256>, Vec<H256>) {
+        let mut missing = Vec::new();
+        let mut undecodable = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = vec![(self.root, false)];
+        while let Some((hash, storage)) = pending.pop() {
+            if hash == KeccakHasher::empty_root() || !seen.insert(hash) {
+                continue;
+            }
+            let node = match self.decode(hash, storage) {
+                Ok(Some(node)) => node,
+                Ok(None) => {
+                    missing.push(hash);
+                    continue;
+                }
+                Err(_) => {
+                    undecodable.push(hash);
+                    continue;
+                }
+            };
+            if let Node::Account(NodeData::Leaf { value, .. }) = &node {
+                pending.push((value.storage_root, true));
+            }
+            pending.extend(node.children().into_iter().map(|child| (child, storage)));
+        }
+
+        let mut unused = self
+            .nodes
+            .keys()
+            .filter(|hash| !seen.contains(*hash))
+            .copied()
+            .collect::<Vec<_>>();
+        missing.sort();
+        unused.sort();
+        undecodable.sort();
+        (missing, unused, undecodable)
+    }
+
+    // runs one command line, returns what to print.
+    pub fn execute(&mut self, line: &str) -> Result<String, Error> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(String::new()),
+        };
+        let arg = words.next();
+        match command {
+            "help" => Ok(format!("{}\n", HELP)),
+            "ls" => self.ls(),
+            "cd" => {
+                self.cd(arg.unwrap_or("/"))?;
+                Ok(String::new())
+            }
+            "pwd" => Ok(format!("{}\n", self.pwd())),
+            "node" => self.node(
+                arg.map(|hash| {
+                    hash.parse::<H256>()
+                        .map_err(|_| Error::InternalError("invalid node hash"))
+                })
+                .transpose()?,
+            ),
+            "accounts" => Ok(self
+                .accounts()?
+                .iter()
+                .map(|(address_hash, account)| {
+                    format!(
+                        "{:?} nonce {} balance {}\n",
+                        address_hash, account.nonce, account.balance
+                    )
+                })
+                .collect()),
+            "check" => {
+                let (missing, unused, undecodable) = self.check();
+                let mut out = String::new();
+                for (name, hashes) in [
+                    ("missing", missing),
+                    ("unused", unused),
+                    ("undecodable", undecodable),
+                ] {
+                    out.push_str(&format!("{} {}\n", hashes.len(), name));
+                    for hash in hashes {
+                        out.push_str(&format!("  {:?}\n", hash));
+                    }
+                }
+                Ok(out)
+            }
+            _ => Err(Error::Usage("unknown command, try help")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WitnessExplorer;
+    use crate::{state_trie::StorageTrie, StateTrie, Witness};
+    use ethers_core::{
+        types::{Address, Bytes, H256, U256},
+        utils::keccak256,
+    };
+
+    fn witness() -> Witness {
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        for i in 1..20u64 {
+            state_trie
+                .account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        state_trie
+            .set_storage_value(Address::from_low_u64_be(1), U256::one(), U256::from(7))
+            .unwrap();
+        state_trie.witness().unwrap()
+    }
+
+    #[test]
+    pub fn test_explorer_navigation() {
+        let mut explorer = WitnessExplorer::new(&witness());
+        assert!(explorer.execute("ls").unwrap().contains("branch"));
+        assert_eq!(explorer.accounts().unwrap().len(), 19);
+
+        // walk to the first account along the nibbles of its address hash.
+        let address_hash = keccak256(Address::from_low_u64_be(1));
+        let nibbles = address_hash
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0xf])
+            .map(|nibble| format!("{:x}", nibble))
+            .collect::<String>();
+        let mut depth = 0;
+        while !explorer.ls().unwrap().contains("account leaf") {
+            depth += 1;
+            explorer.cd(&nibbles[depth - 1..depth]).unwrap();
+        }
+        assert_eq!(explorer.pwd(), format!("/{}", &nibbles[..depth]));
+        assert!(explorer.cd("0").is_err());
+
+        explorer.execute("cd storage").unwrap();
+        assert!(explorer.ls().unwrap().contains("value 0x7"));
+        assert!(explorer.pwd().ends_with('/'));
+
+        explorer.execute("cd ..").unwrap();
+        assert!(explorer.ls().unwrap().contains("account leaf"));
+        explorer.execute("cd /").unwrap();
+        assert_eq!(explorer.pwd(), "/");
+        assert!(explorer.execute("frobnicate").is_err());
+    }
+
+    #[test]
+    pub fn test_explorer_check() {
+        let witness = witness();
+        let explorer = WitnessExplorer::new(&witness);
+        let (missing, unused, undecodable) = explorer.check();
+        assert!(missing.is_empty() && unused.is_empty() && undecodable.is_empty());
+
+        // drop the storage trie (a single leaf) and add a node which is not part of the trie.
+        let address_hash = H256::from(keccak256(Address::from_low_u64_be(1)));
+        let storage_root = explorer
+            .accounts()
+            .unwrap()
+            .into_iter()
+            .find(|(hash, _)| *hash == address_hash)
+            .unwrap()
+            .1
+            .storage_root;
+        let junk = Bytes::from(vec![0xc2, 0x80, 0x80]);
+        let mut nodes = witness
+            .nodes()
+            .iter()
+            .filter(|node| H256::from(keccak256(node)) != storage_root)
+            .cloned()
+            .collect::<Vec<_>>();
+        nodes.push(junk.clone());
+
+        let mut explorer = WitnessExplorer::new(&Witness::new(witness.root(), nodes));
+        let (missing, unused, _) = explorer.check();
+        assert_eq!(missing, vec![storage_root]);
+        assert_eq!(unused, vec![H256::from(keccak256(&junk))]);
+        assert!(explorer.execute("check").unwrap().contains("1 missing"));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CString},
+    ptr, slice,
+};
+
+use ethers_core::types::{Address, EIP1186ProofResponse, H256, U256};
+
+use crate::{Error, StateTrie, Witness};
+
+// return codes, the message of the last error on this thread is in pm_last_error.
+pub const PM_OK: c_int = 0;
+pub const PM_ERR_NULL: c_int = -1;
+pub const PM_ERR_TRIE: c_int = -2;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn result_code(result: Result<(), Error>) -> c_int {
+    match result {
+        Ok(()) => PM_OK,
+        Err(err) => {
+            set_last_error(format!("{:?}", err));
+            PM_ERR_TRIE
+        }
+    }
+}
+
+fn null_error() -> c_int {
+    set_last_error("null pointer".to_string());
+    PM_ERR_NULL
+}
+
+/// Message of the last error on this thread, or null. Valid until the next call that fails.
+#[no_mangle]
+pub extern "C" fn pm_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match last.borrow().as_ref() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Creates a trie from a 32 byte state root. Free it with pm_state_trie_free.
+///
+/// # Safety
+/// `root` has to point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pm_state_trie_new(root: *const u8) -> *mut StateTrie {
+    if root.is_null() {
+        null_error();
+        return ptr::null_mut();
+    }
+    let root = H256::from_slice(slice::from_raw_parts(root, 32));
+    Box::into_raw(Box::new(StateTrie::from_root(root)))
+}
+
+/// Creates a trie from an encoded witness, null on error.
+///
+/// # Safety
+/// `witness` has to point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pm_state_trie_from_witness(
+    witness: *const u8,
+    len: usize,
+) -> *mut StateTrie {
+    if witness.is_null() {
+        null_error();
+        return ptr::null_mut();
+    }
+    let raw = slice::from_raw_parts(witness, len).to_vec();
+    match Witness::decode(raw.into()).and_then(|witness| StateTrie::from_witness(&witness)) {
+        Ok(state_trie) => Box::into_raw(Box::new(state_trie)),
+        Err(err) => {
+            set_last_error(format!("{:?}", err));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `trie` has to come from this library and not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn pm_state_trie_free(trie: *mut StateTrie) {
+    if !trie.is_null() {
+        drop(Box::from_raw(trie));
+    }
+}
+
+/// Loads an eth_getProof result, given as `len` bytes of json.
+///
+/// # Safety
+/// `trie` has to be valid, `proof` has to point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pm_state_trie_load_proof(
+    trie: *mut StateTrie,
+    proof: *const u8,
+    len: usize,
+) -> c_int {
+    if trie.is_null() || proof.is_null() {
+        return null_error();
+    }
+    let proof = slice::from_raw_parts(proof, len);
+    result_code(
+        serde_json::from_slice::<EIP1186ProofResponse>(proof)
+            .map_err(Error::from)
+            .and_then(|proof| (*trie).load_proof(proof)),
+    )
+}
+
+/// Sets the balance of an account, `balance` is a 32 byte big endian number.
+///
+/// # Safety
+/// `trie` has to be valid, `address` has to point to 20 and `balance` to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pm_state_trie_set_balance(
+    trie: *mut StateTrie,
+    address: *const u8,
+    balance: *const u8,
+) -> c_int {
+    if trie.is_null() || address.is_null() || balance.is_null() {
+        return null_error();
+    }
+    let address = Address::from_slice(slice::from_raw_parts(address, 20));
+    let balance = U256::from_big_endian(slice::from_raw_parts(balance, 32));
+    result_code((*trie).account_trie.set_balance(address, balance))
+}
+
+/// Writes the 32 byte state root to `out`.
+///
+/// # Safety
+/// `trie` has to be valid, `out` has to point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pm_state_trie_root(trie: *const StateTrie, out: *mut u8) -> c_int {
+    if trie.is_null() || out.is_null() {
+        return null_error();
+    }
+    match (*trie).root() {
+        Some(root) => {
+            ptr::copy_nonoverlapping(root.as_ptr(), out, 32);
+            PM_OK
+        }
+        None => result_code(Err(Error::Usage("root not set"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CStr, ptr};
+
+    use super::{
+        pm_last_error, pm_state_trie_free, pm_state_trie_from_witness, pm_state_trie_load_proof,
+        pm_state_trie_new, pm_state_trie_root, pm_state_trie_set_balance, PM_ERR_NULL, PM_ERR_TRIE,
+        PM_OK,
+    };
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_ffi_state_trie() {
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        full.account_trie
+            .set_balance(address, U256::from(100))
+            .unwrap();
+        let proof = serde_json::to_vec(&full.get_proof(address, vec![]).unwrap()).unwrap();
+        let root = full.root().unwrap();
+
+        unsafe {
+            let trie = pm_state_trie_new(root.as_ptr());
+            assert_eq!(
+                pm_state_trie_load_proof(trie, proof.as_ptr(), proof.len()),
+                PM_OK
+            );
+
+            let mut balance = [0u8; 32];
+            U256::from(5).to_big_endian(&mut balance);
+            assert_eq!(
+                pm_state_trie_set_balance(trie, address.as_ptr(), balance.as_ptr()),
+                PM_OK
+            );
+            full.account_trie
+                .set_balance(address, U256::from(5))
+                .unwrap();
+
+            let mut out = [0u8; 32];
+            assert_eq!(pm_state_trie_root(trie, out.as_mut_ptr()), PM_OK);
+            assert_eq!(H256::from(out), full.root().unwrap());
+
+            let witness = (*trie).witness().unwrap().encode();
+            let copy = pm_state_trie_from_witness(witness.as_ptr(), witness.len());
+            assert!(!copy.is_null());
+            assert_eq!((*copy).root(), full.root());
+
+            pm_state_trie_free(copy);
+            pm_state_trie_free(trie);
+        }
+    }
+
+    #[test]
+    pub fn test_ffi_errors() {
+        unsafe {
+            assert!(pm_state_trie_new(ptr::null()).is_null());
+            assert_eq!(
+                CStr::from_ptr(pm_last_error()).to_str().unwrap(),
+                "null pointer"
+            );
+
+            // balance of an account which wasn't loaded.
+            let trie = pm_state_trie_new(H256::repeat_byte(1).as_ptr());
+            let balance = [0u8; 32];
+            assert_eq!(
+                pm_state_trie_set_balance(trie, Address::zero().as_ptr(), balance.as_ptr()),
+                PM_ERR_TRIE
+            );
+            assert!(CStr::from_ptr(pm_last_error())
+                .to_str()
+                .unwrap()
+                .contains("not present"));
+            assert_eq!(pm_state_trie_load_proof(trie, ptr::null(), 0), PM_ERR_NULL);
+            pm_state_trie_free(trie);
+        }
+    }
+}
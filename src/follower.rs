@@ -0,0 +1,242 @@
+use ethers_core::types::{Block, Transaction, H256};
+
+use crate::{BlockReport, Error, StateTrie, Witness};
+
+// keeps a partial state in sync with a chain, block by block, without a full node: every
+// block is run on the trie and the root rolls forward when the block checks out. the keys
+// each block needs come in as witnesses for the state before it, only what the trie can't
+// read yet has to be in them.
+#[derive(Clone, Debug)]
+pub struct BlockFollower {
+    state_trie: StateTrie,
+    // the block the state is after.
+    number: u64,
+    hash: H256,
+}
+
+impl BlockFollower {
+    // the state after block `number`. its hash goes into the block hashes of the trie if
+    // they don't have it yet, BLOCKHASH in the next block asks for it.
+    pub fn new(mut state_trie: StateTrie, number: u64, hash: H256) -> Result<Self, Error> {
+        if state_trie.block_hashes().get(number).is_none() {
+            let mut block_hashes = state_trie.block_hashes().clone();
+            block_hashes.push(number, hash)?;
+            state_trie.set_block_hashes(block_hashes);
+        }
+        Ok(BlockFollower {
+            state_trie,
+            number,
+            hash,
+        })
+    }
+
+    pub fn state_trie(&self) -> &StateTrie {
+        &self.state_trie
+    }
+
+    pub fn into_state_trie(self) -> StateTrie {
+        self.state_trie
+    }
+
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+
+    pub fn block_hash(&self) -> H256 {
+        self.hash
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.state_trie.root()
+    }
+
+    // nodes and code for the next block, at the current root.
+    pub fn accept_witness(&mut self, witness: &Witness) -> Result<(), Error> {
+        self.state_trie.load_witness(witness)
+    }
+
+    // runs the next block. the state only moves on when it is valid, otherwise it stays
+    // where it was and the report says why. the block is run on a copy of the trie for
+    // that, so a bad block costs a clone.
+    pub fn apply(&mut self, block: &Block<Transaction>) -> Result<BlockReport, Error> {
+        if block.number.map(|number| number.as_u64()) != Some(self.number + 1) {
+            return Err(Error::Usage("block is not the next one"));
+        }
+        if block.parent_hash != self.hash {
+            return Err(Error::Usage("block is not on top of the state"));
+        }
+        let hash = block
+            .hash
+            .ok_or(Error::InternalError("block has no hash"))?;
+
+        let mut next = self.state_trie.clone();
+        let report = next.apply_block(block)?;
+        if report.is_valid() {
+            let mut block_hashes = next.block_hashes().clone();
+            block_hashes.push(self.number + 1, hash)?;
+            next.set_block_hashes(block_hashes);
+            self.state_trie = next;
+            self.number += 1;
+            self.hash = hash;
+        }
+        Ok(report)
+    }
+
+    pub fn apply_with_witness(
+        &mut self,
+        witness: &Witness,
+        block: &Block<Transaction>,
+    ) -> Result<BlockReport, Error> {
+        self.accept_witness(witness)?;
+        self.apply(block)
+    }
+
+    // fetches the next block with its full transactions, the proofs for the keys it touches
+    // that the trie doesn't have (see StateTrie::from_block_trace) and applies it. None while
+    // the block isn't there yet.
+    #[cfg(feature = "provider")]
+    pub async fn follow_next<M: ethers_providers::Middleware>(
+        &mut self,
+        provider: &M,
+    ) -> Result<Option<BlockReport>, Error> {
+        let block = provider
+            .get_block_with_txs(self.number + 1)
+            .await
+            .map_err(crate::provider::provider_error)?;
+        let Some(block) = block else {
+            return Ok(None);
+        };
+        let accounts = crate::provider::block_accounts(provider, &block).await?;
+        self.state_trie
+            .fetch_missing(provider, accounts, self.hash)
+            .await?;
+        self.apply(&block).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockFollower;
+    use crate::StateTrie;
+    use ethers_core::types::{Address, Block, Transaction, H256, U256, U64};
+
+    fn transfer(from: Address, to: Address, nonce: u64) -> Transaction {
+        Transaction {
+            from,
+            to: Some(to),
+            value: U256::from(1000),
+            nonce: U256::from(nonce),
+            gas: U256::from(21_000),
+            gas_price: Some(U256::zero()),
+            transaction_type: Some(U64::zero()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn test_block_follower() {
+        let sender = Address::from_low_u64_be(0x1000);
+        let receivers: Vec<Address> = (1..=3)
+            .map(|i| Address::from_low_u64_be(0x2000 + i))
+            .collect();
+        let mut full = StateTrie::new();
+        full.account_trie
+            .set_balance(sender, U256::exp10(18))
+            .unwrap();
+        for (i, receiver) in receivers.iter().enumerate() {
+            full.account_trie
+                .set_balance(*receiver, U256::from(i + 1))
+                .unwrap();
+        }
+
+        // the follower starts with the sender and the first receiver and gets the rest from
+        // witnesses built off the full state.
+        let mut start = StateTrie::from_root(full.root().unwrap());
+        for address in [sender, receivers[0]] {
+            start
+                .load_proof(full.get_proof(address, vec![]).unwrap())
+                .unwrap();
+        }
+        let mut follower = BlockFollower::new(start, 100, H256::repeat_byte(100)).unwrap();
+        assert_eq!(
+            follower.state_trie().block_hashes().get(100),
+            Some(H256::repeat_byte(100))
+        );
+
+        for (i, receiver) in receivers.iter().enumerate() {
+            let number = 101 + i as u64;
+            let mut block = Block::<Transaction> {
+                number: Some(U64::from(number)),
+                hash: Some(H256::repeat_byte(number as u8)),
+                parent_hash: follower.block_hash(),
+                gas_limit: U256::from(30_000_000),
+                base_fee_per_gas: Some(U256::zero()),
+                mix_hash: Some(H256::zero()),
+                excess_blob_gas: Some(U256::zero()),
+                transactions: vec![transfer(sender, *receiver, i as u64)],
+                ..Default::default()
+            };
+            // what the block should give, from the full state.
+            let expected = full.clone().apply_block(&block).unwrap();
+            block.state_root = expected.state_root;
+            block.receipts_root = expected.receipts_root;
+            block.gas_used = expected.gas_used;
+
+            let witness = {
+                let mut needed = StateTrie::from_root(full.root().unwrap());
+                needed
+                    .load_proof(full.get_proof(*receiver, vec![]).unwrap())
+                    .unwrap();
+                needed.witness().unwrap()
+            };
+            // a bad root leaves the state where it was.
+            let mut bad = block.clone();
+            bad.state_root = H256::zero();
+            let root = follower.root();
+            assert!(!follower
+                .apply_with_witness(&witness, &bad)
+                .unwrap()
+                .is_valid());
+            assert_eq!(follower.root(), root);
+            assert_eq!(follower.number(), number - 1);
+
+            assert!(follower.apply(&block).unwrap().is_valid());
+            full.apply_block(&block).unwrap();
+            assert_eq!(follower.root(), full.root());
+            assert_eq!(follower.number(), number);
+        }
+        assert_eq!(
+            follower
+                .state_trie()
+                .account_trie
+                .get(receivers[2])
+                .unwrap()
+                .balance,
+            U256::from(1003)
+        );
+        assert_eq!(
+            follower
+                .state_trie()
+                .block_hashes()
+                .blockhash(104, 102)
+                .unwrap(),
+            H256::repeat_byte(102)
+        );
+
+        // blocks have to come in order and on top of the state.
+        let skipped = Block::<Transaction> {
+            number: Some(U64::from(106)),
+            parent_hash: follower.block_hash(),
+            hash: Some(H256::zero()),
+            ..Default::default()
+        };
+        assert!(follower.apply(&skipped).is_err());
+        let fork = Block::<Transaction> {
+            number: Some(U64::from(104)),
+            parent_hash: H256::zero(),
+            hash: Some(H256::zero()),
+            ..Default::default()
+        };
+        assert!(follower.apply(&fork).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use arbitrary::Arbitrary;
+use ethers_core::types::{Bytes, U256};
+
+pub use crate::nodes::NodeData;
+use crate::{state_trie::StorageTrie, AccountData};
+
+// what the targets in fuzz/ run, kept in here so they can be tested and reused. they panic
+// when a property doesn't hold, like a fuzz target should.
+
+// decoding any bytes gives an error or a node, never a panic, and a decoded node encodes to
+// something which decodes to the same node.
+pub fn decode_node(data: &[u8]) {
+    if let Ok(node) = NodeData::<U256>::from_raw_rlp(Bytes::from(data.to_vec())) {
+        let encoded = node.to_raw_rlp().expect("encode a decoded node");
+        assert_eq!(NodeData::<U256>::from_raw_rlp(encoded).ok(), Some(node));
+    }
+    if let Ok(node) = NodeData::<AccountData>::from_raw_rlp(Bytes::from(data.to_vec())) {
+        let encoded = node.to_raw_rlp().expect("encode a decoded node");
+        assert_eq!(
+            NodeData::<AccountData>::from_raw_rlp(encoded).ok(),
+            Some(node)
+        );
+    }
+}
+
+// an encoded node decodes back to itself.
+pub fn roundtrip_node(node: &NodeData<U256>) {
+    let encoded = node.to_raw_rlp().expect("encode a node");
+    let decoded = NodeData::<U256>::from_raw_rlp(encoded).expect("decode an encoded node");
+    // zero values aren't stored in the trie, they don't need to come back as such.
+    if !matches!(node, NodeData::Leaf { value, .. } if value.is_zero()) {
+        assert_eq!(&decoded, node);
+    }
+}
+
+// a proof for `key` in a storage trie with `entries`, broken by `flips` (node, byte, xor)
+// and cut to `keep` nodes. loading it may fail, but if it loads the value has to be right.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct ProofInput {
+    pub entries: Vec<(u8, u64)>,
+    pub key: u8,
+    pub flips: Vec<(u8, u16, u8)>,
+    pub keep: Option<u8>,
+    // raw nodes appended to the proof.
+    pub extra: Vec<Vec<u8>>,
+}
+
+pub fn load_proof(input: &ProofInput) {
+    let entries = input
+        .entries
+        .iter()
+        .map(|(key, value)| (U256::from(*key), U256::from(*value)))
+        .collect::<BTreeMap<_, _>>();
+    let trie = StorageTrie::from_entries(entries.clone()).expect("build the trie");
+    let key = U256::from(input.key);
+    let value = entries.get(&key).copied().unwrap_or_default();
+
+    let mut proof = trie.get_proof(key).expect("proof of a full trie");
+    for (node, byte, xor) in input.flips.iter() {
+        if proof.is_empty() {
+            break;
+        }
+        let node = *node as usize % proof.len();
+        let mut raw = proof[node].to_vec();
+        if raw.is_empty() {
+            continue;
+        }
+        let byte = *byte as usize % raw.len();
+        raw[byte] ^= xor;
+        proof[node] = raw.into();
+    }
+    if let Some(keep) = input.keep {
+        proof.truncate(keep as usize);
+    }
+    proof.extend(input.extra.iter().map(|raw| Bytes::from(raw.to_owned())));
+
+    let mut partial = StorageTrie::from_root(trie.root().unwrap());
+    if partial.load_proof(key, value, proof).is_ok() {
+        assert_eq!(partial.get(key).expect("get a proven key"), value);
+    }
+}
+
+#[derive(Arbitrary, Clone, Debug)]
+pub enum TrieOp {
+    // small keys, so the same ones come up again.
+    Set { key: u8, value: u64 },
+    Remove { key: u8 },
+    Get { key: u8 },
+}
+
+// runs the ops on a storage trie and a map side by side, the trie has to agree with the map
+// after every op and have the same root as a trie built from the map at the end.
+pub fn run_ops(ops: &[TrieOp]) {
+    let mut trie = StorageTrie::empty();
+    let mut model = BTreeMap::new();
+    for op in ops {
+        match op {
+            TrieOp::Set { key, value } => {
+                trie.set(U256::from(*key), U256::from(*value))
+                    .expect("set on a full trie");
+                if *value == 0 {
+                    model.remove(key);
+                } else {
+                    model.insert(*key, *value);
+                }
+            }
+            TrieOp::Remove { key } => {
+                trie.remove(U256::from(*key))
+                    .expect("remove on a full trie");
+                model.remove(key);
+            }
+            TrieOp::Get { key } => {
+                assert_eq!(
+                    trie.get(U256::from(*key)).expect("get on a full trie"),
+                    U256::from(model.get(key).copied().unwrap_or_default())
+                );
+            }
+        }
+    }
+    let rebuilt = StorageTrie::from_entries(
+        model
+            .iter()
+            .map(|(key, value)| (U256::from(*key), U256::from(*value))),
+    )
+    .expect("build from the model");
+    assert_eq!(trie.root(), rebuilt.root());
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+    use ethers_core::{types::U256, utils::keccak256};
+
+    use super::{decode_node, load_proof, roundtrip_node, run_ops, NodeData, ProofInput, TrieOp};
+
+    // the targets on pseudo random inputs, a quick run of what the fuzzer does.
+    #[test]
+    pub fn test_fuzz_targets() {
+        for seed in 0..200u64 {
+            let data = (0..64u64)
+                .flat_map(|i| keccak256((seed * 64 + i).to_be_bytes()))
+                .collect::<Vec<u8>>();
+            decode_node(&data[..(seed as usize % 100)]);
+
+            let mut u = Unstructured::new(&data);
+            if let Ok(node) = NodeData::<U256>::arbitrary(&mut u) {
+                roundtrip_node(&node);
+                decode_node(&node.to_raw_rlp().unwrap());
+            }
+            let mut u = Unstructured::new(&data);
+            if let Ok(input) = ProofInput::arbitrary(&mut u) {
+                load_proof(&input);
+            }
+            let mut u = Unstructured::new(&data);
+            if let Ok(ops) = Vec::<TrieOp>::arbitrary(&mut u) {
+                run_ops(&ops);
+            }
+        }
+    }
+}
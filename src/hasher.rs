@@ -0,0 +1,131 @@
+use std::fmt::Debug;
+
+use ethers_core::{types::H256, utils::keccak256};
+
+use crate::{
+    instrument::{self, Operation},
+    stats,
+};
+
+// hash of rlp(""), which is the root of a trie without any entries.
+const KECCAK_EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+// hash function used to reference trie nodes, keccak for ethereum tries.
+pub trait TrieHasher: Clone + Debug + Default + PartialEq {
+    fn hash(data: &[u8]) -> H256;
+
+    fn empty_root() -> H256 {
+        Self::hash(&[0x80])
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeccakHasher;
+
+impl TrieHasher for KeccakHasher {
+    fn hash(data: &[u8]) -> H256 {
+        let _span = instrument::span(Operation::Keccak);
+        stats::hashed();
+        H256::from(keccak256(data))
+    }
+
+    fn empty_root() -> H256 {
+        KECCAK_EMPTY_ROOT
+    }
+}
+
+#[cfg(feature = "blake3")]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Blake3Hasher;
+
+#[cfg(feature = "blake3")]
+impl TrieHasher for Blake3Hasher {
+    fn hash(data: &[u8]) -> H256 {
+        H256::from(<[u8; 32]>::from(blake3::hash(data)))
+    }
+}
+
+// poseidon over bn254 (circom parameters) for tries that are verified inside circuits.
+#[cfg(feature = "poseidon")]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PoseidonHasher;
+
+#[cfg(feature = "poseidon")]
+impl PoseidonHasher {
+    // bytes per field element, 31 bytes always fit below the bn254 modulus.
+    const CHUNK_SIZE: usize = 31;
+    // inputs absorbed per permutation, one more is taken by the running digest.
+    const CHUNKS_PER_ROUND: usize = 11;
+}
+
+#[cfg(feature = "poseidon")]
+impl TrieHasher for PoseidonHasher {
+    fn hash(data: &[u8]) -> H256 {
+        use ark_bn254::Fr;
+        use light_poseidon::{Poseidon, PoseidonBytesHasher};
+
+        // the running digest starts as the input length, so inputs which only differ
+        // by trailing zero bytes do not collide.
+        let mut digest = [0u8; 32];
+        digest[24..].copy_from_slice(&(data.len() as u64).to_be_bytes());
+
+        let chunks = data.chunks(Self::CHUNK_SIZE).collect::<Vec<_>>();
+        let mut rounds = chunks.chunks(Self::CHUNKS_PER_ROUND).peekable();
+        if rounds.peek().is_none() {
+            // empty input still goes through one permutation.
+            let mut poseidon = Poseidon::<Fr>::new_circom(1).unwrap();
+            digest = poseidon.hash_bytes_be(&[&digest]).unwrap();
+        }
+        for round in rounds {
+            let mut inputs: Vec<&[u8]> = vec![&digest];
+            inputs.extend(round.iter());
+            let mut poseidon = Poseidon::<Fr>::new_circom(inputs.len()).unwrap();
+            digest = poseidon.hash_bytes_be(&inputs).unwrap();
+        }
+        H256::from(digest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeccakHasher, TrieHasher};
+    use ethers_core::utils::hex;
+
+    #[test]
+    pub fn test_keccak_empty_root() {
+        assert_eq!(
+            hex::encode(KeccakHasher::empty_root()),
+            "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+        );
+        assert_eq!(KeccakHasher::empty_root(), KeccakHasher::hash(&[0x80]));
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    pub fn test_blake3_hash() {
+        use super::Blake3Hasher;
+        assert_eq!(
+            hex::encode(Blake3Hasher::hash(b"")),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+
+    #[cfg(feature = "poseidon")]
+    #[test]
+    pub fn test_poseidon_hash() {
+        use super::PoseidonHasher;
+        let long_input = vec![0xab; 1000];
+        assert_eq!(
+            PoseidonHasher::hash(&long_input),
+            PoseidonHasher::hash(&long_input)
+        );
+        assert_ne!(PoseidonHasher::hash(&[]), PoseidonHasher::hash(&[0]));
+        assert_ne!(
+            PoseidonHasher::hash(&long_input),
+            PoseidonHasher::hash(&long_input[..999])
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+use crate::{
+    hasher::TrieHasher,
+    nodes::LeafValue,
+    trie::{MptKey, Trie},
+};
+
+// how often each node and key was walked through since start_heatmap. nodes are by their
+// nibble path rather than their hash, the hash of a node changes with every write below it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeHeatmap {
+    // gets and sets, each walks from the root down to its key.
+    pub walks: u64,
+    pub nodes: BTreeMap<Vec<u8>, u64>,
+    // full nibble paths of the keys which were found, i.e. the hashed key.
+    pub keys: BTreeMap<Vec<u8>, u64>,
+}
+
+fn hottest(counts: &BTreeMap<Vec<u8>, u64>, n: usize) -> Vec<(Vec<u8>, u64)> {
+    let mut hottest = counts
+        .iter()
+        .map(|(path, count)| (path.to_owned(), *count))
+        .collect::<Vec<_>>();
+    // most accessed first, ties by path so the order is stable.
+    hottest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    hottest.truncate(n);
+    hottest
+}
+
+impl NodeHeatmap {
+    pub fn hottest_nodes(&self, n: usize) -> Vec<(Vec<u8>, u64)> {
+        hottest(&self.nodes, n)
+    }
+
+    pub fn hottest_keys(&self, n: usize) -> Vec<(Vec<u8>, u64)> {
+        hottest(&self.keys, n)
+    }
+
+    // accesses of the nodes at `prefix` and below, e.g. to see which subtrees are worth
+    // keeping in a cache.
+    pub fn subtree_accesses(&self, prefix: &[u8]) -> u64 {
+        self.nodes
+            .iter()
+            .filter(|(path, _)| path.starts_with(prefix))
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    pub fn merge(&mut self, other: NodeHeatmap) {
+        self.walks += other.walks;
+        for (path, count) in other.nodes {
+            *self.nodes.entry(path).or_default() += count;
+        }
+        for (path, count) in other.keys {
+            *self.keys.entry(path).or_default() += count;
+        }
+    }
+}
+
+// gets only borrow the trie, so the counts sit behind a mutex. None unless recording, then
+// a get costs nothing extra. a clone of the trie gets a copy of the counts so far.
+#[derive(Default)]
+pub(crate) struct HeatmapRecorder(Option<Mutex<NodeHeatmap>>);
+
+impl HeatmapRecorder {
+    pub(crate) fn is_recording(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub(crate) fn record(&self, f: impl FnOnce(&mut NodeHeatmap)) {
+        if let Some(heatmap) = &self.0 {
+            f(&mut heatmap.lock().unwrap_or_else(|err| err.into_inner()));
+        }
+    }
+}
+
+impl Clone for HeatmapRecorder {
+    fn clone(&self) -> Self {
+        let mut copy = HeatmapRecorder::default();
+        self.record(|heatmap| copy = HeatmapRecorder(Some(Mutex::new(heatmap.clone()))));
+        copy
+    }
+}
+
+impl fmt::Debug for HeatmapRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeatmapRecorder")
+            .field("recording", &self.is_recording())
+            .finish()
+    }
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    // counts the nodes that gets and sets walk through from here on, until take_heatmap.
+    pub fn start_heatmap(&mut self) {
+        if !self.heatmap.is_recording() {
+            self.heatmap = HeatmapRecorder(Some(Mutex::new(NodeHeatmap::default())));
+        }
+    }
+
+    // the counts since start_heatmap, and stops counting. empty if it wasn't started.
+    pub fn take_heatmap(&mut self) -> NodeHeatmap {
+        match std::mem::take(&mut self.heatmap).0 {
+            Some(heatmap) => heatmap.into_inner().unwrap_or_else(|err| err.into_inner()),
+            None => NodeHeatmap::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state_trie::StorageTrie;
+    use ethers_core::types::U256;
+
+    #[test]
+    pub fn test_trie_heatmap() {
+        let mut trie =
+            StorageTrie::from_entries((1..100u64).map(|i| (U256::from(i), U256::from(i)))).unwrap();
+        trie.get(U256::one()).unwrap();
+        assert_eq!(trie.take_heatmap().walks, 0);
+
+        trie.start_heatmap();
+        for _ in 0..5 {
+            trie.get(U256::from(3)).unwrap();
+        }
+        trie.get(U256::from(4)).unwrap();
+        trie.set(U256::from(4), U256::from(40)).unwrap();
+        let heatmap = trie.clone().take_heatmap();
+        assert_eq!(heatmap, trie.take_heatmap());
+        assert!(trie.take_heatmap().nodes.is_empty());
+
+        // the set reads the key first.
+        assert_eq!(heatmap.walks, 7);
+        assert_eq!(heatmap.nodes[&vec![]], 7);
+        assert_eq!(
+            heatmap.subtree_accesses(&[]),
+            heatmap.nodes.values().sum::<u64>()
+        );
+        let keys = heatmap.hottest_keys(10);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].1, 5);
+        assert_eq!(keys[1].1, 2);
+        assert_eq!(heatmap.hottest_nodes(1), vec![(vec![], 7)]);
+    }
+}
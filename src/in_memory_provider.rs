@@ -0,0 +1,120 @@
+use ethers_core::types::{Address, BlockId, Bytes, EIP1186ProofResponse, H256};
+
+use crate::{Error, ProofProvider, StateTrie};
+
+// answers proof queries from a state trie held in memory, e.g. one built from scratch with
+// the set_* methods, so code consuming proofs can be tested without an rpc. there is only
+// one state, the block argument is ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InMemoryProvider {
+    state_trie: StateTrie,
+}
+
+impl InMemoryProvider {
+    pub fn new(state_trie: StateTrie) -> Self {
+        InMemoryProvider { state_trie }
+    }
+
+    pub fn state_trie(&self) -> &StateTrie {
+        &self.state_trie
+    }
+
+    pub fn state_trie_mut(&mut self) -> &mut StateTrie {
+        &mut self.state_trie
+    }
+}
+
+impl ProofProvider for InMemoryProvider {
+    async fn fetch_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        _block: Option<BlockId>,
+    ) -> Result<EIP1186ProofResponse, Error> {
+        self.state_trie.get_proof(address, slots)
+    }
+
+    async fn fetch_node(&self, hash: H256) -> Result<Bytes, Error> {
+        self.state_trie
+            .get_raw_node(hash)?
+            .ok_or(Error::ProviderRejected("node not found".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemoryProvider;
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    fn full_state() -> StateTrie {
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        for i in 1..10u64 {
+            let address = Address::from_low_u64_be(i);
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(i * 100))
+                .unwrap();
+            for slot in 0..i {
+                state_trie
+                    .set_storage_value(address, U256::from(slot), U256::from(slot + 1))
+                    .unwrap();
+            }
+        }
+        state_trie
+    }
+
+    #[tokio::test]
+    pub async fn test_in_memory_provider_roundtrip() {
+        let mut provider = InMemoryProvider::new(full_state());
+        let address = Address::from_low_u64_be(5);
+
+        let mut partial = StateTrie::from_root(provider.state_trie().root().unwrap());
+        partial
+            .fetch_proofs(
+                &provider,
+                vec![(
+                    address,
+                    vec![H256::from_low_u64_be(2), H256::from_low_u64_be(7)],
+                )],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            partial.get_storage_at(address, U256::from(2)).unwrap(),
+            U256::from(3)
+        );
+        assert_eq!(
+            partial.get_storage_at(address, U256::from(7)).unwrap(),
+            U256::zero()
+        );
+
+        // same change on both sides gives the same root.
+        partial
+            .set_storage_value(address, U256::from(2), U256::from(42))
+            .unwrap();
+        provider
+            .state_trie_mut()
+            .set_storage_value(address, U256::from(2), U256::from(42))
+            .unwrap();
+        assert_eq!(partial.root(), provider.state_trie().root());
+    }
+
+    #[tokio::test]
+    pub async fn test_in_memory_provider_fetch_path() {
+        let provider = InMemoryProvider::new(full_state());
+        let address = Address::from_low_u64_be(8);
+
+        let mut partial = StateTrie::from_root(provider.state_trie().root().unwrap());
+        partial
+            .account_trie
+            .fetch_path(&provider, address)
+            .await
+            .unwrap();
+        assert_eq!(
+            partial.account_trie.get(address).unwrap().balance,
+            U256::from(800)
+        );
+    }
+}
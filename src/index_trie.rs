@@ -0,0 +1,84 @@
+use ethers_core::{
+    types::{Bytes, H256},
+    utils::rlp,
+};
+
+use crate::{
+    nibbles::Nibbles,
+    trie::{MptKey, Trie},
+    Error,
+};
+
+// position in a block, the key of the transactions and receipts tries is rlp(index).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IndexKey(pub u64);
+
+impl MptKey for IndexKey {
+    fn to_nibbles(&self) -> Result<Nibbles, Error> {
+        Ok(Nibbles::from_raw_path(Bytes::from(
+            rlp::encode(&self.0).to_vec(),
+        )))
+    }
+}
+
+// trie of encoded items by their index, like the transactions and receipts of a block.
+pub type IndexTrie = Trie<IndexKey, Bytes>;
+
+// root of the trie with the items at 0, 1, 2... the encoded items are all longer than 32
+// bytes in practice (a receipt has its 256 byte bloom, a transaction its signature), so no
+// node ends up inlined in its parent, which this trie doesn't do.
+pub fn ordered_trie_root(items: impl IntoIterator<Item = Bytes>) -> Result<H256, Error> {
+    let trie = IndexTrie::from_entries(
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| (IndexKey(index as u64), item)),
+    )?;
+    trie.root().ok_or(Error::Usage("root not set"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ordered_trie_root, IndexKey};
+    use crate::{hasher::KeccakHasher, trie::MptKey, TrieHasher};
+    use ethers_core::{
+        types::{Bytes, H256},
+        utils::{keccak256, rlp::RlpStream},
+    };
+
+    #[test]
+    pub fn test_index_keys() {
+        // 0 is the empty string, 1..=127 are single bytes, then length prefixed.
+        assert_eq!(IndexKey(0).to_nibbles().unwrap().to_u4_vec(), vec![8, 0]);
+        assert_eq!(IndexKey(1).to_nibbles().unwrap().to_u4_vec(), vec![0, 1]);
+        assert_eq!(
+            IndexKey(128).to_nibbles().unwrap().to_u4_vec(),
+            vec![8, 1, 8, 0]
+        );
+    }
+
+    #[test]
+    pub fn test_ordered_trie_root() {
+        assert_eq!(
+            ordered_trie_root(vec![]).unwrap(),
+            KeccakHasher::empty_root()
+        );
+
+        // a single item is a leaf at the root, keyed by rlp(0) = 0x80.
+        let item = Bytes::from(vec![0xab; 40]);
+        let mut leaf = RlpStream::new_list(2);
+        leaf.append(&vec![0x20u8, 0x80]);
+        leaf.append(&item.to_vec());
+        assert_eq!(
+            ordered_trie_root(vec![item.clone()]).unwrap(),
+            H256::from(keccak256(leaf.out()))
+        );
+
+        // order matters.
+        let other = Bytes::from(vec![0xcd; 40]);
+        assert_ne!(
+            ordered_trie_root(vec![item.clone(), other.clone()]).unwrap(),
+            ordered_trie_root(vec![other, item]).unwrap()
+        );
+    }
+}
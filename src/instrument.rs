@@ -0,0 +1,145 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+
+// what the trie is doing between enter and exit. verifying a proof decodes and hashes its
+// nodes, so those come nested inside a VerifyProof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    // Trie::load_proof, once per account or storage proof.
+    VerifyProof,
+    // one node decoded from rlp.
+    DecodeNode,
+    // one keccak of a node or key.
+    Keccak,
+}
+
+// called around the operations above, e.g. to read a cycle counter inside a zkvm guest or to
+// feed a profiler. there's no timing in here on purpose, Instant isn't available everywhere
+// the trie runs. both default to doing nothing.
+pub trait Instrumentation: Send + Sync {
+    fn enter(&self, _operation: Operation) {}
+
+    fn exit(&self, _operation: Operation) {}
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static INSTRUMENTATION: RwLock<Option<Arc<dyn Instrumentation>>> = RwLock::new(None);
+
+// process wide, replaces the previous one. operations which already entered exit on the
+// instrumentation they entered.
+pub fn set_instrumentation(instrumentation: impl Instrumentation + 'static) {
+    *INSTRUMENTATION
+        .write()
+        .unwrap_or_else(|err| err.into_inner()) = Some(Arc::new(instrumentation));
+    INSTALLED.store(true, Ordering::Release);
+}
+
+pub fn clear_instrumentation() {
+    INSTALLED.store(false, Ordering::Release);
+    *INSTRUMENTATION
+        .write()
+        .unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+// calls exit when dropped.
+pub(crate) struct Span(Option<(Arc<dyn Instrumentation>, Operation)>);
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some((instrumentation, operation)) = self.0.take() {
+            instrumentation.exit(operation);
+        }
+    }
+}
+
+// a single atomic load when nothing is installed.
+pub(crate) fn span(operation: Operation) -> Span {
+    if !INSTALLED.load(Ordering::Acquire) {
+        return Span(None);
+    }
+    let instrumentation = INSTRUMENTATION
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    Span(instrumentation.map(|instrumentation| {
+        instrumentation.enter(operation);
+        (instrumentation, operation)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Mutex,
+        thread::{self, ThreadId},
+    };
+
+    use super::{clear_instrumentation, set_instrumentation, Instrumentation, Operation};
+    use crate::StateTrie;
+    use ethers_core::types::{Address, U256};
+
+    // other tests run on other threads at the same time, only this one's events are kept.
+    struct Recorder {
+        thread: ThreadId,
+        events: &'static Mutex<Vec<(bool, Operation)>>,
+    }
+
+    impl Instrumentation for Recorder {
+        fn enter(&self, operation: Operation) {
+            if thread::current().id() == self.thread {
+                self.events.lock().unwrap().push((true, operation));
+            }
+        }
+
+        fn exit(&self, operation: Operation) {
+            if thread::current().id() == self.thread {
+                self.events.lock().unwrap().push((false, operation));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_instrumentation() {
+        let mut full = StateTrie::new();
+        for i in 1..20u64 {
+            full.account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        let proof = full.get_proof(Address::from_low_u64_be(1), vec![]).unwrap();
+        let nodes = proof.account_proof.len();
+
+        static EVENTS: Mutex<Vec<(bool, Operation)>> = Mutex::new(Vec::new());
+        set_instrumentation(Recorder {
+            thread: thread::current().id(),
+            events: &EVENTS,
+        });
+        StateTrie::from_root(full.root().unwrap())
+            .load_proof(proof)
+            .unwrap();
+        clear_instrumentation();
+
+        let events = EVENTS.lock().unwrap().clone();
+        // the whole verification, with the decodes and hashes inside it.
+        assert_eq!(events.first(), Some(&(true, Operation::VerifyProof)));
+        assert_eq!(events.last(), Some(&(false, Operation::VerifyProof)));
+        let count = |entered: bool, operation: Operation| {
+            events
+                .iter()
+                .filter(|event| **event == (entered, operation))
+                .count()
+        };
+        assert_eq!(count(true, Operation::VerifyProof), 1);
+        assert!(count(true, Operation::DecodeNode) >= nodes);
+        assert!(count(true, Operation::Keccak) >= nodes);
+        for operation in [
+            Operation::VerifyProof,
+            Operation::DecodeNode,
+            Operation::Keccak,
+        ] {
+            assert_eq!(count(true, operation), count(false, operation));
+        }
+    }
+}
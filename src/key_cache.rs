@@ -0,0 +1,137 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use ethers_core::{
+    types::{Address, BigEndianHash, H256, U256},
+    utils::keccak256,
+};
+
+// enough for the accounts and slots a block touches.
+pub const DEFAULT_KEY_CACHE_CAPACITY: usize = 4096;
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_KEY_CACHE_CAPACITY);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Preimage {
+    Address(Address),
+    Slot(U256),
+}
+
+// the hashed keys of the last addresses and slots, the oldest goes first when it's full.
+#[derive(Default)]
+struct KeyCache {
+    hashes: HashMap<Preimage, H256>,
+    order: VecDeque<Preimage>,
+}
+
+impl KeyCache {
+    fn get_or_hash(&mut self, preimage: Preimage, hash: impl FnOnce() -> H256) -> H256 {
+        if let Some(hash) = self.hashes.get(&preimage) {
+            return *hash;
+        }
+        let hash = hash();
+        let capacity = CAPACITY.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return hash;
+        }
+        while self.order.len() >= capacity {
+            let oldest = self.order.pop_front().unwrap();
+            self.hashes.remove(&oldest);
+        }
+        self.hashes.insert(preimage, hash);
+        self.order.push_back(preimage);
+        hash
+    }
+}
+
+// one per thread, so lookups don't wait on a lock.
+thread_local! {
+    static KEY_CACHE: RefCell<KeyCache> = RefCell::new(KeyCache::default());
+}
+
+fn cached(preimage: Preimage, hash: impl FnOnce() -> H256) -> H256 {
+    KEY_CACHE.with(|cache| cache.borrow_mut().get_or_hash(preimage, hash))
+}
+
+// keccak of the address, the path of the account in the account trie.
+pub fn hashed_address(address: Address) -> H256 {
+    cached(Preimage::Address(address), || {
+        H256::from(keccak256(address))
+    })
+}
+
+// keccak of the 32 byte slot, the path of the slot in a storage trie.
+pub fn hashed_slot(slot: U256) -> H256 {
+    cached(Preimage::Slot(slot), || {
+        H256::from(keccak256(H256::from_uint(&slot)))
+    })
+}
+
+// the number of hashed keys kept per thread, 0 turns the cache off. process wide, caches
+// over the new capacity shrink on their next miss.
+pub fn set_key_cache_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    if capacity == 0 {
+        clear_key_cache();
+    }
+}
+
+pub fn key_cache_capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+// empties the cache of this thread.
+pub fn clear_key_cache() {
+    KEY_CACHE.with(|cache| *cache.borrow_mut() = KeyCache::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hashed_address, hashed_slot, KeyCache, Preimage};
+    use ethers_core::{
+        types::{Address, BigEndianHash, H256, U256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_hashed_keys() {
+        let address = Address::from_low_u64_be(7);
+        for _ in 0..2 {
+            assert_eq!(hashed_address(address), H256::from(keccak256(address)));
+            assert_eq!(
+                hashed_slot(U256::from(7)),
+                H256::from(keccak256(H256::from_uint(&U256::from(7))))
+            );
+        }
+        // an address and a slot with the same bytes aren't mixed up.
+        assert_ne!(hashed_address(Address::zero()), hashed_slot(U256::zero()));
+    }
+
+    #[test]
+    pub fn test_key_cache_eviction() {
+        // the capacity is process wide, so this doesn't change it under the other tests.
+        let mut cache = KeyCache::default();
+        let mut hashed = 0;
+        for i in 0..super::DEFAULT_KEY_CACHE_CAPACITY + 10 {
+            cache.get_or_hash(Preimage::Slot(U256::from(i)), || {
+                hashed += 1;
+                H256::zero()
+            });
+        }
+        assert_eq!(cache.hashes.len(), super::DEFAULT_KEY_CACHE_CAPACITY);
+        assert_eq!(cache.order.len(), super::DEFAULT_KEY_CACHE_CAPACITY);
+        // the newest is still there, the oldest was evicted.
+        cache.get_or_hash(Preimage::Slot(U256::from(100)), || {
+            hashed += 1;
+            H256::zero()
+        });
+        cache.get_or_hash(Preimage::Slot(U256::zero()), || {
+            hashed += 1;
+            H256::zero()
+        });
+        assert_eq!(hashed, super::DEFAULT_KEY_CACHE_CAPACITY + 11);
+    }
+}
@@ -0,0 +1,283 @@
+use bytes::BytesMut;
+use ethers_core::{
+    types::{Address, Bytes, H256},
+    utils::{
+        keccak256,
+        rlp::{Rlp, RlpStream},
+    },
+};
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    key_cache::hashed_address,
+    nibbles::Nibbles,
+    Error, StateTrie, Witness,
+};
+
+// message codes of the les/2+ proof messages.
+pub const GET_PROOFS_V2_MSG: u8 = 0x0f;
+pub const PROOFS_V2_MSG: u8 = 0x10;
+
+// one item of GetProofsV2: [block hash, account key, key, from level]. account key is the
+// hashed address for storage proofs and empty for account proofs, key is the unhashed
+// address or slot. the first `from_level` nodes of the proof are left out, the client has
+// them already.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofRequest {
+    pub block_hash: H256,
+    pub account_key: Option<H256>,
+    pub key: Bytes,
+    pub from_level: u64,
+}
+
+impl ProofRequest {
+    pub fn account(block_hash: H256, address: Address) -> Self {
+        ProofRequest {
+            block_hash,
+            account_key: None,
+            key: Bytes::from(address.as_bytes().to_vec()),
+            from_level: 0,
+        }
+    }
+
+    pub fn storage(block_hash: H256, address: Address, slot: H256) -> Self {
+        ProofRequest {
+            block_hash,
+            account_key: Some(hashed_address(address)),
+            key: Bytes::from(slot.as_bytes().to_vec()),
+            from_level: 0,
+        }
+    }
+
+    fn path(&self) -> Nibbles {
+        Nibbles::from_raw_path(Bytes::from(keccak256(&self.key).to_vec()))
+    }
+}
+
+// GetProofsV2: [request id, [request, ...]].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetProofs {
+    pub request_id: u64,
+    pub requests: Vec<ProofRequest>,
+}
+
+impl GetProofs {
+    pub fn encode(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&self.request_id);
+        stream.begin_list(self.requests.len());
+        for request in self.requests.iter() {
+            stream.begin_list(4);
+            stream.append(&request.block_hash);
+            match request.account_key {
+                Some(account_key) => stream.append(&account_key),
+                None => stream.append_empty_data(),
+            };
+            stream.append(&request.key.as_ref());
+            stream.append(&request.from_level);
+        }
+        stream.out().freeze().into()
+    }
+
+    pub fn decode(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        if rlp.item_count()? != 2 {
+            return Err(Error::InternalError("GetProofsV2 should have two fields"));
+        }
+        let mut requests = Vec::new();
+        for item in rlp.at(1)?.iter() {
+            if item.item_count()? != 4 {
+                return Err(Error::InternalError(
+                    "proof request should have four fields",
+                ));
+            }
+            let account_key = item.at(1)?.data()?;
+            requests.push(ProofRequest {
+                block_hash: item.val_at(0)?,
+                account_key: match account_key.len() {
+                    0 => None,
+                    32 => Some(H256::from_slice(account_key)),
+                    _ => return Err(Error::InternalError("account key is not 32 bytes")),
+                },
+                key: Bytes::from(item.at(2)?.data()?.to_vec()),
+                from_level: item.val_at(3)?,
+            });
+        }
+        Ok(GetProofs {
+            request_id: rlp.val_at(0)?,
+            requests,
+        })
+    }
+}
+
+// ProofsV2: [request id, buffer value, [node, ...]], the nodes of all the requested proofs
+// in one deduplicated list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proofs {
+    pub request_id: u64,
+    pub buffer_value: u64,
+    pub nodes: Vec<Bytes>,
+}
+
+impl Proofs {
+    pub fn encode(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&self.request_id);
+        stream.append(&self.buffer_value);
+        stream.begin_list(self.nodes.len());
+        for node in self.nodes.iter() {
+            stream.append(&BytesMut::from(node.as_ref()));
+        }
+        stream.out().freeze().into()
+    }
+
+    pub fn decode(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        if rlp.item_count()? != 3 {
+            return Err(Error::InternalError("ProofsV2 should have three fields"));
+        }
+        let mut nodes = Vec::new();
+        for item in rlp.at(2)?.iter() {
+            nodes.push(Bytes::from(item.data()?.to_vec()));
+        }
+        Ok(Proofs {
+            request_id: rlp.val_at(0)?,
+            buffer_value: rlp.val_at(1)?,
+            nodes,
+        })
+    }
+
+    // the nodes under the state root of the requested block, e.g. for StateTrie::from_witness.
+    pub fn witness(&self, state_root: H256) -> Witness {
+        Witness::new(state_root, self.nodes.clone())
+    }
+}
+
+impl StateTrie {
+    // the nodes answering `requests`, for a server whose trie is the state of the requested
+    // block (requests for other blocks have to go to the trie of that block). nodes shared by
+    // several proofs are sent once, in the order they are first needed.
+    pub fn les_proofs(&self, requests: &[ProofRequest]) -> Result<Vec<Bytes>, Error> {
+        let mut nodes: Vec<Bytes> = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for request in requests {
+            let proof = match request.account_key {
+                None => self.account_trie.get_proof_path(request.path())?,
+                Some(account_key) => {
+                    let account =
+                        self.account_trie
+                            .get_path(Nibbles::from_raw_path(Bytes::from(
+                                account_key.as_bytes().to_vec(),
+                            )))?;
+                    if account.storage_root == KeccakHasher::empty_root() {
+                        vec![]
+                    } else {
+                        self.loaded_storage_trie(&account.storage_root)
+                            .ok_or(Error::MissingData(
+                                "storage trie not present, please add a proof",
+                            ))?
+                            .get_proof_path(request.path())?
+                    }
+                }
+            };
+            for node in proof.into_iter().skip(request.from_level as usize) {
+                if seen.insert(KeccakHasher::hash(&node)) {
+                    nodes.push(node);
+                }
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GetProofs, ProofRequest, Proofs};
+    use crate::StateTrie;
+    use ethers_core::types::{Address, BigEndianHash, H256, U256};
+
+    fn state() -> StateTrie {
+        let mut state_trie = StateTrie::new();
+        for i in 1..30u64 {
+            let address = Address::from_low_u64_be(i);
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+            state_trie
+                .set_storage_value(address, U256::from(i), U256::from(i * 2))
+                .unwrap();
+        }
+        state_trie
+    }
+
+    #[test]
+    pub fn test_les_codecs() {
+        let block = H256::repeat_byte(0xbb);
+        let address = Address::from_low_u64_be(3);
+        let request = GetProofs {
+            request_id: 42,
+            requests: vec![
+                ProofRequest::account(block, address),
+                ProofRequest {
+                    from_level: 2,
+                    ..ProofRequest::storage(block, address, H256::from_uint(&U256::from(3)))
+                },
+            ],
+        };
+        assert_eq!(GetProofs::decode(&request.encode()).unwrap(), request);
+
+        let response = Proofs {
+            request_id: 42,
+            buffer_value: 1_000_000,
+            nodes: vec![vec![0xc1, 0x80].into(), vec![0xab; 40].into()],
+        };
+        assert_eq!(Proofs::decode(&response.encode()).unwrap(), response);
+        assert!(Proofs::decode(&request.encode()).is_err());
+    }
+
+    #[test]
+    pub fn test_les_proofs() {
+        let server = state();
+        let root = server.root().unwrap();
+        let block = H256::repeat_byte(0xbb);
+        let address = Address::from_low_u64_be(7);
+        let requests = vec![
+            ProofRequest::account(block, address),
+            ProofRequest::storage(block, address, H256::from_uint(&U256::from(7))),
+            // an absent slot gives an exclusion proof.
+            ProofRequest::storage(block, address, H256::from_uint(&U256::from(1000))),
+        ];
+        let response = Proofs {
+            request_id: 1,
+            buffer_value: 0,
+            nodes: server.les_proofs(&requests).unwrap(),
+        };
+
+        // the client checks the nodes against the root it trusts and reads from them.
+        let received = Proofs::decode(&response.encode()).unwrap();
+        let mut client = StateTrie::from_witness(&received.witness(root)).unwrap();
+        assert_eq!(
+            client.account_trie.get(address).unwrap().balance,
+            U256::from(7)
+        );
+        assert_eq!(
+            client.get_storage_at(address, U256::from(7)).unwrap(),
+            U256::from(14)
+        );
+        assert_eq!(
+            client.get_storage_at(address, U256::from(1000)).unwrap(),
+            U256::zero()
+        );
+
+        // nodes the client has are left out.
+        let from_level = vec![ProofRequest {
+            from_level: 1,
+            ..ProofRequest::account(block, address)
+        }];
+        assert_eq!(
+            server.les_proofs(&from_level).unwrap().len(),
+            server.account_trie.get_proof(address).unwrap().len() - 1
+        );
+    }
+}
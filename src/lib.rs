@@ -1,9 +1,157 @@
+#[cfg(feature = "binary-trie")]
+mod binary_trie;
+mod block_cache;
+mod block_hashes;
+mod concurrent;
+mod debug_dump;
 mod error;
+#[cfg(feature = "eth-trie")]
+mod eth_trie_compat;
+mod expiry;
+mod explorer;
+#[cfg(feature = "cffi")]
+mod ffi;
+#[cfg(feature = "revm")]
+mod follower;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+mod hasher;
+mod heatmap;
+mod in_memory_provider;
+mod index_trie;
+mod instrument;
+mod key_cache;
+mod les;
+#[cfg(feature = "uniffi")]
+mod mobile;
 mod nibbles;
+#[cfg(feature = "mmap")]
+mod node_file;
 mod nodes;
+#[cfg(feature = "revm")]
+mod payload;
+mod pbss;
+#[cfg(feature = "revm")]
+mod pending;
+mod portal;
+mod proof_provider;
+#[cfg(feature = "provider")]
+mod provider;
+#[cfg(feature = "python")]
+mod python;
+mod range_proof;
+mod raw_trie;
+mod receipts;
+#[cfg(feature = "retry")]
+mod retry;
+#[cfg(feature = "revm")]
+mod revm_db;
+mod slots;
+mod smt;
+mod snap;
 mod state_trie;
+#[cfg(feature = "revm")]
+mod stateless;
+mod stats;
+mod stream_root;
+#[cfg(feature = "test_utils")]
+pub mod test_utils;
+mod transactions;
 mod trie;
+mod trie_builder;
+mod trie_diff;
+mod trie_store;
+mod trie_tests;
 mod utils;
+#[cfg(feature = "verkle")]
+pub mod verkle;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod witness;
 
-pub use error::Error;
-pub use state_trie::StateTrie;
+#[cfg(feature = "binary-trie")]
+pub use binary_trie::{BinaryNode, BinaryTrie};
+pub use block_cache::BlockCache;
+pub use block_hashes::{
+    beacon_root_slots, BlockHashes, BEACON_ROOTS_ADDRESS, BEACON_ROOTS_HISTORY, BLOCK_HASH_HISTORY,
+};
+pub use concurrent::{ConcurrentStateTrie, VersionedStateTrie};
+pub use error::{Error, ErrorContext, ErrorKind};
+#[cfg(feature = "eth-trie")]
+pub use eth_trie_compat::{proof_from_eth_trie, proof_to_eth_trie};
+pub use expiry::ExpiredSubtree;
+pub use explorer::WitnessExplorer;
+#[cfg(feature = "revm")]
+pub use follower::BlockFollower;
+#[cfg(feature = "blake3")]
+pub use hasher::Blake3Hasher;
+#[cfg(feature = "poseidon")]
+pub use hasher::PoseidonHasher;
+pub use hasher::{KeccakHasher, TrieHasher};
+pub use heatmap::NodeHeatmap;
+pub use in_memory_provider::InMemoryProvider;
+pub use index_trie::{ordered_trie_root, IndexKey, IndexTrie};
+pub use instrument::{clear_instrumentation, set_instrumentation, Instrumentation, Operation};
+pub use key_cache::{
+    clear_key_cache, hashed_address, hashed_slot, key_cache_capacity, set_key_cache_capacity,
+    DEFAULT_KEY_CACHE_CAPACITY,
+};
+pub use les::{GetProofs, ProofRequest, Proofs, GET_PROOFS_V2_MSG, PROOFS_V2_MSG};
+#[cfg(feature = "uniffi")]
+pub use mobile::{
+    verify_proof, AccountInfo, PartialStateTrie, StorageSlot, VerifiedProof, VerifyError,
+};
+pub use nibbles::Nibbles;
+#[cfg(feature = "mmap")]
+pub use node_file::{write_node_file, MmapNodeStore, NODE_FILE_MAGIC};
+pub use nodes::{LeafValue, NodeData, Nodes, ValueInterner};
+#[cfg(feature = "revm")]
+pub use payload::{
+    check_payload_root, check_payload_root_with_spec, DivergentAccount, ExecutionPayload,
+    PayloadCheck,
+};
+pub use pbss::{pbss_account_key, pbss_storage_key, PBSS_ACCOUNT_PREFIX, PBSS_STORAGE_PREFIX};
+#[cfg(feature = "revm")]
+pub use pending::{Candidate, PendingState};
+pub use portal::{PortalContent, PortalContentKey};
+pub use proof_provider::ProofProvider;
+pub use raw_trie::{verify_raw_proof, RawKey, RawTrie};
+pub use receipts::{receipts_root, ReceiptData, DEPOSIT_TX_TYPE};
+#[cfg(feature = "retry")]
+pub use retry::{RetryConfig, RetryProvider};
+#[cfg(feature = "revm")]
+pub use revm_db::{BundleSimulation, StateTrieDb, TransactionOutcome};
+pub use slots::{
+    array_data_slot, array_element_slot, mapping_slot, mapping_slot_bytes, nested_mapping_slot,
+    packed_array_element, read_packed, write_packed, MappingKey,
+};
+pub use smt::{SmtNode, SparseMerkleTree};
+pub use snap::{
+    AccountRange, GetAccountRange, GetStorageRanges, StorageRanges, ACCOUNT_RANGE_MSG,
+    GET_ACCOUNT_RANGE_MSG, GET_STORAGE_RANGES_MSG, STORAGE_RANGES_MSG,
+};
+pub use state_trie::{
+    decode_slim_account, encode_slim_account, AccountData, AccountDiff, AccountEntry,
+    AccountFrontier, Anchor, ChangelogEntry, CodeDb, DumpAccount, LazyAccount, Mutation,
+    PrunedNodes, RootChange, SlotResult, StateAccount, StateDiff, StateDump, StateFrontier,
+    StateHeatmap, StateTrie, StorageEntry, VerifiedProofs, SNAPSHOT_ACCOUNT_PREFIX,
+    SNAPSHOT_STORAGE_PREFIX,
+};
+#[cfg(feature = "revm")]
+pub use stateless::{validate_block_stateless, BlockMismatch, BlockReport};
+pub use stream_root::StreamingRoot;
+pub use transactions::{
+    consensus_transaction, encode_transaction, transactions_root, transactions_root_from_raw,
+    BLOB_TX_TYPE, SET_CODE_TX_TYPE,
+};
+pub use trie::{MptKey, NodeVerification, Trie};
+pub use trie_builder::TrieBuilder;
+pub use trie_diff::TrieChange;
+pub use trie_store::TrieStore;
+pub use trie_tests::{run_trie_tests, TrieTestOutcome, TrieTestResult};
+#[cfg(feature = "wasm")]
+pub use wasm::WasmStateTrie;
+pub use witness::{ProofSizeEstimate, Witness};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
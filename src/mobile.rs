@@ -0,0 +1,192 @@
+use std::{fmt, sync::Mutex};
+
+use ethers_core::types::{Address, Bytes, EIP1186ProofResponse, H256, U256};
+
+use crate::{Error, StateTrie, Witness};
+
+// errors cross the ffi boundary as their message.
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum VerifyError {
+    InvalidInput(String),
+    Trie(String),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::InvalidInput(message) => write!(f, "invalid input: {}", message),
+            VerifyError::Trie(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<Error> for VerifyError {
+    fn from(err: Error) -> Self {
+        VerifyError::Trie(format!("{:?}", err))
+    }
+}
+
+fn parse_h256(value: &str) -> Result<H256, VerifyError> {
+    value
+        .parse()
+        .map_err(|_| VerifyError::InvalidInput(format!("not a 32 byte hex: {}", value)))
+}
+
+fn parse_address(value: &str) -> Result<Address, VerifyError> {
+    value
+        .parse()
+        .map_err(|_| VerifyError::InvalidInput(format!("not an address: {}", value)))
+}
+
+fn parse_u256(value: &str) -> Result<U256, VerifyError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(value).ok(),
+    }
+    .ok_or_else(|| VerifyError::InvalidInput(format!("not a number: {}", value)))
+}
+
+// everything is a 0x hex string, numbers included, so kotlin and swift don't need a bigint.
+#[derive(Debug, PartialEq, uniffi::Record)]
+pub struct AccountInfo {
+    pub nonce: String,
+    pub balance: String,
+    pub storage_root: String,
+    pub code_hash: String,
+}
+
+#[derive(Debug, PartialEq, uniffi::Record)]
+pub struct StorageSlot {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, PartialEq, uniffi::Record)]
+pub struct VerifiedProof {
+    pub address: String,
+    pub account: AccountInfo,
+    pub storage: Vec<StorageSlot>,
+}
+
+fn account_info(trie: &StateTrie, address: Address) -> Result<AccountInfo, VerifyError> {
+    let account = trie.account_trie.get(address)?;
+    Ok(AccountInfo {
+        nonce: format!("{:#x}", account.nonce),
+        balance: format!("{:#x}", account.balance),
+        storage_root: format!("{:?}", account.storage_root),
+        code_hash: format!("{:?}", account.code_hash),
+    })
+}
+
+// checks an eth_getProof result (as json) against a state root, and returns the account and
+// slots it proves. fails if any of the account or storage proofs doesn't match.
+#[uniffi::export]
+pub fn verify_proof(state_root: String, proof: String) -> Result<VerifiedProof, VerifyError> {
+    let proof: EIP1186ProofResponse = serde_json::from_str(&proof)
+        .map_err(|err| VerifyError::InvalidInput(format!("proof json: {}", err)))?;
+    let address = proof.address;
+    let keys: Vec<U256> = proof.storage_proof.iter().map(|slot| slot.key).collect();
+
+    let mut trie = StateTrie::from_root(parse_h256(&state_root)?);
+    trie.load_proof(proof)?;
+
+    let account = account_info(&trie, address)?;
+    let mut storage = Vec::new();
+    for key in keys {
+        storage.push(StorageSlot {
+            key: format!("{:#x}", key),
+            value: format!("{:#x}", trie.get_storage_at(address, key)?),
+        });
+    }
+    Ok(VerifiedProof {
+        address: format!("{:?}", address),
+        account,
+        storage,
+    })
+}
+
+// a state trie built from a root and the raw rlp nodes a light client has collected.
+#[derive(uniffi::Object)]
+pub struct PartialStateTrie {
+    inner: Mutex<StateTrie>,
+}
+
+#[uniffi::export]
+impl PartialStateTrie {
+    #[uniffi::constructor]
+    pub fn from_root_and_nodes(root: String, nodes: Vec<Vec<u8>>) -> Result<Self, VerifyError> {
+        let witness = Witness::new(parse_h256(&root)?, nodes.into_iter().map(Bytes::from));
+        Ok(PartialStateTrie {
+            inner: Mutex::new(StateTrie::from_witness(&witness)?),
+        })
+    }
+
+    pub fn root(&self) -> Option<String> {
+        let trie = self.inner.lock().unwrap();
+        trie.root().map(|root| format!("{:?}", root))
+    }
+
+    pub fn get_account(&self, address: String) -> Result<AccountInfo, VerifyError> {
+        let trie = self.inner.lock().unwrap();
+        account_info(&trie, parse_address(&address)?)
+    }
+
+    pub fn get_storage(&self, address: String, slot: String) -> Result<String, VerifyError> {
+        let mut trie = self.inner.lock().unwrap();
+        let value = trie.get_storage_at(parse_address(&address)?, parse_u256(&slot)?)?;
+        Ok(format!("{:#x}", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_proof, PartialStateTrie};
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_mobile_verify_proof() {
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        for i in 1..10u64 {
+            full.account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i * 100))
+                .unwrap();
+        }
+        full.set_storage_value(address, U256::one(), U256::from(7))
+            .unwrap();
+        let proof = full
+            .get_proof(address, vec![H256::from_low_u64_be(1)])
+            .unwrap();
+        let root = format!("{:?}", full.root().unwrap());
+
+        let verified = verify_proof(root.clone(), serde_json::to_string(&proof).unwrap()).unwrap();
+        assert_eq!(verified.account.balance, "0x64");
+        assert_eq!(verified.storage.len(), 1);
+        assert_eq!(verified.storage[0].key, "0x1");
+        assert_eq!(verified.storage[0].value, "0x7");
+
+        // a proof for a different root doesn't verify.
+        let other = format!("{:?}", H256::repeat_byte(1));
+        assert!(verify_proof(other, serde_json::to_string(&proof).unwrap()).is_err());
+        assert!(verify_proof(root.clone(), "{}".to_string()).is_err());
+
+        let mut nodes: Vec<Vec<u8>> = proof.account_proof.iter().map(|n| n.to_vec()).collect();
+        nodes.extend(proof.storage_proof[0].proof.iter().map(|n| n.to_vec()));
+        let trie = PartialStateTrie::from_root_and_nodes(root.clone(), nodes).unwrap();
+        assert_eq!(trie.root(), Some(root));
+        assert_eq!(
+            trie.get_account(format!("{:?}", address)).unwrap(),
+            verified.account
+        );
+        assert_eq!(
+            trie.get_storage(format!("{:?}", address), "1".to_string())
+                .unwrap(),
+            "0x7"
+        );
+        assert!(trie
+            .get_account(format!("{:?}", Address::from_low_u64_be(2)))
+            .is_err());
+    }
+}
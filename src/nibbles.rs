@@ -1,12 +1,18 @@
 use crate::Error;
-use ethers::types::Bytes;
+use ethers_core::types::Bytes;
+use smallvec::SmallVec;
 use std::fmt;
 
-#[derive(Clone, Default, PartialEq)]
-pub struct Nibbles(Vec<u8>);
+// the nibbles of a 32 byte key, the longest path of the state tries.
+const INLINE_NIBBLES: usize = 64;
 
-fn u8_to_u4_vec(u8_vec: Vec<u8>) -> Vec<u8> {
-    let mut u4_vec = Vec::new();
+// kept inline up to a hashed key, so leaves and extensions don't need an allocation of their
+// own and their keys sit next to the rest of the node. longer paths spill to the heap.
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Nibbles(SmallVec<[u8; INLINE_NIBBLES]>);
+
+fn u8_to_u4_vec(u8_vec: &[u8]) -> SmallVec<[u8; INLINE_NIBBLES]> {
+    let mut u4_vec = SmallVec::with_capacity(u8_vec.len() * 2);
     for byte in u8_vec.iter() {
         u4_vec.push(*byte >> 4);
         u4_vec.push(*byte & 0xF);
@@ -30,16 +36,16 @@ impl Nibbles {
     }
 
     pub fn from_raw_path(bytes: Bytes) -> Self {
-        Self(u8_to_u4_vec(bytes.to_vec()))
+        Self(u8_to_u4_vec(&bytes))
     }
 
     pub fn from_u4_vec(nibbles: Vec<u8>) -> Result<Self, Error> {
         for nibble in nibbles.iter() {
             if *nibble > 0xF {
-                return Err(Error::InternalError("cannot be more than 4 bits"));
+                return Err(Error::Usage("cannot be more than 4 bits"));
             }
         }
-        Ok(Self(nibbles))
+        Ok(Self(SmallVec::from_slice(&nibbles)))
     }
 
     pub fn from_encoded_path(bytes: Bytes) -> Result<Self, Error> {
@@ -47,7 +53,10 @@ impl Nibbles {
         Ok(val)
     }
     pub fn from_encoded_path_with_terminator(bytes: Bytes) -> Result<(Self, bool), Error> {
-        let mut u4_vec = u8_to_u4_vec(bytes.to_vec());
+        if bytes.is_empty() {
+            return Err(Error::InternalError("empty encoded path"));
+        }
+        let mut u4_vec = u8_to_u4_vec(&bytes);
 
         let first = u4_vec[0];
         let second = u4_vec[1];
@@ -85,19 +94,24 @@ impl Nibbles {
     }
 
     pub fn to_u4_vec(&self) -> Vec<u8> {
-        self.0.clone()
+        self.0.to_vec()
+    }
+
+    // the nibbles without copying them out, for comparing paths.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
     }
 
     pub fn nibble_at(&self, index: usize) -> Result<usize, Error> {
         if index >= self.0.len() {
-            return Err(Error::InternalError("index out of bounds"));
+            return Err(Error::Usage("index out of bounds"));
         }
         Ok(self.0[index] as usize)
     }
 
     pub fn encode_path(&self, terminator: bool) -> Bytes {
         let mut bytes_vec = self.to_raw_path().to_vec();
-        if self.0.len() % 2 != 0 {
+        if self.0.len() % 2 == 1 {
             // odd length
             bytes_vec[0] += 1 << 4;
         } else {
@@ -111,13 +125,9 @@ impl Nibbles {
 
     pub fn slice(&self, from: usize) -> Result<Self, Error> {
         if self.0.len() < from {
-            return Err(Error::InternalError("slice from is larger than len"));
+            return Err(Error::Usage("slice from is larger than len"));
         }
-        let mut nibbles_vec = Vec::new();
-        for i in from..self.0.len() {
-            nibbles_vec.push(self.0[i]);
-        }
-        Ok(Self(nibbles_vec))
+        Ok(Self(SmallVec::from_slice(&self.0[from..])))
     }
 
     pub fn intersect(&self, other: &Self) -> Result<Self, Error> {
@@ -125,7 +135,7 @@ impl Nibbles {
         let other_vec = other.to_u4_vec();
 
         if self_vec.len() != other_vec.len() {
-            return Err(Error::InternalError(
+            return Err(Error::Usage(
                 "cannot intersect nibbles because lengths differ",
             ));
         }
@@ -157,6 +167,10 @@ impl Nibbles {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 fn fmt(val: Nibbles, f: &mut fmt::Formatter) -> fmt::Result {
@@ -183,15 +197,24 @@ impl fmt::Debug for Nibbles {
     }
 }
 
+// any length, every nibble in 0..16.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Nibbles {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let bytes: Vec<u8> = u.arbitrary()?;
+        Ok(Nibbles(bytes.into_iter().map(|byte| byte & 0xf).collect()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Bytes, Nibbles};
-    use ethers::core::utils::hex;
+    use ethers_core::utils::hex;
 
     #[test]
     pub fn test_raw_path_1() {
         let nibbles = Nibbles::from_raw_path("123456".parse().unwrap());
-        assert_eq!(nibbles.0, vec![0x1, 0x2, 0x3, 0x4, 0x5, 0x6]);
+        assert_eq!(nibbles.to_u4_vec(), vec![0x1, 0x2, 0x3, 0x4, 0x5, 0x6]);
         assert_eq!(
             hex::encode(nibbles.to_raw_path()),
             hex::encode(vec![0x12, 0x34, 0x56])
@@ -208,16 +231,25 @@ mod tests {
     #[test]
     pub fn test_raw_path_2() {
         let nibbles = Nibbles::from_raw_path("000456".parse().unwrap());
-        assert_eq!(nibbles.0, vec![0, 0, 0, 0x4, 0x5, 0x6]);
+        assert_eq!(nibbles.to_u4_vec(), vec![0, 0, 0, 0x4, 0x5, 0x6]);
         assert_eq!(
             hex::encode(nibbles.to_raw_path()),
             hex::encode(vec![0, 0x04, 0x56])
         );
     }
 
+    #[test]
+    pub fn test_hashed_key_inline() {
+        let nibbles = Nibbles::from_raw_path(vec![0xab; 32].into());
+        assert_eq!(nibbles.len(), 64);
+        assert!(!nibbles.0.spilled());
+        assert!(!nibbles.slice(1).unwrap().0.spilled());
+        assert!(nibbles.prepend_nibbles(vec![1]).unwrap().0.spilled());
+    }
+
     #[test]
     pub fn test_raw_path_3_odd_length() {
-        let nibbles = Nibbles(vec![0x4, 0x5, 0x6]);
+        let nibbles = Nibbles::from_u4_vec(vec![0x4, 0x5, 0x6]).unwrap();
         assert_eq!(
             hex::encode(nibbles.to_raw_path()),
             hex::encode(vec![0x04, 0x56])
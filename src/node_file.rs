@@ -0,0 +1,245 @@
+use std::{fs::File, io::Write, path::Path};
+
+use ethers_core::{
+    types::{Address, Bytes, H256, U256},
+    utils::keccak256,
+};
+use memmap2::Mmap;
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    key_cache::{hashed_address, hashed_slot},
+    nibbles::Nibbles,
+    nodes::{LeafValue, NodeData},
+    AccountData, Error, Witness,
+};
+
+// a node file is the magic, the root, the number of nodes (u64 le), an index entry per node
+// sorted by hash (hash, offset as u64 le, length as u32 le) and then the nodes back to back.
+// offsets are from the start of the nodes, so a lookup is a binary search over the index.
+pub const NODE_FILE_MAGIC: &[u8; 8] = b"pmptnode";
+
+const HEADER_LEN: usize = 8 + 32 + 8;
+const INDEX_ENTRY_LEN: usize = 32 + 8 + 4;
+
+fn io_error(err: std::io::Error) -> Error {
+    Error::ProviderError(format!("node file: {}", err))
+}
+
+// writes the witness as a node file, its nodes are sorted by hash already.
+pub fn write_node_file(witness: &Witness, writer: &mut impl Write) -> Result<(), Error> {
+    writer.write_all(NODE_FILE_MAGIC).map_err(io_error)?;
+    writer
+        .write_all(witness.root().as_bytes())
+        .map_err(io_error)?;
+    writer
+        .write_all(&(witness.len() as u64).to_le_bytes())
+        .map_err(io_error)?;
+    let mut offset = 0u64;
+    for node in witness.nodes() {
+        let length = u32::try_from(node.len())
+            .map_err(|_| Error::InternalError("node is too large for a node file"))?;
+        writer.write_all(&keccak256(node)).map_err(io_error)?;
+        writer.write_all(&offset.to_le_bytes()).map_err(io_error)?;
+        writer.write_all(&length.to_le_bytes()).map_err(io_error)?;
+        offset += node.len() as u64;
+    }
+    for node in witness.nodes() {
+        writer.write_all(node).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+// a node file mapped into memory read only, the os pages in what is looked at and nothing is
+// copied to the heap but the nodes on the paths being read. every node is checked against its
+// hash when it's read, a corrupt file gives errors rather than wrong values.
+#[derive(Debug)]
+pub struct MmapNodeStore {
+    map: Mmap,
+    root: H256,
+    len: usize,
+}
+
+impl MmapNodeStore {
+    // the file must not be changed while it's open, the map would change under us.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path).map_err(io_error)?;
+        let map = unsafe { Mmap::map(&file) }.map_err(io_error)?;
+        if map.len() < HEADER_LEN || &map[..8] != NODE_FILE_MAGIC {
+            return Err(Error::InternalError("not a node file"));
+        }
+        let root = H256::from_slice(&map[8..40]);
+        let len = u64::from_le_bytes(map[40..48].try_into().unwrap()) as usize;
+        let index_end = len
+            .checked_mul(INDEX_ENTRY_LEN)
+            .and_then(|index_len| index_len.checked_add(HEADER_LEN));
+        if index_end.is_none_or(|index_end| index_end > map.len()) {
+            return Err(Error::InternalError("node file is truncated"));
+        }
+        Ok(MmapNodeStore { map, root, len })
+    }
+
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn index_entry(&self, i: usize) -> (H256, usize, usize) {
+        let entry = &self.map[HEADER_LEN + i * INDEX_ENTRY_LEN..][..INDEX_ENTRY_LEN];
+        (
+            H256::from_slice(&entry[..32]),
+            u64::from_le_bytes(entry[32..40].try_into().unwrap()) as usize,
+            u32::from_le_bytes(entry[40..44].try_into().unwrap()) as usize,
+        )
+    }
+
+    // the raw node with this hash, None if the file doesn't have it.
+    pub fn get(&self, hash: H256) -> Result<Option<&[u8]>, Error> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = (low + high) / 2;
+            let (entry_hash, offset, length) = self.index_entry(middle);
+            match entry_hash.cmp(&hash) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => {
+                    let start = HEADER_LEN + self.len * INDEX_ENTRY_LEN + offset;
+                    let raw = self
+                        .map
+                        .get(start..start + length)
+                        .ok_or(Error::InternalError("node file is truncated"))?;
+                    if KeccakHasher::hash(raw) != hash {
+                        return Err(Error::InternalError("node does not match its hash"));
+                    }
+                    return Ok(Some(raw));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // reads the value at `path` under `root`, decoding only the nodes on the way.
+    fn get_path<V: LeafValue>(&self, root: H256, path: Nibbles) -> Result<V, Error> {
+        let mut hash = root;
+        let mut i = 0;
+        loop {
+            if hash == KeccakHasher::empty_root() {
+                return Ok(V::default());
+            }
+            let raw = self
+                .get(hash)?
+                .ok_or(Error::MissingData("node not in the node file"))?;
+            match NodeData::<V>::from_raw_rlp(Bytes::from(raw.to_vec()))? {
+                NodeData::Leaf { key, value } => {
+                    return Ok(if key == path.slice(i)? {
+                        value
+                    } else {
+                        V::default()
+                    })
+                }
+                NodeData::Branch(arr) => match arr[path.nibble_at(i)?] {
+                    Some(child) => {
+                        hash = child;
+                        i += 1;
+                    }
+                    None => return Ok(V::default()),
+                },
+                NodeData::Extension { key, node } => {
+                    if !path.slice(i)?.as_slice().starts_with(key.as_slice()) {
+                        return Ok(V::default());
+                    }
+                    hash = node;
+                    i += key.len();
+                }
+            }
+        }
+    }
+
+    pub fn get_account(&self, address: Address) -> Result<AccountData, Error> {
+        self.get_path(
+            self.root,
+            Nibbles::from_raw_path(Bytes::from(hashed_address(address).as_bytes().to_vec())),
+        )
+    }
+
+    pub fn get_storage_at(&self, address: Address, slot: U256) -> Result<U256, Error> {
+        let storage_root = self.get_account(address)?.storage_root;
+        self.get_path(
+            storage_root,
+            Nibbles::from_raw_path(Bytes::from(hashed_slot(slot).as_bytes().to_vec())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_node_file, MmapNodeStore};
+    use crate::StateTrie;
+    use ethers_core::types::{Address, U256};
+
+    fn state() -> StateTrie {
+        let mut state_trie = StateTrie::new();
+        for i in 1..60u64 {
+            let address = Address::from_low_u64_be(i);
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+            state_trie
+                .set_storage_value(address, U256::from(i), U256::from(i * 2))
+                .unwrap();
+        }
+        state_trie
+    }
+
+    #[test]
+    pub fn test_mmap_node_store() {
+        let state_trie = state();
+        let witness = state_trie.witness().unwrap();
+        let path = std::env::temp_dir().join(format!("partial-mpt-{}.nodes", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        write_node_file(&witness, &mut file).unwrap();
+        drop(file);
+
+        let store = MmapNodeStore::open(&path).unwrap();
+        assert_eq!(Some(store.root()), state_trie.root());
+        assert_eq!(store.len(), witness.len());
+        for i in 1..60u64 {
+            let address = Address::from_low_u64_be(i);
+            assert_eq!(store.get_account(address).unwrap().balance, U256::from(i));
+            assert_eq!(
+                store.get_storage_at(address, U256::from(i)).unwrap(),
+                U256::from(i * 2)
+            );
+            assert_eq!(
+                store.get_storage_at(address, U256::from(1000)).unwrap(),
+                U256::zero()
+            );
+        }
+        for node in witness.nodes() {
+            let hash = ethers_core::utils::keccak256(node).into();
+            assert_eq!(store.get(hash).unwrap(), Some(node.as_ref()));
+        }
+        drop(store);
+
+        // a flipped byte in a node is caught by its hash.
+        let mut raw = std::fs::read(&path).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xff;
+        std::fs::write(&path, &raw).unwrap();
+        let store = MmapNodeStore::open(&path).unwrap();
+        assert!((1..60u64).any(|i| store.get_account(Address::from_low_u64_be(i)).is_err()));
+        drop(store);
+
+        std::fs::write(&path, b"not a node file").unwrap();
+        assert!(MmapNodeStore::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,116 +1,255 @@
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     fmt::{self, Debug},
+    marker::PhantomData,
+    sync::Arc,
 };
 
 use bytes::BytesMut;
-use ethers::{
+use ethers_core::{
     types::{Bytes, H256},
     utils::{
-        hex, keccak256,
-        rlp::{self, Rlp, RlpStream},
+        hex,
+        rlp::{Rlp, RlpStream},
     },
 };
 
-use crate::{nibbles::Nibbles, Error};
+use crate::{
+    hasher::TrieHasher,
+    instrument::{self, Operation},
+    nibbles::Nibbles,
+    Error,
+};
 
 pub trait LeafValue: Clone + Debug + Default + PartialEq {
     fn from_raw_rlp(raw: Bytes) -> Result<Self, Error>
     where
         Self: Sized;
     fn to_raw_rlp(&self) -> Result<Bytes, Error>;
+
+    // the value with its byte strings shared with equal ones loaded before, see
+    // Trie::intern_values. values which are kept inline, like U256, have nothing to share.
+    fn intern(self, _interner: &mut ValueInterner) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
 }
 
-#[derive(Clone, Default, Debug, PartialEq)]
-pub struct Nodes<V: LeafValue>(HashMap<H256, NodeData<V>>);
+// one copy of every distinct byte string of the values it has seen, e.g. the many slots set
+// to 1 or the same account leaf in a large witness. equal values loaded later point to it.
+#[derive(Clone, Debug, Default)]
+pub struct ValueInterner(HashSet<Bytes>);
+
+impl ValueInterner {
+    pub fn intern(&mut self, bytes: Bytes) -> Bytes {
+        match self.0.get(&bytes) {
+            Some(interned) => interned.clone(),
+            None => {
+                self.0.insert(bytes.clone());
+                bytes
+            }
+        }
+    }
 
-impl<V: LeafValue> Nodes<V> {
-    pub fn get(&self, hash: &H256) -> Option<&NodeData<V>> {
-        self.0.get(hash)
+    // the number of distinct values.
+    pub fn len(&self) -> usize {
+        self.0.len()
     }
 
-    #[allow(dead_code)]
-    pub fn get_str(&self, hash_str: &str) -> Option<&NodeData<V>> {
-        let hash = hash_str.parse::<H256>().unwrap();
-        self.get(&hash)
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
+}
+
+// anything that can be kept in the node store, i.e. is addressed by its own hash.
+pub trait TrieNode: Clone + Debug + PartialEq {
+    fn node_hash<H: TrieHasher>(&self) -> Result<H256, Error>;
+}
 
-    pub fn insert(&mut self, node_data: NodeData<V>) -> Result<(H256, Option<NodeData<V>>), Error> {
-        let key = node_data.hash()?;
-        Ok((key, self.0.insert(key, node_data)))
+// the nodes of a trie side by side in one buffer which lives as long as the trie, found by
+// hash through the index. a node is a single slot, its nibbles are inline (see Nibbles), so
+// loading or building nodes grows the buffer instead of allocating for each of them, and a
+// walk over all of them reads memory in order.
+#[derive(Clone, Debug)]
+struct NodeArena<N> {
+    nodes: Vec<(H256, N)>,
+    index: HashMap<H256, usize>,
+}
+
+impl<N> Default for NodeArena<N> {
+    fn default() -> Self {
+        NodeArena {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        }
     }
+}
 
-    pub fn remove(&mut self, hash: &H256) -> Option<NodeData<V>> {
-        self.0.remove(hash)
+impl<N> NodeArena<N> {
+    fn get(&self, hash: &H256) -> Option<&N> {
+        self.index.get(hash).map(|slot| &self.nodes[*slot].1)
     }
 
-    pub fn create_leaf(&mut self, key: Nibbles, value: V) -> Result<H256, Error> {
-        let (hash_leaf, _) = self.insert(NodeData::Leaf { key, value })?;
-        Ok(hash_leaf)
+    fn insert(&mut self, hash: H256, node: N) -> Option<N> {
+        match self.index.get(&hash) {
+            Some(slot) => Some(std::mem::replace(&mut self.nodes[*slot].1, node)),
+            None => {
+                self.index.insert(hash, self.nodes.len());
+                self.nodes.push((hash, node));
+                None
+            }
+        }
     }
 
-    pub fn create_branch_or_extension(
-        &mut self,
-        key_a: Nibbles,
-        value_a: V,
-        key_b: Nibbles,
-        value_b: V,
-    ) -> Result<NodeData<V>, Error> {
-        let mut branch_node_arr: [Option<H256>; 17] = [None; 17];
+    // the last node takes the freed slot, so the buffer has no holes.
+    fn remove(&mut self, hash: &H256) -> Option<N> {
+        let slot = self.index.remove(hash)?;
+        let (_, node) = self.nodes.swap_remove(slot);
+        if let Some((moved, _)) = self.nodes.get(slot) {
+            self.index.insert(*moved, slot);
+        }
+        Some(node)
+    }
+}
 
-        let intersection = key_a.intersect(&key_b)?;
+// the same nodes, in whatever slots.
+impl<N: PartialEq> PartialEq for NodeArena<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes.len() == other.nodes.len()
+            && self
+                .nodes
+                .iter()
+                .all(|(hash, node)| other.get(hash) == Some(node))
+    }
+}
+
+impl<N: Eq> Eq for NodeArena<N> {}
 
-        if intersection.len() > 0 {
-            let key_a_prime = key_a.slice(intersection.len())?;
-            let key_b_prime = key_b.slice(intersection.len())?;
+// clones share the arena until one of them writes, then that one gets its own copy. so a copy
+// of a state, e.g. a published version, only costs the arenas which are written to afterwards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nodes<N: TrieNode, H: TrieHasher>(Arc<NodeArena<N>>, PhantomData<H>);
 
-            let nibble_a = key_a_prime.first_nibble() as usize;
-            let nibble_b = key_b_prime.first_nibble() as usize;
+impl<N: TrieNode, H: TrieHasher> Default for Nodes<N, H> {
+    fn default() -> Self {
+        Nodes(Arc::new(NodeArena::default()), PhantomData)
+    }
+}
 
-            let hash_a = self.create_leaf(key_a_prime, value_a)?;
-            let hash_b = self.create_leaf(key_b_prime, value_b)?;
+impl<N: TrieNode, H: TrieHasher> Nodes<N, H> {
+    pub fn get(&self, hash: &H256) -> Option<&N> {
+        self.0.get(hash)
+    }
 
-            branch_node_arr[nibble_a] = Some(hash_a);
-            branch_node_arr[nibble_b] = Some(hash_b);
+    #[allow(dead_code)]
+    pub fn get_str(&self, hash_str: &str) -> Option<&N> {
+        let hash = hash_str.parse::<H256>().unwrap();
+        self.get(&hash)
+    }
 
-            let (branch_hash, _) = self.insert(NodeData::Branch(branch_node_arr))?;
+    pub fn insert(&mut self, node_data: N) -> Result<(H256, Option<N>), Error> {
+        let key = node_data.node_hash::<H>()?;
+        Ok((key, Arc::make_mut(&mut self.0).insert(key, node_data)))
+    }
 
-            Ok(NodeData::Extension {
-                key: intersection,
-                node: branch_hash,
-            })
+    // moves in the nodes of another store, they're under their hashes already.
+    pub fn extend(&mut self, other: Self) {
+        if self.0.nodes.is_empty() {
+            self.0 = other.0;
         } else {
-            let nibble_a = key_a.first_nibble() as usize;
-            let nibble_b = key_b.first_nibble() as usize;
+            let arena = Arc::make_mut(&mut self.0);
+            for (hash, node) in Arc::unwrap_or_clone(other.0).nodes {
+                arena.insert(hash, node);
+            }
+        }
+    }
 
-            let key_a_prime = key_a.slice(1)?;
-            let key_b_prime = key_b.slice(1)?;
+    pub fn remove(&mut self, hash: &H256) -> Option<N> {
+        if !self.contains(hash) {
+            // nothing to remove, so no copy of a shared arena.
+            return None;
+        }
+        Arc::make_mut(&mut self.0).remove(hash)
+    }
 
-            let hash_a = self.create_leaf(key_a_prime, value_a)?;
-            let hash_b = self.create_leaf(key_b_prime, value_b)?;
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.0.index.contains_key(hash)
+    }
 
-            branch_node_arr[nibble_a] = Some(hash_a);
-            branch_node_arr[nibble_b] = Some(hash_b);
+    // in the order of the arena, not by hash.
+    pub fn iter(&self) -> impl Iterator<Item = (&H256, &N)> {
+        self.0.nodes.iter().map(|(hash, node)| (hash, node))
+    }
+}
 
-            Ok(NodeData::Branch(branch_node_arr))
+// a store from one rlp list of raw nodes, a common compact transport for witnesses. the items
+// can be byte strings with a node in them, like in Witness::encode, or the nodes themselves.
+// every node is stored under the hash of its raw bytes, one whose bytes aren't the canonical
+// encoding of what they decode to is rejected. all the nodes have to be of tries with values
+// of type V, the nodes of a state with its storage go into a Witness.
+impl<V: LeafValue, H: TrieHasher> TryFrom<&[u8]> for Nodes<NodeData<V>, H> {
+    type Error = Error;
+
+    fn try_from(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        if !rlp.is_list() {
+            return Err(Error::InternalError("node stream is not a list"));
         }
+        let mut nodes = Nodes::default();
+        for item in rlp.iter() {
+            let raw_node = if item.is_list() {
+                item.as_raw()
+            } else {
+                item.data()?
+            };
+            let (hash, _) =
+                nodes.insert(NodeData::from_raw_rlp(Bytes::from(raw_node.to_vec()))?)?;
+            if hash != H::hash(raw_node) {
+                return Err(Error::InternalError("node does not match its hash"));
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+impl<V: LeafValue, H: TrieHasher> Nodes<NodeData<V>, H> {
+    pub fn create_leaf(&mut self, key: Nibbles, value: V) -> Result<H256, Error> {
+        let (hash_leaf, _) = self.insert(NodeData::Leaf { key, value })?;
+        Ok(hash_leaf)
     }
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum NodeData<V: LeafValue> {
     Leaf { key: Nibbles, value: V },
     Branch([Option<H256>; 17]),
     Extension { key: Nibbles, node: H256 },
 }
 
+thread_local! {
+    static RLP_BUFFER: RefCell<BytesMut> = RefCell::new(BytesMut::with_capacity(1024));
+}
+
 impl<V> NodeData<V>
 where
     V: LeafValue,
 {
-    pub fn hash(&self) -> Result<H256, Error> {
-        Ok(H256::from(keccak256(self.to_raw_rlp()?)))
+    pub fn intern_value(self, interner: &mut ValueInterner) -> Self {
+        match self {
+            NodeData::Leaf { key, value } => NodeData::Leaf {
+                key,
+                value: value.intern(interner),
+            },
+            node_data => node_data,
+        }
+    }
+
+    pub fn hash<H: TrieHasher>(&self) -> Result<H256, Error> {
+        self.with_raw_rlp(H::hash)
     }
 
     #[allow(dead_code)]
@@ -128,6 +267,7 @@ where
         matches!(self, NodeData::Extension { .. })
     }
 
+    #[allow(dead_code)]
     pub fn get_branch_arr(&self) -> Option<[Option<H256>; 17]> {
         match self {
             NodeData::Branch(arr) => Some(arr.to_owned()),
@@ -142,7 +282,7 @@ where
                 *value = new_value;
                 Ok(())
             }
-            _ => Err(Error::InternalError(
+            _ => Err(Error::Usage(
                 "set_value_on_leaf is only valid on leaf nodes",
             )),
         }
@@ -150,6 +290,7 @@ where
 
     #[allow(clippy::needless_range_loop)]
     pub fn from_raw_rlp(raw: Bytes) -> Result<Self, Error> {
+        let _span = instrument::span(Operation::DecodeNode);
         let rlp = Rlp::new(&raw);
         let num_items = rlp.item_count()?;
         match num_items {
@@ -190,37 +331,101 @@ where
         }
     }
 
-    pub fn to_raw_rlp(&self) -> Result<Bytes, Error> {
-        let mut rlp_stream = rlp::RlpStream::new();
+    // fails before anything is appended, an unfinished list can't be taken out of the stream.
+    fn append_to(&self, rlp_stream: &mut RlpStream) -> Result<(), Error> {
         match self {
             NodeData::Leaf { key, value } => {
-                let key_bm = BytesMut::from(key.encode_path(true).to_vec().as_slice());
-                let value_bm = BytesMut::from(value.to_raw_rlp()?.to_vec().as_slice());
-
+                let value = value.to_raw_rlp()?;
                 rlp_stream.begin_list(2);
-                rlp_stream.append(&key_bm);
-                rlp_stream.append(&value_bm);
+                rlp_stream.append(&key.encode_path(true).as_ref());
+                rlp_stream.append(&value.as_ref());
             }
             NodeData::Branch(arr) => {
                 rlp_stream.begin_list(17);
                 for entry in arr.iter() {
-                    let bm = if entry.is_some() {
-                        BytesMut::from(entry.to_owned().unwrap().as_bytes())
-                    } else {
-                        BytesMut::new()
+                    match entry {
+                        Some(hash) => rlp_stream.append(&hash.as_bytes()),
+                        None => rlp_stream.append_empty_data(),
                     };
-                    rlp_stream.append(&bm);
                 }
             }
             NodeData::Extension { key, node } => {
-                let key_bm = BytesMut::from(key.encode_path(false).to_vec().as_slice());
-                let value_bm = BytesMut::from(node.as_bytes());
                 rlp_stream.begin_list(2);
-                rlp_stream.append(&key_bm);
-                rlp_stream.append(&value_bm);
+                rlp_stream.append(&key.encode_path(false).as_ref());
+                rlp_stream.append(&node.as_bytes());
             }
         }
-        Ok(Bytes::from(rlp_stream.out().to_vec()))
+        Ok(())
+    }
+
+    // about the encoded length, so the buffer of to_raw_rlp rarely grows. leaves of accounts
+    // are the largest, with up to 136 bytes of value.
+    fn rlp_len_hint(&self) -> usize {
+        match self {
+            NodeData::Leaf { key, .. } => key.len() / 2 + 144,
+            NodeData::Extension { key, .. } => key.len() / 2 + 40,
+            NodeData::Branch(_) => 3 + 16 * 33 + 1,
+        }
+    }
+
+    pub fn to_raw_rlp(&self) -> Result<Bytes, Error> {
+        let mut rlp_stream =
+            RlpStream::new_with_buffer(BytesMut::with_capacity(self.rlp_len_hint()));
+        self.append_to(&mut rlp_stream)?;
+        Ok(Bytes::from(rlp_stream.out().freeze()))
+    }
+
+    // appends the encoding to a buffer of the caller, e.g. one reused across many nodes.
+    pub fn encode_into(&self, buffer: &mut BytesMut) -> Result<(), Error> {
+        let mut rlp_stream = RlpStream::new_with_buffer(std::mem::take(buffer));
+        let result = self.append_to(&mut rlp_stream);
+        *buffer = rlp_stream.out();
+        result
+    }
+
+    // calls `f` with the encoding in a buffer of this thread, nothing is allocated for nodes
+    // which are only hashed.
+    pub fn with_raw_rlp<T>(&self, f: impl FnOnce(&[u8]) -> T) -> Result<T, Error> {
+        RLP_BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+            Ok(mut buffer) => {
+                buffer.clear();
+                self.encode_into(&mut buffer)?;
+                Ok(f(&buffer))
+            }
+            // `f` encoding another node on the same thread.
+            Err(_) => Ok(f(&self.to_raw_rlp()?)),
+        })
+    }
+}
+
+// nodes this crate can encode: no value in the 17th slot of a branch, which would need a key
+// that is a prefix of another key.
+#[cfg(feature = "arbitrary")]
+impl<'a, V: LeafValue + arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a> for NodeData<V> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => NodeData::Leaf {
+                key: u.arbitrary()?,
+                value: u.arbitrary()?,
+            },
+            1 => {
+                let mut arr: [Option<H256>; 17] = Default::default();
+                for child in arr.iter_mut().take(16) {
+                    *child = u.arbitrary()?;
+                }
+                NodeData::Branch(arr)
+            }
+            _ => NodeData::Extension {
+                key: u.arbitrary()?,
+                node: u.arbitrary()?,
+            },
+        })
+    }
+}
+
+impl<V: LeafValue> TrieNode for NodeData<V> {
+    fn node_hash<H: TrieHasher>(&self) -> Result<H256, Error> {
+        self.hash::<H>()
     }
 }
 
@@ -259,7 +464,7 @@ where
 }
 
 impl LeafValue for u64 {
-    fn from_raw_rlp(raw: ethers::types::Bytes) -> Result<u64, crate::Error> {
+    fn from_raw_rlp(raw: ethers_core::types::Bytes) -> Result<u64, crate::Error> {
         let rlp = Rlp::new(&raw);
         let arr = rlp.data()?.to_owned();
         if arr.len() <= 8 {
@@ -274,7 +479,7 @@ impl LeafValue for u64 {
         }
     }
 
-    fn to_raw_rlp(&self) -> Result<ethers::types::Bytes, crate::Error> {
+    fn to_raw_rlp(&self) -> Result<ethers_core::types::Bytes, crate::Error> {
         let mut vec = Vec::<u8>::new();
         let mut flag = false;
         for i in (0..8).rev() {
@@ -292,10 +497,38 @@ impl LeafValue for u64 {
     }
 }
 
+// raw bytes as the leaf value, like the tries in ethereum/tests. empty means not set.
+impl LeafValue for Bytes {
+    fn from_raw_rlp(raw: Bytes) -> Result<Self, Error> {
+        Ok(raw)
+    }
+
+    fn to_raw_rlp(&self) -> Result<Bytes, Error> {
+        Ok(self.clone())
+    }
+
+    fn intern(self, interner: &mut ValueInterner) -> Self {
+        interner.intern(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Nibbles, NodeData};
-    use ethers::utils::hex;
+    use super::{Nibbles, NodeData, Nodes};
+    use crate::{
+        hasher::{KeccakHasher, TrieHasher},
+        state_trie::StorageTrie,
+        Trie,
+    };
+    use bytes::BytesMut;
+    use ethers_core::{
+        types::{Bytes, U256},
+        utils::{hex, keccak256, rlp::RlpStream},
+    };
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
 
     #[test]
     pub fn test_node_data_new_leaf_node_1() {
@@ -421,4 +654,166 @@ mod tests {
         let node_data = NodeData::<u64>::from_raw_rlp(input_raw_rlp.parse().unwrap()).unwrap();
         assert_eq!(hex::encode(node_data.to_raw_rlp().unwrap()), input_raw_rlp);
     }
+
+    #[test]
+    pub fn test_encode_into_buffer() {
+        let branch = NodeData::<u64>::from_raw_rlp(
+            "0xf851a0e97150c3ed221a6f46bdcd44e8a2d44825bc781fa48f797e9df2f8ceff52a43e8080808080808080808080a09487c8e7f28469b9f72cd6be094b555c3882c0653f11b208ff76bf8caee5043280808080"
+                .parse()
+                .unwrap(),
+        )
+        .unwrap();
+        let leaf = NodeData::Leaf {
+            key: Nibbles::from_raw_path(vec![0x12, 0x34].into()),
+            value: 7u64,
+        };
+        let mut buffer = BytesMut::new();
+        branch.encode_into(&mut buffer).unwrap();
+        leaf.encode_into(&mut buffer).unwrap();
+        let (branch_raw, leaf_raw) = (branch.to_raw_rlp().unwrap(), leaf.to_raw_rlp().unwrap());
+        // appended one after the other.
+        assert_eq!(
+            buffer.to_vec(),
+            [branch_raw.as_ref(), leaf_raw.as_ref()].concat()
+        );
+        assert_eq!(
+            branch.with_raw_rlp(|raw| raw.to_vec()).unwrap(),
+            branch_raw.to_vec()
+        );
+        // nested on the same thread.
+        let nested = branch
+            .with_raw_rlp(|outer| (outer.to_vec(), leaf.with_raw_rlp(|inner| inner.to_vec())))
+            .unwrap();
+        assert_eq!(nested.0, branch_raw.to_vec());
+        assert_eq!(nested.1.unwrap(), leaf_raw.to_vec());
+        assert_eq!(
+            branch.hash::<KeccakHasher>().unwrap(),
+            KeccakHasher::hash(&branch_raw)
+        );
+    }
+
+    #[test]
+    pub fn test_nodes_from_node_stream() {
+        let mut full = StorageTrie::empty();
+        for i in 1..20u64 {
+            full.set(U256::from(i), U256::from(i)).unwrap();
+        }
+        let proof = full.get_proof(U256::from(5)).unwrap();
+
+        // as byte strings, and as the nodes themselves.
+        let mut stream = RlpStream::new_list(proof.len());
+        for node in proof.iter() {
+            stream.append(&node.to_vec());
+        }
+        let nodes = Nodes::<NodeData<U256>, KeccakHasher>::try_from(stream.out().as_ref()).unwrap();
+        assert_eq!(nodes.iter().count(), proof.len());
+        let mut stream = RlpStream::new_list(proof.len());
+        for node in proof.iter() {
+            stream.append_raw(node, 1);
+        }
+        let inline =
+            Nodes::<NodeData<U256>, KeccakHasher>::try_from(stream.out().as_ref()).unwrap();
+        assert_eq!(inline, nodes);
+
+        let trie = Trie::<U256, U256>::from_nodes(full.root().unwrap(), nodes);
+        assert_eq!(trie.get(U256::from(5)).unwrap(), U256::from(5));
+        assert!(trie.get(U256::from(6)).is_err());
+
+        // a leaf with a non canonical value, 0x05 as a one byte string.
+        let mut leaf = RlpStream::new_list(2);
+        leaf.append(&vec![0x20u8]);
+        leaf.append(&vec![0x81u8, 0x05]);
+        let mut stream = RlpStream::new_list(1);
+        stream.append(&leaf.out().to_vec());
+        assert!(Nodes::<NodeData<U256>, KeccakHasher>::try_from(stream.out().as_ref()).is_err());
+        assert!(
+            Nodes::<NodeData<U256>, KeccakHasher>::try_from(Bytes::from(vec![0x80]).as_ref())
+                .is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_nodes_clone_shares_map() {
+        let mut nodes = Nodes::<NodeData<U256>, KeccakHasher>::default();
+        for i in 1..10u8 {
+            nodes
+                .insert(NodeData::Leaf {
+                    key: Nibbles::from_raw_path(Bytes::from(vec![0x20, i])),
+                    value: U256::from(i),
+                })
+                .unwrap();
+        }
+        let mut copy = nodes.clone();
+        assert!(std::sync::Arc::ptr_eq(&nodes.0, &copy.0));
+        // removing a node which isn't there doesn't copy the map either.
+        copy.remove(&KeccakHasher::empty_root());
+        assert!(std::sync::Arc::ptr_eq(&nodes.0, &copy.0));
+        copy.insert(NodeData::Leaf {
+            key: Nibbles::from_raw_path(Bytes::from(vec![0x20])),
+            value: U256::one(),
+        })
+        .unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&nodes.0, &copy.0));
+        assert_eq!(copy.iter().count(), nodes.iter().count() + 1);
+    }
+
+    // counts the allocations of the current thread, tests run on threads of their own.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+
+    #[test]
+    pub fn test_nodes_arena_allocations() {
+        let mut nodes = Nodes::<NodeData<U256>, KeccakHasher>::default();
+        let mut hashes = Vec::new();
+        for i in 0..1000u64 {
+            let (hash, _) = nodes
+                .insert(NodeData::Leaf {
+                    key: Nibbles::from_raw_path(Bytes::from(keccak256(i.to_be_bytes()).to_vec())),
+                    value: U256::from(i),
+                })
+                .unwrap();
+            hashes.push(hash);
+        }
+
+        // the first write to a copy copies the arena, in a few allocations for all the nodes
+        // and their 64 nibble keys rather than one per node.
+        let mut copy = nodes.clone();
+        let before = allocations();
+        copy.remove(&hashes[0]);
+        let copied = allocations() - before;
+        assert!(copied > 0 && copied <= 4);
+        assert_eq!(copy.iter().count(), 999);
+        assert_eq!(nodes.iter().count(), 1000);
+
+        // freed slots are filled, every node can still be found.
+        for hash in hashes[1..500].iter() {
+            assert!(copy.remove(hash).is_some());
+        }
+        for hash in hashes[500..].iter() {
+            assert!(copy.contains(hash));
+        }
+        assert_eq!(copy.iter().count(), 500);
+        assert!(nodes.iter().all(|(hash, _)| hashes.contains(hash)));
+    }
 }
@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+
+use ethers_core::{
+    types::{
+        Address, Block, Bloom, Bytes, EIP1186ProofResponse, Transaction, Withdrawal, H256, U256,
+        U64,
+    },
+    utils::rlp,
+};
+use revm::primitives::SpecId;
+use serde::{Deserialize, Serialize};
+
+use crate::{AccountData, BlockReport, Error, StateDiff, StateTrie, Witness};
+
+// the execution payload of engine_newPayload (v1 to v3), with the raw transactions. the
+// beacon root isn't part of the payload there but goes next to it, it's kept here so the
+// payload is enough to run the block.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionPayload {
+    pub parent_hash: H256,
+    pub fee_recipient: Address,
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub logs_bloom: Bloom,
+    pub prev_randao: H256,
+    pub block_number: U64,
+    pub gas_limit: U64,
+    pub gas_used: U64,
+    pub timestamp: U64,
+    pub extra_data: Bytes,
+    pub base_fee_per_gas: U256,
+    pub block_hash: H256,
+    pub transactions: Vec<Bytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawals: Option<Vec<Withdrawal>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<U64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excess_blob_gas: Option<U64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_beacon_block_root: Option<H256>,
+}
+
+impl ExecutionPayload {
+    // the block with its transactions decoded and their senders recovered. blob transactions
+    // can't be decoded yet.
+    pub fn to_block(&self) -> Result<Block<Transaction>, Error> {
+        let transactions = self
+            .transactions
+            .iter()
+            .map(|raw| {
+                let mut tx: Transaction = rlp::decode(raw).map_err(|_| {
+                    Error::InternalError("payload has a transaction which can't be decoded")
+                })?;
+                tx.recover_from_mut().map_err(|_| {
+                    Error::InternalError("payload has a transaction with a bad signature")
+                })?;
+                Ok(tx)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Block {
+            hash: Some(self.block_hash),
+            parent_hash: self.parent_hash,
+            author: Some(self.fee_recipient),
+            state_root: self.state_root,
+            receipts_root: self.receipts_root,
+            number: Some(self.block_number),
+            gas_used: self.gas_used.as_u64().into(),
+            gas_limit: self.gas_limit.as_u64().into(),
+            extra_data: self.extra_data.clone(),
+            logs_bloom: Some(self.logs_bloom),
+            timestamp: self.timestamp.as_u64().into(),
+            mix_hash: Some(self.prev_randao),
+            base_fee_per_gas: Some(self.base_fee_per_gas),
+            blob_gas_used: self.blob_gas_used.map(|gas| gas.as_u64().into()),
+            excess_blob_gas: self.excess_blob_gas.map(|gas| gas.as_u64().into()),
+            withdrawals: self.withdrawals.clone(),
+            parent_beacon_block_root: self.parent_beacon_block_root,
+            transactions,
+            ..Default::default()
+        })
+    }
+}
+
+// an account which isn't the same in the claimed state and the computed one. storage has the
+// proven slots which differ, as (claimed, computed).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DivergentAccount {
+    pub address: Address,
+    pub claimed: AccountData,
+    pub computed: AccountData,
+    pub storage: BTreeMap<U256, (U256, U256)>,
+}
+
+// a payload run against the state before it. changes are what the payload wrote, against
+// the parent state.
+#[derive(Clone, Debug)]
+pub struct PayloadCheck {
+    pub claimed_root: H256,
+    pub report: BlockReport,
+    pub changes: StateDiff,
+    post_state: StateTrie,
+}
+
+impl PayloadCheck {
+    pub fn root_matches(&self) -> bool {
+        self.report.state_root == self.claimed_root
+    }
+
+    pub fn computed_root(&self) -> H256 {
+        self.report.state_root
+    }
+
+    pub fn post_state(&self) -> &StateTrie {
+        &self.post_state
+    }
+
+    // which accounts the claimed root disagrees on, from proofs against it, e.g. eth_getProof
+    // at the proposed block for the addresses and slots in `changes`. proofs which don't
+    // verify against the claimed root fail.
+    pub fn divergent_accounts(
+        &self,
+        proofs: Vec<EIP1186ProofResponse>,
+    ) -> Result<Vec<DivergentAccount>, Error> {
+        let mut claimed_state = StateTrie::from_root(self.claimed_root);
+        let mut keys = Vec::new();
+        for proof in proofs {
+            keys.push((
+                proof.address,
+                proof
+                    .storage_proof
+                    .iter()
+                    .map(|slot| slot.key)
+                    .collect::<Vec<_>>(),
+            ));
+            claimed_state.load_proof(proof)?;
+        }
+
+        let mut divergent = Vec::new();
+        for (address, slots) in keys {
+            let claimed = claimed_state.account_trie.get(address)?;
+            let computed = self.post_state.account_trie.get(address)?;
+            let mut storage = BTreeMap::new();
+            for slot in slots {
+                let claimed_value = claimed_state.storage_value(claimed.storage_root, slot)?;
+                let computed_value = self.post_state.storage_value(computed.storage_root, slot)?;
+                if claimed_value != computed_value {
+                    storage.insert(slot, (claimed_value, computed_value));
+                }
+            }
+            if claimed != computed || !storage.is_empty() {
+                divergent.push(DivergentAccount {
+                    address,
+                    claimed,
+                    computed,
+                    storage,
+                });
+            }
+        }
+        Ok(divergent)
+    }
+}
+
+// re-executes a proposed payload against the witness of its parent state, under cancun
+// rules, and compares the state root it claims with the computed one. a wrong root isn't an
+// error, see root_matches and divergent_accounts.
+pub fn check_payload_root(
+    parent_witness: &Witness,
+    payload: &ExecutionPayload,
+) -> Result<PayloadCheck, Error> {
+    check_payload_root_with_spec(SpecId::CANCUN, parent_witness, payload)
+}
+
+pub fn check_payload_root_with_spec(
+    spec_id: SpecId,
+    parent_witness: &Witness,
+    payload: &ExecutionPayload,
+) -> Result<PayloadCheck, Error> {
+    let block = payload.to_block()?;
+    let parent = StateTrie::from_witness(parent_witness)?;
+    let mut post_state = parent.clone();
+    let mut touched = BTreeMap::new();
+    let report = post_state.execute_block(spec_id, &block, &mut touched)?;
+    let keys: Vec<(Address, Vec<U256>)> = touched
+        .into_iter()
+        .map(|(address, slots)| (address, slots.into_iter().collect()))
+        .collect();
+    Ok(PayloadCheck {
+        claimed_root: payload.state_root,
+        changes: post_state.diff(&parent, &keys)?,
+        report,
+        post_state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_payload_root, ExecutionPayload};
+    use crate::StateTrie;
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers_core::types::{
+        transaction::eip2718::TypedTransaction, Address, TransactionRequest, U256, U64,
+    };
+
+    #[test]
+    pub fn test_check_payload_root() {
+        let wallet = LocalWallet::from_bytes(&[0x11; 32]).unwrap();
+        let receiver = Address::from_low_u64_be(0x2000);
+        let mut parent = StateTrie::new();
+        parent
+            .account_trie
+            .set_balance(wallet.address(), U256::exp10(18))
+            .unwrap();
+        parent
+            .account_trie
+            .set_balance(receiver, U256::from(7))
+            .unwrap();
+        let witness = parent.witness().unwrap();
+
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(receiver)
+            .value(1000)
+            .gas(21_000)
+            .gas_price(0)
+            .nonce(0)
+            .chain_id(1)
+            .into();
+        let signature = wallet.sign_transaction_sync(&tx).unwrap();
+        let mut payload = ExecutionPayload {
+            block_number: U64::from(100),
+            gas_limit: U64::from(30_000_000),
+            timestamp: U64::from(1_720_000_000),
+            transactions: vec![tx.rlp_signed(&signature)],
+            excess_blob_gas: Some(U64::zero()),
+            ..Default::default()
+        };
+        let block = payload.to_block().unwrap();
+        assert_eq!(block.transactions[0].from, wallet.address());
+
+        // the camel case names of the engine api.
+        let json = serde_json::to_value(&payload).unwrap();
+        assert!(json.get("stateRoot").is_some());
+        assert_eq!(
+            serde_json::from_value::<ExecutionPayload>(json).unwrap(),
+            payload
+        );
+
+        let check = check_payload_root(&witness, &payload).unwrap();
+        assert!(!check.root_matches());
+        assert!(check.report.receipts[0].status == Some(true));
+        assert_eq!(
+            check.changes.accounts[&receiver].balance,
+            Some((U256::from(7), U256::from(1007)))
+        );
+        assert_eq!(check.changes.accounts.len(), 2);
+        payload.state_root = check.computed_root();
+        let check = check_payload_root(&witness, &payload).unwrap();
+        assert!(check.root_matches());
+
+        // a proposer which credited the receiver twice.
+        let mut claimed = check.post_state().clone();
+        claimed
+            .account_trie
+            .set_balance(receiver, U256::from(2007))
+            .unwrap();
+        payload.state_root = claimed.root().unwrap();
+        let check = check_payload_root(&witness, &payload).unwrap();
+        assert!(!check.root_matches());
+        let proofs = [wallet.address(), receiver]
+            .into_iter()
+            .map(|address| claimed.get_proof(address, vec![]).unwrap())
+            .collect();
+        let divergent = check.divergent_accounts(proofs).unwrap();
+        assert_eq!(divergent.len(), 1);
+        assert_eq!(divergent[0].address, receiver);
+        assert_eq!(divergent[0].claimed.balance, U256::from(2007));
+        assert_eq!(divergent[0].computed.balance, U256::from(1007));
+
+        // proofs have to be against the claimed root.
+        let stale = vec![parent.get_proof(receiver, vec![]).unwrap()];
+        assert!(check.divergent_accounts(stale).is_err());
+    }
+}
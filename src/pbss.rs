@@ -0,0 +1,155 @@
+use ethers_core::types::{Bytes, H256};
+
+use crate::{
+    hasher::TrieHasher,
+    nodes::LeafValue,
+    trie::{MptKey, Trie},
+    Error, StateTrie,
+};
+
+// key prefixes of geth's path based scheme (core/rawdb/schema.go).
+pub const PBSS_ACCOUNT_PREFIX: u8 = b'A';
+pub const PBSS_STORAGE_PREFIX: u8 = b'O';
+
+// "A" followed by the nibble path of the node in the account trie, one nibble per byte.
+pub fn pbss_account_key(path: &[u8]) -> Bytes {
+    let mut key = Vec::with_capacity(1 + path.len());
+    key.push(PBSS_ACCOUNT_PREFIX);
+    key.extend_from_slice(path);
+    key.into()
+}
+
+// "O", the hashed address owning the storage trie, then the nibble path in it.
+pub fn pbss_storage_key(owner: H256, path: &[u8]) -> Bytes {
+    let mut key = Vec::with_capacity(33 + path.len());
+    key.push(PBSS_STORAGE_PREFIX);
+    key.extend_from_slice(owner.as_bytes());
+    key.extend_from_slice(path);
+    key.into()
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    // every loaded node under its path scheme key, sorted by key. `owner` is None for the
+    // account trie and the hashed address for a storage trie.
+    pub fn pbss_nodes(&self, owner: Option<H256>) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let mut nodes = self
+            .loaded_nodes()?
+            .into_iter()
+            .map(|node| {
+                let key = match owner {
+                    Some(owner) => pbss_storage_key(owner, &node.path),
+                    None => pbss_account_key(&node.path),
+                };
+                Ok((key, node.node_data.to_raw_rlp()?))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        nodes.sort();
+        Ok(nodes)
+    }
+}
+
+impl StateTrie {
+    // the loaded account and storage nodes as geth path scheme database entries, ready to be
+    // written to a pbss datadir. storage tries are listed once per account using them.
+    pub fn pbss_nodes(&self) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let mut nodes = self.account_trie.pbss_nodes(None)?;
+        for (address_hash, storage_trie) in self.storage_tries_by_hash()? {
+            nodes.extend(storage_trie.pbss_nodes(Some(address_hash))?);
+        }
+        nodes.sort();
+        Ok(nodes)
+    }
+
+    // keys of the loaded nodes which the database has with a different value or not at all,
+    // `lookup` reads a path scheme key from it.
+    pub fn pbss_mismatches(
+        &self,
+        lookup: impl Fn(&[u8]) -> Result<Option<Bytes>, Error>,
+    ) -> Result<Vec<Bytes>, Error> {
+        let mut mismatches = Vec::new();
+        for (key, raw) in self.pbss_nodes()? {
+            if lookup(&key)?.as_ref() != Some(&raw) {
+                mismatches.push(key);
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{pbss_account_key, pbss_storage_key};
+    use crate::{KeccakHasher, StateTrie, TrieHasher};
+    use ethers_core::{
+        types::{Address, Bytes, H256, U256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_pbss_keys() {
+        assert_eq!(pbss_account_key(&[]), Bytes::from(b"A".to_vec()));
+        assert_eq!(
+            pbss_account_key(&[0x1, 0xf]),
+            Bytes::from(vec![b'A', 0x1, 0xf])
+        );
+        let owner = H256::repeat_byte(0xab);
+        let key = pbss_storage_key(owner, &[0x3]);
+        assert_eq!(key[0], b'O');
+        assert_eq!(&key[1..33], owner.as_bytes());
+        assert_eq!(&key[33..], &[0x3]);
+    }
+
+    #[test]
+    pub fn test_pbss_nodes() {
+        let mut state_trie = StateTrie::new();
+        for i in 1..20u64 {
+            let address = Address::from_low_u64_be(i);
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+        }
+        let contract = Address::from_low_u64_be(3);
+        for slot in 0..10u64 {
+            state_trie
+                .set_storage_value(contract, U256::from(slot), U256::from(slot + 1))
+                .unwrap();
+        }
+
+        let nodes = state_trie.pbss_nodes().unwrap();
+        // the root node is under the bare prefix, and every value hashes to a loaded node.
+        let root_raw = state_trie
+            .get_raw_node(state_trie.root().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(nodes.contains(&(pbss_account_key(&[]), root_raw)));
+        let owner = H256::from(keccak256(contract));
+        let storage_count = nodes
+            .iter()
+            .filter(|(key, _)| key.starts_with(&pbss_storage_key(owner, &[])))
+            .count();
+        assert!(storage_count > 1);
+        for (_, raw) in nodes.iter() {
+            assert!(state_trie
+                .get_raw_node(KeccakHasher::hash(raw))
+                .unwrap()
+                .is_some());
+        }
+
+        // a database with the same nodes has no mismatches, one with a stale node has one.
+        let mut database = nodes.into_iter().collect::<HashMap<_, _>>();
+        assert!(state_trie
+            .pbss_mismatches(|key| Ok(database.get(key).cloned()))
+            .unwrap()
+            .is_empty());
+        database.insert(pbss_account_key(&[]), Bytes::from(vec![0xc0]));
+        assert_eq!(
+            state_trie
+                .pbss_mismatches(|key| Ok(database.get(key).cloned()))
+                .unwrap(),
+            vec![pbss_account_key(&[])]
+        );
+    }
+}
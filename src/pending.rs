@@ -0,0 +1,198 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use revm::primitives::{Env, SpecId, TxEnv};
+
+use crate::{Error, StateTrie, TransactionOutcome};
+
+// a mempool transaction run on top of the pending state's base, on its own.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub tx: TxEnv,
+    pub outcome: TransactionOutcome,
+}
+
+// candidate transactions staged on top of the latest canonical state, for mempool simulation.
+// the base is shared and never written to: every candidate runs on a copy of it, and
+// combining candidates runs them again in order on a fresh copy, since one candidate's
+// effects change what the next one does. e.g. on VersionedStateTrie::snapshot().
+#[derive(Clone, Debug)]
+pub struct PendingState {
+    base: Arc<StateTrie>,
+    env: Env,
+    spec_id: SpecId,
+    candidates: BTreeMap<u64, Candidate>,
+    next_id: u64,
+}
+
+impl PendingState {
+    // `env` is the block the candidates would go into, its tx is ignored.
+    pub fn new(base: Arc<StateTrie>, env: Env) -> Self {
+        Self::with_spec(base, env, SpecId::CANCUN)
+    }
+
+    pub fn with_spec(base: Arc<StateTrie>, env: Env, spec_id: SpecId) -> Self {
+        PendingState {
+            base,
+            env,
+            spec_id,
+            candidates: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn base(&self) -> &Arc<StateTrie> {
+        &self.base
+    }
+
+    // runs the transaction on the base and keeps it, returns its id. a transaction which is
+    // invalid on the base isn't staged.
+    pub fn stage(&mut self, tx: TxEnv) -> Result<u64, Error> {
+        let outcome = self.run(std::slice::from_ref(&tx))?.1.remove(0);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.candidates.insert(id, Candidate { tx, outcome });
+        Ok(id)
+    }
+
+    pub fn candidate(&self, id: u64) -> Option<&Candidate> {
+        self.candidates.get(&id)
+    }
+
+    // by id, which is the order they were staged in.
+    pub fn candidates(&self) -> impl Iterator<Item = (u64, &Candidate)> {
+        self.candidates
+            .iter()
+            .map(|(id, candidate)| (*id, candidate))
+    }
+
+    pub fn discard(&mut self, id: u64) -> Option<Candidate> {
+        self.candidates.remove(&id)
+    }
+
+    // the state after the candidates, in the given order, and their outcomes. fails when one
+    // of them is invalid after the ones before it, e.g. two with the same nonce.
+    pub fn combine(&self, ids: &[u64]) -> Result<(StateTrie, Vec<TransactionOutcome>), Error> {
+        let txs = ids
+            .iter()
+            .map(|id| {
+                self.candidates
+                    .get(id)
+                    .map(|candidate| candidate.tx.clone())
+                    .ok_or(Error::Usage("no candidate with this id"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.run(&txs)
+    }
+
+    // moves onto a new canonical state, e.g. after a block, and stages the candidates on it
+    // again. the ones which are invalid now, e.g. because they were included, are dropped
+    // and returned with why.
+    pub fn rebase(&mut self, base: Arc<StateTrie>, env: Env) -> Vec<(Candidate, Error)> {
+        self.base = base;
+        self.env = env;
+        let mut dropped = Vec::new();
+        for (id, candidate) in std::mem::take(&mut self.candidates) {
+            match self.run(std::slice::from_ref(&candidate.tx)) {
+                Ok((_, mut outcomes)) => {
+                    let outcome = outcomes.remove(0);
+                    self.candidates.insert(
+                        id,
+                        Candidate {
+                            tx: candidate.tx,
+                            outcome,
+                        },
+                    );
+                }
+                Err(err) => dropped.push((candidate, err)),
+            }
+        }
+        dropped
+    }
+
+    fn run(&self, txs: &[TxEnv]) -> Result<(StateTrie, Vec<TransactionOutcome>), Error> {
+        let mut state_trie = StateTrie::clone(&self.base);
+        let outcomes = txs
+            .iter()
+            .map(|tx| {
+                state_trie.apply_transaction_with_spec(self.spec_id, self.env.clone(), tx.clone())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((state_trie, outcomes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PendingState;
+    use crate::StateTrie;
+    use ethers_core::types::{Address, U256};
+    use revm::primitives::{self, Env, TransactTo, TxEnv};
+    use std::sync::Arc;
+
+    fn transfer(from: Address, to: Address, nonce: u64) -> TxEnv {
+        TxEnv {
+            caller: primitives::Address::from(from.0),
+            transact_to: TransactTo::Call(primitives::Address::from(to.0)),
+            value: primitives::U256::from(10),
+            gas_limit: 21_000,
+            gas_price: primitives::U256::ZERO,
+            nonce: Some(nonce),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn test_pending_state() {
+        let alice = Address::from_low_u64_be(0xa);
+        let bob = Address::from_low_u64_be(0xb);
+        let carol = Address::from_low_u64_be(0xc);
+        let mut state_trie = StateTrie::new();
+        for address in [alice, bob] {
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(100))
+                .unwrap();
+        }
+        let base = Arc::new(state_trie);
+        let root = base.root();
+        let mut pending = PendingState::new(base.clone(), Env::default());
+
+        let first = pending.stage(transfer(alice, carol, 0)).unwrap();
+        let second = pending.stage(transfer(bob, carol, 0)).unwrap();
+        // the same nonce again is fine against the base on its own.
+        let same_nonce = pending.stage(transfer(alice, bob, 0)).unwrap();
+        // not enough balance on the base.
+        assert!(pending.stage(transfer(carol, alice, 0)).is_err());
+        assert_eq!(pending.candidates().count(), 3);
+        assert_ne!(
+            pending.candidate(first).unwrap().outcome.root,
+            root.unwrap()
+        );
+        assert_eq!(base.root(), root);
+
+        let (combined, outcomes) = pending.combine(&[first, second]).unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[1].root, combined.root().unwrap());
+        assert_eq!(
+            combined.account_trie.get(carol).unwrap().balance,
+            U256::from(20)
+        );
+        assert!(pending.combine(&[first, same_nonce]).is_err());
+        assert!(pending.discard(same_nonce).is_some());
+        assert!(pending.combine(&[same_nonce]).is_err());
+
+        // after a block with the first one, it is dropped and the second runs on the new state.
+        let mut next = StateTrie::clone(&base);
+        next.apply_transaction(Env::default(), transfer(alice, carol, 0))
+            .unwrap();
+        let dropped = pending.rebase(Arc::new(next), Env::default());
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(pending.candidates().count(), 1);
+        let (after, _) = pending.combine(&[second]).unwrap();
+        assert_eq!(
+            after.account_trie.get(carol).unwrap().balance,
+            U256::from(20)
+        );
+        assert_eq!(base.root(), root);
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use ethers_core::types::{Bytes, H256};
+
+use crate::{
+    nibbles::Nibbles, nodes::NodeData, utils::nibbles_to_hash, Error, KeccakHasher, StateTrie,
+    TrieHasher,
+};
+
+// content types of the portal state network, see
+// https://github.com/ethereum/portal-network-specs/blob/master/legacy/state/state-network.md
+const ACCOUNT_TRIE_NODE: u8 = 0x20;
+const CONTRACT_STORAGE_TRIE_NODE: u8 = 0x21;
+const CONTRACT_BYTECODE: u8 = 0x22;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortalContentKey {
+    AccountTrieNode {
+        path: Nibbles,
+        node_hash: H256,
+    },
+    ContractStorageTrieNode {
+        address_hash: H256,
+        path: Nibbles,
+        node_hash: H256,
+    },
+    ContractBytecode {
+        address_hash: H256,
+        code_hash: H256,
+    },
+}
+
+impl PortalContentKey {
+    // selector byte followed by the ssz encoded key.
+    pub fn encode(&self) -> Bytes {
+        let mut out = Vec::new();
+        match self {
+            PortalContentKey::AccountTrieNode { path, node_hash } => {
+                out.push(ACCOUNT_TRIE_NODE);
+                out.extend(ssz_container(&[
+                    SszField::Variable(encode_nibbles(path)),
+                    SszField::Fixed(node_hash.as_bytes().to_vec()),
+                ]));
+            }
+            PortalContentKey::ContractStorageTrieNode {
+                address_hash,
+                path,
+                node_hash,
+            } => {
+                out.push(CONTRACT_STORAGE_TRIE_NODE);
+                out.extend(ssz_container(&[
+                    SszField::Fixed(address_hash.as_bytes().to_vec()),
+                    SszField::Variable(encode_nibbles(path)),
+                    SszField::Fixed(node_hash.as_bytes().to_vec()),
+                ]));
+            }
+            PortalContentKey::ContractBytecode {
+                address_hash,
+                code_hash,
+            } => {
+                out.push(CONTRACT_BYTECODE);
+                out.extend(address_hash.as_bytes());
+                out.extend(code_hash.as_bytes());
+            }
+        }
+        Bytes::from(out)
+    }
+}
+
+// an offer, i.e. the content key along with the value carrying the proof from the state root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortalContent {
+    pub key: PortalContentKey,
+    pub value: Bytes,
+}
+
+enum SszField {
+    Fixed(Vec<u8>),
+    Variable(Vec<u8>),
+}
+
+fn ssz_container(fields: &[SszField]) -> Vec<u8> {
+    let fixed_len: usize = fields
+        .iter()
+        .map(|field| match field {
+            SszField::Fixed(bytes) => bytes.len(),
+            SszField::Variable(_) => 4,
+        })
+        .sum();
+
+    let mut fixed = Vec::with_capacity(fixed_len);
+    let mut variable: Vec<u8> = Vec::new();
+    for field in fields {
+        match field {
+            SszField::Fixed(bytes) => fixed.extend(bytes),
+            SszField::Variable(bytes) => {
+                fixed.extend(((fixed_len + variable.len()) as u32).to_le_bytes());
+                variable.extend(bytes);
+            }
+        }
+    }
+    fixed.extend(variable);
+    fixed
+}
+
+// List[ByteList, N] is a list of variable size items: offsets first, then the items.
+fn ssz_trie_proof(proof: &[Bytes]) -> Vec<u8> {
+    ssz_container(
+        &proof
+            .iter()
+            .map(|node| SszField::Variable(node.to_vec()))
+            .collect::<Vec<_>>(),
+    )
+}
+
+// packed nibbles, the first byte is 0x00 for even length and 0x1N for odd length.
+fn encode_nibbles(path: &Nibbles) -> Vec<u8> {
+    let nibbles = path.to_u4_vec();
+    let (mut out, rest) = if nibbles.len() % 2 == 1 {
+        (vec![0x10 | nibbles[0]], &nibbles[1..])
+    } else {
+        (vec![0x00], &nibbles[..])
+    };
+    out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+impl StateTrie {
+    // offers for every loaded trie node, plus the bytecode of loaded accounts whose code is
+    // in codes (keyed by code hash).
+    pub fn portal_content(
+        &self,
+        block_hash: H256,
+        codes: &HashMap<H256, Bytes>,
+    ) -> Result<Vec<PortalContent>, Error> {
+        let mut content = Vec::new();
+        for account_node in self.account_trie.loaded_nodes()? {
+            content.push(PortalContent {
+                key: PortalContentKey::AccountTrieNode {
+                    path: Nibbles::from_u4_vec(account_node.path.clone())?,
+                    node_hash: account_node.hash,
+                },
+                value: Bytes::from(ssz_container(&[
+                    SszField::Variable(ssz_trie_proof(&account_node.proof)),
+                    SszField::Fixed(block_hash.as_bytes().to_vec()),
+                ])),
+            });
+
+            let (key, account_data) = match &account_node.node_data {
+                NodeData::Leaf { key, value } => (key, value),
+                _ => continue,
+            };
+            let mut leaf_path = account_node.path.clone();
+            leaf_path.extend(key.to_u4_vec());
+            let address_hash = nibbles_to_hash(&leaf_path)?;
+
+            if let Some(code) = codes.get(&account_data.code_hash) {
+                content.push(PortalContent {
+                    key: PortalContentKey::ContractBytecode {
+                        address_hash,
+                        code_hash: account_data.code_hash,
+                    },
+                    value: Bytes::from(ssz_container(&[
+                        SszField::Variable(code.to_vec()),
+                        SszField::Variable(ssz_trie_proof(&account_node.proof)),
+                        SszField::Fixed(block_hash.as_bytes().to_vec()),
+                    ])),
+                });
+            }
+
+            if account_data.storage_root == KeccakHasher::empty_root() {
+                continue;
+            }
+            let storage_trie = match self.loaded_storage_trie(&account_data.storage_root) {
+                Some(storage_trie) => storage_trie,
+                None => continue,
+            };
+            for storage_node in storage_trie.loaded_nodes()? {
+                content.push(PortalContent {
+                    key: PortalContentKey::ContractStorageTrieNode {
+                        address_hash,
+                        path: Nibbles::from_u4_vec(storage_node.path)?,
+                        node_hash: storage_node.hash,
+                    },
+                    value: Bytes::from(ssz_container(&[
+                        SszField::Variable(ssz_trie_proof(&storage_node.proof)),
+                        SszField::Variable(ssz_trie_proof(&account_node.proof)),
+                        SszField::Fixed(block_hash.as_bytes().to_vec()),
+                    ])),
+                });
+            }
+        }
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{encode_nibbles, PortalContentKey};
+    use crate::{state_trie::StorageTrie, Nibbles, StateTrie};
+    use ethers_core::{
+        types::{Address, Bytes, H256, U256},
+        utils::{hex, keccak256},
+    };
+
+    #[test]
+    pub fn test_portal_nibbles_encoding() {
+        assert_eq!(
+            encode_nibbles(&Nibbles::from_u4_vec(vec![1, 2, 0, 1]).unwrap()),
+            vec![0x00, 0x12, 0x01]
+        );
+        assert_eq!(
+            encode_nibbles(&Nibbles::from_u4_vec(vec![1, 2, 0]).unwrap()),
+            vec![0x11, 0x20]
+        );
+        assert_eq!(
+            encode_nibbles(&Nibbles::from_u4_vec(vec![]).unwrap()),
+            vec![0x00]
+        );
+    }
+
+    #[test]
+    pub fn test_portal_account_trie_node_key() {
+        let node_hash: H256 = "0xb8be7903aee73b8f6a59cd44a1f52c62148e1f376c0dfa1f5f773a98666efc2b"
+            .parse()
+            .unwrap();
+        let key = PortalContentKey::AccountTrieNode {
+            path: Nibbles::from_u4_vec(vec![1, 2, 0, 1]).unwrap(),
+            node_hash,
+        };
+        assert_eq!(
+            hex::encode(key.encode()),
+            "2024000000b8be7903aee73b8f6a59cd44a1f52c62148e1f376c0dfa1f5f773a98666efc2b001201"
+        );
+    }
+
+    #[test]
+    pub fn test_portal_content_from_state_trie() {
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        let address = Address::from_low_u64_be(1);
+        let code = Bytes::from(vec![0x60, 0x00]);
+        let code_hash = H256::from(keccak256(&code));
+        state_trie
+            .account_trie
+            .set_code_hash(address, code_hash)
+            .unwrap();
+        state_trie
+            .set_storage_value(address, U256::from(1), U256::from(2))
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_balance(Address::from_low_u64_be(2), U256::from(1))
+            .unwrap();
+
+        let content = state_trie
+            .portal_content(H256::zero(), &HashMap::from([(code_hash, code)]))
+            .unwrap();
+
+        let address_hash = H256::from(keccak256(address));
+        let mut account_nodes = 0;
+        let mut storage_nodes = 0;
+        let mut bytecodes = 0;
+        for item in content {
+            match item.key {
+                PortalContentKey::AccountTrieNode { .. } => account_nodes += 1,
+                PortalContentKey::ContractStorageTrieNode {
+                    address_hash: hash, ..
+                } => {
+                    assert_eq!(hash, address_hash);
+                    storage_nodes += 1
+                }
+                PortalContentKey::ContractBytecode {
+                    address_hash: hash, ..
+                } => {
+                    assert_eq!(hash, address_hash);
+                    bytecodes += 1
+                }
+            }
+        }
+        // branch with two account leaves, and a single leaf storage trie.
+        assert_eq!(account_nodes, 3);
+        assert_eq!(storage_nodes, 1);
+        assert_eq!(bytecodes, 1);
+    }
+}
@@ -0,0 +1,207 @@
+use std::future::Future;
+
+use ethers_core::types::{Address, BlockId, Bytes, EIP1186ProofResponse, H256};
+
+use crate::{
+    hasher::TrieHasher,
+    nodes::LeafValue,
+    trie::{MptKey, Trie},
+    Error, StateTrie,
+};
+
+// where proofs and trie nodes come from. this is all the loading helpers need, so any
+// async runtime or transport can be used by implementing it. with the `provider` feature
+// it is implemented for every ethers `Middleware`.
+pub trait ProofProvider {
+    // EIP-1186 proof for an account and some of its storage slots.
+    fn fetch_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        block: Option<BlockId>,
+    ) -> impl Future<Output = Result<EIP1186ProofResponse, Error>> + Send;
+
+    // raw rlp of a trie node by its hash.
+    fn fetch_node(&self, hash: H256) -> impl Future<Output = Result<Bytes, Error>> + Send;
+}
+
+impl StateTrie {
+    // downloads EIP-1186 proofs for the given accounts and their slots and loads them.
+    pub async fn fetch_proofs<P: ProofProvider>(
+        &mut self,
+        provider: &P,
+        accounts: Vec<(Address, Vec<H256>)>,
+        block: Option<BlockId>,
+    ) -> Result<(), Error> {
+        for (address, slots) in accounts {
+            let proof = provider.fetch_proof(address, slots, block).await?;
+            self.load_proof(proof)?;
+        }
+        Ok(())
+    }
+
+    // same as fetch_proofs, at the block of the trie's anchor, so the proofs can't come from
+    // any other state than the verified root.
+    pub async fn fetch_anchored_proofs<P: ProofProvider>(
+        &mut self,
+        provider: &P,
+        accounts: Vec<(Address, Vec<H256>)>,
+    ) -> Result<(), Error> {
+        let anchor = self
+            .anchor()
+            .cloned()
+            .ok_or(Error::Usage("trie has no anchor"))?;
+        for (address, slots) in accounts {
+            let proof = provider
+                .fetch_proof(address, slots, Some(anchor.block_id()))
+                .await?;
+            self.load_anchored_proof(&anchor, proof)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    // fetches the nodes on the path to the key one by one, until the key resolves.
+    pub async fn fetch_path<P: ProofProvider>(
+        &mut self,
+        provider: &P,
+        key: K,
+    ) -> Result<(), Error> {
+        while let Some(hash) = self.missing_node(key.clone())? {
+            let raw = provider.fetch_node(hash).await?;
+            self.insert_fetched_node(hash, raw)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::ProofProvider;
+    use crate::{state_trie::StorageTrie, Anchor, Error, StateTrie};
+    use ethers_core::types::{Address, BlockId, Bytes, EIP1186ProofResponse, H256, U256};
+
+    // serves everything from an in memory trie.
+    struct MockProvider {
+        state_trie: StateTrie,
+        nodes: HashMap<H256, Bytes>,
+    }
+
+    impl ProofProvider for MockProvider {
+        async fn fetch_proof(
+            &self,
+            address: Address,
+            _slots: Vec<H256>,
+            _block: Option<BlockId>,
+        ) -> Result<EIP1186ProofResponse, Error> {
+            let account = self.state_trie.account_trie.get(address)?;
+            Ok(EIP1186ProofResponse {
+                address,
+                balance: account.balance,
+                code_hash: account.code_hash,
+                nonce: account.nonce.as_u64().into(),
+                storage_hash: account.storage_root,
+                account_proof: self.state_trie.account_trie.get_proof(address)?,
+                storage_proof: vec![],
+            })
+        }
+
+        async fn fetch_node(&self, hash: H256) -> Result<Bytes, Error> {
+            self.nodes
+                .get(&hash)
+                .cloned()
+                .ok_or(Error::ProviderError("unknown node".to_string()))
+        }
+    }
+
+    fn mock_provider() -> MockProvider {
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        for i in 1..20u64 {
+            state_trie
+                .account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        MockProvider {
+            nodes: state_trie.witness().unwrap().node_map(),
+            state_trie,
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_proofs_with_custom_provider() {
+        let provider = mock_provider();
+        let mut state_trie = StateTrie::from_root(provider.state_trie.root().unwrap());
+        state_trie
+            .fetch_proofs(&provider, vec![(Address::from_low_u64_be(7), vec![])], None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            state_trie
+                .account_trie
+                .get(Address::from_low_u64_be(7))
+                .unwrap()
+                .balance,
+            U256::from(7)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_anchored_proofs() {
+        let provider = mock_provider();
+        let mut state_trie = StateTrie::from_root(provider.state_trie.root().unwrap());
+        let accounts = vec![(Address::from_low_u64_be(7), vec![])];
+        assert!(state_trie
+            .fetch_anchored_proofs(&provider, accounts.clone())
+            .await
+            .is_err());
+
+        let mut state_trie = StateTrie::from_anchor(Anchor {
+            state_root: provider.state_trie.root().unwrap(),
+            source: "test".to_string(),
+            ..Anchor::default()
+        });
+        state_trie
+            .fetch_anchored_proofs(&provider, accounts)
+            .await
+            .unwrap();
+        assert_eq!(state_trie.anchor().unwrap().source, "test");
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_path_node_by_node() {
+        let provider = mock_provider();
+        let mut state_trie = StateTrie::from_root(provider.state_trie.root().unwrap());
+        let address = Address::from_low_u64_be(3);
+        assert!(state_trie.account_trie.get(address).is_err());
+
+        state_trie
+            .account_trie
+            .fetch_path(&provider, address)
+            .await
+            .unwrap();
+        assert_eq!(
+            state_trie.account_trie.get(address).unwrap().balance,
+            U256::from(3)
+        );
+
+        // empty key also resolves, through the branch where it is missing.
+        state_trie
+            .account_trie
+            .fetch_path(&provider, Address::from_low_u64_be(1000))
+            .await
+            .unwrap();
+        assert_eq!(
+            state_trie
+                .account_trie
+                .get(Address::from_low_u64_be(1000))
+                .unwrap()
+                .balance,
+            U256::zero()
+        );
+    }
+}
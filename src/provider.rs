@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethers_core::types::{
+    Address, BigEndianHash, Block, BlockId, BlockNumber, Bytes, EIP1186ProofResponse, PreStateMode,
+    H256, U256,
+};
+use ethers_providers::{Middleware, MiddlewareError};
+use serde_json::{json, Value};
+
+use crate::{
+    beacon_root_slots, concurrent::get_storage, Error, ProofProvider, StateTrie,
+    BEACON_ROOTS_ADDRESS,
+};
+
+// json-rpc error codes of rate limits, 429 like http and infura's limit exceeded.
+const RATE_LIMIT_CODES: [i64; 2] = [429, -32005];
+
+// an error response from the node is its answer to the request, e.g. an unsupported method,
+// asking again doesn't change it unless it is a rate limit. everything else, like a timeout
+// or a dropped connection, is worth another try.
+pub(crate) fn provider_error(err: impl MiddlewareError) -> Error {
+    match err.as_error_response() {
+        Some(response) if !RATE_LIMIT_CODES.contains(&response.code) => {
+            Error::ProviderRejected(err.to_string())
+        }
+        _ => Error::ProviderError(err.to_string()),
+    }
+}
+
+impl<M: Middleware> ProofProvider for M {
+    async fn fetch_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        block: Option<BlockId>,
+    ) -> Result<EIP1186ProofResponse, Error> {
+        self.get_proof(address, slots, block)
+            .await
+            .map_err(provider_error)
+    }
+
+    // nodes by hash are only served by the debug namespace, e.g. geth's debug_dbGet.
+    async fn fetch_node(&self, hash: H256) -> Result<Bytes, Error> {
+        self.provider()
+            .request("debug_dbGet", [hash])
+            .await
+            .map_err(provider_error)
+    }
+}
+
+// the slots of an account a block reads, and its code if it has any.
+pub(crate) type TracedAccounts = BTreeMap<Address, (BTreeSet<H256>, Option<Bytes>)>;
+
+// the accounts, slots and code a block reads, from its prestate traces. geth wraps each
+// transaction's trace in {txHash, result}, other clients return the bare frames.
+fn prestate_accounts(traces: &Value) -> Result<TracedAccounts, Error> {
+    let traces = traces
+        .as_array()
+        .ok_or(Error::InternalError("block trace is not a list"))?;
+    let mut accounts = TracedAccounts::new();
+    for trace in traces {
+        let frame = trace.get("result").unwrap_or(trace);
+        let prestate: PreStateMode = serde_json::from_value(frame.clone())?;
+        for (address, state) in prestate.0 {
+            let (slots, code) = accounts.entry(address).or_default();
+            slots.extend(state.storage.unwrap_or_default().into_keys());
+            if let Some(state_code) = state.code.filter(|code| code != "0x" && !code.is_empty()) {
+                let state_code = state_code
+                    .parse::<Bytes>()
+                    .map_err(|_| Error::InternalError("code in the trace is not hex"))?;
+                *code = Some(state_code);
+            }
+        }
+    }
+    Ok(accounts)
+}
+
+// everything a block touches: what its prestate traces (debug_traceBlockByNumber) have, and
+// the beacon root slots and withdrawal recipients, which are written outside of the
+// transactions.
+pub(crate) async fn block_accounts<M: Middleware, TX>(
+    provider: &M,
+    block: &Block<TX>,
+) -> Result<TracedAccounts, Error> {
+    let number = block
+        .number
+        .ok_or(Error::InternalError("block has no number"))?;
+    let traces: Value = provider
+        .provider()
+        .request(
+            "debug_traceBlockByNumber",
+            (
+                BlockNumber::Number(number),
+                json!({ "tracer": "prestateTracer" }),
+            ),
+        )
+        .await
+        .map_err(provider_error)?;
+    let mut accounts = prestate_accounts(&traces)?;
+    if block.parent_beacon_block_root.is_some() {
+        let (timestamp_slot, root_slot) = beacon_root_slots(block.timestamp.as_u64());
+        accounts.entry(BEACON_ROOTS_ADDRESS).or_default().0.extend([
+            H256::from_uint(&timestamp_slot),
+            H256::from_uint(&root_slot),
+        ]);
+    }
+    for withdrawal in block.withdrawals.iter().flatten() {
+        accounts.entry(withdrawal.address).or_default();
+    }
+    Ok(accounts)
+}
+
+impl StateTrie {
+    // the state before block `number`, with everything it touches (see block_accounts) and
+    // the code of the called contracts, from proofs at the parent block. the blocks are
+    // fetched by hash, so a reorg in between fails the proofs instead of mixing states.
+    // BLOCKHASH isn't traced, set_block_hashes for that.
+    pub async fn from_block_trace<M: Middleware>(
+        provider: &M,
+        number: u64,
+    ) -> Result<StateTrie, Error> {
+        if number == 0 {
+            return Err(Error::Usage("genesis has no parent state"));
+        }
+        let block = provider
+            .get_block(number)
+            .await
+            .map_err(provider_error)?
+            .ok_or(Error::ProviderError(format!("block {} not found", number)))?;
+        let parent = provider
+            .get_block(block.parent_hash)
+            .await
+            .map_err(provider_error)?
+            .ok_or(Error::ProviderError(format!(
+                "block {} not found",
+                number - 1
+            )))?;
+
+        let accounts = block_accounts(provider, &block).await?;
+        let mut state_trie = StateTrie::from_root(parent.state_root);
+        state_trie
+            .fetch_missing(provider, accounts, block.parent_hash)
+            .await?;
+        Ok(state_trie)
+    }
+
+    // proofs at block `block_hash`, which has to be the state of the trie, for the accounts
+    // and slots the trie can't read yet, and the code it doesn't have.
+    pub(crate) async fn fetch_missing<M: Middleware>(
+        &mut self,
+        provider: &M,
+        accounts: TracedAccounts,
+        block_hash: H256,
+    ) -> Result<(), Error> {
+        let mut missing = Vec::new();
+        let mut codes = Vec::new();
+        for (address, (slots, code)) in accounts {
+            let slots: Vec<H256> = if self.account_trie.get(address).is_err() {
+                slots.into_iter().collect()
+            } else {
+                slots
+                    .into_iter()
+                    .filter(|slot| {
+                        get_storage(self, address, U256::from_big_endian(slot.as_bytes())).is_err()
+                    })
+                    .collect()
+            };
+            if !slots.is_empty() || self.account_trie.get(address).is_err() {
+                missing.push((address, slots));
+            }
+            if let Some(code) = code {
+                codes.push((address, code));
+            }
+        }
+        self.fetch_proofs(provider, missing, Some(BlockId::Hash(block_hash)))
+            .await?;
+        for (address, code) in codes {
+            let code_hash = self.account_trie.get(address)?.code_hash;
+            if self.code_db().get(code_hash).is_none() {
+                self.code_db_mut().insert_checked(code_hash, code)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prestate_accounts, provider_error};
+    use crate::Error;
+    use ethers_core::types::{Address, Bytes, H256};
+    use ethers_providers::{HttpClientError, JsonRpcError, ProviderError};
+    use serde_json::json;
+
+    fn error_response(code: i64) -> ProviderError {
+        ProviderError::JsonRpcClientError(Box::new(HttpClientError::JsonRpcError(JsonRpcError {
+            code,
+            message: "error".to_string(),
+            data: None,
+        })))
+    }
+
+    #[test]
+    pub fn test_provider_error() {
+        assert!(matches!(
+            provider_error(error_response(-32601)),
+            Error::ProviderRejected(_)
+        ));
+        assert!(provider_error(error_response(429)).is_transient());
+        assert!(provider_error(ProviderError::CustomError("timeout".to_string())).is_transient());
+    }
+
+    #[test]
+    pub fn test_prestate_accounts() {
+        let contract = Address::from_low_u64_be(0x20);
+        let sender = Address::from_low_u64_be(0x10);
+        let traces = json!([
+            {
+                "txHash": H256::zero(),
+                "result": {
+                    format!("{:?}", sender): { "balance": "0x100", "nonce": 1 },
+                    format!("{:?}", contract): {
+                        "balance": "0x0",
+                        "code": "0x600055",
+                        "storage": { format!("{:?}", H256::from_low_u64_be(1)): H256::zero() }
+                    }
+                }
+            },
+            {
+                format!("{:?}", contract): {
+                    "balance": "0x0",
+                    "code": "0x600055",
+                    "storage": { format!("{:?}", H256::from_low_u64_be(2)): H256::zero() }
+                }
+            }
+        ]);
+        let accounts = prestate_accounts(&traces).unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[&sender].0.len(), 0);
+        assert_eq!(accounts[&sender].1, None);
+        assert_eq!(
+            accounts[&contract].0,
+            [H256::from_low_u64_be(1), H256::from_low_u64_be(2)].into()
+        );
+        assert_eq!(
+            accounts[&contract].1,
+            Some(Bytes::from(vec![0x60, 0x00, 0x55]))
+        );
+        assert!(prestate_accounts(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use ethers_core::types::{Address, H256, U256};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBytes, PyInt},
+};
+
+use crate::{Error, StateTrie, Witness};
+
+fn py_error(err: Error) -> PyErr {
+    PyValueError::new_err(format!("{:?}", err))
+}
+
+fn parse_h256(value: &str) -> PyResult<H256> {
+    value
+        .parse()
+        .map_err(|_| py_error(Error::InternalError("invalid 32 byte hex")))
+}
+
+fn parse_address(value: &str) -> PyResult<Address> {
+    value
+        .parse()
+        .map_err(|_| py_error(Error::InternalError("invalid address")))
+}
+
+// python ints are arbitrary precision, go through 32 big endian bytes.
+fn to_u256(value: &Bound<'_, PyAny>) -> PyResult<U256> {
+    let bytes: Vec<u8> = value.call_method1("to_bytes", (32, "big"))?.extract()?;
+    Ok(U256::from_big_endian(&bytes))
+}
+
+fn to_py_int(py: Python<'_>, value: U256) -> PyResult<PyObject> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    Ok(py
+        .get_type::<PyInt>()
+        .call_method1("from_bytes", (PyBytes::new(py, &bytes), "big"))?
+        .unbind())
+}
+
+// python facing StateTrie. hashes and addresses are hex strings, numbers are ints.
+#[pyclass(name = "StateTrie")]
+pub struct PyStateTrie {
+    inner: StateTrie,
+}
+
+#[pymethods]
+impl PyStateTrie {
+    #[new]
+    fn new(root: &str) -> PyResult<Self> {
+        Ok(PyStateTrie {
+            inner: StateTrie::from_root(parse_h256(root)?),
+        })
+    }
+
+    #[staticmethod]
+    fn from_witness(witness: &[u8]) -> PyResult<Self> {
+        let witness = Witness::decode(witness.to_vec().into()).map_err(py_error)?;
+        Ok(PyStateTrie {
+            inner: StateTrie::from_witness(&witness).map_err(py_error)?,
+        })
+    }
+
+    // result of eth_getProof as json, or the whole rpc response.
+    fn load_proof(&mut self, proof: &str) -> PyResult<()> {
+        self.inner.load_proof_json_str(proof).map_err(py_error)
+    }
+
+    fn root(&self) -> Option<String> {
+        self.inner.root().map(|root| format!("{:?}", root))
+    }
+
+    fn get_balance(&self, py: Python<'_>, address: &str) -> PyResult<PyObject> {
+        let account = self
+            .inner
+            .account_trie
+            .get(parse_address(address)?)
+            .map_err(py_error)?;
+        to_py_int(py, account.balance)
+    }
+
+    fn get_nonce(&self, py: Python<'_>, address: &str) -> PyResult<PyObject> {
+        let account = self
+            .inner
+            .account_trie
+            .get(parse_address(address)?)
+            .map_err(py_error)?;
+        to_py_int(py, account.nonce)
+    }
+
+    fn get_storage(
+        &mut self,
+        py: Python<'_>,
+        address: &str,
+        slot: &Bound<'_, PyAny>,
+    ) -> PyResult<PyObject> {
+        let value = self
+            .inner
+            .get_storage_at(parse_address(address)?, to_u256(slot)?)
+            .map_err(py_error)?;
+        to_py_int(py, value)
+    }
+
+    fn set_balance(&mut self, address: &str, balance: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.inner
+            .account_trie
+            .set_balance(parse_address(address)?, to_u256(balance)?)
+            .map_err(py_error)
+    }
+
+    fn set_nonce(&mut self, address: &str, nonce: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.inner
+            .account_trie
+            .set_nonce(parse_address(address)?, to_u256(nonce)?)
+            .map_err(py_error)
+    }
+
+    fn set_storage(
+        &mut self,
+        address: &str,
+        slot: &Bound<'_, PyAny>,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        self.inner
+            .set_storage_value(parse_address(address)?, to_u256(slot)?, to_u256(value)?)
+            .map_err(py_error)
+    }
+
+    // canonical witness encoding of everything that is loaded.
+    fn witness<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let witness = self.inner.witness().map_err(py_error)?.encode();
+        Ok(PyBytes::new(py, &witness))
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        Ok(self.inner.to_json().map_err(py_error)?.to_string())
+    }
+}
+
+#[pymodule]
+fn partial_mpt(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyStateTrie>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PyStateTrie;
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+    use pyo3::{prelude::*, types::PyInt};
+
+    #[test]
+    pub fn test_python_state_trie() {
+        pyo3::prepare_freethreaded_python();
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        full.account_trie
+            .set_balance(address, U256::from(100))
+            .unwrap();
+        full.set_storage_value(address, U256::one(), U256::from(7))
+            .unwrap();
+        let proof = full
+            .get_proof(address, vec![H256::from_low_u64_be(1)])
+            .unwrap();
+
+        Python::with_gil(|py| {
+            let root = format!("{:?}", full.root().unwrap());
+            let mut trie = PyStateTrie::new(&root).unwrap();
+            trie.load_proof(&serde_json::to_string(&proof).unwrap())
+                .unwrap();
+            assert_eq!(trie.root(), Some(root));
+
+            let address = "0x0000000000000000000000000000000000000001";
+            let balance: u64 = trie.get_balance(py, address).unwrap().extract(py).unwrap();
+            assert_eq!(balance, 100);
+            let slot = 1u8.into_pyobject(py).unwrap();
+            let value: u64 = trie
+                .get_storage(py, address, slot.as_any())
+                .unwrap()
+                .extract(py)
+                .unwrap();
+            assert_eq!(value, 7);
+
+            // bigger than a u64, goes through the 32 byte conversion.
+            let big = py
+                .get_type::<PyInt>()
+                .call1(("1000000000000000000000000000000",))
+                .unwrap();
+            trie.set_balance(address, &big).unwrap();
+            full.account_trie
+                .set_balance(Address::from_low_u64_be(1), U256::exp10(30))
+                .unwrap();
+            assert_eq!(trie.root(), Some(format!("{:?}", full.root().unwrap())));
+            let balance = trie.get_balance(py, address).unwrap();
+            assert!(balance.bind(py).eq(&big).unwrap());
+
+            let witness = trie.witness(py).unwrap();
+            let from_witness = PyStateTrie::from_witness(witness.as_bytes()).unwrap();
+            assert_eq!(from_witness.root(), trie.root());
+
+            assert!(trie.set_nonce("0x00", &big).is_err());
+        });
+    }
+}
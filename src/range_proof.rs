@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use ethers_core::types::{Bytes, H256};
+
+use crate::{
+    hasher::TrieHasher,
+    nibbles::Nibbles,
+    nodes::{LeafValue, NodeData},
+    trie::{MptKey, Trie},
+    Error,
+};
+
+// range proofs are over hashed keys, i.e. 32 byte paths.
+const KEY_NIBBLES: usize = 64;
+
+fn hash_path(hash: H256) -> Vec<u8> {
+    Nibbles::from_raw_path(Bytes::from(hash.as_bytes().to_vec())).to_u4_vec()
+}
+
+// the smallest (0) or largest (f) key under a path.
+fn padded(path: &[u8], nibble: u8) -> Vec<u8> {
+    let mut padded = path.to_vec();
+    padded.resize(KEY_NIBBLES, nibble);
+    padded
+}
+
+fn mismatch() -> Error {
+    Error::InternalError("range proof does not match the entries")
+}
+
+struct RangeCheck {
+    proof: HashMap<H256, Bytes>,
+    origin: Vec<u8>,
+    // the last key of the range, the last entry.
+    last: Vec<u8>,
+    // whether the proof shows keys after the range.
+    more: bool,
+}
+
+impl RangeCheck {
+    fn build<K: MptKey, V: LeafValue, H: TrieHasher>(
+        trie: &mut Trie<K, V, H>,
+        entries: &[(Vec<u8>, V)],
+        depth: usize,
+    ) -> Result<Option<H256>, Error> {
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(trie.build_subtrie(entries, depth)?))
+    }
+
+    // the subtree at `path` rebuilt from the entries under it: subtrees outside of the range
+    // are taken from the proof as they are, the ones inside are built from the entries and
+    // the ones cut by a boundary come from the proof with their children rebuilt. whether the
+    // entries were all the keys of the range shows in the root.
+    fn rebuild<K: MptKey, V: LeafValue, H: TrieHasher>(
+        &mut self,
+        trie: &mut Trie<K, V, H>,
+        hash: Option<H256>,
+        path: Vec<u8>,
+        entries: &[(Vec<u8>, V)],
+    ) -> Result<Option<H256>, Error> {
+        let hash = match hash {
+            // empty as far as the proof goes, entries here give a different root.
+            None => return Self::build(trie, entries, path.len()),
+            Some(hash) => hash,
+        };
+        if padded(&path, 0xf) < self.origin {
+            return Ok(Some(hash));
+        }
+        if padded(&path, 0) > self.last {
+            self.more = true;
+            return Ok(Some(hash));
+        }
+        if self.origin <= padded(&path, 0) && padded(&path, 0xf) <= self.last {
+            return Self::build(trie, entries, path.len());
+        }
+
+        let raw = self
+            .proof
+            .get(&hash)
+            .ok_or(Error::MissingData("range proof is missing a boundary node"))?;
+        let rebuilt = match NodeData::<V>::from_raw_rlp(raw.to_owned())? {
+            NodeData::Branch(arr) => {
+                let mut children = arr;
+                let mut start = 0;
+                for nibble in 0..16 {
+                    let end = start
+                        + entries[start..]
+                            .iter()
+                            .take_while(|(key, _)| key[path.len()] == nibble as u8)
+                            .count();
+                    let mut child_path = path.clone();
+                    child_path.push(nibble as u8);
+                    children[nibble] =
+                        self.rebuild(trie, arr[nibble], child_path, &entries[start..end])?;
+                    start = end;
+                }
+                NodeData::Branch(children)
+            }
+            NodeData::Extension { key, node } => {
+                let mut child_path = path.clone();
+                child_path.extend(key.to_u4_vec());
+                if entries.iter().any(|(key, _)| !key.starts_with(&child_path)) {
+                    return Err(mismatch());
+                }
+                let node = self
+                    .rebuild(trie, Some(node), child_path, entries)?
+                    .ok_or_else(mismatch)?;
+                NodeData::Extension { key, node }
+            }
+            NodeData::Leaf { key, value } => {
+                let mut full = path.clone();
+                full.extend(key.to_u4_vec());
+                if self.origin <= full && full <= self.last {
+                    // a key of the range, it has to be one of the entries.
+                    return Self::build(trie, entries, path.len());
+                }
+                if !entries.is_empty() {
+                    return Err(mismatch());
+                }
+                if full > self.last {
+                    self.more = true;
+                }
+                NodeData::Leaf { key, value }
+            }
+        };
+        Ok(Some(trie.insert_node(rebuilt)?))
+    }
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    // checks a range proof like the ones of snap/1 and loads the entries and the boundary
+    // nodes. entries are by hashed key, sorted, and have to be every key from `origin` to the
+    // last entry. the proof has the nodes on the paths to origin and to the last entry, or
+    // to origin only without entries (no keys from origin on), or is empty when the entries
+    // are the whole trie. returns whether the trie has keys after the last entry.
+    pub(crate) fn load_range(
+        &mut self,
+        origin: H256,
+        entries: Vec<(H256, V)>,
+        proof: &[Bytes],
+    ) -> Result<bool, Error> {
+        let root = self.root().ok_or(Error::Usage("root not set"))?;
+        let paths: Vec<(Vec<u8>, V)> = entries
+            .into_iter()
+            .map(|(hash, value)| (hash_path(hash), value))
+            .collect();
+        if paths.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(Error::InternalError("range entries are not sorted by key"));
+        }
+        if paths.iter().any(|(_, value)| *value == V::default()) {
+            return Err(Error::InternalError("range entries can't be empty values"));
+        }
+
+        let mut check = RangeCheck {
+            proof: proof
+                .iter()
+                .map(|raw| (H::hash(raw), raw.clone()))
+                .collect(),
+            origin: hash_path(origin),
+            last: match paths.last() {
+                Some((last, _)) => last.clone(),
+                None => vec![0xf; KEY_NIBBLES],
+            },
+            more: false,
+        };
+        if proof.is_empty() {
+            check.origin = vec![0; KEY_NIBBLES];
+            check.last = vec![0xf; KEY_NIBBLES];
+        }
+        if paths
+            .first()
+            .is_some_and(|(first, _)| *first < check.origin)
+        {
+            return Err(Error::InternalError(
+                "range entries start before the origin",
+            ));
+        }
+
+        let mut checked = Self::from_root(root);
+        let root_hash = (root != H::empty_root()).then_some(root);
+        let rebuilt = check.rebuild(&mut checked, root_hash, vec![], &paths)?;
+        if rebuilt.unwrap_or_else(H::empty_root) != root {
+            return Err(Error::InternalError("range proof does not match the root"));
+        }
+        self.merge_nodes(checked)?;
+        Ok(check.more)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hash_path;
+    use crate::{nibbles::Nibbles, state_trie::StorageTrie};
+    use ethers_core::{
+        types::{BigEndianHash, Bytes, H256, U256},
+        utils::keccak256,
+    };
+
+    fn full() -> (StorageTrie, Vec<(H256, U256)>) {
+        let slots: Vec<(U256, U256)> = (1..=200u64)
+            .map(|i| (U256::from(i), U256::from(i * 3)))
+            .collect();
+        let mut entries: Vec<(H256, U256)> = slots
+            .iter()
+            .map(|(slot, value)| (H256::from(keccak256(H256::from_uint(slot))), *value))
+            .collect();
+        entries.sort();
+        (StorageTrie::from_entries(slots).unwrap(), entries)
+    }
+
+    fn proof(trie: &StorageTrie, keys: &[H256]) -> Vec<Bytes> {
+        let mut nodes: Vec<Bytes> = Vec::new();
+        for key in keys {
+            let path = Nibbles::from_u4_vec(hash_path(*key)).unwrap();
+            for node in trie.get_proof_path(path).unwrap() {
+                if !nodes.contains(&node) {
+                    nodes.push(node);
+                }
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    pub fn test_load_range() {
+        let (full, entries) = full();
+        let root = full.root().unwrap();
+
+        // the whole trie without a proof.
+        let mut partial = StorageTrie::from_root(root);
+        assert!(!partial
+            .load_range(H256::zero(), entries.clone(), &[])
+            .unwrap());
+        assert_eq!(partial.get(U256::from(7)).unwrap(), U256::from(21));
+
+        // a page from an origin between two keys.
+        let origin = H256::from_uint(&(U256::from_big_endian(entries[50].0.as_bytes()) + 1));
+        let page = entries[51..120].to_vec();
+        let page_proof = proof(&full, &[origin, page[page.len() - 1].0]);
+        let mut partial = StorageTrie::from_root(root);
+        assert!(partial
+            .load_range(origin, page.clone(), &page_proof)
+            .unwrap());
+        let mut unresolved = 0;
+        for i in 1..=200u64 {
+            let slot_hash = H256::from(keccak256(H256::from_uint(&U256::from(i))));
+            if page.iter().any(|(hash, _)| *hash == slot_hash) {
+                assert_eq!(partial.get(U256::from(i)).unwrap(), U256::from(i * 3));
+            } else {
+                // outside of the page only the leaves in the boundary proofs are loaded.
+                match partial.get(U256::from(i)) {
+                    Ok(value) => assert_eq!(value, U256::from(i * 3)),
+                    Err(_) => unresolved += 1,
+                }
+            }
+        }
+        assert!(unresolved > 100);
+
+        // the last page, and nothing after it.
+        let last = entries[150..].to_vec();
+        let last_proof = proof(&full, &[last[0].0, last[last.len() - 1].0]);
+        assert!(!StorageTrie::from_root(root)
+            .load_range(last[0].0, last.clone(), &last_proof)
+            .unwrap());
+        let after =
+            H256::from_uint(&(U256::from_big_endian(last[last.len() - 1].0.as_bytes()) + 1));
+        assert!(!StorageTrie::from_root(root)
+            .load_range(after, vec![], &proof(&full, &[after]))
+            .unwrap());
+    }
+
+    #[test]
+    pub fn test_load_range_rejects() {
+        let (full, entries) = full();
+        let root = full.root().unwrap();
+        let page = entries[20..80].to_vec();
+        let page_proof = proof(&full, &[page[0].0, page[page.len() - 1].0]);
+        let load = |page: Vec<(H256, U256)>, page_proof: &[Bytes]| {
+            let mut partial = StorageTrie::from_root(root);
+            let result = partial.load_range(page[0].0, page, page_proof);
+            // nothing is loaded from a bad range.
+            if result.is_err() {
+                assert!(partial.raw_nodes().unwrap().is_empty());
+            }
+            result
+        };
+        assert!(load(page.clone(), &page_proof).is_ok());
+
+        // a key left out of the middle.
+        let mut gap = page.clone();
+        gap.remove(30);
+        assert!(load(gap, &page_proof).is_err());
+        // a changed value.
+        let mut changed = page.clone();
+        changed[10].1 = U256::from(1);
+        assert!(load(changed, &page_proof).is_err());
+        // unsorted.
+        let mut unsorted = page.clone();
+        unsorted.swap(3, 4);
+        assert!(load(unsorted, &page_proof).is_err());
+        // without the nodes of the right boundary.
+        let left_proof = proof(&full, &[page[0].0]);
+        assert!(load(page.clone(), &left_proof).is_err());
+        // a page claimed to be the whole trie.
+        assert!(load(page, &[]).is_err());
+    }
+}
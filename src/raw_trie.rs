@@ -0,0 +1,163 @@
+use ethers_core::{
+    types::{Bytes, H256},
+    utils::keccak256,
+};
+
+use crate::{
+    nibbles::Nibbles,
+    nodes::LeafValue,
+    trie::{MptKey, Trie},
+    Error,
+};
+
+// a key of application data, e.g. an id in a rollup or a channel. the path is its keccak like
+// for the keys of the state, so keys can have any length, none is a prefix of another and no
+// node is shorter than its hash. the root is the one of a secure trie over the same data.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RawKey(pub Bytes);
+
+impl MptKey for RawKey {
+    fn to_nibbles(&self) -> Result<Nibbles, Error> {
+        Ok(Nibbles::from_raw_path(Bytes::from(
+            keccak256(&self.0).to_vec(),
+        )))
+    }
+}
+
+impl From<Bytes> for RawKey {
+    fn from(key: Bytes) -> Self {
+        RawKey(key)
+    }
+}
+
+impl From<Vec<u8>> for RawKey {
+    fn from(key: Vec<u8>) -> Self {
+        RawKey(key.into())
+    }
+}
+
+impl From<&[u8]> for RawKey {
+    fn from(key: &[u8]) -> Self {
+        RawKey(key.to_vec().into())
+    }
+}
+
+impl From<&str> for RawKey {
+    fn from(key: &str) -> Self {
+        key.as_bytes().into()
+    }
+}
+
+// keccak mpt over any keys and values, with proofs and partial loading like the state. values
+// are byte strings unless the application has its own LeafValue.
+pub type RawTrie<V = Bytes> = Trie<RawKey, V>;
+
+// the value of the key under the root, checked with the proof alone. the default value when
+// the proof shows the key isn't there.
+pub fn verify_raw_proof<V: LeafValue>(
+    root: H256,
+    key: impl Into<RawKey>,
+    proof: Vec<Bytes>,
+) -> Result<V, Error> {
+    RawTrie::<V>::from_root(root).load_proof_nodes(key.into(), proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_raw_proof, RawKey, RawTrie};
+    use crate::{state_trie::AccountTrie, trie::MptKey, AccountData};
+    use ethers_core::types::{Address, Bytes, H256, U256};
+
+    #[test]
+    pub fn test_raw_trie() {
+        let mut trie = RawTrie::empty();
+        for i in 0..20u8 {
+            trie.set(
+                vec![i; i as usize].into(),
+                Bytes::from(vec![0xaa; 1 + i as usize]),
+            )
+            .unwrap();
+        }
+        trie.set("channel/7".into(), Bytes::from_static(b"open"))
+            .unwrap();
+        let root = trie.root().unwrap();
+
+        let proof = trie.get_proof("channel/7".into()).unwrap();
+        let value: Bytes = verify_raw_proof(root, "channel/7", proof.clone()).unwrap();
+        assert_eq!(value, Bytes::from_static(b"open"));
+        assert!(verify_raw_proof::<Bytes>(H256::repeat_byte(1), "channel/7", proof).is_err());
+        // a key which isn't there.
+        let proof = trie.get_proof("channel/8".into()).unwrap();
+        assert!(verify_raw_proof::<Bytes>(root, "channel/8", proof)
+            .unwrap()
+            .is_empty());
+
+        // only the proven keys are loaded, writes to them work like on the full trie.
+        let mut partial = RawTrie::from_root(root);
+        partial
+            .load_proof_nodes(
+                RawKey::from(vec![3; 3]),
+                trie.get_proof(vec![3; 3].into()).unwrap(),
+            )
+            .unwrap();
+        partial
+            .set(vec![3; 3].into(), Bytes::from_static(b"new"))
+            .unwrap();
+        trie.set(vec![3; 3].into(), Bytes::from_static(b"new"))
+            .unwrap();
+        assert_eq!(partial.root(), trie.root());
+        assert!(partial.get(vec![4; 4].into()).is_err());
+    }
+
+    #[test]
+    pub fn test_verify_raw_proof_forged() {
+        let mut trie = RawTrie::empty();
+        for i in 0..20u8 {
+            trie.set(vec![i].into(), Bytes::from(vec![0xaa; 1 + i as usize]))
+                .unwrap();
+        }
+        let root = trie.root().unwrap();
+        let key = RawKey::from(vec![7]);
+        let proof = trie.get_proof(key.clone()).unwrap();
+
+        // cut at the root branch, whose slot for the key has a child.
+        assert!(verify_raw_proof::<Bytes>(root, key.clone(), vec![proof[0].clone()]).is_err());
+
+        // the proof of a key under another child of the root.
+        let first_nibble = |key: &RawKey| key.to_nibbles().unwrap().as_slice()[0];
+        let other = (0..20u8)
+            .map(|i| RawKey::from(vec![i]))
+            .find(|other| first_nibble(other) != first_nibble(&key))
+            .unwrap();
+        let wrong = trie.get_proof(other).unwrap();
+        assert!(verify_raw_proof::<Bytes>(root, key.clone(), wrong).is_err());
+        assert_eq!(
+            verify_raw_proof::<Bytes>(root, key, proof).unwrap(),
+            Bytes::from(vec![0xaa; 8])
+        );
+    }
+
+    #[test]
+    pub fn test_raw_trie_matches_account_trie() {
+        // the account trie is a secure trie keyed by the address bytes.
+        let accounts: Vec<(Address, AccountData)> = (1..=10u64)
+            .map(|i| {
+                (
+                    Address::from_low_u64_be(i),
+                    AccountData {
+                        balance: U256::from(i),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        let raw = RawTrie::<AccountData>::from_entries(
+            accounts
+                .iter()
+                .map(|(address, account)| (address.as_bytes().into(), account.clone())),
+        )
+        .unwrap();
+        let account_trie = AccountTrie::from_entries(accounts).unwrap();
+        assert_eq!(raw.root(), account_trie.root());
+    }
+}
@@ -0,0 +1,232 @@
+use ethers_core::{
+    types::{Bloom, Bytes, Log, TransactionReceipt, H256, U256, U64},
+    utils::rlp::RlpStream,
+};
+
+use crate::{index_trie::ordered_trie_root, Error};
+
+// optimism deposit transactions (and their receipts) have type 0x7e.
+pub const DEPOSIT_TX_TYPE: u8 = 0x7e;
+
+// the consensus fields of a receipt, which is what the receipts root commits to. json-rpc
+// receipts carry more (gas used, addresses, hashes) which isn't part of it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReceiptData {
+    pub tx_type: u8,
+    // since byzantium, before that receipts have the intermediate state root instead.
+    pub status: Option<bool>,
+    pub root: Option<H256>,
+    pub cumulative_gas_used: U256,
+    pub logs_bloom: Bloom,
+    pub logs: Vec<Log>,
+    // deposit receipts only: the nonce of the depositor since regolith, and the receipt
+    // version since canyon. both go at the end of the receipt when set.
+    pub deposit_nonce: Option<u64>,
+    pub deposit_receipt_version: Option<u64>,
+}
+
+impl ReceiptData {
+    // from an eth_getTransactionReceipt result. the op-stack fields aren't in ethers'
+    // receipt without its optimism feature, so they are read from the extra json fields.
+    pub fn from_receipt(receipt: &TransactionReceipt) -> Result<Self, Error> {
+        let other_u64 = |name: &str| -> Result<Option<u64>, Error> {
+            match receipt.other.get_deserialized::<U64>(name) {
+                Some(value) => Ok(Some(value?.as_u64())),
+                None => Ok(None),
+            }
+        };
+        let tx_type = receipt.transaction_type.unwrap_or_default().as_u64();
+        if tx_type > 0x7f {
+            return Err(Error::InternalError("receipt type out of range"));
+        }
+        Ok(ReceiptData {
+            tx_type: tx_type as u8,
+            status: receipt.status.map(|status| status == U64::one()),
+            root: receipt.root,
+            cumulative_gas_used: receipt.cumulative_gas_used,
+            logs_bloom: receipt.logs_bloom,
+            logs: receipt.logs.clone(),
+            deposit_nonce: other_u64("depositNonce")?,
+            deposit_receipt_version: other_u64("depositReceiptVersion")?,
+        })
+    }
+
+    // rlp([status or root, cumulative gas, bloom, logs]), prefixed by the type for typed
+    // receipts. this is both how they are in the trie and in eth/66+ messages.
+    pub fn encode(&self) -> Result<Bytes, Error> {
+        if self.tx_type != DEPOSIT_TX_TYPE
+            && (self.deposit_nonce.is_some() || self.deposit_receipt_version.is_some())
+        {
+            return Err(Error::InternalError(
+                "deposit fields on a receipt which is not a deposit",
+            ));
+        }
+        let deposit_fields = match (self.deposit_nonce, self.deposit_receipt_version) {
+            (None, None) => vec![],
+            (Some(nonce), None) => vec![nonce],
+            (Some(nonce), Some(version)) => vec![nonce, version],
+            (None, Some(_)) => {
+                return Err(Error::InternalError(
+                    "deposit receipt version without a deposit nonce",
+                ))
+            }
+        };
+
+        let mut stream = RlpStream::new_list(4 + deposit_fields.len());
+        match (self.status, self.root) {
+            (Some(status), _) => stream.append(&(status as u8)),
+            (None, Some(root)) => stream.append(&root),
+            (None, None) => return Err(Error::InternalError("receipt without status or root")),
+        };
+        stream.append(&self.cumulative_gas_used);
+        stream.append(&self.logs_bloom.as_bytes());
+        stream.append_list(&self.logs);
+        for field in deposit_fields {
+            stream.append(&field);
+        }
+
+        let mut encoded = Vec::new();
+        if self.tx_type != 0 {
+            encoded.push(self.tx_type);
+        }
+        encoded.extend_from_slice(&stream.out());
+        Ok(encoded.into())
+    }
+}
+
+// receiptsRoot of a block with these receipts, in block order.
+pub fn receipts_root(receipts: &[ReceiptData]) -> Result<H256, Error> {
+    ordered_trie_root(
+        receipts
+            .iter()
+            .map(|receipt| receipt.encode())
+            .collect::<Result<Vec<_>, _>>()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{receipts_root, ReceiptData, DEPOSIT_TX_TYPE};
+    use crate::{KeccakHasher, TrieHasher};
+    use ethers_core::{
+        types::{Address, Bloom, Bytes, Log, TransactionReceipt, H256, U256, U64},
+        utils::{hex, keccak256, rlp::RlpStream},
+    };
+
+    fn receipt(tx_type: u8) -> ReceiptData {
+        ReceiptData {
+            tx_type,
+            status: Some(true),
+            cumulative_gas_used: U256::from(21000),
+            logs: vec![Log {
+                address: Address::repeat_byte(0x11),
+                topics: vec![H256::repeat_byte(0x22)],
+                data: Bytes::from(vec![1, 2, 3]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn test_receipt_encoding() {
+        // legacy receipts are the bare list, typed ones are prefixed by the type.
+        let legacy = receipt(0).encode().unwrap();
+        assert!(legacy[0] >= 0xc0);
+        let typed = receipt(2).encode().unwrap();
+        assert_eq!(typed[0], 2);
+        assert_eq!(&typed[1..], &legacy[..]);
+
+        // pre-byzantium receipts have a root instead of the status.
+        let mut old = receipt(0);
+        old.status = None;
+        old.root = Some(H256::repeat_byte(0x33));
+        assert_ne!(old.encode().unwrap(), legacy);
+        old.root = None;
+        assert!(old.encode().is_err());
+
+        // a single receipt is a leaf under the key rlp(0).
+        let mut leaf = RlpStream::new_list(2);
+        leaf.append(&vec![0x20u8, 0x80]);
+        leaf.append(&legacy.to_vec());
+        assert_eq!(
+            receipts_root(&[receipt(0)]).unwrap(),
+            H256::from(keccak256(leaf.out()))
+        );
+        assert_eq!(receipts_root(&[]).unwrap(), KeccakHasher::empty_root());
+    }
+
+    #[test]
+    pub fn test_deposit_receipts() {
+        // before regolith deposit receipts are like the others, apart from the type.
+        let bedrock = receipt(DEPOSIT_TX_TYPE);
+        let encoded = bedrock.encode().unwrap();
+        assert_eq!(encoded[0], DEPOSIT_TX_TYPE);
+        assert_eq!(&encoded[1..], &receipt(0).encode().unwrap()[..]);
+
+        let mut regolith = bedrock.clone();
+        regolith.deposit_nonce = Some(7);
+        let mut canyon = regolith.clone();
+        canyon.deposit_receipt_version = Some(1);
+
+        // the extra fields are appended to the list.
+        let mut expected = RlpStream::new_list(6);
+        expected.append(&1u8);
+        expected.append(&U256::from(21000));
+        expected.append(&Bloom::zero().as_bytes());
+        expected.append_list(&bedrock.logs);
+        expected.append(&7u64);
+        expected.append(&1u64);
+        let mut expected_bytes = vec![DEPOSIT_TX_TYPE];
+        expected_bytes.extend_from_slice(&expected.out());
+        assert_eq!(canyon.encode().unwrap().to_vec(), expected_bytes);
+
+        let roots = [bedrock, regolith.clone(), canyon]
+            .map(|deposit| receipts_root(&[deposit, receipt(2)]).unwrap());
+        assert_ne!(roots[0], roots[1]);
+        assert_ne!(roots[1], roots[2]);
+
+        // only deposits have these, and the version needs the nonce.
+        let mut not_deposit = receipt(2);
+        not_deposit.deposit_nonce = Some(7);
+        assert!(not_deposit.encode().is_err());
+        regolith.deposit_nonce = None;
+        regolith.deposit_receipt_version = Some(1);
+        assert!(regolith.encode().is_err());
+    }
+
+    #[test]
+    pub fn test_from_receipt() {
+        let json = r#"{
+            "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "transactionIndex": "0x0",
+            "blockHash": null,
+            "blockNumber": null,
+            "from": "0x0000000000000000000000000000000000000002",
+            "to": null,
+            "cumulativeGasUsed": "0x5208",
+            "gasUsed": "0x5208",
+            "contractAddress": null,
+            "logs": [],
+            "status": "0x1",
+            "logsBloom": "0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+            "type": "0x7e",
+            "depositNonce": "0x2a",
+            "depositReceiptVersion": "0x1"
+        }"#;
+        let rpc_receipt: TransactionReceipt = serde_json::from_str(json).unwrap();
+        let receipt = ReceiptData::from_receipt(&rpc_receipt).unwrap();
+        assert_eq!(receipt.tx_type, DEPOSIT_TX_TYPE);
+        assert_eq!(receipt.status, Some(true));
+        assert_eq!(receipt.deposit_nonce, Some(42));
+        assert_eq!(receipt.deposit_receipt_version, Some(1));
+        assert!(hex::encode(receipt.encode().unwrap()).ends_with("2a01"));
+
+        // plain ethereum receipts have neither.
+        let mut rpc_receipt = rpc_receipt;
+        rpc_receipt.other = Default::default();
+        rpc_receipt.transaction_type = Some(U64::from(2));
+        let receipt = ReceiptData::from_receipt(&rpc_receipt).unwrap();
+        assert_eq!(receipt.deposit_nonce, None);
+    }
+}
@@ -0,0 +1,234 @@
+use std::{future::Future, time::Duration};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{sync::Mutex, time::Instant};
+
+use ethers_core::types::{Address, BlockId, Bytes, EIP1186ProofResponse, H256};
+use futures_timer::Delay;
+
+use crate::{Error, ProofProvider};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryConfig {
+    // retries after the first attempt, so a request is tried at most max_retries + 1 times.
+    pub max_retries: u32,
+    // wait before the first retry, doubled on every retry after that.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // upper bound on requests started per second, None for no limit. not enforced on wasm32,
+    // std's clock panics there.
+    pub requests_per_second: Option<u32>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            requests_per_second: None,
+        }
+    }
+}
+
+// wraps any ProofProvider with retries and a rate limit. only transient errors (see
+// Error::is_transient) are retried, a provider refusing the request and errors from decoding
+// or verifying proofs are returned as is. timers come from futures-timer so this works on any
+// async runtime.
+#[derive(Debug)]
+pub struct RetryProvider<P> {
+    inner: P,
+    config: RetryConfig,
+    #[cfg(not(target_arch = "wasm32"))]
+    next_request: Mutex<Instant>,
+}
+
+impl<P> RetryProvider<P> {
+    pub fn new(inner: P, config: RetryConfig) -> Self {
+        RetryProvider {
+            inner,
+            config,
+            #[cfg(not(target_arch = "wasm32"))]
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn config(&self) -> &RetryConfig {
+        &self.config
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        self.config
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.config.max_backoff)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    async fn throttle(&self) {}
+
+    // waits for the next free slot of the rate limiter.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn throttle(&self) {
+        let requests_per_second = match self.config.requests_per_second {
+            Some(requests_per_second) if requests_per_second > 0 => requests_per_second,
+            _ => return,
+        };
+
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next_request).max(now);
+            *next_request = slot + Duration::from_secs(1) / requests_per_second;
+            slot - now
+        };
+        if !wait.is_zero() {
+            Delay::new(wait).await;
+        }
+    }
+
+    async fn with_retry<T, F, Fut>(&self, mut request: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut retry = 0;
+        loop {
+            self.throttle().await;
+            match request().await {
+                Err(err) if err.is_transient() && retry < self.config.max_retries => {
+                    Delay::new(self.backoff(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<P: ProofProvider + Sync> ProofProvider for RetryProvider<P> {
+    async fn fetch_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        block: Option<BlockId>,
+    ) -> Result<EIP1186ProofResponse, Error> {
+        self.with_retry(|| self.inner.fetch_proof(address, slots.clone(), block))
+            .await
+    }
+
+    async fn fetch_node(&self, hash: H256) -> Result<Bytes, Error> {
+        self.with_retry(|| self.inner.fetch_node(hash)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use super::{RetryConfig, RetryProvider};
+    use crate::{Error, ProofProvider};
+    use ethers_core::types::{Address, BlockId, Bytes, EIP1186ProofResponse, H256};
+
+    // fails the first `failures` requests for nodes, like a public rpc returning 429. proofs
+    // it doesn't serve at all.
+    struct FlakyProvider {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    impl ProofProvider for FlakyProvider {
+        async fn fetch_proof(
+            &self,
+            _address: Address,
+            _slots: Vec<H256>,
+            _block: Option<BlockId>,
+        ) -> Result<EIP1186ProofResponse, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(Error::ProviderRejected(
+                "the method eth_getProof does not exist".to_string(),
+            ))
+        }
+
+        async fn fetch_node(&self, _hash: H256) -> Result<Bytes, Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(Error::ProviderError("429 Too Many Requests".to_string()));
+            }
+            Ok(Bytes::from(vec![0xc0]))
+        }
+    }
+
+    fn flaky(failures: u32, config: RetryConfig) -> RetryProvider<FlakyProvider> {
+        RetryProvider::new(
+            FlakyProvider {
+                failures,
+                calls: AtomicU32::new(0),
+            },
+            config,
+        )
+    }
+
+    fn fast_config() -> RetryConfig {
+        RetryConfig {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            requests_per_second: None,
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_retry_transient_errors() {
+        let provider = flaky(3, fast_config());
+        assert!(provider.fetch_node(H256::zero()).await.is_ok());
+        assert_eq!(provider.inner().calls.load(Ordering::SeqCst), 4);
+
+        let provider = flaky(4, fast_config());
+        assert!(matches!(
+            provider.fetch_node(H256::zero()).await,
+            Err(Error::ProviderError(_))
+        ));
+        assert_eq!(provider.inner().calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    pub async fn test_retry_skips_permanent_errors() {
+        let provider = flaky(0, fast_config());
+        assert!(matches!(
+            provider.fetch_proof(Address::zero(), vec![], None).await,
+            Err(Error::ProviderRejected(_))
+        ));
+        assert_eq!(provider.inner().calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    pub fn test_retry_backoff_is_capped() {
+        let provider = flaky(0, fast_config());
+        assert_eq!(provider.backoff(0), Duration::from_millis(1));
+        assert_eq!(provider.backoff(2), Duration::from_millis(4));
+        assert_eq!(provider.backoff(100), Duration::from_millis(4));
+    }
+
+    #[tokio::test]
+    pub async fn test_rate_limit() {
+        let provider = flaky(
+            0,
+            RetryConfig {
+                requests_per_second: Some(20),
+                ..fast_config()
+            },
+        );
+        let start = Instant::now();
+        for _ in 0..5 {
+            provider.fetch_node(H256::zero()).await.unwrap();
+        }
+        // first request goes out right away, the next four are 50ms apart.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
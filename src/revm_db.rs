@@ -0,0 +1,422 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethers_core::{
+    abi::ethereum_types::BloomInput,
+    types::{Address, Bloom, Bytes, Log, H256, U256},
+};
+use revm::{
+    primitives::{
+        self, AccountInfo, Bytecode, EVMError, Env, ExecutionResult, SpecId, TxEnv, B256,
+        KECCAK_EMPTY,
+    },
+    DatabaseRef, Evm,
+};
+
+use crate::{concurrent::get_storage, AccountData, Error, ReceiptData, StateDiff, StateTrie};
+
+pub(crate) fn to_address(address: primitives::Address) -> Address {
+    Address::from(address.0 .0)
+}
+
+pub(crate) fn from_address(address: Address) -> primitives::Address {
+    primitives::Address::from(address.0)
+}
+
+pub(crate) fn to_u256(value: primitives::U256) -> U256 {
+    U256::from_big_endian(&value.to_be_bytes::<32>())
+}
+
+pub(crate) fn from_u256(value: U256) -> primitives::U256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    primitives::U256::from_be_bytes(bytes)
+}
+
+// reads for revm straight from the trie and its code db, e.g. to run an Evm of your own with
+// `with_ref_db`. a node the witness doesn't have fails the transaction.
+#[derive(Clone, Copy, Debug)]
+pub struct StateTrieDb<'a>(pub &'a StateTrie);
+
+impl DatabaseRef for StateTrieDb<'_> {
+    type Error = Error;
+
+    fn basic_ref(&self, address: primitives::Address) -> Result<Option<AccountInfo>, Error> {
+        let account = self.0.account_trie.get(to_address(address))?;
+        if account == AccountData::default() {
+            return Ok(None);
+        }
+        // code which isn't in the code db is asked for by hash, and fails there.
+        let code = self
+            .0
+            .code_db()
+            .get(account.code_hash)
+            .map(|code| Bytecode::new_raw(code.0.into()));
+        Ok(Some(AccountInfo {
+            balance: from_u256(account.balance),
+            nonce: account.nonce.as_u64(),
+            code_hash: B256::from(account.code_hash.0),
+            code,
+        }))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Error> {
+        self.0
+            .code_db()
+            .get(H256::from(code_hash.0))
+            .map(|code| Bytecode::new_raw(code.0.into()))
+            .ok_or(Error::MissingData("code not in the code db"))
+    }
+
+    fn storage_ref(
+        &self,
+        address: primitives::Address,
+        index: primitives::U256,
+    ) -> Result<primitives::U256, Error> {
+        get_storage(self.0, to_address(address), to_u256(index)).map(from_u256)
+    }
+
+    // revm only asks for the 256 blocks before the current one.
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Error> {
+        self.0
+            .block_hashes()
+            .get(number)
+            .map(|hash| B256::from(hash.0))
+            .ok_or(Error::MissingData("block hash not in the store"))
+    }
+}
+
+// what came out of apply_transaction, the receipt fields and the root after it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionOutcome {
+    pub success: bool,
+    pub gas_used: u64,
+    pub gas_refunded: u64,
+    pub logs: Vec<Log>,
+    // return data, or the code of a created contract. empty when halted.
+    pub output: Bytes,
+    pub created: Option<Address>,
+    pub root: H256,
+}
+
+// what simulate_bundle gave: the outcome of every transaction, what changed and the root
+// the state would have after the bundle.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BundleSimulation {
+    pub outcomes: Vec<TransactionOutcome>,
+    pub diff: StateDiff,
+    pub root: H256,
+}
+
+impl TransactionOutcome {
+    // the consensus receipt, with the gas of the transactions before it in the block.
+    pub fn receipt(&self, tx_type: u8, cumulative_gas_used: U256) -> ReceiptData {
+        let mut logs_bloom = Bloom::zero();
+        for log in self.logs.iter() {
+            logs_bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+            for topic in log.topics.iter() {
+                logs_bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+            }
+        }
+        ReceiptData {
+            tx_type,
+            status: Some(self.success),
+            cumulative_gas_used: cumulative_gas_used + self.gas_used,
+            logs_bloom,
+            logs: self.logs.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+fn evm_error(err: EVMError<Error>) -> Error {
+    match err {
+        EVMError::Database(err) => err,
+        err => Error::ProviderError(format!("evm: {:?}", err)),
+    }
+}
+
+impl StateTrie {
+    // runs `tx` in `env` under cancun rules and writes what it changed back into the trie,
+    // for what-if runs on a witness. a transaction which reverts or halts still pays for its
+    // gas, an invalid one (nonce, balance, gas limit) changes nothing and is an error.
+    pub fn apply_transaction(&mut self, env: Env, tx: TxEnv) -> Result<TransactionOutcome, Error> {
+        self.apply_transaction_with_spec(SpecId::CANCUN, env, tx)
+    }
+
+    pub fn apply_transaction_with_spec(
+        &mut self,
+        spec_id: SpecId,
+        env: Env,
+        tx: TxEnv,
+    ) -> Result<TransactionOutcome, Error> {
+        self.execute(spec_id, env, tx, &mut BTreeMap::new())
+    }
+
+    // runs a bundle on a copy of the trie, in order, and diffs the accounts and slots it
+    // wrote against this one. reverted transactions are in the outcomes, an invalid one fails
+    // the whole bundle. the trie itself isn't changed.
+    pub fn simulate_bundle(&self, env: Env, txs: Vec<TxEnv>) -> Result<BundleSimulation, Error> {
+        self.simulate_bundle_with_spec(SpecId::CANCUN, env, txs)
+    }
+
+    pub fn simulate_bundle_with_spec(
+        &self,
+        spec_id: SpecId,
+        env: Env,
+        txs: Vec<TxEnv>,
+    ) -> Result<BundleSimulation, Error> {
+        let mut state_trie = self.clone();
+        let mut touched = BTreeMap::new();
+        let outcomes = txs
+            .into_iter()
+            .map(|tx| state_trie.execute(spec_id, env.clone(), tx, &mut touched))
+            .collect::<Result<Vec<_>, _>>()?;
+        let keys: Vec<(Address, Vec<U256>)> = touched
+            .into_iter()
+            .map(|(address, slots)| (address, slots.into_iter().collect()))
+            .collect();
+        Ok(BundleSimulation {
+            diff: state_trie.diff(self, &keys)?,
+            root: state_trie.root().ok_or(Error::Usage("root not set"))?,
+            outcomes,
+        })
+    }
+
+    // apply_transaction, adding the accounts and slots it writes to `touched`.
+    pub(crate) fn execute(
+        &mut self,
+        spec_id: SpecId,
+        mut env: Env,
+        tx: TxEnv,
+        touched: &mut BTreeMap<Address, BTreeSet<U256>>,
+    ) -> Result<TransactionOutcome, Error> {
+        env.tx = tx;
+        let result = Evm::builder()
+            .with_ref_db(StateTrieDb(self))
+            .with_env(Box::new(env))
+            .with_spec_id(spec_id)
+            .build()
+            .transact()
+            .map_err(evm_error)?;
+
+        for (address, account) in result.state {
+            if !account.is_touched() {
+                continue;
+            }
+            let address = to_address(address);
+            touched.entry(address).or_default().extend(
+                account
+                    .storage
+                    .iter()
+                    .filter(|(_, value)| account.is_created() || value.is_changed())
+                    .map(|(slot, _)| to_u256(*slot)),
+            );
+            // touched empty accounts are gone since spurious dragon.
+            if account.is_selfdestructed() || account.is_empty() {
+                self.clear_storage(address)?;
+                self.account_trie.set(address, AccountData::default())?;
+                continue;
+            }
+            if account.is_created() {
+                self.clear_storage(address)?;
+            }
+            let mut account_data = self.account_trie.get(address)?;
+            account_data.nonce = U256::from(account.info.nonce);
+            account_data.balance = to_u256(account.info.balance);
+            account_data.code_hash = H256::from(account.info.code_hash.0);
+            if let Some(code) = &account.info.code {
+                if account.info.code_hash != KECCAK_EMPTY {
+                    self.code_db_mut()
+                        .insert_checked(account_data.code_hash, code.original_bytes().0.into())?;
+                }
+            }
+            self.account_trie.set(address, account_data)?;
+            for (slot, value) in account.storage.iter() {
+                if account.is_created() || value.is_changed() {
+                    self.set_storage_value(address, to_u256(*slot), to_u256(value.present_value))?;
+                }
+            }
+        }
+
+        let root = self.root().ok_or(Error::Usage("root not set"))?;
+        let mut outcome = TransactionOutcome {
+            success: result.result.is_success(),
+            gas_used: result.result.gas_used(),
+            output: result.result.output().cloned().unwrap_or_default().0.into(),
+            root,
+            ..Default::default()
+        };
+        if let ExecutionResult::Success {
+            gas_refunded,
+            logs,
+            output,
+            ..
+        } = result.result
+        {
+            outcome.gas_refunded = gas_refunded;
+            outcome.created = output.address().copied().map(to_address);
+            outcome.logs = logs
+                .into_iter()
+                .map(|log| Log {
+                    address: to_address(log.address),
+                    topics: log
+                        .topics()
+                        .iter()
+                        .map(|topic| H256::from(topic.0))
+                        .collect(),
+                    data: log.data.data.0.into(),
+                    ..Default::default()
+                })
+                .collect();
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateTrieDb;
+    use crate::{AccountDiff, StateTrie};
+    use ethers_core::types::{Address, Bytes, U256};
+    use revm::{
+        primitives::{self, Env, TransactTo, TxEnv},
+        DatabaseRef,
+    };
+
+    #[test]
+    pub fn test_apply_transaction() {
+        let sender = Address::from_low_u64_be(0x1000);
+        let contract = Address::from_low_u64_be(0x2000);
+        let mut state_trie = StateTrie::new();
+        state_trie
+            .account_trie
+            .set_balance(sender, U256::exp10(18))
+            .unwrap();
+        // sstore(1, 42), then log1(0, 0, 7).
+        state_trie
+            .set_code(
+                contract,
+                Bytes::from(vec![
+                    0x60, 0x2a, 0x60, 0x01, 0x55, 0x60, 0x07, 0x60, 0x00, 0x80, 0xa1, 0x00,
+                ]),
+            )
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_nonce(contract, U256::one())
+            .unwrap();
+
+        let db = StateTrieDb(&state_trie);
+        let info = db
+            .basic_ref(primitives::Address::from(sender.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            info.balance,
+            primitives::U256::from(10u64).pow(primitives::U256::from(18))
+        );
+        assert!(db
+            .basic_ref(primitives::Address::repeat_byte(0xaa))
+            .unwrap()
+            .is_none());
+
+        let tx = TxEnv {
+            caller: primitives::Address::from(sender.0),
+            transact_to: TransactTo::Call(primitives::Address::from(contract.0)),
+            value: primitives::U256::from(5),
+            gas_limit: 100_000,
+            gas_price: primitives::U256::ZERO,
+            nonce: Some(0),
+            ..Default::default()
+        };
+        let outcome = state_trie
+            .apply_transaction(Env::default(), tx.clone())
+            .unwrap();
+        assert!(outcome.success);
+        assert_eq!(outcome.root, state_trie.root().unwrap());
+        assert_eq!(outcome.logs.len(), 1);
+        assert_eq!(outcome.logs[0].address, contract);
+        assert_eq!(outcome.logs[0].topics[0].to_low_u64_be(), 7);
+        assert_eq!(
+            outcome.receipt(2, U256::zero()).cumulative_gas_used,
+            outcome.gas_used.into()
+        );
+        assert_eq!(
+            state_trie.get_storage_at(contract, U256::one()).unwrap(),
+            U256::from(42)
+        );
+        let sender_data = state_trie.account_trie.get(sender).unwrap();
+        assert_eq!(sender_data.nonce, U256::one());
+        assert_eq!(sender_data.balance, U256::exp10(18) - 5);
+        assert_eq!(
+            state_trie.account_trie.get(contract).unwrap().balance,
+            U256::from(5)
+        );
+
+        // the same nonce again is invalid and changes nothing.
+        assert!(state_trie.apply_transaction(Env::default(), tx).is_err());
+        assert_eq!(state_trie.root(), Some(outcome.root));
+    }
+
+    #[test]
+    pub fn test_simulate_bundle() {
+        let sender = Address::from_low_u64_be(0x1000);
+        let contract = Address::from_low_u64_be(0x2000);
+        let receiver = Address::from_low_u64_be(0x3000);
+        let mut state_trie = StateTrie::new();
+        state_trie
+            .account_trie
+            .set_balance(sender, U256::exp10(18))
+            .unwrap();
+        // sstore(1, 42)
+        state_trie
+            .set_code(
+                contract,
+                Bytes::from(vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00]),
+            )
+            .unwrap();
+        let root = state_trie.root();
+
+        let tx = |to: Address, value: u64, nonce: u64| TxEnv {
+            caller: primitives::Address::from(sender.0),
+            transact_to: TransactTo::Call(primitives::Address::from(to.0)),
+            value: primitives::U256::from(value),
+            gas_limit: 100_000,
+            gas_price: primitives::U256::ZERO,
+            nonce: Some(nonce),
+            ..Default::default()
+        };
+        let simulation = state_trie
+            .simulate_bundle(Env::default(), vec![tx(contract, 5, 0), tx(receiver, 7, 1)])
+            .unwrap();
+        assert_eq!(simulation.outcomes.len(), 2);
+        assert_eq!(simulation.root, simulation.outcomes[1].root);
+        assert_ne!(Some(simulation.root), root);
+        assert_eq!(state_trie.root(), root);
+
+        let accounts = &simulation.diff.accounts;
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts[&sender].nonce, Some((U256::zero(), U256::from(2))));
+        assert_eq!(
+            accounts[&sender].balance,
+            Some((U256::exp10(18), U256::exp10(18) - 12))
+        );
+        assert_eq!(
+            accounts[&contract].storage,
+            [(U256::one(), (U256::zero(), U256::from(42)))].into()
+        );
+        assert_eq!(
+            accounts[&receiver],
+            AccountDiff {
+                balance: Some((U256::zero(), U256::from(7))),
+                created: true,
+                ..Default::default()
+            }
+        );
+
+        // a bundle with an invalid transaction doesn't simulate.
+        assert!(state_trie
+            .simulate_bundle(Env::default(), vec![tx(contract, 5, 0), tx(receiver, 7, 5)])
+            .is_err());
+    }
+}
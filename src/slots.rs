@@ -0,0 +1,218 @@
+use ethers_core::{
+    types::{Address, BigEndianHash, H256, U256},
+    utils::keccak256,
+};
+
+// value type keys of a solidity mapping, abi encoded to a 32 byte word.
+pub trait MappingKey {
+    fn to_word(&self) -> H256;
+}
+
+impl MappingKey for H256 {
+    fn to_word(&self) -> H256 {
+        *self
+    }
+}
+
+impl MappingKey for U256 {
+    fn to_word(&self) -> H256 {
+        H256::from_uint(self)
+    }
+}
+
+impl MappingKey for Address {
+    fn to_word(&self) -> H256 {
+        H256::from(*self)
+    }
+}
+
+impl MappingKey for u64 {
+    fn to_word(&self) -> H256 {
+        H256::from_low_u64_be(*self)
+    }
+}
+
+impl MappingKey for bool {
+    fn to_word(&self) -> H256 {
+        H256::from_low_u64_be(*self as u64)
+    }
+}
+
+// slot of `mapping[key]` for a mapping declared at `slot`: keccak256(abi.encode(key, slot)).
+pub fn mapping_slot(key: &impl MappingKey, slot: U256) -> U256 {
+    let mut input = key.to_word().as_bytes().to_vec();
+    input.extend_from_slice(H256::from_uint(&slot).as_bytes());
+    U256::from_big_endian(&keccak256(input))
+}
+
+// string and bytes keys aren't padded: keccak256(abi.encodePacked(key, slot)).
+pub fn mapping_slot_bytes(key: &[u8], slot: U256) -> U256 {
+    let mut input = key.to_vec();
+    input.extend_from_slice(H256::from_uint(&slot).as_bytes());
+    U256::from_big_endian(&keccak256(input))
+}
+
+// slot of `mapping[keys[0]][keys[1]]...`, outermost key first. use to_word for mixed key types.
+pub fn nested_mapping_slot(keys: &[H256], slot: U256) -> U256 {
+    keys.iter().fold(slot, |slot, key| mapping_slot(key, slot))
+}
+
+// where the elements of a dynamic array (or long bytes/string) declared at `slot` start.
+pub fn array_data_slot(slot: U256) -> U256 {
+    U256::from_big_endian(&keccak256(H256::from_uint(&slot)))
+}
+
+// slot of `array[index]` for elements taking `element_slots` whole slots each (1 for
+// uint256/address, the struct size for arrays of structs). wraps around like the evm does.
+pub fn array_element_slot(slot: U256, index: U256, element_slots: u64) -> U256 {
+    let offset = index.overflowing_mul(U256::from(element_slots)).0;
+    array_data_slot(slot).overflowing_add(offset).0
+}
+
+// slot and byte offset of `array[index]` for elements smaller than a slot (e.g. uint8[]),
+// which solidity packs starting from the low order bytes.
+pub fn packed_array_element(slot: U256, index: u64, element_bytes: usize) -> (U256, usize) {
+    let per_slot = (32 / element_bytes) as u64;
+    let element_slot = array_data_slot(slot).overflowing_add(U256::from(index / per_slot));
+    (element_slot.0, (index % per_slot) as usize * element_bytes)
+}
+
+// the `size` byte member at byte `offset` of a slot, counted from the low order end like
+// solidity packs struct members and small state variables.
+pub fn read_packed(word: U256, offset: usize, size: usize) -> U256 {
+    let shifted = word >> (offset * 8);
+    if size >= 32 {
+        return shifted;
+    }
+    shifted & ((U256::one() << (size * 8)) - 1)
+}
+
+// the slot with the member at `offset` replaced by `value`, the other members unchanged.
+pub fn write_packed(word: U256, offset: usize, size: usize, value: U256) -> U256 {
+    let mask = if size >= 32 {
+        U256::MAX
+    } else {
+        (U256::one() << (size * 8)) - 1
+    };
+    (word & !(mask << (offset * 8))) | ((value & mask) << (offset * 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        array_data_slot, array_element_slot, mapping_slot, mapping_slot_bytes, nested_mapping_slot,
+        packed_array_element, read_packed, write_packed, MappingKey,
+    };
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::{
+        types::{Address, H256, U256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_mapping_slot() {
+        // keccak256 of 64 zero bytes.
+        assert_eq!(
+            mapping_slot(&U256::zero(), U256::zero()),
+            "0xad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5"
+                .parse::<H256>()
+                .map(|hash| U256::from_big_endian(hash.as_bytes()))
+                .unwrap()
+        );
+
+        // address keys are left padded.
+        let owner = Address::repeat_byte(0x11);
+        let mut input = vec![0u8; 12];
+        input.extend_from_slice(owner.as_bytes());
+        input.extend_from_slice(H256::from_low_u64_be(3).as_bytes());
+        assert_eq!(
+            mapping_slot(&owner, U256::from(3)),
+            U256::from_big_endian(&keccak256(input))
+        );
+
+        let mut input = b"name".to_vec();
+        input.extend_from_slice(H256::from_low_u64_be(1).as_bytes());
+        assert_eq!(
+            mapping_slot_bytes(b"name", U256::one()),
+            U256::from_big_endian(&keccak256(input))
+        );
+    }
+
+    #[test]
+    pub fn test_nested_mapping_slot() {
+        // allowance[owner][spender] at slot 2.
+        let owner = Address::from_low_u64_be(1);
+        let spender = Address::from_low_u64_be(2);
+        let slot = nested_mapping_slot(&[owner.to_word(), spender.to_word()], U256::from(2));
+        assert_eq!(
+            slot,
+            mapping_slot(&spender, mapping_slot(&owner, U256::from(2)))
+        );
+
+        let token = Address::from_low_u64_be(9);
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        state_trie
+            .account_trie
+            .set_balance(token, U256::one())
+            .unwrap();
+        state_trie
+            .set_storage_u256(token, slot, U256::from(500))
+            .unwrap();
+        assert_eq!(
+            state_trie
+                .get_storage_u256(
+                    token,
+                    nested_mapping_slot(&[owner.to_word(), spender.to_word()], U256::from(2))
+                )
+                .unwrap(),
+            U256::from(500)
+        );
+        assert_eq!(nested_mapping_slot(&[], U256::from(2)), U256::from(2));
+    }
+
+    #[test]
+    pub fn test_array_slots() {
+        // keccak256 of the zero word.
+        let data = "0x290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563"
+            .parse::<H256>()
+            .map(|hash| U256::from_big_endian(hash.as_bytes()))
+            .unwrap();
+        assert_eq!(array_data_slot(U256::zero()), data);
+        assert_eq!(array_element_slot(U256::zero(), U256::from(5), 1), data + 5);
+        // struct { uint256 a; address b; }[] takes 2 slots per element.
+        assert_eq!(
+            array_element_slot(U256::zero(), U256::from(5), 2),
+            data + 10
+        );
+
+        // 32 uint8 per slot.
+        assert_eq!(packed_array_element(U256::zero(), 3, 1), (data, 3));
+        assert_eq!(packed_array_element(U256::zero(), 33, 1), (data + 1, 1));
+        // 2 uint128 per slot.
+        assert_eq!(packed_array_element(U256::zero(), 3, 16), (data + 1, 16));
+    }
+
+    #[test]
+    pub fn test_packed_members() {
+        // struct { uint64 a; address b; bool c; } packed into one slot.
+        let owner = Address::repeat_byte(0x22);
+        let mut word = U256::zero();
+        word = write_packed(word, 0, 8, U256::from(42));
+        let owner_word = U256::from_big_endian(owner.to_word().as_bytes());
+        word = write_packed(word, 8, 20, owner_word);
+        word = write_packed(word, 28, 1, U256::one());
+
+        assert_eq!(read_packed(word, 0, 8), U256::from(42));
+        assert_eq!(read_packed(word, 8, 20), owner_word);
+        assert_eq!(read_packed(word, 28, 1), U256::one());
+
+        // replacing a member leaves the others alone.
+        let updated = write_packed(word, 0, 8, U256::from(7));
+        assert_eq!(read_packed(updated, 0, 8), U256::from(7));
+        assert_eq!(read_packed(updated, 8, 20), read_packed(word, 8, 20));
+        // values wider than the member are cut to its size.
+        let cut = write_packed(word, 28, 1, U256::from(0x1ff));
+        assert_eq!(read_packed(cut, 28, 1), U256::from(0xff));
+        assert_eq!(read_packed(cut, 0, 28), read_packed(word, 0, 28));
+    }
+}
@@ -0,0 +1,261 @@
+use ethers_core::types::H256;
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nodes::{Nodes, TrieNode},
+    Error,
+};
+
+pub const SMT_DEPTH: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SmtNode {
+    Leaf(H256),
+    Internal([H256; 2]),
+}
+
+impl TrieNode for SmtNode {
+    fn node_hash<H: TrieHasher>(&self) -> Result<H256, Error> {
+        Ok(match self {
+            SmtNode::Leaf(value) => leaf_hash::<H>(*value),
+            SmtNode::Internal([left, right]) => internal_hash::<H>(*left, *right),
+        })
+    }
+}
+
+// a zero value is the same as an empty leaf.
+fn leaf_hash<H: TrieHasher>(value: H256) -> H256 {
+    if value.is_zero() {
+        return H256::zero();
+    }
+    H::hash(value.as_bytes())
+}
+
+fn internal_hash<H: TrieHasher>(left: H256, right: H256) -> H256 {
+    let mut preimage = [0u8; 64];
+    preimage[..32].copy_from_slice(left.as_bytes());
+    preimage[32..].copy_from_slice(right.as_bytes());
+    H::hash(&preimage)
+}
+
+fn bit_at(key: &H256, depth: usize) -> usize {
+    ((key[depth / 8] >> (7 - depth % 8)) & 1) as usize
+}
+
+// fixed depth sparse merkle tree over 256 bit keys. subtrees that are entirely empty are
+// never stored, their hashes come from the cached default hash for that height.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseMerkleTree<H: TrieHasher = KeccakHasher> {
+    root: Option<H256>,
+    nodes: Nodes<SmtNode, H>,
+    // default_hashes[h] is the root of an empty subtree of height h.
+    default_hashes: Vec<H256>,
+}
+
+impl<H: TrieHasher> Default for SparseMerkleTree<H> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<H: TrieHasher> SparseMerkleTree<H> {
+    pub fn from_root(root: H256) -> Self {
+        let mut default_hashes = Vec::with_capacity(SMT_DEPTH + 1);
+        default_hashes.push(H256::zero());
+        for height in 0..SMT_DEPTH {
+            let below = default_hashes[height];
+            default_hashes.push(internal_hash::<H>(below, below));
+        }
+
+        SparseMerkleTree {
+            root: Some(root),
+            nodes: Nodes::default(),
+            default_hashes,
+        }
+    }
+
+    pub fn empty() -> Self {
+        let mut smt = Self::from_root(H256::zero());
+        smt.root = Some(smt.default_hashes[SMT_DEPTH]);
+        smt
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.root
+    }
+
+    pub fn default_hash(&self, height: usize) -> H256 {
+        self.default_hashes[height]
+    }
+
+    pub fn get(&self, key: H256) -> Result<H256, Error> {
+        let mut current = self.root.ok_or(Error::Usage("root not set"))?;
+        for depth in 0..SMT_DEPTH {
+            if current == self.default_hashes[SMT_DEPTH - depth] {
+                return Ok(H256::zero());
+            }
+            match self.nodes.get(&current) {
+                Some(SmtNode::Internal(children)) => current = children[bit_at(&key, depth)],
+                _ => return Err(Error::MissingData("node not present, please add a proof")),
+            }
+        }
+
+        if current.is_zero() {
+            return Ok(H256::zero());
+        }
+        match self.nodes.get(&current) {
+            Some(SmtNode::Leaf(value)) => Ok(*value),
+            _ => Err(Error::MissingData("leaf not present, please add a proof")),
+        }
+    }
+
+    // siblings from the root down to the leaf.
+    pub fn get_proof(&self, key: H256) -> Result<Vec<H256>, Error> {
+        let mut current = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut siblings = Vec::with_capacity(SMT_DEPTH);
+        for depth in 0..SMT_DEPTH {
+            let height = SMT_DEPTH - depth;
+            if current == self.default_hashes[height] {
+                // the rest of the path is empty.
+                siblings.extend((0..height).rev().map(|h| self.default_hashes[h]));
+                break;
+            }
+            match self.nodes.get(&current) {
+                Some(SmtNode::Internal(children)) => {
+                    let bit = bit_at(&key, depth);
+                    siblings.push(children[1 - bit]);
+                    current = children[bit];
+                }
+                _ => return Err(Error::MissingData("node not present, please add a proof")),
+            }
+        }
+        Ok(siblings)
+    }
+
+    pub fn load_proof(&mut self, key: H256, value: H256, siblings: Vec<H256>) -> Result<(), Error> {
+        let (root, path) = self.compute_path(key, value, &siblings)?;
+        match self.root {
+            Some(expected) if expected != root => {
+                return Err(Error::InternalError(
+                    "proof does not match the root of the tree",
+                ))
+            }
+            Some(_) => {}
+            None => self.root = Some(root),
+        }
+
+        for node in path {
+            self.nodes.insert(node)?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: H256, value: H256) -> Result<(), Error> {
+        // siblings are read from the loaded path, so this errors when the proof is missing.
+        let siblings = self.get_proof(key)?;
+        let (root, path) = self.compute_path(key, value, &siblings)?;
+
+        // old path nodes are left in place, equal subtrees can be shared by other keys.
+        for node in path {
+            self.nodes.insert(node)?;
+        }
+        self.root = Some(root);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: H256) -> Result<(), Error> {
+        self.set(key, H256::zero())
+    }
+
+    // hashes the leaf up to the root, returns the root and the non empty nodes on the path.
+    fn compute_path(
+        &self,
+        key: H256,
+        value: H256,
+        siblings: &[H256],
+    ) -> Result<(H256, Vec<SmtNode>), Error> {
+        if siblings.len() != SMT_DEPTH {
+            return Err(Error::InternalError("smt proof should have 256 siblings"));
+        }
+
+        let mut path = Vec::new();
+        let mut current = leaf_hash::<H>(value);
+        if !current.is_zero() {
+            path.push(SmtNode::Leaf(value));
+        }
+        for depth in (0..SMT_DEPTH).rev() {
+            let children = if bit_at(&key, depth) == 1 {
+                [siblings[depth], current]
+            } else {
+                [current, siblings[depth]]
+            };
+            current = internal_hash::<H>(children[0], children[1]);
+            if current != self.default_hashes[SMT_DEPTH - depth] && !self.nodes.contains(&current) {
+                path.push(SmtNode::Internal(children));
+            }
+        }
+        Ok((current, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SparseMerkleTree;
+    use crate::hasher::KeccakHasher;
+    use ethers_core::types::H256;
+
+    #[test]
+    pub fn test_smt_empty_root_is_default_hash() {
+        let smt = SparseMerkleTree::<KeccakHasher>::empty();
+        assert_eq!(smt.root(), Some(smt.default_hash(256)));
+        assert_eq!(smt.get(H256::repeat_byte(7)).unwrap(), H256::zero());
+    }
+
+    #[test]
+    pub fn test_smt_set_get_remove() {
+        let mut smt = SparseMerkleTree::<KeccakHasher>::empty();
+        let empty_root = smt.root();
+
+        smt.set(H256::repeat_byte(1), H256::from_low_u64_be(10))
+            .unwrap();
+        smt.set(H256::repeat_byte(2), H256::from_low_u64_be(20))
+            .unwrap();
+        assert_eq!(
+            smt.get(H256::repeat_byte(1)).unwrap(),
+            H256::from_low_u64_be(10)
+        );
+        assert_eq!(smt.get(H256::repeat_byte(3)).unwrap(), H256::zero());
+
+        smt.remove(H256::repeat_byte(1)).unwrap();
+        smt.remove(H256::repeat_byte(2)).unwrap();
+        assert_eq!(smt.root(), empty_root);
+    }
+
+    #[test]
+    pub fn test_smt_load_proof_and_update() {
+        let mut full = SparseMerkleTree::<KeccakHasher>::empty();
+        for i in 1..10u64 {
+            full.set(
+                H256::from_low_u64_be(i * 1_000_003),
+                H256::from_low_u64_be(i),
+            )
+            .unwrap();
+        }
+
+        let key = H256::from_low_u64_be(5 * 1_000_003);
+        let mut partial = SparseMerkleTree::<KeccakHasher>::from_root(full.root().unwrap());
+        assert!(partial
+            .load_proof(key, H256::zero(), full.get_proof(key).unwrap())
+            .is_err());
+        partial
+            .load_proof(key, H256::from_low_u64_be(5), full.get_proof(key).unwrap())
+            .unwrap();
+        assert_eq!(partial.get(key).unwrap(), H256::from_low_u64_be(5));
+
+        full.set(key, H256::from_low_u64_be(55)).unwrap();
+        partial.set(key, H256::from_low_u64_be(55)).unwrap();
+        assert_eq!(partial.root(), full.root());
+
+        assert!(partial.get(H256::from_low_u64_be(1_000_003)).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+use bytes::BytesMut;
+use ethers_core::{
+    types::{Bytes, H256, U256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+use crate::{
+    nodes::LeafValue,
+    state_trie::{decode_slim_account, encode_slim_account, AccountData},
+    Error, StateTrie,
+};
+
+// message codes of snap/1, without the devp2p offset.
+pub const GET_ACCOUNT_RANGE_MSG: u8 = 0x00;
+pub const ACCOUNT_RANGE_MSG: u8 = 0x01;
+pub const GET_STORAGE_RANGES_MSG: u8 = 0x02;
+pub const STORAGE_RANGES_MSG: u8 = 0x03;
+
+fn append_proof(stream: &mut RlpStream, proof: &[Bytes]) {
+    stream.begin_list(proof.len());
+    for node in proof {
+        stream.append(&BytesMut::from(node.as_ref()));
+    }
+}
+
+fn decode_proof(rlp: Rlp) -> Result<Vec<Bytes>, Error> {
+    rlp.iter()
+        .map(|node| Ok(Bytes::from(node.data()?.to_vec())))
+        .collect()
+}
+
+// origin and limit may be sent empty, for the first and the last hash.
+fn decode_bound(rlp: Rlp, empty: H256) -> Result<H256, Error> {
+    match rlp.data()? {
+        [] => Ok(empty),
+        raw if raw.len() == 32 => Ok(H256::from_slice(raw)),
+        _ => Err(Error::InternalError("range bound is not 32 bytes")),
+    }
+}
+
+fn check_fields(rlp: &Rlp, fields: usize) -> Result<(), Error> {
+    if rlp.item_count()? != fields {
+        return Err(Error::InternalError(
+            "snap message has the wrong number of fields",
+        ));
+    }
+    Ok(())
+}
+
+// GetAccountRange: [request id, state root, origin, limit, response bytes].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetAccountRange {
+    pub request_id: u64,
+    pub root: H256,
+    pub origin: H256,
+    pub limit: H256,
+    pub response_bytes: u64,
+}
+
+impl GetAccountRange {
+    pub fn encode(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(5);
+        stream.append(&self.request_id);
+        stream.append(&self.root);
+        stream.append(&self.origin);
+        stream.append(&self.limit);
+        stream.append(&self.response_bytes);
+        stream.out().freeze().into()
+    }
+
+    pub fn decode(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        check_fields(&rlp, 5)?;
+        Ok(GetAccountRange {
+            request_id: rlp.val_at(0)?,
+            root: rlp.val_at(1)?,
+            origin: decode_bound(rlp.at(2)?, H256::zero())?,
+            limit: decode_bound(rlp.at(3)?, H256::repeat_byte(0xff))?,
+            response_bytes: rlp.val_at(4)?,
+        })
+    }
+}
+
+// AccountRange: [request id, [[account hash, slim account], ...], [node, ...]], the accounts
+// from the origin on and the proof of the origin and of the last account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountRange {
+    pub request_id: u64,
+    pub accounts: Vec<(H256, AccountData)>,
+    pub proof: Vec<Bytes>,
+}
+
+impl AccountRange {
+    pub fn encode(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&self.request_id);
+        stream.begin_list(self.accounts.len());
+        for (account_hash, account) in self.accounts.iter() {
+            stream.begin_list(2);
+            stream.append(account_hash);
+            stream.append(&encode_slim_account(account).as_ref());
+        }
+        append_proof(&mut stream, &self.proof);
+        stream.out().freeze().into()
+    }
+
+    pub fn decode(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        check_fields(&rlp, 3)?;
+        let mut accounts = Vec::new();
+        for item in rlp.at(1)?.iter() {
+            check_fields(&item, 2)?;
+            accounts.push((item.val_at(0)?, decode_slim_account(item.at(1)?.data()?)?));
+        }
+        Ok(AccountRange {
+            request_id: rlp.val_at(0)?,
+            accounts,
+            proof: decode_proof(rlp.at(2)?)?,
+        })
+    }
+}
+
+// GetStorageRanges: [request id, state root, [account hash, ...], origin, limit, response
+// bytes]. origin and limit are for the first account, the others are asked for whole.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetStorageRanges {
+    pub request_id: u64,
+    pub root: H256,
+    pub accounts: Vec<H256>,
+    pub origin: H256,
+    pub limit: H256,
+    pub response_bytes: u64,
+}
+
+impl GetStorageRanges {
+    pub fn encode(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(6);
+        stream.append(&self.request_id);
+        stream.append(&self.root);
+        stream.append_list(&self.accounts);
+        stream.append(&self.origin);
+        stream.append(&self.limit);
+        stream.append(&self.response_bytes);
+        stream.out().freeze().into()
+    }
+
+    pub fn decode(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        check_fields(&rlp, 6)?;
+        Ok(GetStorageRanges {
+            request_id: rlp.val_at(0)?,
+            root: rlp.val_at(1)?,
+            accounts: rlp.list_at(2)?,
+            origin: decode_bound(rlp.at(3)?, H256::zero())?,
+            limit: decode_bound(rlp.at(4)?, H256::repeat_byte(0xff))?,
+            response_bytes: rlp.val_at(5)?,
+        })
+    }
+}
+
+// StorageRanges: [request id, [[[slot hash, rlp of the value], ...], ...], [node, ...]], the
+// slots of the first accounts asked for. only the last one can be cut short, the proof is
+// for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageRanges {
+    pub request_id: u64,
+    pub slots: Vec<Vec<(H256, U256)>>,
+    pub proof: Vec<Bytes>,
+}
+
+impl StorageRanges {
+    pub fn encode(&self) -> Result<Bytes, Error> {
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&self.request_id);
+        stream.begin_list(self.slots.len());
+        for slots in self.slots.iter() {
+            stream.begin_list(slots.len());
+            for (slot_hash, value) in slots {
+                stream.begin_list(2);
+                stream.append(slot_hash);
+                stream.append(&value.to_raw_rlp()?.as_ref());
+            }
+        }
+        append_proof(&mut stream, &self.proof);
+        Ok(stream.out().freeze().into())
+    }
+
+    pub fn decode(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        check_fields(&rlp, 3)?;
+        let mut slots = Vec::new();
+        for account in rlp.at(1)?.iter() {
+            let mut account_slots = Vec::new();
+            for item in account.iter() {
+                check_fields(&item, 2)?;
+                let value = Bytes::from(item.at(1)?.data()?.to_vec());
+                account_slots.push((item.val_at(0)?, U256::from_raw_rlp(value)?));
+            }
+            slots.push(account_slots);
+        }
+        Ok(StorageRanges {
+            request_id: rlp.val_at(0)?,
+            slots,
+            proof: decode_proof(rlp.at(2)?)?,
+        })
+    }
+}
+
+impl StateTrie {
+    fn check_snap_response(
+        &self,
+        request_id: u64,
+        response_id: u64,
+        root: H256,
+    ) -> Result<(), Error> {
+        if request_id != response_id {
+            return Err(Error::InternalError("snap response is for another request"));
+        }
+        if self.root() != Some(root) {
+            return Err(Error::InternalError(
+                "snap request is for another state root",
+            ));
+        }
+        Ok(())
+    }
+
+    // checks an AccountRange against the request it answers and loads it, see
+    // load_account_range. returns whether there are more accounts to ask for.
+    pub fn load_snap_accounts(
+        &mut self,
+        request: &GetAccountRange,
+        response: &AccountRange,
+    ) -> Result<bool, Error> {
+        self.check_snap_response(request.request_id, response.request_id, request.root)?;
+        self.load_account_range(request.origin, response.accounts.clone(), &response.proof)
+    }
+
+    // same for a StorageRanges, the accounts have to be loaded. the answer can stop before the
+    // last account asked for, returns whether the last account it has got more slots.
+    pub fn load_snap_storage(
+        &mut self,
+        request: &GetStorageRanges,
+        response: &StorageRanges,
+    ) -> Result<bool, Error> {
+        self.check_snap_response(request.request_id, response.request_id, request.root)?;
+        if response.slots.len() > request.accounts.len() {
+            return Err(Error::InternalError(
+                "storage ranges for more accounts than asked for",
+            ));
+        }
+        let mut more = false;
+        for (i, (account_hash, slots)) in request.accounts.iter().zip(&response.slots).enumerate() {
+            let origin = if i == 0 { request.origin } else { H256::zero() };
+            let proof: &[Bytes] = if i + 1 == response.slots.len() {
+                &response.proof
+            } else {
+                &[]
+            };
+            more = self.load_storage_range(*account_hash, origin, slots.clone(), proof)?;
+        }
+        Ok(more)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccountRange, GetAccountRange, GetStorageRanges, StorageRanges};
+    use crate::{nibbles::Nibbles, state_trie::AccountData, StateTrie};
+    use ethers_core::{
+        types::{Address, BigEndianHash, Bytes, H256, U256},
+        utils::keccak256,
+    };
+
+    fn state() -> StateTrie {
+        let mut state_trie = StateTrie::new();
+        for i in 1..=40u64 {
+            state_trie
+                .account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        for slot in 1..=30u64 {
+            state_trie
+                .set_storage_value(
+                    Address::from_low_u64_be(5),
+                    U256::from(slot),
+                    U256::from(slot + 100),
+                )
+                .unwrap();
+        }
+        state_trie
+    }
+
+    fn path(hash: H256) -> Nibbles {
+        Nibbles::from_raw_path(Bytes::from(hash.as_bytes().to_vec()))
+    }
+
+    fn next(hash: H256) -> H256 {
+        H256::from_uint(&(hash.into_uint() + 1))
+    }
+
+    // what a server sends for `entries`, the proof of the origin and of the last entry.
+    fn proof(proofs: impl Fn(Nibbles) -> Vec<Bytes>, origin: H256, last: H256) -> Vec<Bytes> {
+        let mut nodes = proofs(path(origin));
+        for node in proofs(path(last)) {
+            if !nodes.contains(&node) {
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    pub fn test_snap_codecs() {
+        let get_accounts = GetAccountRange {
+            request_id: 1,
+            root: H256::repeat_byte(1),
+            origin: H256::zero(),
+            limit: H256::repeat_byte(0xff),
+            response_bytes: 512 * 1024,
+        };
+        assert_eq!(
+            GetAccountRange::decode(&get_accounts.encode()).unwrap(),
+            get_accounts
+        );
+        let accounts = AccountRange {
+            request_id: 1,
+            accounts: vec![
+                (H256::repeat_byte(2), AccountData::default()),
+                (
+                    H256::repeat_byte(3),
+                    AccountData {
+                        balance: U256::from(5),
+                        storage_root: H256::repeat_byte(4),
+                        ..Default::default()
+                    },
+                ),
+            ],
+            proof: vec![vec![0xc2, 0x80, 0x80].into()],
+        };
+        assert_eq!(AccountRange::decode(&accounts.encode()).unwrap(), accounts);
+
+        let get_storage = GetStorageRanges {
+            request_id: 2,
+            root: H256::repeat_byte(1),
+            accounts: vec![H256::repeat_byte(2), H256::repeat_byte(3)],
+            origin: H256::repeat_byte(0x10),
+            limit: H256::repeat_byte(0xff),
+            response_bytes: 1024,
+        };
+        assert_eq!(
+            GetStorageRanges::decode(&get_storage.encode()).unwrap(),
+            get_storage
+        );
+        let storage = StorageRanges {
+            request_id: 2,
+            slots: vec![
+                vec![(H256::repeat_byte(5), U256::from(1))],
+                vec![
+                    (H256::repeat_byte(6), U256::MAX),
+                    (H256::repeat_byte(7), U256::from(9)),
+                ],
+            ],
+            proof: vec![],
+        };
+        assert_eq!(
+            StorageRanges::decode(&storage.encode().unwrap()).unwrap(),
+            storage
+        );
+        assert!(AccountRange::decode(&get_accounts.encode()).is_err());
+    }
+
+    #[test]
+    pub fn test_load_snap_ranges() {
+        let full = state();
+        let root = full.root().unwrap();
+        let mut accounts: Vec<(H256, AccountData)> = (1..=40u64)
+            .map(|i| {
+                let address = Address::from_low_u64_be(i);
+                (
+                    H256::from(keccak256(address)),
+                    full.account_trie.get(address).unwrap(),
+                )
+            })
+            .collect();
+        accounts.sort_by_key(|(hash, _)| *hash);
+        let account_proofs = |path: Nibbles| full.account_trie.get_proof_path(path).unwrap();
+
+        // two pages of accounts.
+        let mut partial = StateTrie::from_root(root);
+        let mut origin = H256::zero();
+        for (page, more) in [(&accounts[..25], true), (&accounts[25..], false)] {
+            let request = GetAccountRange {
+                request_id: 7,
+                root,
+                origin,
+                limit: H256::repeat_byte(0xff),
+                response_bytes: 1024,
+            };
+            let last = page[page.len() - 1].0;
+            let response = AccountRange {
+                request_id: 7,
+                accounts: page.to_vec(),
+                proof: proof(account_proofs, origin, last),
+            };
+            let response = AccountRange::decode(&response.encode()).unwrap();
+            assert_eq!(
+                partial.load_snap_accounts(&request, &response).unwrap(),
+                more
+            );
+            origin = next(last);
+        }
+        for i in 1..=40u64 {
+            assert_eq!(
+                partial
+                    .account_trie
+                    .get(Address::from_low_u64_be(i))
+                    .unwrap()
+                    .balance,
+                U256::from(i)
+            );
+        }
+
+        // two pages of the contract's storage.
+        let contract = Address::from_low_u64_be(5);
+        let mut slots: Vec<(H256, U256)> = (1..=30u64)
+            .map(|slot| {
+                (
+                    H256::from(keccak256(H256::from_uint(&U256::from(slot)))),
+                    U256::from(slot + 100),
+                )
+            })
+            .collect();
+        slots.sort();
+        let storage_trie = full
+            .loaded_storage_trie(&full.get_storage_root(contract).unwrap())
+            .unwrap();
+        let storage_proofs = |path: Nibbles| storage_trie.get_proof_path(path).unwrap();
+        let mut origin = H256::zero();
+        for (page, more) in [(&slots[..10], true), (&slots[10..], false)] {
+            let request = GetStorageRanges {
+                request_id: 8,
+                root,
+                accounts: vec![H256::from(keccak256(contract))],
+                origin,
+                limit: H256::repeat_byte(0xff),
+                response_bytes: 1024,
+            };
+            let last = page[page.len() - 1].0;
+            let response = StorageRanges {
+                request_id: 8,
+                slots: vec![page.to_vec()],
+                proof: proof(storage_proofs, origin, last),
+            };
+            assert_eq!(
+                partial.load_snap_storage(&request, &response).unwrap(),
+                more
+            );
+            // the wrong request id.
+            assert!(partial
+                .load_snap_storage(
+                    &GetStorageRanges {
+                        request_id: 9,
+                        ..request
+                    },
+                    &response
+                )
+                .is_err());
+            origin = next(last);
+        }
+        for slot in 1..=30u64 {
+            assert_eq!(
+                partial.get_storage_at(contract, U256::from(slot)).unwrap(),
+                U256::from(slot + 100)
+            );
+        }
+        assert_eq!(partial.root(), full.root());
+    }
+}
@@ -1,27 +1,35 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
+
+use bytes::BytesMut;
 
 use crate::{
+    key_cache::hashed_address,
     nibbles::Nibbles,
     nodes::LeafValue,
     trie::{MptKey, Trie},
-    Error,
+    Error, ErrorContext,
 };
-use ethers::{
-    types::{Address, Bytes, H256, U256},
-    utils::{
-        keccak256,
-        rlp::{Rlp, RlpStream},
-    },
+use ethers_core::{
+    types::{Address, Bytes, H256, I256, U256},
+    utils::rlp::{Rlp, RlpStream},
 };
+use serde::{Deserialize, Serialize};
 
 pub type AccountTrie = Trie<Address, AccountData>;
 
 impl MptKey for Address {
     fn to_nibbles(&self) -> Result<Nibbles, Error> {
         Ok(Nibbles::from_raw_path(Bytes::from(
-            H256::from(keccak256(self)).as_bytes().to_vec(),
+            hashed_address(*self).as_bytes().to_vec(),
         )))
     }
+
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext {
+            address: Some(*self),
+            ..Default::default()
+        }
+    }
 }
 
 impl AccountTrie {
@@ -42,9 +50,88 @@ impl AccountTrie {
         data.code_hash = new_code_hash;
         self.set(address, data)
     }
+
+    // adds signed deltas to balances, e.g. the transfers of a bundle. all accounts are read
+    // and every delta checked before anything is written, so an overflow, a balance going
+    // below zero or an account which isn't loaded leaves the trie as it was, and so does a
+    // write which fails (see set_all). deltas for the same address apply in order, and each
+    // account is written once.
+    pub fn apply_balance_deltas(
+        &mut self,
+        deltas: impl IntoIterator<Item = (Address, I256)>,
+    ) -> Result<(), Error> {
+        let mut staged: Vec<(Address, AccountData)> = Vec::new();
+        let mut index: HashMap<Address, usize> = HashMap::new();
+        for (address, delta) in deltas {
+            let i = match index.get(&address) {
+                Some(i) => *i,
+                None => {
+                    staged.push((address, self.get(address)?));
+                    index.insert(address, staged.len() - 1);
+                    staged.len() - 1
+                }
+            };
+            let balance = staged[i].1.balance;
+            staged[i].1.balance = if delta.is_negative() {
+                balance
+                    .checked_sub(delta.unsigned_abs())
+                    .ok_or(Error::InternalError("balance would go below zero"))?
+            } else {
+                balance
+                    .checked_add(delta.unsigned_abs())
+                    .ok_or(Error::InternalError("balance overflows"))?
+            };
+        }
+
+        self.set_all(staged)
+    }
+
+    // reads the account once for several changes, which are written once by or_insert or
+    // or_default. reading errors (account not loaded) come out of those too.
+    pub fn entry(&mut self, address: Address) -> AccountEntry<'_> {
+        let account = self
+            .get(address)
+            .map(|account| Some(account).filter(|account| *account != AccountData::default()));
+        AccountEntry {
+            trie: self,
+            address,
+            account,
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// an account which is empty (all default fields) doesn't exist, like eip-161 says.
+#[must_use = "changes are only written by or_insert or or_default"]
+pub struct AccountEntry<'a> {
+    trie: &'a mut AccountTrie,
+    address: Address,
+    account: Result<Option<AccountData>, Error>,
+}
+
+impl AccountEntry<'_> {
+    // changes the account if it exists.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut AccountData)) -> Self {
+        if let Ok(Some(account)) = &mut self.account {
+            f(account);
+        }
+        self
+    }
+
+    // writes the account, or `default` if it doesn't exist, and returns what was written.
+    pub fn or_insert(self, default: AccountData) -> Result<AccountData, Error> {
+        let account = self.account?.unwrap_or(default);
+        self.trie.set(self.address, account.clone())?;
+        Ok(account)
+    }
+
+    pub fn or_default(self) -> Result<AccountData, Error> {
+        self.or_insert(AccountData::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(rename_all = "camelCase")]
 pub struct AccountData {
     pub nonce: U256,
     pub balance: U256,
@@ -64,13 +151,12 @@ impl LeafValue for AccountData {
     }
 
     fn to_raw_rlp(&self) -> Result<Bytes, Error> {
-        let mut rlp_stream = RlpStream::default();
-        rlp_stream.begin_list(4);
+        let mut rlp_stream = RlpStream::new_list_with_buffer(BytesMut::with_capacity(136), 4);
         rlp_stream.append(&self.nonce);
         rlp_stream.append(&self.balance);
         rlp_stream.append(&self.storage_root);
         rlp_stream.append(&self.code_hash);
-        Ok(Bytes::from(rlp_stream.out().to_vec()))
+        Ok(Bytes::from(rlp_stream.out().freeze()))
     }
 }
 
@@ -97,9 +183,9 @@ mod tests {
 
     use crate::trie::MptKey;
 
-    use super::{AccountData, AccountTrie, Address, LeafValue, U256};
-    use ethers::core::utils::hex;
-    use ethers::utils::parse_ether;
+    use super::{AccountData, AccountTrie, Address, LeafValue, I256, U256};
+    use ethers_core::utils::hex;
+    use ethers_core::utils::parse_ether;
 
     #[test]
     pub fn test_from_address_1() {
@@ -253,4 +339,127 @@ mod tests {
             "8da9a5b0d31d90c6aee4d3a29f80f026425ab967bb50b3a75b363ffde1c9c882"
         );
     }
+
+    #[test]
+    pub fn test_account_entry() {
+        let address = Address::from_low_u64_be(1);
+        let mut trie = AccountTrie::empty();
+
+        // doesn't exist, so only the default is written.
+        let account = trie
+            .entry(address)
+            .and_modify(|account| account.nonce += U256::one())
+            .or_insert(AccountData {
+                balance: U256::from(100),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(account.nonce, U256::zero());
+        assert_eq!(trie.get(address).unwrap().balance, U256::from(100));
+
+        trie.entry(address)
+            .and_modify(|account| {
+                account.nonce += U256::one();
+                account.balance -= U256::from(30);
+            })
+            .or_default()
+            .unwrap();
+        let mut expected = AccountTrie::empty();
+        expected.set_nonce(address, U256::one()).unwrap();
+        expected.set_balance(address, U256::from(70)).unwrap();
+        assert_eq!(trie.get(address).unwrap(), expected.get(address).unwrap());
+        assert_eq!(trie.root(), expected.root());
+
+        // not loaded.
+        let mut partial = AccountTrie::from_root(trie.root().unwrap());
+        assert!(partial.entry(address).or_default().is_err());
+    }
+
+    #[test]
+    pub fn test_apply_balance_deltas() {
+        let (a, b, c) = (
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            Address::from_low_u64_be(3),
+        );
+        let mut trie = AccountTrie::empty();
+        trie.set_balance(a, U256::from(100)).unwrap();
+        trie.set_balance(b, U256::from(50)).unwrap();
+
+        trie.apply_balance_deltas([
+            (a, I256::from(-30)),
+            (b, I256::from(30)),
+            (c, I256::from(7)),
+            (a, I256::from(-70)),
+        ])
+        .unwrap();
+        let mut expected = AccountTrie::empty();
+        expected.set_balance(b, U256::from(80)).unwrap();
+        expected.set_balance(c, U256::from(7)).unwrap();
+        assert_eq!(trie.get(a).unwrap().balance, U256::zero());
+        assert_eq!(trie.root(), expected.root());
+
+        // nothing is written if one delta fails.
+        let root = trie.root();
+        assert!(trie
+            .apply_balance_deltas([(b, I256::from(-10)), (c, I256::from(-8))])
+            .is_err());
+        assert_eq!(trie.root(), root);
+        trie.set_balance(c, U256::MAX).unwrap();
+        assert!(trie.apply_balance_deltas([(c, I256::one())]).is_err());
+        assert_eq!(trie.get(b).unwrap().balance, U256::from(80));
+
+        // not loaded.
+        let mut partial = AccountTrie::from_root(root.unwrap());
+        assert!(partial.apply_balance_deltas([(a, I256::one())]).is_err());
+    }
+
+    #[test]
+    pub fn test_apply_balance_deltas_failing_write() {
+        // three accounts right under the root branch.
+        let mut addresses: Vec<Address> = Vec::new();
+        for i in 1.. {
+            let address = Address::from_low_u64_be(i);
+            let nibble = address.to_nibbles().unwrap().first_nibble();
+            if addresses
+                .iter()
+                .all(|other| other.to_nibbles().unwrap().first_nibble() != nibble)
+            {
+                addresses.push(address);
+            }
+            if addresses.len() == 3 {
+                break;
+            }
+        }
+        let (a, b, c) = (addresses[0], addresses[1], addresses[2]);
+        let mut full = AccountTrie::empty();
+        for address in [a, b, c] {
+            full.set_balance(address, U256::from(5)).unwrap();
+        }
+        let mut partial = AccountTrie::from_root(full.root().unwrap());
+        for address in [a, c] {
+            partial
+                .load_proof(
+                    address,
+                    full.get(address).unwrap(),
+                    full.get_proof(address).unwrap(),
+                )
+                .unwrap();
+        }
+        let before = partial.clone();
+
+        // emptying c works, but then a and b are the only children of the root and emptying a
+        // needs b, which isn't loaded. c is back afterwards.
+        assert!(partial
+            .apply_balance_deltas([(c, I256::from(-5)), (a, I256::from(-5))])
+            .is_err());
+        assert_eq!(partial, before);
+        assert_eq!(partial.get(c).unwrap().balance, U256::from(5));
+        partial
+            .apply_balance_deltas([(c, I256::from(-5)), (a, I256::one())])
+            .unwrap();
+        full.apply_balance_deltas([(c, I256::from(-5)), (a, I256::one())])
+            .unwrap();
+        assert_eq!(partial.root(), full.root());
+    }
 }
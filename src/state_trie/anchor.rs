@@ -0,0 +1,118 @@
+use ethers_core::types::{BlockId, EIP1186ProofResponse, H256};
+
+use super::StateTrie;
+use crate::Error;
+
+// a state root from outside of the rpc, along with where it comes from, e.g. a root
+// verified by a helios or beacon light client. proofs are only as good as their anchor.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Anchor {
+    pub state_root: H256,
+    pub block_number: u64,
+    pub block_hash: H256,
+    // who verified the root, e.g. "helios".
+    pub source: String,
+}
+
+impl Anchor {
+    pub fn block_id(&self) -> BlockId {
+        BlockId::Hash(self.block_hash)
+    }
+}
+
+impl StateTrie {
+    pub fn from_anchor(anchor: Anchor) -> Self {
+        let mut state_trie = StateTrie::from_root(anchor.state_root);
+        state_trie.anchor = Some(anchor);
+        state_trie
+    }
+
+    pub fn anchor(&self) -> Option<&Anchor> {
+        self.anchor.as_ref()
+    }
+
+    // loads a proof that was verified against the given anchor. an unanchored trie takes the
+    // anchor if it has the same root, after that proofs for any other anchor are refused.
+    pub fn load_anchored_proof(
+        &mut self,
+        anchor: &Anchor,
+        proof: EIP1186ProofResponse,
+    ) -> Result<(), Error> {
+        match &self.anchor {
+            Some(current) if current != anchor => {
+                return Err(Error::InternalError(
+                    "proof is anchored to a different root than the trie",
+                ))
+            }
+            Some(_) => {}
+            None => {
+                if self.root() != Some(anchor.state_root) {
+                    return Err(Error::InternalError(
+                        "anchor state root does not match the trie root",
+                    ));
+                }
+                self.anchor = Some(anchor.clone());
+            }
+        }
+        self.load_proof(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Anchor;
+    use crate::{state_trie::StorageTrie, InMemoryProvider, ProofProvider, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    fn full_state() -> StateTrie {
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        for i in 1..5u64 {
+            state_trie
+                .account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        state_trie
+    }
+
+    #[tokio::test]
+    pub async fn test_anchored_proofs() {
+        let provider = InMemoryProvider::new(full_state());
+        let anchor = Anchor {
+            state_root: provider.state_trie().root().unwrap(),
+            block_number: 1,
+            block_hash: H256::repeat_byte(1),
+            source: "test".to_string(),
+        };
+        let proof = provider
+            .fetch_proof(Address::from_low_u64_be(1), vec![], Some(anchor.block_id()))
+            .await
+            .unwrap();
+
+        let mut state_trie = StateTrie::from_anchor(anchor.clone());
+        state_trie
+            .load_anchored_proof(&anchor, proof.clone())
+            .unwrap();
+        assert_eq!(state_trie.anchor(), Some(&anchor));
+
+        // same root, but a different provenance is still refused.
+        let other = Anchor {
+            source: "other".to_string(),
+            ..anchor.clone()
+        };
+        assert!(state_trie
+            .load_anchored_proof(&other, proof.clone())
+            .is_err());
+
+        // an unanchored trie picks up the anchor when the roots match.
+        let mut state_trie = StateTrie::from_root(anchor.state_root);
+        assert!(state_trie.anchor().is_none());
+        state_trie
+            .load_anchored_proof(&other, proof.clone())
+            .unwrap();
+        assert_eq!(state_trie.anchor(), Some(&other));
+
+        let mut state_trie = StateTrie::default();
+        assert!(state_trie.load_anchored_proof(&anchor, proof).is_err());
+    }
+}
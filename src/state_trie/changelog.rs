@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+use ethers_core::types::{Address, U256};
+
+use super::{AccountData, StateTrie};
+use crate::Error;
+
+// one write, with what was there before so a replay can check it starts from the same state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Mutation {
+    Account {
+        address: Address,
+        old: AccountData,
+        new: AccountData,
+    },
+    // replayed with set_storage_value, which also updates the account's storage root.
+    Storage {
+        address: Address,
+        slot: U256,
+        old: U256,
+        new: U256,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangelogEntry {
+    // position in the log of this trie, counting on across take_changelog calls.
+    pub seq: u64,
+    #[serde(flatten)]
+    pub mutation: Mutation,
+}
+
+// storage writes with the length of the account log when they were made, the account write
+// right there is the storage root change they caused.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Changelog {
+    pub(super) recording: bool,
+    next_seq: u64,
+    pub(super) storage: Vec<(usize, Address, U256, U256, U256)>,
+}
+
+// like the root subscribers, the log doesn't count for equality.
+impl PartialEq for Changelog {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Changelog {}
+
+impl StateTrie {
+    // records every account and storage write from here on, through the state trie or
+    // directly on account_trie, until the log is taken.
+    pub fn start_changelog(&mut self) {
+        self.changelog.recording = true;
+        self.account_trie.start_changelog();
+    }
+
+    // the writes since start_changelog or the last call, in order, and empties the log.
+    pub fn take_changelog(&mut self) -> Vec<ChangelogEntry> {
+        let accounts = self.account_trie.take_changelog();
+        let mut storage = std::mem::take(&mut self.changelog.storage)
+            .into_iter()
+            .peekable();
+
+        let mut mutations = Vec::with_capacity(accounts.len());
+        for (i, (address, old, new)) in accounts.into_iter().enumerate() {
+            let mut caused_by_storage = false;
+            while let Some((_, address, slot, old, new)) = storage.next_if(|entry| entry.0 == i) {
+                mutations.push(Mutation::Storage {
+                    address,
+                    slot,
+                    old,
+                    new,
+                });
+                caused_by_storage = true;
+            }
+            if !caused_by_storage {
+                mutations.push(Mutation::Account { address, old, new });
+            }
+        }
+
+        mutations
+            .into_iter()
+            .map(|mutation| {
+                let seq = self.changelog.next_seq;
+                self.changelog.next_seq += 1;
+                ChangelogEntry { seq, mutation }
+            })
+            .collect()
+    }
+
+    // applies a log taken from another trie, which has to start from the same values. an
+    // entry whose old value doesn't match is an error, entries before it stay applied.
+    pub fn apply_changelog(
+        &mut self,
+        entries: impl IntoIterator<Item = ChangelogEntry>,
+    ) -> Result<(), Error> {
+        for entry in entries {
+            match entry.mutation {
+                Mutation::Account { address, old, new } => {
+                    if self.account_trie.get(address)? != old {
+                        return Err(Error::InternalError(
+                            "changelog account does not match the trie",
+                        ));
+                    }
+                    self.account_trie.set(address, new)?;
+                }
+                Mutation::Storage {
+                    address,
+                    slot,
+                    old,
+                    new,
+                } => {
+                    if self.get_storage_at(address, slot)? != old {
+                        return Err(Error::InternalError(
+                            "changelog slot does not match the trie",
+                        ));
+                    }
+                    self.set_storage_value(address, slot, new)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangelogEntry, Mutation};
+    use crate::StateTrie;
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_changelog_replay() {
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut state_trie = StateTrie::new();
+        state_trie
+            .account_trie
+            .set_balance(a, U256::from(5))
+            .unwrap();
+        let mut replica = state_trie.clone();
+
+        state_trie.start_changelog();
+        state_trie
+            .account_trie
+            .set_balance(a, U256::from(7))
+            .unwrap();
+        state_trie
+            .set_storage_value(b, U256::one(), U256::from(3))
+            .unwrap();
+        state_trie
+            .set_storage_value(b, U256::one(), U256::from(4))
+            .unwrap();
+        state_trie.account_trie.set_nonce(b, U256::one()).unwrap();
+        // unchanged values aren't logged.
+        state_trie
+            .set_storage_value(b, U256::one(), U256::from(4))
+            .unwrap();
+
+        let changelog = state_trie.take_changelog();
+        assert_eq!(changelog.len(), 4);
+        assert_eq!(
+            changelog.iter().map(|entry| entry.seq).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(
+            changelog[1].mutation,
+            Mutation::Storage {
+                address: b,
+                slot: U256::one(),
+                old: U256::zero(),
+                new: U256::from(3),
+            }
+        );
+        assert!(matches!(
+            &changelog[3].mutation,
+            Mutation::Account { address, old, new }
+                if *address == b && old.nonce.is_zero() && new.nonce == U256::one()
+        ));
+
+        // exported as json and replayed.
+        let json = serde_json::to_string(&changelog).unwrap();
+        let imported: Vec<ChangelogEntry> = serde_json::from_str(&json).unwrap();
+        assert_eq!(imported, changelog);
+        replica.apply_changelog(imported).unwrap();
+        assert_eq!(replica.root(), state_trie.root());
+
+        // a replay on a different state is refused.
+        assert!(replica.apply_changelog(changelog).is_err());
+
+        // the log keeps counting.
+        state_trie
+            .account_trie
+            .set_balance(a, U256::from(8))
+            .unwrap();
+        assert_eq!(state_trie.take_changelog()[0].seq, 4);
+        assert!(state_trie.take_changelog().is_empty());
+    }
+}
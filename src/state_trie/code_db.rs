@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use ethers_core::{
+    types::{Address, Bytes, H256},
+    utils::keccak256,
+};
+
+use super::{AccountData, StateTrie};
+use crate::Error;
+
+// contract code by its hash, next to the trie which only has the hashes. stateless execution
+// needs the code of every account it calls. anything in here hashes to its key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CodeDb(HashMap<H256, Bytes>);
+
+impl CodeDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // returns the code hash.
+    pub fn insert(&mut self, code: Bytes) -> H256 {
+        let code_hash = H256::from(keccak256(&code));
+        self.0.insert(code_hash, code);
+        code_hash
+    }
+
+    // for code that comes with its hash, e.g. from a witness or a node.
+    pub fn insert_checked(&mut self, code_hash: H256, code: Bytes) -> Result<(), Error> {
+        if H256::from(keccak256(&code)) != code_hash {
+            return Err(Error::InternalError("code does not match its hash"));
+        }
+        self.0.insert(code_hash, code);
+        Ok(())
+    }
+
+    // the empty code is always known.
+    pub fn get(&self, code_hash: H256) -> Option<Bytes> {
+        if code_hash == AccountData::default().code_hash {
+            return Some(Bytes::new());
+        }
+        self.0.get(&code_hash).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // for the functions which take the codes as a map, e.g. portal_content.
+    pub fn as_map(&self) -> &HashMap<H256, Bytes> {
+        &self.0
+    }
+}
+
+impl StateTrie {
+    pub fn code_db(&self) -> &CodeDb {
+        &self.codes
+    }
+
+    pub fn code_db_mut(&mut self) -> &mut CodeDb {
+        &mut self.codes
+    }
+
+    // the code of an account, None if it isn't in the code db.
+    pub fn get_code(&self, address: Address) -> Result<Option<Bytes>, Error> {
+        Ok(self.codes.get(self.account_trie.get(address)?.code_hash))
+    }
+
+    // stores the code and points the account to it.
+    pub fn set_code(&mut self, address: Address, code: Bytes) -> Result<(), Error> {
+        let code_hash = self.codes.insert(code);
+        let mut account = self.account_trie.get(address)?;
+        account.code_hash = code_hash;
+        self.account_trie.set(address, account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CodeDb;
+    use crate::StateTrie;
+    use ethers_core::{
+        types::{Address, Bytes, H256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_code_db() {
+        let code = Bytes::from(vec![0x60, 0x00, 0x60, 0x00]);
+        let mut codes = CodeDb::new();
+        let code_hash = codes.insert(code.clone());
+        assert_eq!(code_hash, H256::from(keccak256(&code)));
+        assert_eq!(codes.get(code_hash), Some(code.clone()));
+        assert_eq!(codes.get(H256::from(keccak256([]))), Some(Bytes::new()));
+        assert!(codes.get(H256::zero()).is_none());
+        assert!(codes.insert_checked(H256::zero(), code.clone()).is_err());
+        assert_eq!(codes.len(), 1);
+
+        let address = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::new();
+        assert_eq!(state_trie.get_code(address).unwrap(), Some(Bytes::new()));
+        state_trie.set_code(address, code.clone()).unwrap();
+        assert_eq!(state_trie.get_code(address).unwrap(), Some(code));
+        assert_eq!(
+            state_trie.account_trie.get(address).unwrap().code_hash,
+            code_hash
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ethers_core::types::{spoof, Address, Bytes, H256, U256, U64};
+
+use super::{AccountData, StateTrie, StorageTrie};
+use crate::Error;
+
+// changed fields of an account, as (from, to). None when the field is the same.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountDiff {
+    pub nonce: Option<(U256, U256)>,
+    pub balance: Option<(U256, U256)>,
+    pub code_hash: Option<(H256, H256)>,
+    pub storage: BTreeMap<U256, (U256, U256)>,
+    // the account was empty before (created) or is empty after (destroyed), fields that are
+    // not in the diff have their default value then.
+    pub created: bool,
+    pub destroyed: bool,
+}
+
+impl AccountDiff {
+    pub fn is_empty(&self) -> bool {
+        self.nonce.is_none()
+            && self.balance.is_none()
+            && self.code_hash.is_none()
+            && self.storage.is_empty()
+    }
+}
+
+// only accounts with at least one change are in here.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateDiff {
+    pub accounts: BTreeMap<Address, AccountDiff>,
+}
+
+fn changed<T: PartialEq + Copy>(from: T, to: T) -> Option<(T, T)> {
+    if from == to {
+        None
+    } else {
+        Some((from, to))
+    }
+}
+
+impl StateDiff {
+    // eth_call state override set with the new values. the trie only has code hashes, so
+    // the code of accounts whose code hash changed has to be in codes (keyed by code hash).
+    pub fn to_state_override(&self, codes: &HashMap<H256, Bytes>) -> Result<spoof::State, Error> {
+        let mut state = spoof::state();
+        for (address, diff) in self.accounts.iter() {
+            let account = state.account(*address);
+            if let Some((_, nonce)) = diff.nonce {
+                if nonce > U256::from(u64::MAX) {
+                    return Err(Error::InternalError("nonce does not fit in 64 bits"));
+                }
+                account.nonce(U64::from(nonce.as_u64()));
+            }
+            if let Some((_, balance)) = diff.balance {
+                account.balance(balance);
+            }
+            if let Some((_, code_hash)) = diff.code_hash {
+                let code = if code_hash == AccountData::default().code_hash {
+                    Bytes::default()
+                } else {
+                    codes.get(&code_hash).cloned().ok_or(Error::MissingData(
+                        "code for a changed code hash is not in codes",
+                    ))?
+                };
+                account.code(code);
+            }
+            for (slot, (_, value)) in diff.storage.iter() {
+                let mut key = [0u8; 32];
+                slot.to_big_endian(&mut key);
+                let mut val = [0u8; 32];
+                value.to_big_endian(&mut val);
+                account.store(H256::from(key), H256::from(val));
+            }
+        }
+        Ok(state)
+    }
+}
+
+impl StateTrie {
+    pub(crate) fn storage_value(&self, storage_root: H256, slot: U256) -> Result<U256, Error> {
+        match self.storage_tries.get(&storage_root) {
+            Some(storage_trie) => storage_trie.get(slot),
+            None => StorageTrie::from_root(storage_root).get(slot),
+        }
+    }
+
+    // changes from original to self for the given accounts and slots. both tries need the
+    // paths to these keys, e.g. a clone taken before applying the changes.
+    pub fn diff(
+        &self,
+        original: &StateTrie,
+        keys: &[(Address, Vec<U256>)],
+    ) -> Result<StateDiff, Error> {
+        let mut state_diff = StateDiff::default();
+        for (address, slots) in keys {
+            let from = original.account_trie.get(*address)?;
+            let to = self.account_trie.get(*address)?;
+
+            let mut diff = AccountDiff {
+                nonce: changed(from.nonce, to.nonce),
+                balance: changed(from.balance, to.balance),
+                code_hash: changed(from.code_hash, to.code_hash),
+                storage: BTreeMap::new(),
+                created: from == AccountData::default(),
+                destroyed: to == AccountData::default(),
+            };
+            if from.storage_root != to.storage_root {
+                for slot in slots {
+                    let from_value = original.storage_value(from.storage_root, *slot)?;
+                    let to_value = self.storage_value(to.storage_root, *slot)?;
+                    if from_value != to_value {
+                        diff.storage.insert(*slot, (from_value, to_value));
+                    }
+                }
+            }
+
+            if !diff.is_empty() {
+                state_diff.accounts.insert(*address, diff);
+            }
+        }
+        Ok(state_diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::{
+        types::{Address, Bytes, H256, U256},
+        utils::keccak256,
+    };
+
+    fn state_trie() -> StateTrie {
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        for i in 1..5u64 {
+            let address = Address::from_low_u64_be(i);
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(i * 100))
+                .unwrap();
+            state_trie
+                .set_storage_value(address, U256::from(i), U256::from(i))
+                .unwrap();
+        }
+        state_trie
+    }
+
+    #[test]
+    pub fn test_state_diff() {
+        let original = state_trie();
+        let mut state_trie = original.clone();
+
+        let sender = Address::from_low_u64_be(1);
+        let contract = Address::from_low_u64_be(2);
+        let code = Bytes::from(vec![0x60, 0x00]);
+        let code_hash = H256::from(keccak256(&code));
+
+        state_trie
+            .account_trie
+            .set_nonce(sender, U256::one())
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_balance(sender, U256::from(50))
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_code_hash(contract, code_hash)
+            .unwrap();
+        state_trie
+            .set_storage_value(contract, U256::from(2), U256::from(20))
+            .unwrap();
+        state_trie
+            .set_storage_value(contract, U256::from(9), U256::from(90))
+            .unwrap();
+
+        let keys = vec![
+            (sender, vec![]),
+            (contract, vec![U256::from(2), U256::from(3), U256::from(9)]),
+            (Address::from_low_u64_be(3), vec![U256::from(3)]),
+        ];
+        let diff = state_trie.diff(&original, &keys).unwrap();
+        assert_eq!(diff.accounts.len(), 2);
+
+        let sender_diff = &diff.accounts[&sender];
+        assert_eq!(sender_diff.nonce, Some((U256::zero(), U256::one())));
+        assert_eq!(sender_diff.balance, Some((U256::from(100), U256::from(50))));
+        assert!(sender_diff.storage.is_empty());
+        assert!(!sender_diff.created && !sender_diff.destroyed);
+
+        let contract_diff = &diff.accounts[&contract];
+        assert_eq!(contract_diff.balance, None);
+        assert_eq!(contract_diff.code_hash.unwrap().1, code_hash);
+        assert_eq!(contract_diff.storage.len(), 2);
+        assert_eq!(
+            contract_diff.storage[&U256::from(9)],
+            (U256::zero(), U256::from(90))
+        );
+
+        // a code hash without its code can't be overridden.
+        assert!(diff.to_state_override(&HashMap::new()).is_err());
+
+        let state = diff
+            .to_state_override(&HashMap::from([(code_hash, code)]))
+            .unwrap();
+        let json = serde_json::to_value(&state).unwrap();
+        let sender_json = &json[format!("{:?}", sender)];
+        assert_eq!(sender_json["nonce"], "0x1");
+        assert_eq!(sender_json["balance"], "0x32");
+        assert!(sender_json.get("stateDiff").is_none());
+
+        let contract_json = &json[format!("{:?}", contract)];
+        assert_eq!(contract_json["code"], "0x6000");
+        assert!(contract_json.get("balance").is_none());
+        assert_eq!(
+            contract_json["stateDiff"]
+                ["0x0000000000000000000000000000000000000000000000000000000000000009"],
+            "0x000000000000000000000000000000000000000000000000000000000000005a"
+        );
+    }
+
+    #[test]
+    pub fn test_state_diff_needs_loaded_keys() {
+        let original = state_trie();
+        let mut partial = StateTrie::from_root(original.root().unwrap());
+        let address = Address::from_low_u64_be(1);
+        assert!(partial.diff(&original, &[(address, vec![])]).is_err());
+
+        partial = original.clone();
+        assert!(partial
+            .diff(&original, &[(address, vec![U256::from(1)])])
+            .unwrap()
+            .accounts
+            .is_empty());
+    }
+}
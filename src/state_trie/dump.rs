@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use ethers_core::types::{Address, Bytes, H256, U256};
+use serde::Deserialize;
+
+use super::{AccountData, StateTrie, StorageTrie};
+use crate::Error;
+
+// account entry of geth's state dump (debug_dumpBlock, `geth dump`).
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpAccount {
+    // decimal string in geth's output, hex with 0x is accepted as well.
+    pub balance: String,
+    #[serde(default)]
+    pub nonce: u64,
+    pub root: Option<H256>,
+    pub code_hash: Option<H256>,
+    pub code: Option<Bytes>,
+    // slot to value, values are hex without leading zeros (and often without 0x).
+    #[serde(default)]
+    pub storage: BTreeMap<H256, String>,
+    // only set in the line by line format, or when the map key is not the address.
+    pub address: Option<Address>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct StateDump {
+    pub root: H256,
+    #[serde(default)]
+    pub accounts: BTreeMap<String, DumpAccount>,
+}
+
+impl StateDump {
+    // single json object, as returned by debug_dumpBlock.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    // `geth dump` line by line output: the root first, then one account per line.
+    pub fn from_json_lines(lines: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct RootLine {
+            root: H256,
+        }
+
+        let mut lines = lines.lines().filter(|line| !line.trim().is_empty());
+        let root = serde_json::from_str::<RootLine>(
+            lines
+                .next()
+                .ok_or(Error::InternalError("state dump is empty"))?,
+        )?
+        .root;
+
+        let mut accounts = BTreeMap::new();
+        for line in lines {
+            let account: DumpAccount = serde_json::from_str(line)?;
+            let address = account.address.ok_or(Error::InternalError(
+                "dump account is missing its address, preimages are needed",
+            ))?;
+            accounts.insert(format!("{:?}", address), account);
+        }
+        Ok(StateDump { root, accounts })
+    }
+}
+
+fn parse_u256(value: &str) -> Result<U256, Error> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(value).ok(),
+    };
+    parsed.ok_or(Error::InternalError("invalid number in state dump"))
+}
+
+fn parse_storage_value(value: &str) -> Result<U256, Error> {
+    let hex = value.strip_prefix("0x").unwrap_or(value);
+    if hex.is_empty() {
+        return Ok(U256::zero());
+    }
+    U256::from_str_radix(hex, 16)
+        .map_err(|_| Error::InternalError("invalid storage value in state dump"))
+}
+
+impl StateTrie {
+    // builds the complete account and storage tries of a dump, and checks the roots against
+    // the ones in it. storage keys need to be slots (preimages), not their hashes.
+    pub fn from_state_dump(dump: &StateDump) -> Result<Self, Error> {
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+
+        for (key, account) in dump.accounts.iter() {
+            let address = match account.address {
+                Some(address) => address,
+                None => key.parse::<Address>().map_err(|_| {
+                    Error::InternalError(
+                        "dump account is missing its address, preimages are needed",
+                    )
+                })?,
+            };
+
+            let mut storage_trie = StorageTrie::empty();
+            for (slot, value) in account.storage.iter() {
+                storage_trie.set(
+                    U256::from_big_endian(slot.as_bytes()),
+                    parse_storage_value(value)?,
+                )?;
+            }
+            let storage_root = storage_trie.root().unwrap();
+            if let Some(root) = account.root {
+                if root != storage_root {
+                    return Err(Error::InternalError(
+                        "storage root mismatch, dump may have hashed storage keys",
+                    ));
+                }
+            }
+
+            let code_hash = match (&account.code_hash, &account.code) {
+                (Some(code_hash), Some(code)) => {
+                    state_trie.codes.insert_checked(*code_hash, code.clone())?;
+                    *code_hash
+                }
+                (Some(code_hash), None) => *code_hash,
+                (None, Some(code)) => state_trie.codes.insert(code.clone()),
+                (None, None) => AccountData::default().code_hash,
+            };
+
+            state_trie.account_trie.set(
+                address,
+                AccountData {
+                    nonce: U256::from(account.nonce),
+                    balance: parse_u256(&account.balance)?,
+                    storage_root,
+                    code_hash,
+                },
+            )?;
+            state_trie.storage_tries.insert(storage_root, storage_trie);
+        }
+
+        if !dump.root.is_zero() && state_trie.root() != Some(dump.root) {
+            return Err(Error::InternalError("state root mismatch with the dump"));
+        }
+        Ok(state_trie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateDump;
+    use crate::StateTrie;
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_state_dump_roundtrip() {
+        // build a dump, compute its root, and check both formats import to the same root.
+        let json = r#"{
+            "root": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "accounts": {
+                "0x0000000000000000000000000000000000000001": {
+                    "balance": "1000000000000000000",
+                    "nonce": 3,
+                    "storage": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000000": "2a",
+                        "0x0000000000000000000000000000000000000000000000000000000000000005": "0x0100"
+                    }
+                },
+                "0x0000000000000000000000000000000000000002": {
+                    "balance": "0x10",
+                    "codeHash": "0x1111111111111111111111111111111111111111111111111111111111111111"
+                }
+            }
+        }"#;
+        let mut state_trie =
+            StateTrie::from_state_dump(&StateDump::from_json(json).unwrap()).unwrap();
+        let root = state_trie.root().unwrap();
+
+        let address = Address::from_low_u64_be(1);
+        assert_eq!(
+            state_trie.account_trie.get(address).unwrap().nonce,
+            U256::from(3)
+        );
+        assert_eq!(
+            state_trie.get_storage_at(address, U256::zero()).unwrap(),
+            U256::from(42)
+        );
+        assert_eq!(
+            state_trie.get_storage_at(address, U256::from(5)).unwrap(),
+            U256::from(256)
+        );
+
+        let lines = format!(
+            "{{\"root\": \"{:?}\"}}\n{}\n{}\n",
+            root,
+            r#"{"balance": "1000000000000000000", "nonce": 3, "address": "0x0000000000000000000000000000000000000001", "storage": {"0x0000000000000000000000000000000000000000000000000000000000000000": "2a", "0x0000000000000000000000000000000000000000000000000000000000000005": "0100"}}"#,
+            r#"{"balance": "16", "address": "0x0000000000000000000000000000000000000002", "codeHash": "0x1111111111111111111111111111111111111111111111111111111111111111"}"#,
+        );
+        let from_lines = StateTrie::from_state_dump(&StateDump::from_json_lines(&lines).unwrap());
+        assert_eq!(from_lines.unwrap().root(), Some(root));
+    }
+
+    #[test]
+    pub fn test_state_dump_storage_root_from_geth() {
+        // contract from the geth --dev state in state_trie tests, slot[1] = 2 and slot[2] = 4.
+        let json = r#"{
+            "root": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "accounts": {
+                "0x730E01e70B028b44a9387119d78E1392E4848Cbc": {
+                    "balance": "0",
+                    "nonce": 1,
+                    "root": "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c",
+                    "codeHash": "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16",
+                    "storage": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000001": "02",
+                        "0x0000000000000000000000000000000000000000000000000000000000000002": "04"
+                    }
+                }
+            }
+        }"#;
+        assert!(StateTrie::from_state_dump(&StateDump::from_json(json).unwrap()).is_ok());
+
+        // a wrong value gives a different storage root.
+        let json = json.replace("\"04\"", "\"05\"");
+        assert!(StateTrie::from_state_dump(&StateDump::from_json(&json).unwrap()).is_err());
+    }
+
+    #[test]
+    pub fn test_state_dump_root_mismatch() {
+        let json = r#"{
+            "root": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "accounts": {
+                "0x0000000000000000000000000000000000000001": { "balance": "1" }
+            }
+        }"#;
+        assert!(StateTrie::from_state_dump(&StateDump::from_json(json).unwrap()).is_err());
+    }
+}
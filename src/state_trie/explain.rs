@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use ethers_core::types::{Bytes, H256, U256};
+
+use super::{AccountData, StateTrie};
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nodes::{LeafValue, NodeData},
+    proof_provider::ProofProvider,
+    utils::{hex_nibbles, nibbles_to_hash},
+    Error,
+};
+
+type NodeSource<'a> = &'a dyn Fn(H256) -> Result<Option<Bytes>, Error>;
+
+enum Divergence<V> {
+    Same,
+    // their side needs this node to go on.
+    NeedNode(H256),
+    Explained(Vec<String>),
+    // the same key with different values.
+    Leaf { key: H256, ours: V, theirs: V },
+}
+
+fn describe<V: LeafValue>(node: &NodeData<V>, path: &[u8]) -> String {
+    match node {
+        NodeData::Leaf { key, .. } => {
+            let mut full = path.to_vec();
+            full.extend(key.to_u4_vec());
+            format!("a leaf for key {}", hex_nibbles(&full))
+        }
+        NodeData::Branch(_) => "a branch".to_string(),
+        NodeData::Extension { key, .. } => {
+            format!("an extension by {}", hex_nibbles(&key.to_u4_vec()))
+        }
+    }
+}
+
+// descends from two differing hashes along the children which differ, down to the first
+// place the tries aren't the same: a different value, a different shape or a missing node.
+fn first_divergence<V: LeafValue>(
+    ours: NodeSource,
+    theirs: NodeSource,
+    our_hash: H256,
+    their_hash: H256,
+    path: Vec<u8>,
+) -> Result<Divergence<V>, Error> {
+    if our_hash == their_hash {
+        return Ok(Divergence::Same);
+    }
+    let at = |path: &[u8]| match path.len() {
+        0 => "at the root".to_string(),
+        _ => format!("at path {}", hex_nibbles(path)),
+    };
+    let empty = KeccakHasher::empty_root();
+    if our_hash == empty || their_hash == empty {
+        let side = if our_hash == empty { "ours" } else { "theirs" };
+        return Ok(Divergence::Explained(vec![format!(
+            "{} {} is empty, the other side is not",
+            side,
+            at(&path)
+        )]));
+    }
+    let our_node = match ours(our_hash)? {
+        Some(raw) => NodeData::<V>::from_raw_rlp(raw)?,
+        None => {
+            return Ok(Divergence::Explained(vec![format!(
+                "our node {} ({:?}) is not loaded, theirs is {:?}",
+                at(&path),
+                our_hash,
+                their_hash
+            )]))
+        }
+    };
+    let their_node = match theirs(their_hash)? {
+        Some(raw) => NodeData::<V>::from_raw_rlp(raw)?,
+        None => return Ok(Divergence::NeedNode(their_hash)),
+    };
+
+    match (&our_node, &their_node) {
+        (NodeData::Branch(our_children), NodeData::Branch(their_children)) => {
+            for nibble in 0..16 {
+                let our_child = our_children[nibble].unwrap_or(empty);
+                let their_child = their_children[nibble].unwrap_or(empty);
+                if our_child != their_child {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble as u8);
+                    return first_divergence(ours, theirs, our_child, their_child, child_path);
+                }
+            }
+            Ok(Divergence::Same)
+        }
+        (
+            NodeData::Extension {
+                key: our_key,
+                node: our_child,
+            },
+            NodeData::Extension {
+                key: their_key,
+                node: their_child,
+            },
+        ) if our_key == their_key => {
+            let mut child_path = path.clone();
+            child_path.extend(our_key.to_u4_vec());
+            first_divergence(ours, theirs, *our_child, *their_child, child_path)
+        }
+        (
+            NodeData::Leaf {
+                key: our_key,
+                value: our_value,
+            },
+            NodeData::Leaf {
+                key: their_key,
+                value: their_value,
+            },
+        ) if our_key == their_key => {
+            let mut full = path.clone();
+            full.extend(our_key.to_u4_vec());
+            Ok(Divergence::Leaf {
+                key: nibbles_to_hash(&full)?,
+                ours: our_value.to_owned(),
+                theirs: their_value.to_owned(),
+            })
+        }
+        _ => Ok(Divergence::Explained(vec![format!(
+            "different keys {}: ours is {}, theirs is {}",
+            at(&path),
+            describe(&our_node, &path),
+            describe(&their_node, &path)
+        )])),
+    }
+}
+
+impl StateTrie {
+    fn account_name(&self, address_hash: H256) -> String {
+        match self.addresses.get(&address_hash) {
+            Some(address) => format!("account {:?}", address),
+            None => format!("account with hash {:?}", address_hash),
+        }
+    }
+
+    fn slot_name(&self, slot_hash: H256) -> String {
+        match self.slots.get(&slot_hash) {
+            Some(slot) => format!("slot {:#x}", slot),
+            None => format!("slot with hash {:?}", slot_hash),
+        }
+    }
+
+    fn explain_from(&self, theirs: NodeSource, their_root: H256) -> Result<Divergence<()>, Error> {
+        let ours = |hash: H256| self.get_raw_node(hash);
+        let our_root = self.root().ok_or(Error::Usage("root not set"))?;
+        let (address_hash, ours_account, theirs_account) =
+            match first_divergence::<AccountData>(&ours, theirs, our_root, their_root, vec![])? {
+                Divergence::Leaf { key, ours, theirs } => (key, ours, theirs),
+                Divergence::Same => return Ok(Divergence::Same),
+                Divergence::NeedNode(hash) => return Ok(Divergence::NeedNode(hash)),
+                Divergence::Explained(lines) => return Ok(Divergence::Explained(lines)),
+            };
+
+        let account = self.account_name(address_hash);
+        let mut lines = Vec::new();
+        if ours_account.nonce != theirs_account.nonce {
+            lines.push(format!(
+                "{} nonce differs: ours {}, theirs {}",
+                account, ours_account.nonce, theirs_account.nonce
+            ));
+        }
+        if ours_account.balance != theirs_account.balance {
+            lines.push(format!(
+                "{} balance differs: ours {}, theirs {}",
+                account, ours_account.balance, theirs_account.balance
+            ));
+        }
+        if ours_account.code_hash != theirs_account.code_hash {
+            lines.push(format!(
+                "{} code hash differs: ours {:?}, theirs {:?}",
+                account, ours_account.code_hash, theirs_account.code_hash
+            ));
+        }
+        if ours_account.storage_root != theirs_account.storage_root {
+            match first_divergence::<U256>(
+                &ours,
+                theirs,
+                ours_account.storage_root,
+                theirs_account.storage_root,
+                vec![],
+            )? {
+                Divergence::Leaf { key, ours, theirs } => lines.push(format!(
+                    "{} of {} differs: ours {:#x}, theirs {:#x}",
+                    self.slot_name(key),
+                    account,
+                    ours,
+                    theirs
+                )),
+                Divergence::Explained(storage_lines) => lines.extend(
+                    storage_lines
+                        .into_iter()
+                        .map(|line| format!("storage of {}: {}", account, line)),
+                ),
+                Divergence::NeedNode(hash) => return Ok(Divergence::NeedNode(hash)),
+                Divergence::Same => {}
+            }
+        }
+        Ok(Divergence::Explained(lines))
+    }
+
+    // why the root isn't the one of `reference`: the first place the two tries differ, as a
+    // few lines like "account 0xab.. balance differs: ours 1, theirs 2". empty if the roots
+    // match. only loaded nodes can be compared, a missing node is reported as such.
+    pub fn explain(&self, reference: &StateTrie) -> Result<Vec<String>, Error> {
+        let their_root = reference.root().ok_or(Error::Usage("root not set"))?;
+        let theirs = |hash: H256| reference.get_raw_node(hash);
+        match self.explain_from(&theirs, their_root)? {
+            Divergence::Explained(lines) => Ok(lines),
+            Divergence::NeedNode(hash) => Ok(vec![format!(
+                "their node {:?} is not loaded, load more of the reference",
+                hash
+            )]),
+            _ => Ok(vec![]),
+        }
+    }
+
+    // same as explain against the state with `expected_root`, fetching their nodes from the
+    // provider as the walk needs them.
+    pub async fn explain_with_provider<P: ProofProvider>(
+        &self,
+        expected_root: H256,
+        provider: &P,
+    ) -> Result<Vec<String>, Error> {
+        let mut fetched: HashMap<H256, Bytes> = HashMap::new();
+        loop {
+            let theirs = |hash: H256| Ok(fetched.get(&hash).cloned());
+            match self.explain_from(&theirs, expected_root)? {
+                Divergence::Explained(lines) => return Ok(lines),
+                Divergence::NeedNode(hash) => {
+                    let raw = provider.fetch_node(hash).await?;
+                    if KeccakHasher::hash(&raw) != hash {
+                        return Err(Error::InternalError("fetched node does not match its hash"));
+                    }
+                    fetched.insert(hash, raw);
+                }
+                _ => return Ok(vec![]),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{InMemoryProvider, StateTrie};
+    use ethers_core::types::{Address, U256};
+
+    fn state() -> StateTrie {
+        let mut state_trie = StateTrie::new();
+        for i in 1..30u64 {
+            let address = Address::from_low_u64_be(i);
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+            state_trie
+                .set_storage_value(address, U256::from(i), U256::from(i * 2))
+                .unwrap();
+        }
+        state_trie
+    }
+
+    #[test]
+    pub fn test_explain() {
+        let reference = state();
+        let mut ours = state();
+        assert!(ours.explain(&reference).unwrap().is_empty());
+
+        let address = Address::from_low_u64_be(7);
+        ours.account_trie
+            .set_balance(address, U256::from(1000))
+            .unwrap();
+        assert_eq!(
+            ours.explain(&reference).unwrap(),
+            vec![format!(
+                "account {:?} balance differs: ours 1000, theirs 7",
+                address
+            )]
+        );
+
+        let mut ours = state();
+        ours.set_storage_value(address, U256::from(7), U256::from(1))
+            .unwrap();
+        assert_eq!(
+            ours.explain(&reference).unwrap(),
+            vec![format!(
+                "slot 0x7 of account {:?} differs: ours 0x1, theirs 0xe",
+                address
+            )]
+        );
+
+        // a key only on one side.
+        let mut ours = state();
+        ours.account_trie
+            .set_balance(Address::from_low_u64_be(100), U256::one())
+            .unwrap();
+        let lines = ours.explain(&reference).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0].starts_with("different keys at path"),
+            "{}",
+            lines[0]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_explain_with_provider() {
+        let reference = state();
+        let address = Address::from_low_u64_be(3);
+        let mut ours = StateTrie::from_root(reference.root().unwrap());
+        ours.load_proof(reference.get_proof(address, vec![]).unwrap())
+            .unwrap();
+        ours.account_trie.set_nonce(address, U256::from(5)).unwrap();
+
+        let provider = InMemoryProvider::new(reference.clone());
+        let lines = ours
+            .explain_with_provider(reference.root().unwrap(), &provider)
+            .await
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![format!(
+                "account {:?} nonce differs: ours 5, theirs 0",
+                address
+            )]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use ethers_core::types::{Address, U256};
+
+use super::{AccountData, AccountTrie, StateTrie, StorageTrie};
+use crate::Error;
+
+impl StateTrie {
+    // builds the complete tries from a flat (plain state) export, like erigon's PlainState:
+    // address -> account and (address, slot) -> value. storage roots are computed from the
+    // slots, the storage_root in the given accounts is ignored.
+    pub fn from_flat_state(
+        accounts: impl IntoIterator<Item = (Address, AccountData)>,
+        storage: impl IntoIterator<Item = (Address, U256, U256)>,
+    ) -> Result<Self, Error> {
+        let mut slots_by_address: HashMap<Address, Vec<(U256, U256)>> = HashMap::new();
+        let mut slots = Vec::new();
+        for (address, slot, value) in storage {
+            slots.push(slot);
+            slots_by_address
+                .entry(address)
+                .or_default()
+                .push((slot, value));
+        }
+
+        let mut storage_tries = HashMap::new();
+        let mut account_entries = Vec::new();
+        for (address, mut account_data) in accounts {
+            let storage_trie = match slots_by_address.remove(&address) {
+                Some(slots) => StorageTrie::from_entries(slots)?,
+                None => StorageTrie::empty(),
+            };
+            account_data.storage_root = storage_trie.root().unwrap();
+            storage_tries.insert(account_data.storage_root, storage_trie);
+            account_entries.push((address, account_data));
+        }
+        if !slots_by_address.is_empty() {
+            return Err(Error::InternalError(
+                "storage for an account which is not in the export",
+            ));
+        }
+
+        let addresses: Vec<Address> = account_entries
+            .iter()
+            .map(|(address, _)| *address)
+            .collect();
+        let mut state_trie = StateTrie {
+            account_trie: AccountTrie::from_entries(account_entries)?,
+            storage_tries,
+            ..Default::default()
+        };
+        for address in addresses {
+            state_trie.remember_address(address);
+        }
+        for slot in slots {
+            state_trie.remember_slot(slot);
+        }
+        Ok(state_trie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        state_trie::{AccountData, StateDump},
+        StateTrie,
+    };
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_flat_state_matches_dump() {
+        let json = r#"{
+            "root": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "accounts": {
+                "0x0000000000000000000000000000000000000001": {
+                    "balance": "100", "nonce": 1,
+                    "storage": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000001": "02",
+                        "0x0000000000000000000000000000000000000000000000000000000000000002": "04"
+                    }
+                },
+                "0x0000000000000000000000000000000000000002": { "balance": "5" }
+            }
+        }"#;
+        let from_dump = StateTrie::from_state_dump(&StateDump::from_json(json).unwrap()).unwrap();
+
+        let accounts = vec![
+            (
+                Address::from_low_u64_be(1),
+                AccountData {
+                    balance: U256::from(100),
+                    nonce: U256::one(),
+                    ..Default::default()
+                },
+            ),
+            (
+                Address::from_low_u64_be(2),
+                AccountData {
+                    balance: U256::from(5),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let storage = vec![
+            (Address::from_low_u64_be(1), U256::from(2), U256::from(4)),
+            (Address::from_low_u64_be(1), U256::from(1), U256::from(2)),
+        ];
+        let mut from_flat = StateTrie::from_flat_state(accounts.clone(), storage.clone()).unwrap();
+        assert_eq!(from_flat.root(), from_dump.root());
+        assert_eq!(
+            from_flat
+                .get_storage_at(Address::from_low_u64_be(1), U256::from(2))
+                .unwrap(),
+            U256::from(4)
+        );
+
+        // storage without its account is rejected.
+        assert!(StateTrie::from_flat_state(
+            accounts,
+            vec![(Address::from_low_u64_be(3), U256::one(), U256::one())]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use ethers_core::types::{Address, H256};
+
+use super::{json::leaves, StateTrie};
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    Error,
+};
+
+// unresolved nodes of an account's storage, by nibble path from the storage root.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountFrontier {
+    // when the trie has seen the address, the account trie only has its hash.
+    pub address: Option<Address>,
+    pub nodes: Vec<(Vec<u8>, H256)>,
+}
+
+// the boundary of a partial state: every hash the loaded nodes point to whose node isn't
+// loaded, with its nibble path. a proof for a key under one of them moves the boundary down.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateFrontier {
+    pub accounts: Vec<(Vec<u8>, H256)>,
+    // by address hash, only the accounts which are loaded and have storage that isn't. a
+    // storage trie with nothing loaded is its root at the empty path.
+    pub storage: BTreeMap<H256, AccountFrontier>,
+}
+
+impl StateFrontier {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // unresolved nodes in all the tries.
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+            + self
+                .storage
+                .values()
+                .map(|account| account.nodes.len())
+                .sum::<usize>()
+    }
+}
+
+impl StateTrie {
+    pub fn frontier(&self) -> Result<StateFrontier, Error> {
+        let mut frontier = StateFrontier {
+            accounts: self.account_trie.frontier()?,
+            ..Default::default()
+        };
+        for (address_hash, account) in leaves(&self.account_trie)? {
+            if account.storage_root == KeccakHasher::empty_root() {
+                continue;
+            }
+            let nodes = match self.storage_tries.get(&account.storage_root) {
+                Some(storage_trie) => storage_trie.frontier()?,
+                None => vec![(vec![], account.storage_root)],
+            };
+            if !nodes.is_empty() {
+                frontier.storage.insert(
+                    address_hash,
+                    AccountFrontier {
+                        address: self.addresses.get(&address_hash).copied(),
+                        nodes,
+                    },
+                );
+            }
+        }
+        Ok(frontier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{key_cache::hashed_address, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_frontier() {
+        let accounts: Vec<Address> = (1..=4).map(Address::from_low_u64_be).collect();
+        let mut full = StateTrie::new();
+        for (i, address) in accounts.iter().enumerate() {
+            for slot in 1..=2u64 {
+                full.set_storage_value(*address, U256::from(slot), U256::from(i + 1))
+                    .unwrap();
+            }
+        }
+        assert!(full.frontier().unwrap().is_empty());
+
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        let frontier = partial.frontier().unwrap();
+        assert_eq!(frontier.accounts, vec![(vec![], full.root().unwrap())]);
+        assert!(frontier.storage.is_empty());
+
+        // one slot of the first account, and the second account without its storage.
+        partial
+            .load_proof(
+                full.get_proof(accounts[0], vec![H256::from_low_u64_be(1)])
+                    .unwrap(),
+            )
+            .unwrap();
+        partial
+            .load_proof(full.get_proof(accounts[1], vec![]).unwrap())
+            .unwrap();
+        let frontier = partial.frontier().unwrap();
+        assert!(!frontier.accounts.is_empty());
+        assert!(frontier.accounts.iter().all(|(_, hash)| partial
+            .account_trie
+            .get_raw_node(*hash)
+            .unwrap()
+            .is_none()));
+
+        let first = &frontier.storage[&hashed_address(accounts[0])];
+        assert_eq!(first.address, Some(accounts[0]));
+        assert_eq!(first.nodes.len(), 1);
+        assert_eq!(first.nodes[0].0.len(), 1);
+        let second = &frontier.storage[&hashed_address(accounts[1])];
+        assert_eq!(
+            second.nodes,
+            vec![(vec![], full.get_storage_root(accounts[1]).unwrap())]
+        );
+        assert_eq!(frontier.storage.len(), 2);
+        assert_eq!(frontier.len(), frontier.accounts.len() + 2);
+
+        // loading the other slot closes the first account's storage.
+        partial
+            .load_proof(
+                full.get_proof(accounts[0], vec![H256::from_low_u64_be(2)])
+                    .unwrap(),
+            )
+            .unwrap();
+        let frontier = partial.frontier().unwrap();
+        assert!(!frontier.storage.contains_key(&hashed_address(accounts[0])));
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use ethers_core::{
+    types::{Address, U256},
+    utils::{keccak256, Genesis, GenesisAccount},
+};
+
+use super::{AccountData, StateTrie};
+use crate::Error;
+
+impl StateTrie {
+    // genesis state of a chain from its alloc, the genesis state root is then root().
+    pub fn from_genesis_alloc(alloc: &HashMap<Address, GenesisAccount>) -> Result<Self, Error> {
+        let mut accounts = Vec::with_capacity(alloc.len());
+        let mut storage = Vec::new();
+        let mut codes = Vec::new();
+        for (address, account) in alloc.iter() {
+            let mut account_data = AccountData {
+                nonce: U256::from(account.nonce.unwrap_or_default()),
+                balance: account.balance,
+                ..Default::default()
+            };
+            if let Some(code) = &account.code {
+                account_data.code_hash = keccak256(code).into();
+                codes.push(code.clone());
+            }
+            accounts.push((*address, account_data));
+
+            for (slot, value) in account.storage.iter().flatten() {
+                storage.push((
+                    *address,
+                    U256::from_big_endian(slot.as_bytes()),
+                    U256::from_big_endian(value.as_bytes()),
+                ));
+            }
+        }
+        let mut state_trie = StateTrie::from_flat_state(accounts, storage)?;
+        for code in codes {
+            state_trie.codes.insert(code);
+        }
+        Ok(state_trie)
+    }
+
+    // same as from_genesis_alloc, reading a genesis.json.
+    pub fn from_genesis_json(json: &str) -> Result<Self, Error> {
+        let genesis: Genesis = serde_json::from_str(json)?;
+        StateTrie::from_genesis_alloc(&genesis.alloc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state_trie::StateDump, StateTrie};
+    use ethers_core::{
+        types::{Address, H256, U256},
+        utils::keccak256,
+    };
+
+    const GENESIS: &str = r#"{
+        "config": { "chainId": 1337 },
+        "difficulty": "0x1",
+        "gasLimit": "0x1c9c380",
+        "alloc": {
+            "0x0000000000000000000000000000000000000001": { "balance": "0x64", "nonce": "0x1" },
+            "0x0000000000000000000000000000000000000002": {
+                "balance": "0",
+                "code": "0x6000",
+                "storage": {
+                    "0x0000000000000000000000000000000000000000000000000000000000000001": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                    "0x0000000000000000000000000000000000000000000000000000000000000002": "0x0000000000000000000000000000000000000000000000000000000000000004"
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    pub fn test_genesis_alloc() {
+        let mut state_trie = StateTrie::from_genesis_json(GENESIS).unwrap();
+
+        let contract = Address::from_low_u64_be(2);
+        let account_data = state_trie.account_trie.get(contract).unwrap();
+        assert_eq!(account_data.code_hash, H256::from(keccak256([0x60, 0x00])));
+        assert_eq!(
+            state_trie.get_code(contract).unwrap().unwrap().to_vec(),
+            vec![0x60, 0x00]
+        );
+        // same storage as the geth dev contract in the state_trie tests.
+        assert_eq!(
+            account_data.storage_root,
+            "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            state_trie.get_storage_at(contract, U256::from(2)).unwrap(),
+            U256::from(4)
+        );
+
+        let json = r#"{
+            "root": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "accounts": {
+                "0x0000000000000000000000000000000000000001": { "balance": "100", "nonce": 1 },
+                "0x0000000000000000000000000000000000000002": {
+                    "balance": "0", "code": "0x6000",
+                    "storage": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000001": "02",
+                        "0x0000000000000000000000000000000000000000000000000000000000000002": "04"
+                    }
+                }
+            }
+        }"#;
+        let from_dump = StateTrie::from_state_dump(&StateDump::from_json(json).unwrap()).unwrap();
+        assert_eq!(state_trie.root(), from_dump.root());
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use ethers_core::types::Address;
+
+use super::{StateTrie, StorageTrie};
+use crate::heatmap::NodeHeatmap;
+
+// node accesses of the account trie, and of the storage of every account whose slots were
+// read or written through the state trie.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateHeatmap {
+    pub accounts: NodeHeatmap,
+    pub storage: BTreeMap<Address, NodeHeatmap>,
+}
+
+impl StateHeatmap {
+    // accounts with the most storage accesses first, the ones dominating a witness.
+    pub fn hottest_storage(&self, n: usize) -> Vec<(Address, u64)> {
+        let mut hottest = self
+            .storage
+            .iter()
+            .map(|(address, heatmap)| (*address, heatmap.subtree_accesses(&[])))
+            .collect::<Vec<_>>();
+        hottest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        hottest.truncate(n);
+        hottest
+    }
+}
+
+// storage tries are shared by root between accounts and copied for reads, so their
+// accesses are counted per call and added up by address here. doesn't count for equality.
+#[derive(Clone, Debug, Default)]
+pub(super) struct StorageHeat(Option<BTreeMap<Address, NodeHeatmap>>);
+
+impl StorageHeat {
+    pub(super) fn start(&self, storage_trie: &mut StorageTrie) {
+        if self.0.is_some() {
+            storage_trie.start_heatmap();
+        }
+    }
+
+    pub(super) fn add(&mut self, address: Address, storage_trie: &mut StorageTrie) {
+        let heatmap = storage_trie.take_heatmap();
+        if let Some(storage) = &mut self.0 {
+            storage.entry(address).or_default().merge(heatmap);
+        }
+    }
+}
+
+impl PartialEq for StorageHeat {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for StorageHeat {}
+
+impl StateTrie {
+    // counts node accesses of gets and sets from here on, on account_trie directly or through
+    // the state trie, until take_heatmap.
+    pub fn start_heatmap(&mut self) {
+        self.account_trie.start_heatmap();
+        self.storage_heat.0.get_or_insert_with(BTreeMap::new);
+    }
+
+    // the counts since start_heatmap, and stops counting.
+    pub fn take_heatmap(&mut self) -> StateHeatmap {
+        StateHeatmap {
+            accounts: self.account_trie.take_heatmap(),
+            storage: self.storage_heat.0.take().unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StateTrie;
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_state_heatmap() {
+        let mut state_trie = StateTrie::new();
+        let token = Address::from_low_u64_be(1);
+        let other = Address::from_low_u64_be(2);
+        for address in [token, other] {
+            state_trie
+                .account_trie
+                .set_balance(address, U256::one())
+                .unwrap();
+        }
+
+        state_trie.start_heatmap();
+        for i in 0..10u64 {
+            state_trie
+                .set_storage_value(token, U256::from(i), U256::from(i + 1))
+                .unwrap();
+        }
+        state_trie.get_storage_at(token, U256::one()).unwrap();
+        state_trie.get_storage_at(token, U256::one()).unwrap();
+        state_trie.get_storage_at(other, U256::one()).unwrap();
+        let heatmap = state_trie.take_heatmap();
+
+        assert_eq!(heatmap.storage[&token].walks, 12);
+        // an empty storage trie has no nodes to walk through.
+        assert_eq!(heatmap.storage[&other].walks, 1);
+        assert_eq!(heatmap.hottest_storage(1)[0].0, token);
+        assert_eq!(heatmap.storage[&token].hottest_keys(1)[0].1, 2);
+        // every storage access reads and writes the account too.
+        assert!(heatmap.accounts.walks >= 13);
+
+        assert!(state_trie.take_heatmap().storage.is_empty());
+    }
+}
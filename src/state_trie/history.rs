@@ -0,0 +1,100 @@
+use ethers_core::types::{Address, H256, U256};
+
+use super::{AccountData, StateTrie};
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    Error,
+};
+
+// versions of the state, see Trie::start_history. storage tries are kept by their root
+// already, the account trie keeps its replaced nodes too once history is started, so every
+// committed state root can be read.
+impl StateTrie {
+    pub fn start_history(&mut self) {
+        self.account_trie.start_history();
+    }
+
+    // marks the current state root as a version to keep reading, returns it.
+    pub fn commit_version(&mut self) -> Result<H256, Error> {
+        self.account_trie.commit_version()
+    }
+
+    // the committed state roots, oldest first.
+    pub fn versions(&self) -> &[H256] {
+        self.account_trie.versions()
+    }
+
+    pub fn get_account_at_root(&self, root: H256, address: Address) -> Result<AccountData, Error> {
+        self.account_trie.get_at(root, address)
+    }
+
+    // the value of the slot at a committed state root, or the current one.
+    pub fn get_storage_at_root(
+        &self,
+        root: H256,
+        address: Address,
+        slot: U256,
+    ) -> Result<U256, Error> {
+        let storage_root = self.get_account_at_root(root, address)?.storage_root;
+        if storage_root == KeccakHasher::empty_root() {
+            return Ok(U256::zero());
+        }
+        self.storage_tries
+            .get(&storage_root)
+            .ok_or(Error::MissingData(
+                "storage trie not present, please add a proof",
+            ))?
+            .get(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StateTrie;
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_state_history() {
+        let contract = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::new();
+        state_trie.start_history();
+        state_trie
+            .set_storage_value(contract, U256::one(), U256::from(10))
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_balance(contract, U256::from(5))
+            .unwrap();
+        let first = state_trie.commit_version().unwrap();
+
+        state_trie
+            .set_storage_value(contract, U256::one(), U256::from(20))
+            .unwrap();
+        state_trie
+            .set_storage_value(contract, U256::from(2), U256::from(30))
+            .unwrap();
+        let second = state_trie.commit_version().unwrap();
+        // storage of a cleared account stays readable at the older roots.
+        state_trie.clear_storage(contract).unwrap();
+        let third = state_trie.commit_version().unwrap();
+        assert_eq!(state_trie.versions(), &[first, second, third]);
+
+        let slot = |root, slot: u64| {
+            state_trie
+                .get_storage_at_root(root, contract, U256::from(slot))
+                .unwrap()
+        };
+        assert_eq!(slot(first, 1), U256::from(10));
+        assert_eq!(slot(first, 2), U256::zero());
+        assert_eq!(slot(second, 1), U256::from(20));
+        assert_eq!(slot(second, 2), U256::from(30));
+        assert_eq!(slot(third, 1), U256::zero());
+        assert_eq!(
+            state_trie
+                .get_account_at_root(first, contract)
+                .unwrap()
+                .balance,
+            U256::from(5)
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use ethers_core::types::H256;
+use serde_json::{json, Map, Value};
+
+use super::StateTrie;
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nodes::{LeafValue, NodeData},
+    trie::{MptKey, Trie},
+    utils::{hex_nibbles, nibbles_to_hash},
+    Error,
+};
+
+fn unresolved_json<K: MptKey, V: LeafValue>(trie: &Trie<K, V>) -> Result<Vec<Value>, Error> {
+    Ok(trie
+        .unresolved_nodes()?
+        .into_iter()
+        .map(|(path, hash)| json!({ "path": hex_nibbles(&path), "hash": hash }))
+        .collect())
+}
+
+// hashed keys and values of the loaded leaves.
+pub(super) fn leaves<K: MptKey, V: LeafValue>(trie: &Trie<K, V>) -> Result<Vec<(H256, V)>, Error> {
+    let mut leaves = Vec::new();
+    for loaded in trie.loaded_nodes()? {
+        if let NodeData::Leaf { key, value } = loaded.node_data {
+            let mut path = loaded.path;
+            path.extend(key.to_u4_vec());
+            leaves.push((nibbles_to_hash(&path)?, value));
+        }
+    }
+    leaves.sort_by_key(|(hash, _)| *hash);
+    Ok(leaves)
+}
+
+impl StateTrie {
+    // an eth_getProof response as json, on its own or as the whole rpc response with it in
+    // "result". for callers which pass raw rpc json around and don't want to depend on the
+    // ethers version of the crate.
+    pub fn load_proof_json(&mut self, proof: &Value) -> Result<(), Error> {
+        let proof = proof.get("result").unwrap_or(proof);
+        self.load_proof(serde_json::from_value(proof.clone())?)
+    }
+
+    pub fn load_proof_json_str(&mut self, proof: &str) -> Result<(), Error> {
+        self.load_proof_json(&serde_json::from_str(proof)?)
+    }
+
+    // everything that is loaded: accounts by address hash with their storage by slot hash,
+    // and the unresolved boundaries (hashes of nodes that are referenced but not loaded,
+    // with their nibble path) of the account trie and every storage trie.
+    pub fn to_json(&self) -> Result<Value, Error> {
+        let mut accounts = Map::new();
+        for (address_hash, account) in leaves(&self.account_trie)? {
+            let mut storage = Map::new();
+            let unresolved = if account.storage_root == KeccakHasher::empty_root() {
+                vec![]
+            } else if let Some(storage_trie) = self.storage_tries.get(&account.storage_root) {
+                for (slot_hash, value) in leaves(storage_trie)? {
+                    storage.insert(format!("{:?}", slot_hash), json!(value));
+                }
+                unresolved_json(storage_trie)?
+            } else {
+                vec![json!({ "path": "", "hash": account.storage_root })]
+            };
+
+            accounts.insert(
+                format!("{:?}", address_hash),
+                json!({
+                    "nonce": account.nonce,
+                    "balance": account.balance,
+                    "storageRoot": account.storage_root,
+                    "codeHash": account.code_hash,
+                    "storage": storage,
+                    "unresolved": unresolved,
+                }),
+            );
+        }
+
+        Ok(json!({
+            "root": self.root(),
+            "accounts": accounts,
+            "unresolved": unresolved_json(&self.account_trie)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state_trie::StorageTrie, Error, StateTrie};
+    use ethers_core::{
+        types::{Address, H256, U256},
+        utils::keccak256,
+    };
+    use serde_json::json;
+
+    #[test]
+    pub fn test_state_trie_to_json() {
+        let mut full = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        for i in 1..20u64 {
+            full.account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        let address = Address::from_low_u64_be(3);
+        full.set_storage_value(address, U256::one(), U256::from(7))
+            .unwrap();
+        full.set_storage_value(address, U256::from(2), U256::from(8))
+            .unwrap();
+
+        // full trie has no boundaries.
+        let json = full.to_json().unwrap();
+        assert_eq!(json["accounts"].as_object().unwrap().len(), 19);
+        assert!(json["unresolved"].as_array().unwrap().is_empty());
+
+        // a partial trie with one account and one of its slots.
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        partial
+            .load_proof(
+                full.get_proof(address, vec![H256::from_low_u64_be(1)])
+                    .unwrap(),
+            )
+            .unwrap();
+        let json = partial.to_json().unwrap();
+        assert_eq!(json["root"], json!(full.root()));
+
+        let accounts = json["accounts"].as_object().unwrap();
+        assert_eq!(accounts.len(), 1);
+        let account = &accounts[&format!("{:?}", H256::from(keccak256(address)))];
+        assert_eq!(account["balance"], "0x3");
+
+        let slot_hash = H256::from(keccak256(H256::from_low_u64_be(1)));
+        assert_eq!(account["storage"][format!("{:?}", slot_hash)], "0x7");
+        assert_eq!(account["storage"].as_object().unwrap().len(), 1);
+        assert_eq!(account["unresolved"].as_array().unwrap().len(), 1);
+        assert!(!json["unresolved"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_load_proof_json() {
+        let address = Address::from_low_u64_be(3);
+        let mut full = StateTrie::new();
+        full.set_storage_value(address, U256::one(), U256::from(7))
+            .unwrap();
+        let proof = serde_json::to_value(
+            full.get_proof(address, vec![H256::from_low_u64_be(1)])
+                .unwrap(),
+        )
+        .unwrap();
+
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        partial
+            .load_proof_json(&json!({ "jsonrpc": "2.0", "id": 1, "result": proof }))
+            .unwrap();
+        assert_eq!(
+            partial.get_storage_at(address, U256::one()).unwrap(),
+            U256::from(7)
+        );
+
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        partial.load_proof_json_str(&proof.to_string()).unwrap();
+        assert_eq!(
+            partial.account_trie.get(address).unwrap(),
+            full.account_trie.get(address).unwrap()
+        );
+        assert!(matches!(
+            partial.load_proof_json(&json!({ "address": address })),
+            Err(Error::JsonError(_))
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::{Arc, OnceLock};
+
+use ethers_core::{
+    types::{Bytes, H256},
+    utils::rlp::Rlp,
+};
+
+use super::{AccountData, StateAccount};
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nodes::{LeafValue, ValueInterner},
+    Error,
+};
+
+// an account leaf which is decoded on first use, for StateTrie<LazyAccount>: loading a
+// witness only checks that the leaves are lists of 4, most accounts of a block are passed
+// through untouched and are never decoded. the decoded account is shared by the clones, so
+// a read through `account_trie.get` decodes the one in the trie too.
+#[derive(Clone, Debug)]
+pub struct LazyAccount {
+    // the leaf as it was loaded, None for accounts written as an AccountData.
+    raw: Option<Bytes>,
+    decoded: Arc<OnceLock<AccountData>>,
+}
+
+fn default_raw() -> &'static Bytes {
+    static DEFAULT_RAW: OnceLock<Bytes> = OnceLock::new();
+    DEFAULT_RAW.get_or_init(|| AccountData::default().to_raw_rlp().unwrap())
+}
+
+impl LazyAccount {
+    pub fn account(&self) -> Result<&AccountData, Error> {
+        if let Some(account) = self.decoded.get() {
+            return Ok(account);
+        }
+        // raw is set whenever nothing is decoded yet.
+        let account = AccountData::from_raw_rlp(self.raw.clone().unwrap_or_default())?;
+        Ok(self.decoded.get_or_init(|| account))
+    }
+
+    pub fn into_account(self) -> Result<AccountData, Error> {
+        self.account().cloned()
+    }
+
+    pub fn is_decoded(&self) -> bool {
+        self.decoded.get().is_some()
+    }
+
+    // the leaf value, as loaded or encoded.
+    pub fn raw(&self) -> Result<Bytes, Error> {
+        match &self.raw {
+            Some(raw) => Ok(raw.clone()),
+            None => self.account()?.to_raw_rlp(),
+        }
+    }
+}
+
+impl From<AccountData> for LazyAccount {
+    fn from(account: AccountData) -> Self {
+        LazyAccount {
+            raw: None,
+            decoded: Arc::new(OnceLock::from(account)),
+        }
+    }
+}
+
+impl Default for LazyAccount {
+    fn default() -> Self {
+        AccountData::default().into()
+    }
+}
+
+// by encoding, which is the same for the same account.
+impl PartialEq for LazyAccount {
+    fn eq(&self, other: &Self) -> bool {
+        if let (Some(raw), Some(other_raw)) = (&self.raw, &other.raw) {
+            return raw == other_raw;
+        }
+        if let (Some(account), Some(other_account)) = (self.decoded.get(), other.decoded.get()) {
+            return account == other_account;
+        }
+        // e.g. a loaded leaf against the default, which trie writes compare to.
+        let raw = |account: &LazyAccount| match account.decoded.get() {
+            Some(decoded) if *decoded == AccountData::default() => Ok(default_raw().clone()),
+            _ => account.raw(),
+        };
+        matches!((raw(self), raw(other)), (Ok(raw), Ok(other_raw)) if raw == other_raw)
+    }
+}
+
+impl Eq for LazyAccount {}
+
+impl LeafValue for LazyAccount {
+    fn from_raw_rlp(raw: Bytes) -> Result<Self, Error> {
+        let rlp = Rlp::new(&raw);
+        if !rlp.is_list() || rlp.item_count()? != 4 {
+            return Err(Error::InternalError("account leaf is not a list of 4"));
+        }
+        Ok(LazyAccount {
+            raw: Some(raw),
+            decoded: Arc::default(),
+        })
+    }
+
+    fn to_raw_rlp(&self) -> Result<Bytes, Error> {
+        self.raw()
+    }
+
+    fn intern(self, interner: &mut ValueInterner) -> Self {
+        match self.raw {
+            Some(raw) => LazyAccount {
+                raw: Some(interner.intern(raw)),
+                decoded: self.decoded,
+            },
+            None => self,
+        }
+    }
+}
+
+impl StateAccount for LazyAccount {
+    // only the storage root is read from a leaf which isn't decoded, storage tries are loaded
+    // for every account. a leaf it can't be read from has no storage, account() says why.
+    fn storage_root(&self) -> H256 {
+        if let Some(account) = self.decoded.get() {
+            return account.storage_root;
+        }
+        self.raw
+            .as_ref()
+            .and_then(|raw| Rlp::new(raw).val_at(2).ok())
+            .unwrap_or_else(KeccakHasher::empty_root)
+    }
+
+    fn set_storage_root(&mut self, storage_root: H256) {
+        let mut account = self.account().cloned().unwrap_or_default();
+        account.storage_root = storage_root;
+        *self = account.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LazyAccount;
+    use crate::{AccountData, StateTrie};
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_lazy_account() {
+        let mut full = StateTrie::new();
+        for i in 1..=20u64 {
+            let address = Address::from_low_u64_be(i);
+            full.account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+            full.set_storage_value(address, U256::one(), U256::from(i * 2))
+                .unwrap();
+        }
+        let witness = full.witness().unwrap();
+
+        let mut lazy = StateTrie::<LazyAccount>::with_witness(&witness).unwrap();
+        assert_eq!(lazy.root(), full.root());
+        let account = lazy.account_trie.get(Address::from_low_u64_be(3)).unwrap();
+        assert!(!account.is_decoded());
+        assert_eq!(account.account().unwrap().balance, U256::from(3));
+        // decoded once for every clone.
+        assert!(lazy
+            .account_trie
+            .get(Address::from_low_u64_be(3))
+            .unwrap()
+            .is_decoded());
+        assert!(!lazy
+            .account_trie
+            .get(Address::from_low_u64_be(4))
+            .unwrap()
+            .is_decoded());
+        assert_eq!(
+            lazy.get_storage_at(Address::from_low_u64_be(5), U256::one())
+                .unwrap(),
+            U256::from(10)
+        );
+
+        // writes give the same roots as with decoded accounts.
+        let address = Address::from_low_u64_be(6);
+        let mut changed = lazy
+            .account_trie
+            .get(address)
+            .unwrap()
+            .into_account()
+            .unwrap();
+        changed.nonce = U256::from(9);
+        lazy.account_trie.set(address, changed.into()).unwrap();
+        full.account_trie.set_nonce(address, U256::from(9)).unwrap();
+        lazy.set_storage_value(address, U256::from(2), U256::one())
+            .unwrap();
+        full.set_storage_value(address, U256::from(2), U256::one())
+            .unwrap();
+        assert_eq!(lazy.root(), full.root());
+
+        // absent accounts and deletes compare to the default.
+        let absent = lazy.account_trie.get(Address::repeat_byte(0xaa)).unwrap();
+        assert_eq!(absent, LazyAccount::default());
+        lazy.account_trie
+            .set(Address::from_low_u64_be(7), LazyAccount::default())
+            .unwrap();
+        full.account_trie
+            .set(Address::from_low_u64_be(7), AccountData::default())
+            .unwrap();
+        assert_eq!(lazy.root(), full.root());
+    }
+}
@@ -1,31 +1,317 @@
-use crate::Error;
-use ethers::types::{Address, EIP1186ProofResponse, H256, U256};
-use std::collections::HashMap;
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    key_cache::{hashed_address, hashed_slot},
+    nodes::NodeData,
+    trie::{proof_value, Trie},
+    witness::ProofSizeEstimate,
+    BlockHashes, Error, Witness,
+};
+use ethers_core::types::{Address, Bytes, EIP1186ProofResponse, StorageProof, H256, U256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 mod account_trie;
-pub use account_trie::{AccountData, AccountTrie};
+pub use account_trie::{AccountData, AccountEntry, AccountTrie};
+
+mod state_account;
+pub use state_account::StateAccount;
 
 mod storage_trie;
 pub use storage_trie::StorageTrie;
 
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct StateTrie {
-    pub account_trie: AccountTrie,
+mod anchor;
+pub use anchor::Anchor;
+
+mod code_db;
+pub use code_db::CodeDb;
+mod changelog;
+use changelog::Changelog;
+pub use changelog::{ChangelogEntry, Mutation};
+
+mod diff;
+pub use diff::{AccountDiff, StateDiff};
+
+mod dump;
+pub use dump::{DumpAccount, StateDump};
+
+mod explain;
+mod heatmap;
+pub use heatmap::StateHeatmap;
+use heatmap::StorageHeat;
+mod flat;
+mod frontier;
+pub use frontier::{AccountFrontier, StateFrontier};
+mod genesis;
+mod history;
+mod json;
+use json::leaves;
+mod lazy_account;
+pub use lazy_account::LazyAccount;
+mod parallel;
+pub use parallel::VerifiedProofs;
+mod parity;
+mod prune;
+pub use prune::PrunedNodes;
+mod range_proof;
+mod snapshot;
+pub use snapshot::{
+    decode_slim_account, encode_slim_account, SNAPSHOT_ACCOUNT_PREFIX, SNAPSHOT_STORAGE_PREFIX,
+};
+mod storage_range;
+pub use storage_range::StorageEntry;
+mod typed_storage;
+mod watch;
+pub use watch::RootChange;
+use watch::RootWatch;
+
+// a storage proof's slot and whether it verified, see load_proof_per_slot.
+pub type SlotResult = (U256, Result<(), Error>);
+
+// generic over the account leaf for other chains, see StateAccount. proofs (load_proof,
+// get_proof) and the helpers in the other files of this module are for AccountData.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateTrie<A: StateAccount = AccountData> {
+    pub account_trie: Trie<Address, A>,
     storage_tries: HashMap<H256, StorageTrie>,
+    // addresses of the accounts we've seen by their hashed key, the trie only has the hash.
+    addresses: BTreeMap<H256, Address>,
+    // same for slots, they hash the same in every storage trie.
+    slots: HashMap<H256, U256>,
+    // contract code, filled by the loaders which have it, e.g. from_genesis_alloc.
+    codes: CodeDb,
+    // the hashes of the blocks before the state's, for BLOCKHASH. kept in the witness.
+    block_hashes: BlockHashes,
+    watch: RootWatch,
+    changelog: Changelog,
+    storage_heat: StorageHeat,
+    anchor: Option<Anchor>,
 }
 
 impl StateTrie {
+    // an empty state to build on, everything is loaded so any account can be written.
+    pub fn new() -> Self {
+        StateTrie::from_root(KeccakHasher::empty_root())
+    }
+
     pub fn from_root(root: H256) -> Self {
+        StateTrie::with_root(root)
+    }
+
+    // fails on a bad account proof or the first bad storage proof. every good storage proof
+    // is loaded all the same, also the ones after the bad one.
+    pub fn load_proof(&mut self, proof: EIP1186ProofResponse) -> Result<(), Error> {
+        for (_, result) in self.load_proof_per_slot(proof)? {
+            result?;
+        }
+        Ok(())
+    }
+
+    // load_proof for proofs as raw nodes, the account proof and (slot, nodes) per slot. the
+    // account and the slot values are read from the nodes. returns the account, the default
+    // one when the proof shows it doesn't exist.
+    pub fn load_raw_proof(
+        &mut self,
+        address: Address,
+        account_proof: Vec<Bytes>,
+        storage_proof: Vec<(U256, Vec<Bytes>)>,
+    ) -> Result<AccountData, Error> {
+        let account: AccountData = proof_value(&address, &account_proof)?;
+        let proven = account.clone();
+        let storage_proof = storage_proof
+            .into_iter()
+            .map(|(key, proof)| {
+                Ok(StorageProof {
+                    key,
+                    value: proof_value(&key, &proof)?,
+                    proof,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        for (_, result) in
+            self.load_account_proof_per_slot(address, account, account_proof, storage_proof)?
+        {
+            result?;
+        }
+        Ok(proven)
+    }
+
+    // verifies the account proof once and then every storage proof against the account's
+    // storage root, with a result per slot in the order of the response. a bad account proof
+    // fails the whole thing, a bad storage proof only its own slot.
+    pub fn load_proof_per_slot(
+        &mut self,
+        proof: EIP1186ProofResponse,
+    ) -> Result<Vec<SlotResult>, Error> {
+        let account = AccountData {
+            balance: proof.balance,
+            nonce: U256::from(proof.nonce.as_u64()),
+            code_hash: proof.code_hash,
+            storage_root: proof.storage_hash,
+        };
+        self.load_account_proof_per_slot(
+            proof.address,
+            account,
+            proof.account_proof,
+            proof.storage_proof,
+        )
+    }
+
+    // eth_getProof response for the account and slots, needs their paths to be loaded.
+    pub fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+    ) -> Result<EIP1186ProofResponse, Error> {
+        let account_data = self.account_trie.get(address)?;
+        let mut storage_proof = Vec::with_capacity(slots.len());
+        if !slots.is_empty() {
+            // absent accounts and accounts without storage have nothing loaded to prove with.
+            let empty;
+            let storage_trie = match self.storage_tries.get(&account_data.storage_root) {
+                Some(storage_trie) => storage_trie,
+                None if account_data.storage_root == KeccakHasher::empty_root() => {
+                    empty = StorageTrie::empty();
+                    &empty
+                }
+                None => {
+                    return Err(
+                        Error::MissingData("storage trie not present, please add a proof")
+                            .at_address(address),
+                    )
+                }
+            };
+            for slot in slots {
+                let key = U256::from_big_endian(slot.as_bytes());
+                storage_proof.push(StorageProof {
+                    key,
+                    proof: storage_trie
+                        .get_proof(key)
+                        .map_err(|err| err.at_address(address))?,
+                    value: storage_trie
+                        .get(key)
+                        .map_err(|err| err.at_address(address))?,
+                });
+            }
+        }
+
+        Ok(EIP1186ProofResponse {
+            address,
+            balance: account_data.balance,
+            code_hash: account_data.code_hash,
+            nonce: account_data.nonce.as_u64().into(),
+            storage_hash: account_data.storage_root,
+            account_proof: self.account_trie.get_proof(address)?,
+            storage_proof,
+        })
+    }
+
+    // adds the nodes and code of another witness for the current root, e.g. the keys the
+    // next block needs on top of what the trie already has.
+    pub fn load_witness(&mut self, witness: &Witness) -> Result<(), Error> {
+        if self.root() != Some(witness.root()) {
+            return Err(Error::InternalError("witness is for another root"));
+        }
+        let other = Self::from_witness(witness)?;
+        for code in witness.codes() {
+            self.codes.insert(code.clone());
+        }
+        self.merge_loaded(other);
+        Ok(())
+    }
+
+    pub fn from_witness(witness: &Witness) -> Result<Self, Error> {
+        StateTrie::with_witness(witness)
+    }
+}
+
+impl<A: StateAccount> StateTrie<A> {
+    // from_root for other account types, e.g. `StateTrie::<MyAccount>::with_root(root)`.
+    pub fn with_root(root: H256) -> Self {
         StateTrie {
-            account_trie: AccountTrie::from_root(root),
+            account_trie: Trie::from_root(root),
             storage_tries: HashMap::default(),
+            addresses: BTreeMap::default(),
+            slots: HashMap::default(),
+            codes: CodeDb::default(),
+            block_hashes: BlockHashes::default(),
+            watch: RootWatch::default(),
+            changelog: Changelog::default(),
+            storage_heat: StorageHeat::default(),
+            anchor: None,
         }
     }
 
+    // from_witness for other account types.
+    pub fn with_witness(witness: &Witness) -> Result<Self, Error> {
+        let raw_nodes = witness.node_map();
+        let mut state_trie = Self::with_root(witness.root());
+        state_trie.block_hashes = witness.block_hashes().cloned().unwrap_or_default();
+        for code in witness.codes() {
+            state_trie.codes.insert(code.clone());
+        }
+        for account in state_trie.account_trie.load_raw_nodes(&raw_nodes)? {
+            let mut storage_trie = StorageTrie::from_root(account.storage_root());
+            storage_trie.load_raw_nodes(&raw_nodes)?;
+            state_trie
+                .storage_tries
+                .insert(account.storage_root(), storage_trie);
+        }
+        Ok(state_trie)
+    }
+
     pub fn root(&self) -> Option<H256> {
         self.account_trie.root()
     }
 
+    // load_proof with the account as the chain encodes it, for account types eth_getProof
+    // has no fields for.
+    pub fn load_account_proof(
+        &mut self,
+        address: Address,
+        account: A,
+        account_proof: Vec<Bytes>,
+        storage_proof: Vec<StorageProof>,
+    ) -> Result<(), Error> {
+        for (_, result) in
+            self.load_account_proof_per_slot(address, account, account_proof, storage_proof)?
+        {
+            result?;
+        }
+        Ok(())
+    }
+
+    pub fn load_account_proof_per_slot(
+        &mut self,
+        address: Address,
+        account: A,
+        account_proof: Vec<Bytes>,
+        storage_proof: Vec<StorageProof>,
+    ) -> Result<Vec<SlotResult>, Error> {
+        let storage_root = account.storage_root();
+        self.account_trie
+            .load_proof(address, account, account_proof)?;
+        self.remember_address(address);
+
+        // taken out of the map rather than cloned, the nodes near its root are decoded only for
+        // the first slot.
+        let mut storage_trie = self
+            .storage_tries
+            .remove(&storage_root)
+            .unwrap_or_else(|| StorageTrie::from_root(storage_root));
+        let results = storage_proof
+            .into_iter()
+            .map(|slot| {
+                let result = storage_trie.load_proof(slot.key, slot.value, slot.proof);
+                (slot.key, result)
+            })
+            .collect::<Vec<_>>();
+        self.storage_tries.insert(storage_root, storage_trie);
+        for (slot, _) in results.iter().filter(|(_, result)| result.is_ok()) {
+            self.remember_slot(*slot);
+        }
+
+        Ok(results)
+    }
+
     pub fn get_storage_trie(&mut self, storage_root: H256) -> StorageTrie {
         if !self.storage_tries.contains_key(&storage_root) {
             StorageTrie::from_root(storage_root)
@@ -34,9 +320,87 @@ impl StateTrie {
         }
     }
 
+    pub(crate) fn loaded_storage_trie(&self, storage_root: &H256) -> Option<&StorageTrie> {
+        self.storage_tries.get(storage_root)
+    }
+
+    pub(crate) fn remember_address(&mut self, address: Address) {
+        self.addresses.insert(hashed_address(address), address);
+    }
+
+    pub(crate) fn remember_slot(&mut self, slot: U256) {
+        self.slots.insert(hashed_slot(slot), slot);
+    }
+
+    // the loaded storage trie of every account whose address is known, i.e. it was loaded with
+    // a proof, written to or built from a state export. accounts loaded from a witness are only
+    // known by their hash, see storage_tries_by_hash. ordered by address hash.
+    pub fn storage_tries(&self) -> impl Iterator<Item = (Address, &StorageTrie)> {
+        self.addresses.values().filter_map(|address| {
+            let storage_root = self.account_trie.get(*address).ok()?.storage_root();
+            Some((*address, self.storage_tries.get(&storage_root)?))
+        })
+    }
+
+    // the loaded storage trie of every loaded account, by address hash.
+    pub fn storage_tries_by_hash(&self) -> Result<Vec<(H256, &StorageTrie)>, Error> {
+        Ok(leaves(&self.account_trie)?
+            .into_iter()
+            .filter_map(|(address_hash, account)| {
+                Some((
+                    address_hash,
+                    self.storage_tries.get(&account.storage_root())?,
+                ))
+            })
+            .collect())
+    }
+
+    // the account's storage trie, None if no part of it is loaded. errors if the account
+    // isn't loaded.
+    pub fn storage_trie(&self, address: Address) -> Result<Option<&StorageTrie>, Error> {
+        let storage_root = self.account_trie.get(address)?.storage_root();
+        Ok(self.storage_tries.get(&storage_root))
+    }
+
+    // an owned copy of the account's storage trie with only the nodes under its storage root,
+    // the ones older roots left behind aren't in it. to change on its own or to send elsewhere,
+    // insert_storage_trie takes it back. nothing of it is loaded if the state has nothing of
+    // it. errors if the account isn't loaded.
+    pub fn extract_storage_trie(&self, address: Address) -> Result<StorageTrie, Error> {
+        let storage_root = self.account_trie.get(address)?.storage_root();
+        let mut storage_trie = match self.storage_tries.get(&storage_root) {
+            Some(storage_trie) => storage_trie.to_owned(),
+            None => StorageTrie::from_root(storage_root),
+        };
+        storage_trie.prune()?;
+        Ok(storage_trie)
+    }
+
+    // storage root in the account leaf, which set_storage_value keeps up to date.
+    pub fn get_storage_root(&self, address: Address) -> Result<H256, Error> {
+        Ok(self.account_trie.get(address)?.storage_root())
+    }
+
+    // the value of the account's leaf as it is in the trie, for other decoders or to pass on
+    // to another proof system. None if the trie has no leaf for it. with LazyAccount these are
+    // the bytes the leaf was loaded with, never decoded.
+    pub fn get_account_rlp(&self, address: Address) -> Result<Option<Bytes>, Error> {
+        let account = self.account_trie.get(address)?;
+        if account == A::default() {
+            return Ok(None);
+        }
+        Ok(Some(account.to_raw_rlp()?))
+    }
+
     pub fn get_storage_at(&mut self, address: Address, key: U256) -> Result<U256, Error> {
         let account_data = self.account_trie.get(address)?;
-        self.get_storage_trie(account_data.storage_root).get(key)
+        self.remember_address(address);
+        self.remember_slot(key);
+        let mut storage_trie = self.get_storage_trie(account_data.storage_root());
+        self.storage_heat.start(&mut storage_trie);
+        let value = storage_trie.get(key);
+        self.storage_heat.add(address, &mut storage_trie);
+        value.map_err(|err| err.at_address(address))
     }
 
     pub fn set_storage_value(
@@ -46,45 +410,166 @@ impl StateTrie {
         value: U256,
     ) -> Result<(), Error> {
         let mut account_data = self.account_trie.get(address)?;
-        let mut storage_trie = self
-            .storage_tries
-            .remove(&account_data.storage_root)
-            .expect("storage trie not present, this should not happen");
-        storage_trie.set(slot, value)?;
-        account_data.storage_root = storage_trie.root().unwrap();
+        // other accounts can have the same storage root, so the old trie is kept around.
+        let mut storage_trie = self.get_storage_trie(account_data.storage_root());
+        self.storage_heat.start(&mut storage_trie);
+        if self.changelog.recording {
+            let old_value = storage_trie
+                .get(slot)
+                .map_err(|err| err.at_address(address))?;
+            if old_value != value {
+                let index = self.account_trie.changelog_len();
+                self.changelog
+                    .storage
+                    .push((index, address, slot, old_value, value));
+            }
+        }
+        let written = storage_trie.set(slot, value);
+        self.storage_heat.add(address, &mut storage_trie);
+        written.map_err(|err| err.at_address(address))?;
+        account_data.set_storage_root(storage_trie.root().unwrap());
         self.storage_tries
             .insert(storage_trie.root().unwrap(), storage_trie);
         self.account_trie.set(address, account_data)?;
+        self.remember_address(address);
+        self.remember_slot(slot);
+        self.watch.touch_storage(address, slot);
         Ok(())
     }
 
-    pub fn load_proof(&mut self, proof: EIP1186ProofResponse) -> Result<(), Error> {
-        self.account_trie.load_proof(
-            proof.address,
-            AccountData {
-                balance: proof.balance,
-                nonce: U256::from(proof.nonce.as_u64()),
-                code_hash: proof.code_hash,
-                storage_root: proof.storage_hash,
-            },
-            proof.account_proof,
-        )?;
-
-        let mut storage_trie = self.get_storage_trie(proof.storage_hash);
-        for proof in proof.storage_proof {
-            let mut proof_big_endian = vec![0u8; 32];
-            proof.key.to_big_endian(&mut proof_big_endian);
+    // empties the account's storage, like a selfdestruct does. the old storage trie is dropped
+    // unless another loaded account still has the same storage root.
+    pub fn clear_storage(&mut self, address: Address) -> Result<(), Error> {
+        self.set_storage_root(address, KeccakHasher::empty_root(), true)
+    }
 
-            storage_trie.load_proof(
-                U256::from_big_endian(&proof_big_endian),
-                proof.value,
-                proof.proof,
-            )?;
+    // overrides the storage root in the account leaf, e.g. to splice in a storage trie built
+    // elsewhere. storage is then read from a loaded trie with that root, if there is one.
+    // `detach` drops the trie for the old root, unless another loaded account still uses it.
+    pub fn set_storage_root(
+        &mut self,
+        address: Address,
+        storage_root: H256,
+        detach: bool,
+    ) -> Result<(), Error> {
+        let mut account_data = self.account_trie.get(address)?;
+        let old_root = account_data.storage_root();
+        if old_root == storage_root {
+            return Ok(());
+        }
+        account_data.set_storage_root(storage_root);
+        self.account_trie.set(address, account_data)?;
+        self.remember_address(address);
+        // an old version may still read the old storage.
+        if detach && !self.account_trie.records_history() && !self.storage_root_in_use(old_root)? {
+            self.storage_tries.remove(&old_root);
         }
-        self.storage_tries.insert(proof.storage_hash, storage_trie);
+        Ok(())
+    }
 
+    // makes a storage trie available under its root, for accounts pointing at it with
+    // set_storage_root.
+    pub fn insert_storage_trie(&mut self, storage_trie: StorageTrie) -> Result<(), Error> {
+        let root = storage_trie.root().ok_or(Error::Usage("root not set"))?;
+        self.storage_tries.insert(root, storage_trie);
         Ok(())
     }
+
+    // whether any loaded account has this storage root.
+    fn storage_root_in_use(&self, storage_root: H256) -> Result<bool, Error> {
+        Ok(self.account_trie.loaded_nodes()?.iter().any(|loaded| {
+            matches!(&loaded.node_data, NodeData::Leaf { value, .. } if value.storage_root() == storage_root)
+        }))
+    }
+
+    // proof of one slot: the account proof from the state root to the account leaf, whose
+    // storage root anchors the storage proof, and the storage proof itself. works for state
+    // changed locally, the proofs are against the current roots.
+    pub fn get_storage_proof(
+        &self,
+        address: Address,
+        slot: U256,
+    ) -> Result<(Vec<Bytes>, StorageProof), Error> {
+        let storage_root = self.get_storage_root(address)?;
+        let storage_proof = if storage_root == KeccakHasher::empty_root() {
+            StorageProof {
+                key: slot,
+                proof: vec![],
+                value: U256::zero(),
+            }
+        } else {
+            let storage_trie = self
+                .storage_tries
+                .get(&storage_root)
+                .ok_or(Error::MissingData(
+                    "storage trie not present, please add a proof",
+                ))?;
+            StorageProof {
+                key: slot,
+                proof: storage_trie.get_proof(slot)?,
+                value: storage_trie.get(slot)?,
+            }
+        };
+        Ok((self.account_trie.get_proof(address)?, storage_proof))
+    }
+
+    // raw node by hash from the account trie or any of the storage tries.
+    pub fn get_raw_node(&self, hash: H256) -> Result<Option<Bytes>, Error> {
+        if let Some(raw) = self.account_trie.get_raw_node(hash)? {
+            return Ok(Some(raw));
+        }
+        for storage_trie in self.storage_tries.values() {
+            if let Some(raw) = storage_trie.get_raw_node(hash)? {
+                return Ok(Some(raw));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn witness(&self) -> Result<Witness, Error> {
+        let root = self.root().ok_or(Error::Usage("root not set"))?;
+        let mut nodes = self.account_trie.raw_nodes()?;
+        for storage_trie in self.storage_tries.values() {
+            nodes.extend(storage_trie.raw_nodes()?);
+        }
+        Ok(Witness::new(root, nodes)
+            .with_block_hashes(self.block_hashes.clone())
+            .with_codes(self.codes.as_map().values().cloned()))
+    }
+
+    pub fn block_hashes(&self) -> &BlockHashes {
+        &self.block_hashes
+    }
+
+    pub fn set_block_hashes(&mut self, block_hashes: BlockHashes) {
+        self.block_hashes = block_hashes;
+    }
+
+    // marginal_bytes has one entry per account, covering the account and its storage keys.
+    pub fn estimate_proof_size(
+        &self,
+        keys: &[(Address, Vec<U256>)],
+    ) -> Result<ProofSizeEstimate, Error> {
+        let mut estimate = ProofSizeEstimate::default();
+        let mut seen = HashSet::new();
+        for (address, slots) in keys {
+            let mut nodes = self.account_trie.proof_node_sizes(*address)?;
+            if !slots.is_empty() {
+                let storage_root = self.account_trie.get(*address)?.storage_root();
+                let storage_trie =
+                    self.storage_tries
+                        .get(&storage_root)
+                        .ok_or(Error::MissingData(
+                            "storage trie not present, please add a proof",
+                        ))?;
+                for slot in slots {
+                    nodes.extend(storage_trie.proof_node_sizes(*slot)?);
+                }
+            }
+            estimate.add_key(&mut seen, nodes);
+        }
+        Ok(estimate)
+    }
 }
 
 #[cfg(test)]
@@ -93,10 +578,488 @@ mod tests {
     use std::str::FromStr;
 
     use super::{EIP1186ProofResponse, StateTrie, U256};
-    use ethers::core::utils::hex;
     use ethers::providers::{Middleware, Provider};
-    use ethers::types::{Address, BigEndianHash, BlockId, BlockNumber, StorageProof, H256};
-    use ethers::utils::keccak256;
+    use ethers_core::types::{Address, BigEndianHash, BlockId, BlockNumber, StorageProof, H256};
+    use ethers_core::utils::hex;
+    use ethers_core::utils::keccak256;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    pub fn test_trie_types_are_send_sync() {
+        // shareable with async services and caches without wrappers.
+        assert_send_sync::<StateTrie>();
+        assert_send_sync::<super::StorageTrie>();
+        assert_send_sync::<crate::nodes::NodeData<super::AccountData>>();
+        assert_send_sync::<crate::nibbles::Nibbles>();
+        assert_send_sync::<crate::Witness>();
+
+        // and usable as keys.
+        let mut seen = std::collections::HashSet::new();
+        assert!(seen.insert(super::AccountData::default()));
+        assert!(!seen.insert(super::AccountData::default()));
+        assert_eq!(StateTrie::new(), StateTrie::new());
+    }
+
+    #[test]
+    pub fn test_new() {
+        let mut state_trie = StateTrie::new();
+        assert_eq!(state_trie.root(), Some(H256::from(keccak256([0x80]))));
+        let address = Address::from_low_u64_be(1);
+        assert_eq!(
+            state_trie.account_trie.get(address).unwrap().balance,
+            U256::zero()
+        );
+        state_trie
+            .set_storage_value(address, U256::one(), U256::from(2))
+            .unwrap();
+        let expected = StateTrie::from_flat_state(
+            [(address, Default::default())],
+            [(address, U256::one(), U256::from(2))],
+        )
+        .unwrap();
+        assert_eq!(state_trie.root(), expected.root());
+    }
+
+    #[test]
+    pub fn test_get_storage_root() {
+        let empty_root = super::StorageTrie::empty().root().unwrap();
+        let address = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::from_root(empty_root);
+        state_trie
+            .account_trie
+            .set_balance(address, U256::one())
+            .unwrap();
+        assert_eq!(state_trie.get_storage_root(address).unwrap(), empty_root);
+
+        state_trie
+            .set_storage_value(address, U256::one(), U256::from(2))
+            .unwrap();
+        let expected = super::StorageTrie::from_entries([(U256::one(), U256::from(2))])
+            .unwrap()
+            .root()
+            .unwrap();
+        assert_eq!(state_trie.get_storage_root(address).unwrap(), expected);
+
+        // not loaded.
+        let partial = StateTrie::from_root(state_trie.root().unwrap());
+        assert!(partial.get_storage_root(address).is_err());
+    }
+
+    #[test]
+    pub fn test_set_storage_root() {
+        let empty_root = super::StorageTrie::empty().root().unwrap();
+        let address = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::from_root(empty_root);
+        state_trie
+            .account_trie
+            .set_balance(address, U256::one())
+            .unwrap();
+        state_trie
+            .set_storage_value(address, U256::one(), U256::from(2))
+            .unwrap();
+        let old_root = state_trie.get_storage_root(address).unwrap();
+
+        // splice in a storage trie built elsewhere.
+        let entries = (0..10u64).map(|i| (U256::from(i), U256::from(i + 100)));
+        let spliced = super::StorageTrie::from_entries(entries).unwrap();
+        let spliced_root = spliced.root().unwrap();
+        state_trie.insert_storage_trie(spliced).unwrap();
+        state_trie
+            .set_storage_root(address, spliced_root, false)
+            .unwrap();
+        assert_eq!(
+            state_trie.get_storage_at(address, U256::from(3)).unwrap(),
+            U256::from(103)
+        );
+        // not detached, the old trie is still there.
+        assert!(state_trie.storage_tries.contains_key(&old_root));
+
+        // same state root as building it in place.
+        let mut expected = StateTrie::from_root(empty_root);
+        expected
+            .account_trie
+            .set_balance(address, U256::one())
+            .unwrap();
+        for i in 0..10u64 {
+            expected
+                .set_storage_value(address, U256::from(i), U256::from(i + 100))
+                .unwrap();
+        }
+        assert_eq!(state_trie.root(), expected.root());
+
+        state_trie
+            .set_storage_root(address, old_root, true)
+            .unwrap();
+        assert!(!state_trie.storage_tries.contains_key(&spliced_root));
+        assert_eq!(
+            state_trie.get_storage_at(address, U256::one()).unwrap(),
+            U256::from(2)
+        );
+
+        // a root without a loaded trie can be set, but not read from.
+        state_trie
+            .set_storage_root(address, H256::repeat_byte(1), true)
+            .unwrap();
+        assert!(state_trie.get_storage_at(address, U256::one()).is_err());
+    }
+
+    #[test]
+    pub fn test_clear_storage() {
+        let empty_root = super::StorageTrie::empty().root().unwrap();
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut state_trie = StateTrie::from_root(empty_root);
+        for address in [a, b] {
+            state_trie
+                .account_trie
+                .set_balance(address, U256::one())
+                .unwrap();
+        }
+        let root_without_storage = state_trie.root();
+
+        // same storage on both, so they share a storage trie.
+        for address in [a, b] {
+            state_trie
+                .set_storage_value(address, U256::one(), U256::from(2))
+                .unwrap();
+        }
+        let storage_root = state_trie.account_trie.get(a).unwrap().storage_root;
+
+        state_trie.clear_storage(a).unwrap();
+        assert_eq!(
+            state_trie.account_trie.get(a).unwrap().storage_root,
+            empty_root
+        );
+        assert_eq!(
+            state_trie.get_storage_at(a, U256::one()).unwrap(),
+            U256::zero()
+        );
+        // still used by b.
+        assert!(state_trie.storage_tries.contains_key(&storage_root));
+        assert_eq!(
+            state_trie.get_storage_at(b, U256::one()).unwrap(),
+            U256::from(2)
+        );
+
+        state_trie.clear_storage(b).unwrap();
+        assert!(!state_trie.storage_tries.contains_key(&storage_root));
+        assert_eq!(state_trie.root(), root_without_storage);
+        // clearing empty storage does nothing.
+        state_trie.clear_storage(b).unwrap();
+        assert_eq!(state_trie.root(), root_without_storage);
+    }
+
+    #[test]
+    pub fn test_load_proof_per_slot() {
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::from_root(super::StorageTrie::empty().root().unwrap());
+        for i in 1..40u64 {
+            full.set_storage_value(address, U256::from(i), U256::from(i * 10))
+                .unwrap();
+        }
+        let slots = (1..45u64).map(H256::from_low_u64_be).collect();
+        let mut proof = full.get_proof(address, slots).unwrap();
+        // a proof for another slot's value.
+        proof.storage_proof[3].value = U256::from(1);
+
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        let results = partial.load_proof_per_slot(proof.clone()).unwrap();
+        assert_eq!(results.len(), 44);
+        for (i, (slot, result)) in results.iter().enumerate() {
+            assert_eq!(*slot, U256::from(i + 1));
+            assert_eq!(result.is_ok(), i != 3);
+        }
+        // the other slots are loaded, including the absent ones.
+        assert_eq!(
+            partial.get_storage_at(address, U256::from(5)).unwrap(),
+            U256::from(50)
+        );
+        assert_eq!(
+            partial.get_storage_at(address, U256::from(44)).unwrap(),
+            U256::zero()
+        );
+        assert!(StateTrie::from_root(full.root().unwrap())
+            .load_proof(proof.clone())
+            .is_err());
+
+        // a bad account proof fails before any slot.
+        proof.balance = U256::from(1);
+        assert!(StateTrie::from_root(full.root().unwrap())
+            .load_proof_per_slot(proof)
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_load_proof_per_slot_forged() {
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::new();
+        for i in 1..20u64 {
+            full.set_storage_value(address, U256::from(i), U256::from(i * 10))
+                .unwrap();
+        }
+        let slots = (1..6u64).map(H256::from_low_u64_be).collect();
+        let mut proof = full.get_proof(address, slots).unwrap();
+        // slot 2 shown as absent by the root node alone, slot 4 by the proof of a slot under
+        // another child of the root.
+        let first_nibble = |slot: u64| keccak256(H256::from_low_u64_be(slot))[0] >> 4;
+        let other = (6..20u64)
+            .find(|slot| first_nibble(*slot) != first_nibble(4))
+            .unwrap();
+        let other_proof = full
+            .get_proof(address, vec![H256::from_low_u64_be(other)])
+            .unwrap()
+            .storage_proof[0]
+            .proof
+            .clone();
+        proof.storage_proof[1].value = U256::zero();
+        proof.storage_proof[1].proof.truncate(1);
+        proof.storage_proof[3].value = U256::zero();
+        proof.storage_proof[3].proof = other_proof;
+
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        let results = partial.load_proof_per_slot(proof).unwrap();
+        for (i, (_, result)) in results.iter().enumerate() {
+            assert_eq!(result.is_ok(), i != 1 && i != 3);
+        }
+        assert_eq!(
+            partial.get_storage_at(address, U256::from(5)).unwrap(),
+            U256::from(50)
+        );
+        assert!(partial.get_storage_at(address, U256::from(2)).is_err());
+    }
+
+    #[test]
+    pub fn test_load_raw_proof() {
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::new();
+        full.account_trie
+            .set_balance(Address::from_low_u64_be(2), U256::from(7))
+            .unwrap();
+        for i in 1..20u64 {
+            full.set_storage_value(address, U256::from(i), U256::from(i * 10))
+                .unwrap();
+        }
+        let slots = vec![H256::from_low_u64_be(3), H256::from_low_u64_be(25)];
+        let proof = full.get_proof(address, slots).unwrap();
+        let storage_proof = proof
+            .storage_proof
+            .iter()
+            .map(|slot| (slot.key, slot.proof.clone()))
+            .collect();
+
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        let account = partial
+            .load_raw_proof(address, proof.account_proof.clone(), storage_proof)
+            .unwrap();
+        assert_eq!(account, full.account_trie.get(address).unwrap());
+        assert_eq!(
+            partial.get_storage_at(address, U256::from(3)).unwrap(),
+            U256::from(30)
+        );
+        assert_eq!(
+            partial.get_storage_at(address, U256::from(25)).unwrap(),
+            U256::zero()
+        );
+
+        // an absent account reads as the default one.
+        let absent = Address::from_low_u64_be(3);
+        let absent_proof = full.get_proof(absent, vec![]).unwrap().account_proof;
+        assert_eq!(
+            partial
+                .load_raw_proof(absent, absent_proof, vec![])
+                .unwrap(),
+            Default::default()
+        );
+
+        // the nodes still have to hash up to the root.
+        let mut storage_trie = super::StorageTrie::empty();
+        storage_trie.set(U256::one(), U256::one()).unwrap();
+        let mut other = StateTrie::from_root(storage_trie.root().unwrap());
+        assert!(other
+            .load_raw_proof(address, proof.account_proof, vec![])
+            .is_err());
+        assert_eq!(
+            storage_trie
+                .load_proof_nodes(U256::one(), storage_trie.get_proof(U256::one()).unwrap())
+                .unwrap(),
+            U256::one()
+        );
+    }
+
+    #[test]
+    pub fn test_get_account_rlp() {
+        let mut full = StateTrie::new();
+        for i in 1..10u64 {
+            let address = Address::from_low_u64_be(i);
+            full.account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+            full.set_storage_value(address, U256::one(), U256::from(i))
+                .unwrap();
+        }
+        let address = Address::from_low_u64_be(4);
+        let proof = full.get_proof(address, vec![]).unwrap();
+        // the value of the leaf at the end of the proof.
+        let leaf = proof.account_proof.last().unwrap();
+        let value: Vec<u8> = ethers_core::utils::rlp::Rlp::new(leaf).val_at(1).unwrap();
+        assert_eq!(
+            full.get_account_rlp(address).unwrap().unwrap().to_vec(),
+            value
+        );
+        assert_eq!(
+            full.get_account_rlp(Address::from_low_u64_be(100)).unwrap(),
+            None
+        );
+
+        let lazy = StateTrie::<super::LazyAccount>::with_witness(&full.witness().unwrap()).unwrap();
+        assert_eq!(
+            lazy.get_account_rlp(address).unwrap().unwrap().to_vec(),
+            value
+        );
+        assert!(!lazy.account_trie.get(address).unwrap().is_decoded());
+        assert!(StateTrie::from_root(full.root().unwrap())
+            .get_account_rlp(address)
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_get_storage_proof() {
+        let empty_root = super::StorageTrie::empty().root().unwrap();
+        let address = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::from_root(empty_root);
+        state_trie
+            .account_trie
+            .set_balance(address, U256::one())
+            .unwrap();
+        let (_, empty) = state_trie.get_storage_proof(address, U256::one()).unwrap();
+        assert!(empty.proof.is_empty());
+        for i in 1..10u64 {
+            state_trie
+                .set_storage_value(address, U256::from(i), U256::from(i + 100))
+                .unwrap();
+        }
+
+        let (account_proof, storage_proof) = state_trie
+            .get_storage_proof(address, U256::from(4))
+            .unwrap();
+        assert_eq!(storage_proof.value, U256::from(104));
+        let account = state_trie.account_trie.get(address).unwrap();
+
+        // verifies against the current roots.
+        let mut account_trie = super::AccountTrie::from_root(state_trie.root().unwrap());
+        account_trie
+            .load_proof(address, account.clone(), account_proof)
+            .unwrap();
+        let mut storage_trie = super::StorageTrie::from_root(account.storage_root);
+        storage_trie
+            .load_proof(storage_proof.key, storage_proof.value, storage_proof.proof)
+            .unwrap();
+        assert_eq!(storage_trie.get(U256::from(4)).unwrap(), U256::from(104));
+
+        // absent slot.
+        let (_, absent) = state_trie
+            .get_storage_proof(address, U256::from(50))
+            .unwrap();
+        assert_eq!(absent.value, U256::zero());
+        assert!(super::StorageTrie::from_root(account.storage_root)
+            .load_proof(absent.key, absent.value, absent.proof)
+            .is_ok());
+    }
+
+    #[test]
+    pub fn test_extract_storage_trie() {
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut state_trie = StateTrie::new();
+        for slot in 1..=5u64 {
+            state_trie
+                .set_storage_value(a, U256::from(slot), U256::from(slot))
+                .unwrap();
+        }
+        state_trie.account_trie.set_balance(b, U256::one()).unwrap();
+
+        let mut storage_trie = state_trie.extract_storage_trie(a).unwrap();
+        assert_eq!(storage_trie.root(), state_trie.get_storage_root(a).ok());
+        assert!(storage_trie.prune_dry_run().unwrap().is_empty());
+        assert_eq!(storage_trie.get(U256::from(4)).unwrap(), U256::from(4));
+        assert_eq!(
+            state_trie.extract_storage_trie(b).unwrap().root(),
+            super::StorageTrie::empty().root()
+        );
+        assert!(state_trie
+            .extract_storage_trie(Address::from_low_u64_be(3))
+            .unwrap()
+            .get(U256::one())
+            .unwrap()
+            .is_zero());
+        assert!(StateTrie::from_root(state_trie.root().unwrap())
+            .extract_storage_trie(a)
+            .is_err());
+
+        // writes to it stay out of the state until it's put back.
+        storage_trie.set(U256::from(6), U256::from(6)).unwrap();
+        assert!(state_trie
+            .get_storage_at(a, U256::from(6))
+            .unwrap()
+            .is_zero());
+        let storage_root = storage_trie.root().unwrap();
+        state_trie.insert_storage_trie(storage_trie).unwrap();
+        state_trie.set_storage_root(b, storage_root, true).unwrap();
+        assert_eq!(
+            state_trie.get_storage_at(b, U256::from(6)).unwrap(),
+            U256::from(6)
+        );
+    }
+
+    #[test]
+    pub fn test_storage_tries() {
+        let empty_root = super::StorageTrie::empty().root().unwrap();
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut state_trie = StateTrie::from_root(empty_root);
+        state_trie.account_trie.set_balance(a, U256::one()).unwrap();
+        state_trie
+            .set_storage_value(b, U256::one(), U256::from(2))
+            .unwrap();
+
+        // a was set through the account trie, so its address isn't known here.
+        let tries: Vec<_> = state_trie.storage_tries().collect();
+        assert_eq!(tries.len(), 1);
+        assert_eq!(tries[0].0, b);
+        assert_eq!(
+            tries[0].1.root(),
+            Some(state_trie.get_storage_root(b).unwrap())
+        );
+        assert_eq!(
+            state_trie
+                .storage_trie(b)
+                .unwrap()
+                .unwrap()
+                .get(U256::one())
+                .unwrap(),
+            U256::from(2)
+        );
+        // empty storage isn't loaded until it's read.
+        assert!(state_trie.storage_trie(a).unwrap().is_none());
+        assert!(state_trie
+            .storage_trie(Address::from_low_u64_be(3))
+            .unwrap()
+            .is_none());
+
+        // from a witness only the hashes are known.
+        let proof = state_trie
+            .get_proof(b, vec![H256::from_low_u64_be(1)])
+            .unwrap();
+        let from_witness = StateTrie::from_witness(&state_trie.witness().unwrap()).unwrap();
+        assert_eq!(from_witness.storage_tries().count(), 0);
+        let by_hash = from_witness.storage_tries_by_hash().unwrap();
+        assert_eq!(by_hash.len(), 2);
+        assert!(by_hash
+            .iter()
+            .any(|(hash, _)| *hash == H256::from(keccak256(b))));
+
+        let mut partial = StateTrie::from_root(state_trie.root().unwrap());
+        partial.load_proof(proof).unwrap();
+        assert_eq!(partial.storage_tries().next().unwrap().0, b);
+    }
 
     #[test]
     pub fn test_geth_dev_state_1() {
@@ -478,16 +1441,14 @@ mod tests {
         let rpc_url = env::var("RPC").expect("pass RPC env var");
         let provider = Provider::try_from(rpc_url).unwrap();
 
-        let prev_block_number = Some(BlockId::Number(BlockNumber::from(block_number - 1)));
-        let current_block_number = Some(BlockId::Number(BlockNumber::from(block_number)));
+        let prev_block_id = BlockId::Number(BlockNumber::from(block_number - 1));
+        let current_block_id = BlockId::Number(BlockNumber::from(block_number));
+        let prev_block_number = Some(prev_block_id);
+        let current_block_number = Some(current_block_id);
 
-        let prev_block = provider
-            .get_block(prev_block_number.unwrap())
-            .await
-            .unwrap()
-            .unwrap();
+        let prev_block = provider.get_block(prev_block_id).await.unwrap().unwrap();
         let current_block = provider
-            .get_block_with_txs(current_block_number.unwrap())
+            .get_block_with_txs(current_block_id)
             .await
             .unwrap()
             .unwrap();
@@ -503,10 +1464,10 @@ mod tests {
         account_slots_parsed.push((current_block.author.unwrap(), vec![]));
         for tx in current_block.transactions {
             account_slots_parsed.push((tx.from, vec![]));
-            if tx.to.is_some() {
-                account_slots_parsed.push((tx.to.unwrap(), vec![]));
+            if let Some(to) = tx.to {
+                account_slots_parsed.push((to, vec![]));
             } else {
-                let contract_address = ethers::utils::get_contract_address(tx.from, tx.nonce);
+                let contract_address = ethers_core::utils::get_contract_address(tx.from, tx.nonce);
                 account_slots_parsed.push((contract_address, vec![]));
             }
         }
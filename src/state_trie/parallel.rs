@@ -0,0 +1,189 @@
+use std::collections::hash_map::Entry;
+
+use ethers_core::types::{EIP1186ProofResponse, H256};
+
+use super::StateTrie;
+use crate::Error;
+
+// the proofs checked against a root, with a result per proof in the order they were given.
+// failed proofs load nothing, not even the slots before the failing one.
+#[derive(Debug)]
+pub struct VerifiedProofs {
+    root: H256,
+    state_trie: StateTrie,
+    pub results: Vec<Result<(), Error>>,
+}
+
+impl VerifiedProofs {
+    // verifies and decodes the proofs on `threads` threads, each proof into a trie of its own
+    // which goes into the thread's trie when it's good. nothing is shared until the end, when
+    // the tries of the threads are merged. a single thread verifies on the calling one, e.g.
+    // on wasm where threads can't be spawned.
+    pub fn verify(root: H256, proofs: Vec<EIP1186ProofResponse>, threads: usize) -> Self {
+        let threads = threads.clamp(1, proofs.len().max(1));
+        let chunk_size = proofs.len().div_ceil(threads).max(1);
+        let mut chunks: Vec<Vec<EIP1186ProofResponse>> = Vec::with_capacity(threads);
+        let mut proofs = proofs.into_iter().peekable();
+        while proofs.peek().is_some() {
+            chunks.push(proofs.by_ref().take(chunk_size).collect());
+        }
+
+        let verified: Vec<(StateTrie, Vec<Result<(), Error>>)> = if chunks.len() <= 1 {
+            chunks
+                .into_iter()
+                .map(|chunk| verify_chunk(root, chunk))
+                .collect()
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = chunks
+                    .into_iter()
+                    .map(|chunk| scope.spawn(move || verify_chunk(root, chunk)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("proof verification panicked"))
+                    .collect()
+            })
+        };
+
+        let mut state_trie = StateTrie::from_root(root);
+        let mut results = Vec::new();
+        for (chunk_trie, chunk_results) in verified {
+            state_trie.merge_loaded(chunk_trie);
+            results.extend(chunk_results);
+        }
+        VerifiedProofs {
+            root,
+            state_trie,
+            results,
+        }
+    }
+
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+}
+
+fn verify_chunk(
+    root: H256,
+    proofs: Vec<EIP1186ProofResponse>,
+) -> (StateTrie, Vec<Result<(), Error>>) {
+    let mut chunk_trie = StateTrie::from_root(root);
+    let results = proofs
+        .into_iter()
+        .map(|proof| {
+            let mut proof_trie = StateTrie::from_root(root);
+            proof_trie.load_proof(proof)?;
+            chunk_trie.merge_loaded(proof_trie);
+            Ok(())
+        })
+        .collect();
+    (chunk_trie, results)
+}
+
+impl StateTrie {
+    // load_proof for many responses at once, see VerifiedProofs::verify. fails only if the
+    // root isn't set, otherwise there's a result per proof.
+    pub fn load_proofs_parallel(
+        &mut self,
+        proofs: Vec<EIP1186ProofResponse>,
+        threads: usize,
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        let root = self.root().ok_or(Error::Usage("root not set"))?;
+        let verified = VerifiedProofs::verify(root, proofs, threads);
+        self.load_verified(verified)
+    }
+
+    // takes the nodes of proofs verified against the current root, without checking them
+    // again.
+    pub fn load_verified(
+        &mut self,
+        verified: VerifiedProofs,
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        if self.root() != Some(verified.root) {
+            return Err(Error::InternalError(
+                "proofs were verified against another root",
+            ));
+        }
+        self.merge_loaded(verified.state_trie);
+        Ok(verified.results)
+    }
+
+    // the loaded nodes and known keys of a state trie with the same root.
+    pub(super) fn merge_loaded(&mut self, other: StateTrie) {
+        self.account_trie.merge_loaded_nodes(other.account_trie);
+        for (storage_root, storage_trie) in other.storage_tries {
+            match self.storage_tries.entry(storage_root) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge_loaded_nodes(storage_trie),
+                Entry::Vacant(entry) => {
+                    entry.insert(storage_trie);
+                }
+            }
+        }
+        self.addresses.extend(other.addresses);
+        self.slots.extend(other.slots);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VerifiedProofs;
+    use crate::{ConcurrentStateTrie, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_load_proofs_parallel() {
+        let mut full = StateTrie::new();
+        for i in 1..=100u64 {
+            let address = Address::from_low_u64_be(i);
+            full.account_trie
+                .set_balance(address, U256::from(i))
+                .unwrap();
+            for slot in 0..i % 4 {
+                full.set_storage_value(address, U256::from(slot), U256::from(i + slot))
+                    .unwrap();
+            }
+        }
+        let root = full.root().unwrap();
+        let mut proofs: Vec<_> = (1..=100u64)
+            .map(|i| {
+                let slots = (0..i % 4).map(H256::from_low_u64_be).collect();
+                full.get_proof(Address::from_low_u64_be(i), slots).unwrap()
+            })
+            .collect();
+        // a bad storage proof fails its whole response, the others still load.
+        let bad = proofs
+            .iter()
+            .position(|proof| proof.storage_proof.len() > 1)
+            .unwrap();
+        proofs[bad].storage_proof[1].value += U256::one();
+
+        let mut serial = StateTrie::from_root(root);
+        for (i, proof) in proofs.iter().enumerate() {
+            if i != bad {
+                serial.load_proof(proof.clone()).unwrap();
+            }
+        }
+
+        for threads in [1, 3, 8] {
+            let mut parallel = StateTrie::from_root(root);
+            let results = parallel
+                .load_proofs_parallel(proofs.clone(), threads)
+                .unwrap();
+            assert_eq!(results.len(), proofs.len());
+            for (i, result) in results.iter().enumerate() {
+                assert_eq!(result.is_err(), i == bad);
+            }
+            assert_eq!(parallel, serial);
+        }
+
+        // verified outside of the lock, merged under one write.
+        let shared = ConcurrentStateTrie::new(StateTrie::from_root(root));
+        let results = shared.load_proofs(proofs.clone(), 4).unwrap();
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert_eq!(shared.into_inner(), serial);
+
+        let verified = VerifiedProofs::verify(root, proofs, 2);
+        assert!(StateTrie::new().load_verified(verified).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ethers_core::types::{
+    AccountDiff as ParityAccountDiff, BigEndianHash, Bytes, ChangedType, Diff,
+    StateDiff as ParityStateDiff, H256, U256,
+};
+
+use super::{AccountData, AccountDiff, StateDiff};
+use crate::Error;
+
+fn code(codes: &HashMap<H256, Bytes>, code_hash: H256) -> Result<Bytes, Error> {
+    if code_hash == AccountData::default().code_hash {
+        return Ok(Bytes::default());
+    }
+    codes.get(&code_hash).cloned().ok_or(Error::MissingData(
+        "code for a changed code hash is not in codes",
+    ))
+}
+
+// = for unchanged, * with from and to otherwise.
+fn field<T: Clone>(change: &Option<(T, T)>) -> Diff<T> {
+    match change {
+        Some((from, to)) => Diff::Changed(ChangedType {
+            from: from.clone(),
+            to: to.clone(),
+        }),
+        None => Diff::Same,
+    }
+}
+
+// the value after a change, or the default when the account was created.
+fn born<T: Clone + Default>(change: &Option<(T, T)>) -> T {
+    change
+        .as_ref()
+        .map(|(_, to)| to.clone())
+        .unwrap_or_default()
+}
+
+fn died<T: Clone + Default>(change: &Option<(T, T)>) -> T {
+    change
+        .as_ref()
+        .map(|(from, _)| from.clone())
+        .unwrap_or_default()
+}
+
+fn storage_diff(from: U256, to: U256) -> Diff<H256> {
+    // storage is a map of non zero values, so zero to x is a new entry.
+    match (from.is_zero(), to.is_zero()) {
+        (true, _) => Diff::Born(H256::from_uint(&to)),
+        (_, true) => Diff::Died(H256::from_uint(&from)),
+        _ => Diff::Changed(ChangedType {
+            from: H256::from_uint(&from),
+            to: H256::from_uint(&to),
+        }),
+    }
+}
+
+fn account_diff(
+    diff: &AccountDiff,
+    codes: &HashMap<H256, Bytes>,
+) -> Result<ParityAccountDiff, Error> {
+    let storage = diff
+        .storage
+        .iter()
+        .map(|(slot, (from, to))| (H256::from_uint(slot), storage_diff(*from, *to)))
+        .collect::<BTreeMap<_, _>>();
+
+    if diff.created {
+        return Ok(ParityAccountDiff {
+            balance: Diff::Born(born(&diff.balance)),
+            nonce: Diff::Born(born(&diff.nonce)),
+            code: Diff::Born(code(
+                codes,
+                diff.code_hash
+                    .map(|(_, to)| to)
+                    .unwrap_or(AccountData::default().code_hash),
+            )?),
+            storage,
+        });
+    }
+    if diff.destroyed {
+        return Ok(ParityAccountDiff {
+            balance: Diff::Died(died(&diff.balance)),
+            nonce: Diff::Died(died(&diff.nonce)),
+            code: Diff::Died(code(
+                codes,
+                diff.code_hash
+                    .map(|(from, _)| from)
+                    .unwrap_or(AccountData::default().code_hash),
+            )?),
+            storage,
+        });
+    }
+
+    let code = match diff.code_hash {
+        Some((from, to)) => Diff::Changed(ChangedType {
+            from: code(codes, from)?,
+            to: code(codes, to)?,
+        }),
+        None => Diff::Same,
+    };
+    Ok(ParityAccountDiff {
+        balance: field(&diff.balance),
+        nonce: field(&diff.nonce),
+        code,
+        storage,
+    })
+}
+
+impl StateDiff {
+    // the diff in the stateDiff format of openethereum's trace_replayTransaction, with from
+    // and to per field. codes needs the code for every changed code hash, old and new.
+    pub fn to_parity_state_diff(
+        &self,
+        codes: &HashMap<H256, Bytes>,
+    ) -> Result<ParityStateDiff, Error> {
+        let mut accounts = BTreeMap::new();
+        for (address, diff) in self.accounts.iter() {
+            accounts.insert(*address, account_diff(diff, codes)?);
+        }
+        Ok(ParityStateDiff(accounts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::{
+        types::{Address, Bytes, Diff, H256, U256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_parity_state_diff() {
+        let sender = Address::from_low_u64_be(1);
+        let contract = Address::from_low_u64_be(2);
+        let mut original = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        original
+            .account_trie
+            .set_balance(sender, U256::from(100))
+            .unwrap();
+        original
+            .account_trie
+            .set_balance(Address::from_low_u64_be(3), U256::from(1))
+            .unwrap();
+
+        let code = Bytes::from(vec![0x60, 0x00]);
+        let code_hash = H256::from(keccak256(&code));
+        let mut state_trie = original.clone();
+        state_trie
+            .account_trie
+            .set_balance(sender, U256::from(60))
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_nonce(sender, U256::one())
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_code_hash(contract, code_hash)
+            .unwrap();
+        state_trie
+            .set_storage_value(contract, U256::one(), U256::from(7))
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_balance(Address::from_low_u64_be(3), U256::zero())
+            .unwrap();
+
+        let diff = state_trie
+            .diff(
+                &original,
+                &[
+                    (sender, vec![]),
+                    (contract, vec![U256::one()]),
+                    (Address::from_low_u64_be(3), vec![]),
+                ],
+            )
+            .unwrap();
+        let parity = diff
+            .to_parity_state_diff(&HashMap::from([(code_hash, code.clone())]))
+            .unwrap();
+
+        let sender_diff = &parity.0[&sender];
+        assert!(matches!(sender_diff.code, Diff::Same));
+        assert!(
+            matches!(&sender_diff.balance, Diff::Changed(changed) if changed.to == U256::from(60))
+        );
+
+        let contract_diff = &parity.0[&contract];
+        assert_eq!(contract_diff.code, Diff::Born(code));
+        assert_eq!(contract_diff.balance, Diff::Born(U256::zero()));
+        assert_eq!(
+            contract_diff.storage[&H256::from_low_u64_be(1)],
+            Diff::Born(H256::from_low_u64_be(7))
+        );
+
+        let json = serde_json::to_value(&parity).unwrap();
+        assert_eq!(json[format!("{:?}", sender)]["code"], "=");
+        assert_eq!(json[format!("{:?}", sender)]["nonce"]["*"]["to"], "0x1");
+        assert_eq!(
+            json[format!("{:?}", Address::from_low_u64_be(3))]["balance"]["-"],
+            "0x1"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashSet};
+
+use ethers_core::types::H256;
+
+use super::{StateAccount, StateTrie};
+use crate::{nodes::NodeData, Error};
+
+// what a prune of the state removes, or would remove.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrunedNodes {
+    pub account_nodes: Vec<H256>,
+    // by storage root. a storage trie no reachable account has anymore goes with all its
+    // nodes, e.g. the ones left behind by writes to the storage.
+    pub storage_nodes: BTreeMap<H256, Vec<H256>>,
+    // roots of those storage tries.
+    pub storage_tries: Vec<H256>,
+}
+
+impl PrunedNodes {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // nodes in all the tries.
+    pub fn len(&self) -> usize {
+        self.account_nodes.len() + self.storage_nodes.values().map(Vec::len).sum::<usize>()
+    }
+}
+
+impl<A: StateAccount> StateTrie<A> {
+    // what prune would remove, without removing it.
+    pub fn prune_dry_run(&self) -> Result<PrunedNodes, Error> {
+        // storage roots in the account leaves the current root or a committed version reach.
+        let mut storage_roots = HashSet::new();
+        let mut roots = vec![self
+            .account_trie
+            .root()
+            .ok_or(Error::Usage("root not set"))?];
+        roots.extend(self.account_trie.versions());
+        let mut pending = roots;
+        let mut seen = HashSet::new();
+        while let Some(hash) = pending.pop() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            match self.account_trie.node(&hash) {
+                Some(NodeData::Leaf { key: _, value }) => {
+                    storage_roots.insert(value.storage_root());
+                }
+                Some(NodeData::Branch(arr)) => pending.extend(arr.iter().flatten()),
+                Some(NodeData::Extension { key: _, node }) => pending.push(*node),
+                None => {}
+            }
+        }
+
+        let mut pruned = PrunedNodes {
+            account_nodes: self.account_trie.prune_dry_run()?,
+            ..Default::default()
+        };
+        for (storage_root, storage_trie) in self.storage_tries.iter() {
+            let nodes = if storage_roots.contains(storage_root) {
+                storage_trie.prune_dry_run()?
+            } else {
+                pruned.storage_tries.push(*storage_root);
+                storage_trie.unreachable_from(vec![])
+            };
+            if !nodes.is_empty() {
+                pruned.storage_nodes.insert(*storage_root, nodes);
+            }
+        }
+        pruned.storage_tries.sort();
+        Ok(pruned)
+    }
+
+    // drops every node and storage trie which neither the current state root nor a committed
+    // version reaches, so a long lived state doesn't keep what writes replaced. all the data
+    // which can still be read stays.
+    pub fn prune(&mut self) -> Result<PrunedNodes, Error> {
+        let pruned = self.prune_dry_run()?;
+        self.account_trie.prune()?;
+        for storage_root in pruned.storage_tries.iter() {
+            self.storage_tries.remove(storage_root);
+        }
+        for storage_trie in self.storage_tries.values_mut() {
+            storage_trie.prune()?;
+        }
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{NodeVerification, StateTrie};
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_prune() {
+        let address = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::new();
+        for slot in 1..=5u64 {
+            state_trie
+                .set_storage_value(address, U256::from(slot), U256::from(slot))
+                .unwrap();
+        }
+        // every write leaves the storage trie of the root before.
+        let pruned = state_trie.prune_dry_run().unwrap();
+        assert!(pruned.account_nodes.is_empty());
+        assert!(!pruned.storage_tries.is_empty());
+        assert!(!pruned
+            .storage_tries
+            .contains(&state_trie.get_storage_root(address).unwrap()));
+        let root = state_trie.root();
+        let witness = state_trie.witness().unwrap();
+
+        // a trusted node the trie doesn't point to.
+        let mut other = StateTrie::new();
+        other
+            .account_trie
+            .set_balance(Address::from_low_u64_be(2), U256::one())
+            .unwrap();
+        let stray = other
+            .get_proof(Address::from_low_u64_be(2), vec![])
+            .unwrap()
+            .account_proof[0]
+            .clone();
+        let stray_hash = state_trie
+            .account_trie
+            .insert_raw_node(stray, NodeVerification::Trust)
+            .unwrap();
+        let pruned = state_trie.prune().unwrap();
+        assert_eq!(pruned.account_nodes, vec![stray_hash]);
+        assert!(state_trie.prune_dry_run().unwrap().is_empty());
+        assert_eq!(state_trie.root(), root);
+        // the witness had the replaced storage tries too.
+        let pruned_witness = state_trie.witness().unwrap();
+        assert!(pruned_witness.node_map().len() < witness.node_map().len());
+        let mut loaded = StateTrie::from_witness(&pruned_witness).unwrap();
+        assert_eq!(
+            loaded.get_storage_at(address, U256::from(5)).unwrap(),
+            U256::from(5)
+        );
+        for slot in 1..=5u64 {
+            assert_eq!(
+                state_trie
+                    .get_storage_at(address, U256::from(slot))
+                    .unwrap(),
+                U256::from(slot)
+            );
+        }
+
+        // committed versions keep their nodes.
+        state_trie.start_history();
+        let version = state_trie.commit_version().unwrap();
+        state_trie
+            .account_trie
+            .set_balance(address, U256::one())
+            .unwrap();
+        state_trie.prune().unwrap();
+        assert_eq!(
+            state_trie
+                .get_storage_at_root(version, address, U256::from(3))
+                .unwrap(),
+            U256::from(3)
+        );
+        assert!(state_trie
+            .account_trie
+            .get_proof_at(version, address)
+            .is_ok());
+    }
+}
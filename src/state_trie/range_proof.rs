@@ -0,0 +1,42 @@
+use ethers_core::types::{Bytes, H256, U256};
+
+use super::{AccountData, StateTrie, StorageTrie};
+use crate::{nibbles::Nibbles, Error};
+
+impl StateTrie {
+    // loads consecutive accounts by address hash from `origin` with their range proof (see
+    // Trie::load_range), e.g. a snap/1 AccountRange. returns whether there are accounts
+    // after the last one, i.e. whether to ask for the next range.
+    pub fn load_account_range(
+        &mut self,
+        origin: H256,
+        accounts: Vec<(H256, AccountData)>,
+        proof: &[Bytes],
+    ) -> Result<bool, Error> {
+        self.account_trie.load_range(origin, accounts, proof)
+    }
+
+    // same for slots by slot hash of the account with `account_hash`, which has to be loaded
+    // already (e.g. from its account range) for its storage root.
+    pub fn load_storage_range(
+        &mut self,
+        account_hash: H256,
+        origin: H256,
+        slots: Vec<(H256, U256)>,
+        proof: &[Bytes],
+    ) -> Result<bool, Error> {
+        let storage_root = self
+            .account_trie
+            .get_path(Nibbles::from_raw_path(Bytes::from(
+                account_hash.as_bytes().to_vec(),
+            )))?
+            .storage_root;
+        let mut storage_trie = self
+            .storage_tries
+            .remove(&storage_root)
+            .unwrap_or_else(|| StorageTrie::from_root(storage_root));
+        let more = storage_trie.load_range(origin, slots, proof);
+        self.storage_tries.insert(storage_root, storage_trie);
+        more
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use ethers_core::{
+    types::{Bytes, H256, U256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+use super::{AccountData, AccountTrie, StateTrie, StorageTrie};
+use crate::{nodes::LeafValue, Error};
+
+// key prefixes of geth's snapshot (core/rawdb/schema.go): "a" ++ account hash for accounts,
+// "o" ++ account hash ++ slot hash for storage.
+pub const SNAPSHOT_ACCOUNT_PREFIX: u8 = b'a';
+pub const SNAPSHOT_STORAGE_PREFIX: u8 = b'o';
+
+fn hash_or_default(raw: &[u8], default: H256) -> Result<H256, Error> {
+    match raw.len() {
+        0 => Ok(default),
+        32 => Ok(H256::from_slice(raw)),
+        _ => Err(Error::InternalError("slim account hash is not 32 bytes")),
+    }
+}
+
+// the snapshot's slim account: rlp([nonce, balance, root, code hash]) where the root and the
+// code hash are empty when they are the empty ones.
+pub fn decode_slim_account(raw: &[u8]) -> Result<AccountData, Error> {
+    let rlp = Rlp::new(raw);
+    if rlp.item_count()? != 4 {
+        return Err(Error::InternalError("slim account should have 4 fields"));
+    }
+    let empty = AccountData::default();
+    Ok(AccountData {
+        nonce: rlp.val_at(0)?,
+        balance: rlp.val_at(1)?,
+        storage_root: hash_or_default(rlp.at(2)?.data()?, empty.storage_root)?,
+        code_hash: hash_or_default(rlp.at(3)?.data()?, empty.code_hash)?,
+    })
+}
+
+// decode_slim_account the other way around, snap/1 sends accounts like this too.
+pub fn encode_slim_account(account: &AccountData) -> Bytes {
+    let empty = AccountData::default();
+    let mut stream = RlpStream::new_list(4);
+    stream.append(&account.nonce);
+    stream.append(&account.balance);
+    for (hash, empty_hash) in [
+        (account.storage_root, empty.storage_root),
+        (account.code_hash, empty.code_hash),
+    ] {
+        if hash == empty_hash {
+            stream.append_empty_data();
+        } else {
+            stream.append(&hash);
+        }
+    }
+    stream.out().freeze().into()
+}
+
+impl StateTrie {
+    // builds the complete tries from a geth snapshot: account hash -> slim account and
+    // (account hash, slot hash) -> rlp of the slot value. the snapshot has no preimages, so
+    // the tries are read by address and slot as usual but can't list them. the storage roots
+    // the accounts claim are checked against the rebuilt storage tries.
+    pub fn from_snapshot(
+        accounts: impl IntoIterator<Item = (H256, Bytes)>,
+        storage: impl IntoIterator<Item = (H256, H256, Bytes)>,
+    ) -> Result<Self, Error> {
+        let mut slots_by_account: HashMap<H256, Vec<(H256, U256)>> = HashMap::new();
+        for (account_hash, slot_hash, value) in storage {
+            slots_by_account
+                .entry(account_hash)
+                .or_default()
+                .push((slot_hash, U256::from_raw_rlp(value)?));
+        }
+
+        let mut storage_tries = HashMap::new();
+        let mut account_entries = Vec::new();
+        for (account_hash, raw) in accounts {
+            let account_data = decode_slim_account(&raw)?;
+            let storage_trie = match slots_by_account.remove(&account_hash) {
+                Some(slots) => StorageTrie::from_hashed_entries(slots)?,
+                None => StorageTrie::empty(),
+            };
+            if storage_trie.root() != Some(account_data.storage_root) {
+                return Err(Error::InternalError(
+                    "storage root mismatch, is the snapshot missing storage?",
+                ));
+            }
+            storage_tries.insert(account_data.storage_root, storage_trie);
+            account_entries.push((account_hash, account_data));
+        }
+        if !slots_by_account.is_empty() {
+            return Err(Error::InternalError(
+                "storage for an account which is not in the snapshot",
+            ));
+        }
+
+        Ok(StateTrie {
+            account_trie: AccountTrie::from_hashed_entries(account_entries)?,
+            storage_tries,
+            ..Default::default()
+        })
+    }
+
+    // from_snapshot over raw snapshot database entries, e.g. everything under the "a" and
+    // "o" prefixes of a geth datadir. other keys are an error.
+    pub fn from_snapshot_entries(
+        entries: impl IntoIterator<Item = (Bytes, Bytes)>,
+    ) -> Result<Self, Error> {
+        let mut accounts = Vec::new();
+        let mut storage = Vec::new();
+        for (key, value) in entries {
+            match (key.first(), key.len()) {
+                (Some(&SNAPSHOT_ACCOUNT_PREFIX), 33) => {
+                    accounts.push((H256::from_slice(&key[1..]), value))
+                }
+                (Some(&SNAPSHOT_STORAGE_PREFIX), 65) => storage.push((
+                    H256::from_slice(&key[1..33]),
+                    H256::from_slice(&key[33..]),
+                    value,
+                )),
+                _ => {
+                    return Err(Error::InternalError(
+                        "not a snapshot account or storage key",
+                    ))
+                }
+            }
+        }
+        Self::from_snapshot(accounts, storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_slim_account, encode_slim_account, SNAPSHOT_ACCOUNT_PREFIX, SNAPSHOT_STORAGE_PREFIX,
+    };
+    use crate::{nodes::LeafValue, state_trie::AccountData, StateTrie};
+    use ethers_core::{
+        types::{Address, BigEndianHash, Bytes, H256, U256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_decode_slim_account() {
+        let account = AccountData {
+            nonce: U256::from(3),
+            balance: U256::from(1000),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_slim_account(&encode_slim_account(&account)).unwrap(),
+            account
+        );
+        let contract = AccountData {
+            storage_root: H256::repeat_byte(1),
+            code_hash: H256::repeat_byte(2),
+            ..account
+        };
+        assert_eq!(
+            decode_slim_account(&encode_slim_account(&contract)).unwrap(),
+            contract
+        );
+        assert!(decode_slim_account(&[0xc0]).is_err());
+    }
+
+    #[test]
+    pub fn test_from_snapshot() {
+        let mut full = StateTrie::new();
+        let contract = Address::from_low_u64_be(1);
+        for i in 1..10u64 {
+            full.account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        for slot in 0..5u64 {
+            full.set_storage_value(contract, U256::from(slot), U256::from(slot + 7))
+                .unwrap();
+        }
+
+        // as geth's snapshot database has them.
+        let mut entries = Vec::new();
+        for i in 1..10u64 {
+            let address = Address::from_low_u64_be(i);
+            let mut key = vec![SNAPSHOT_ACCOUNT_PREFIX];
+            key.extend_from_slice(&keccak256(address));
+            entries.push((
+                Bytes::from(key),
+                encode_slim_account(&full.account_trie.get(address).unwrap()),
+            ));
+        }
+        for slot in 0..5u64 {
+            let mut key = vec![SNAPSHOT_STORAGE_PREFIX];
+            key.extend_from_slice(&keccak256(contract));
+            key.extend_from_slice(&keccak256(H256::from_uint(&U256::from(slot))));
+            entries.push((Bytes::from(key), U256::from(slot + 7).to_raw_rlp().unwrap()));
+        }
+
+        let mut imported = StateTrie::from_snapshot_entries(entries.clone()).unwrap();
+        assert_eq!(imported.root(), full.root());
+        assert_eq!(
+            imported.get_storage_at(contract, U256::from(3)).unwrap(),
+            U256::from(10)
+        );
+        assert_eq!(
+            imported
+                .account_trie
+                .get(Address::from_low_u64_be(5))
+                .unwrap()
+                .balance,
+            U256::from(5)
+        );
+
+        // a snapshot missing a slot doesn't match the account's storage root.
+        entries.pop();
+        assert!(StateTrie::from_snapshot_entries(entries.clone()).is_err());
+        entries.push((Bytes::from(vec![b'x'; 33]), Bytes::new()));
+        assert!(StateTrie::from_snapshot_entries(entries).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use ethers_core::types::H256;
+
+use super::AccountData;
+use crate::nodes::LeafValue;
+
+// the value of an account leaf. LeafValue is its rlp codec, and the state trie only needs to
+// find the account's storage trie, so chains with extra account fields or another layout
+// plug their own type into StateTrie<A>. the mainnet 4 field account is AccountData.
+pub trait StateAccount: LeafValue {
+    fn storage_root(&self) -> H256;
+
+    fn set_storage_root(&mut self, storage_root: H256);
+}
+
+impl StateAccount for AccountData {
+    fn storage_root(&self) -> H256 {
+        self.storage_root
+    }
+
+    fn set_storage_root(&mut self, storage_root: H256) {
+        self.storage_root = storage_root;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateAccount;
+    use crate::{hasher::KeccakHasher, nodes::LeafValue, Error, StateTrie, TrieHasher};
+    use ethers_core::{
+        types::{Address, Bytes, StorageProof, H256, U256},
+        utils::{
+            keccak256,
+            rlp::{Rlp, RlpStream},
+        },
+    };
+
+    // an account with a fifth field, like some l2s have.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct ExtraAccount {
+        nonce: U256,
+        balance: U256,
+        storage_root: H256,
+        code_hash: H256,
+        extra: U256,
+    }
+
+    impl Default for ExtraAccount {
+        fn default() -> Self {
+            ExtraAccount {
+                nonce: U256::zero(),
+                balance: U256::zero(),
+                storage_root: KeccakHasher::empty_root(),
+                code_hash: H256::from(keccak256([])),
+                extra: U256::zero(),
+            }
+        }
+    }
+
+    impl LeafValue for ExtraAccount {
+        fn from_raw_rlp(raw: Bytes) -> Result<Self, Error> {
+            let rlp = Rlp::new(&raw);
+            Ok(ExtraAccount {
+                nonce: rlp.val_at(0)?,
+                balance: rlp.val_at(1)?,
+                storage_root: rlp.val_at(2)?,
+                code_hash: rlp.val_at(3)?,
+                extra: rlp.val_at(4)?,
+            })
+        }
+
+        fn to_raw_rlp(&self) -> Result<Bytes, Error> {
+            let mut rlp_stream = RlpStream::new_list(5);
+            rlp_stream.append(&self.nonce);
+            rlp_stream.append(&self.balance);
+            rlp_stream.append(&self.storage_root);
+            rlp_stream.append(&self.code_hash);
+            rlp_stream.append(&self.extra);
+            Ok(Bytes::from(rlp_stream.out().to_vec()))
+        }
+    }
+
+    impl StateAccount for ExtraAccount {
+        fn storage_root(&self) -> H256 {
+            self.storage_root
+        }
+
+        fn set_storage_root(&mut self, storage_root: H256) {
+            self.storage_root = storage_root;
+        }
+    }
+
+    #[test]
+    pub fn test_custom_account_type() {
+        let address = Address::from_low_u64_be(1);
+        let slot = U256::from(3);
+        let mut state_trie = StateTrie::<ExtraAccount>::with_root(KeccakHasher::empty_root());
+        state_trie
+            .account_trie
+            .set(
+                address,
+                ExtraAccount {
+                    balance: U256::from(10),
+                    extra: U256::from(7),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        state_trie
+            .set_storage_value(address, slot, U256::from(99))
+            .unwrap();
+
+        let account = state_trie.account_trie.get(address).unwrap();
+        assert_eq!(account.extra, U256::from(7));
+        assert_ne!(account.storage_root, KeccakHasher::empty_root());
+        assert_eq!(
+            state_trie.get_storage_at(address, slot).unwrap(),
+            U256::from(99)
+        );
+
+        // the same account without the extra field has another root.
+        let mut mainnet = StateTrie::new();
+        mainnet
+            .account_trie
+            .set_balance(address, U256::from(10))
+            .unwrap();
+        mainnet
+            .set_storage_value(address, slot, U256::from(99))
+            .unwrap();
+        assert_ne!(mainnet.root(), state_trie.root());
+        assert_eq!(
+            mainnet.get_storage_root(address).unwrap(),
+            state_trie.get_storage_root(address).unwrap()
+        );
+
+        // proofs for the custom account load like eth_getProof ones.
+        let storage_trie = state_trie.storage_trie(address).unwrap().unwrap();
+        let storage_proof = StorageProof {
+            key: slot,
+            proof: storage_trie.get_proof(slot).unwrap(),
+            value: U256::from(99),
+        };
+        let mut partial = StateTrie::<ExtraAccount>::with_root(state_trie.root().unwrap());
+        partial
+            .load_account_proof(
+                address,
+                account.clone(),
+                state_trie.account_trie.get_proof(address).unwrap(),
+                vec![storage_proof],
+            )
+            .unwrap();
+        assert_eq!(
+            partial.get_storage_at(address, slot).unwrap(),
+            U256::from(99)
+        );
+
+        // a different extra field doesn't verify.
+        let mut wrong = account;
+        wrong.extra = U256::from(8);
+        assert!(
+            StateTrie::<ExtraAccount>::with_root(state_trie.root().unwrap())
+                .load_account_proof(
+                    address,
+                    wrong,
+                    state_trie.account_trie.get_proof(address).unwrap(),
+                    vec![],
+                )
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::ops::RangeBounds;
+
+use ethers_core::types::{Address, H256, U256};
+
+use super::{leaves, StateTrie};
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    Error,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageEntry {
+    pub slot_hash: H256,
+    // the slot itself, if this trie has seen it in a proof, a read or a write.
+    pub slot: Option<U256>,
+    pub value: U256,
+}
+
+impl StateTrie {
+    // the loaded slots of an account in trie order (by slot hash) within `range`, e.g. `..`
+    // for all of them or `start..end` for a page. only the loaded part of the storage trie is
+    // covered, which is all of it after a dump or a complete range proof.
+    pub fn storage_range(
+        &self,
+        address: Address,
+        range: impl RangeBounds<H256>,
+    ) -> Result<impl Iterator<Item = StorageEntry> + '_, Error> {
+        let storage_root = self.get_storage_root(address)?;
+        let mut entries = if storage_root == KeccakHasher::empty_root() {
+            vec![]
+        } else {
+            let storage_trie = self
+                .storage_tries
+                .get(&storage_root)
+                .ok_or(Error::MissingData(
+                    "storage trie not present, please add a proof",
+                ))?;
+            leaves(storage_trie)?
+        };
+
+        entries.retain(|(slot_hash, _)| range.contains(slot_hash));
+        Ok(entries.into_iter().map(|(slot_hash, value)| StorageEntry {
+            slot_hash,
+            slot: self.slots.get(&slot_hash).copied(),
+            value,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::{
+        types::{Address, BigEndianHash, H256, U256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_storage_range() {
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        full.account_trie.set_balance(address, U256::one()).unwrap();
+        assert_eq!(full.storage_range(address, ..).unwrap().count(), 0);
+        for i in 1..=20u64 {
+            full.set_storage_value(address, U256::from(i), U256::from(i * 10))
+                .unwrap();
+        }
+
+        let entries: Vec<_> = full.storage_range(address, ..).unwrap().collect();
+        assert_eq!(entries.len(), 20);
+        assert!(entries.windows(2).all(|w| w[0].slot_hash < w[1].slot_hash));
+        for entry in &entries {
+            let slot = entry.slot.unwrap();
+            assert_eq!(
+                entry.slot_hash,
+                H256::from(keccak256(H256::from_uint(&slot)))
+            );
+            assert_eq!(entry.value, slot * 10);
+        }
+
+        // pages split at a slot hash cover everything once.
+        let middle = entries[7].slot_hash;
+        let first: Vec<_> = full.storage_range(address, ..middle).unwrap().collect();
+        let rest: Vec<_> = full.storage_range(address, middle..).unwrap().collect();
+        assert_eq!(first.len(), 7);
+        assert_eq!([first, rest].concat(), entries);
+
+        // slots aren't known from a witness.
+        let witness = StateTrie::from_witness(&full.witness().unwrap()).unwrap();
+        let entries: Vec<_> = witness.storage_range(address, ..).unwrap().collect();
+        assert_eq!(entries.len(), 20);
+        assert!(entries.iter().all(|entry| entry.slot.is_none()));
+
+        // only the proven slot is loaded.
+        let proof = full
+            .get_proof(address, vec![H256::from_low_u64_be(3)])
+            .unwrap();
+        let mut partial = StateTrie::from_root(full.root().unwrap());
+        partial.load_proof(proof).unwrap();
+        let entries: Vec<_> = partial.storage_range(address, ..).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].slot, Some(U256::from(3)));
+        assert!(StateTrie::from_root(full.root().unwrap())
+            .storage_range(address, ..)
+            .is_err());
+    }
+}
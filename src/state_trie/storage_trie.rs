@@ -1,17 +1,14 @@
 use crate::{
+    key_cache::hashed_slot,
     nibbles::Nibbles,
     nodes::LeafValue,
     trie::{MptKey, Trie},
-    Error,
+    Error, ErrorContext,
 };
 
-use bytes::BytesMut;
-use ethers::{
-    types::{BigEndianHash, Bytes, H256, U256},
-    utils::{
-        keccak256,
-        rlp::{self, Rlp},
-    },
+use ethers_core::{
+    types::{Bytes, U256},
+    utils::rlp::{self, Rlp},
 };
 
 pub type StorageTrie = Trie<U256, U256>;
@@ -19,39 +16,37 @@ pub type StorageTrie = Trie<U256, U256>;
 impl MptKey for U256 {
     fn to_nibbles(&self) -> Result<Nibbles, Error> {
         Ok(Nibbles::from_raw_path(Bytes::from(
-            H256::from(keccak256(Bytes::from(
-                H256::from_uint(self).as_bytes().to_vec(),
-            )))
-            .as_bytes()
-            .to_vec(),
+            hashed_slot(*self).as_bytes().to_vec(),
         )))
     }
+
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext {
+            slot: Some(*self),
+            ..Default::default()
+        }
+    }
 }
 
 impl LeafValue for U256 {
-    fn from_raw_rlp(raw: ethers::types::Bytes) -> Result<Self, crate::Error> {
+    fn from_raw_rlp(raw: ethers_core::types::Bytes) -> Result<Self, crate::Error> {
         let rlp = Rlp::new(&raw);
         let bytes = rlp.data()?.to_owned();
+        if bytes.len() > 32 {
+            return Err(crate::Error::InternalError(
+                "storage value is longer than 32 bytes",
+            ));
+        }
         Ok(U256::from_big_endian(bytes.to_vec().as_slice()))
     }
 
-    fn to_raw_rlp(&self) -> Result<ethers::types::Bytes, crate::Error> {
+    fn to_raw_rlp(&self) -> Result<ethers_core::types::Bytes, crate::Error> {
         if self.is_zero() {
             return Ok(Bytes::from(vec![0]));
         }
 
-        let mut vec = H256::from_uint(self).as_bytes().to_vec();
-        loop {
-            if vec[0] == 0 {
-                vec.remove(0);
-            } else {
-                break;
-            }
-        }
-
-        Ok(Bytes::from(
-            rlp::encode(&BytesMut::from(vec.as_slice())).to_vec(),
-        ))
+        // u256 encodes without its leading zeros.
+        Ok(Bytes::from(rlp::encode(self).freeze()))
     }
 }
 
@@ -59,7 +54,7 @@ impl LeafValue for U256 {
 mod tests {
     use std::str::FromStr;
 
-    use ethers::{
+    use ethers_core::{
         types::{Bytes, U256},
         utils::hex,
     };
@@ -0,0 +1,207 @@
+use ethers_core::types::{Address, BigEndianHash, H256, U256};
+
+use super::StateTrie;
+use crate::{
+    slots::{read_packed, write_packed},
+    Error,
+};
+
+// slot values are stored as big endian integers without leading zero bytes, these read and
+// write them as the solidity types which take a whole slot. packed slots (several values in
+// one slot) are an error for address and bool rather than silently cut.
+impl StateTrie {
+    pub fn get_storage_u256(&mut self, address: Address, slot: U256) -> Result<U256, Error> {
+        self.get_storage_at(address, slot)
+    }
+
+    pub fn set_storage_u256(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: U256,
+    ) -> Result<(), Error> {
+        self.set_storage_value(address, slot, value)
+    }
+
+    // bytes32, left padded to 32 bytes.
+    pub fn get_storage_h256(&mut self, address: Address, slot: U256) -> Result<H256, Error> {
+        Ok(H256::from_uint(&self.get_storage_at(address, slot)?))
+    }
+
+    pub fn set_storage_h256(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: H256,
+    ) -> Result<(), Error> {
+        self.set_storage_value(address, slot, value.into_uint())
+    }
+
+    pub fn get_storage_address(&mut self, address: Address, slot: U256) -> Result<Address, Error> {
+        let value = self.get_storage_h256(address, slot)?;
+        if value[..12].iter().any(|byte| *byte != 0) {
+            return Err(Error::InternalError(
+                "slot value is wider than an address, is the slot packed?",
+            ));
+        }
+        Ok(Address::from(value))
+    }
+
+    pub fn set_storage_address(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: Address,
+    ) -> Result<(), Error> {
+        self.set_storage_h256(address, slot, H256::from(value))
+    }
+
+    pub fn get_storage_bool(&mut self, address: Address, slot: U256) -> Result<bool, Error> {
+        let value = self.get_storage_at(address, slot)?;
+        if value > U256::one() {
+            return Err(Error::InternalError(
+                "slot value is not a bool, is the slot packed?",
+            ));
+        }
+        Ok(value == U256::one())
+    }
+
+    pub fn set_storage_bool(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: bool,
+    ) -> Result<(), Error> {
+        self.set_storage_value(address, slot, U256::from(value as u8))
+    }
+
+    // a member of a packed slot, see read_packed.
+    pub fn get_storage_packed(
+        &mut self,
+        address: Address,
+        slot: U256,
+        offset: usize,
+        size: usize,
+    ) -> Result<U256, Error> {
+        Ok(read_packed(
+            self.get_storage_at(address, slot)?,
+            offset,
+            size,
+        ))
+    }
+
+    // replaces one member of a packed slot and keeps the others.
+    pub fn set_storage_packed(
+        &mut self,
+        address: Address,
+        slot: U256,
+        offset: usize,
+        size: usize,
+        value: U256,
+    ) -> Result<(), Error> {
+        let word = self.get_storage_at(address, slot)?;
+        self.set_storage_value(address, slot, write_packed(word, offset, size, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    #[test]
+    pub fn test_typed_storage() {
+        let contract = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        state_trie
+            .account_trie
+            .set_balance(contract, U256::one())
+            .unwrap();
+
+        let owner = Address::repeat_byte(0xab);
+        state_trie
+            .set_storage_address(contract, U256::zero(), owner)
+            .unwrap();
+        state_trie
+            .set_storage_bool(contract, U256::one(), true)
+            .unwrap();
+        let hash = H256::from_low_u64_be(0x1234);
+        state_trie
+            .set_storage_h256(contract, U256::from(2), hash)
+            .unwrap();
+        state_trie
+            .set_storage_u256(contract, U256::from(3), U256::MAX)
+            .unwrap();
+
+        assert_eq!(
+            state_trie
+                .get_storage_address(contract, U256::zero())
+                .unwrap(),
+            owner
+        );
+        assert!(state_trie.get_storage_bool(contract, U256::one()).unwrap());
+        // leading zeros of a bytes32 are trimmed in the trie and padded back.
+        assert_eq!(
+            state_trie.get_storage_at(contract, U256::from(2)).unwrap(),
+            U256::from(0x1234)
+        );
+        assert_eq!(
+            state_trie
+                .get_storage_h256(contract, U256::from(2))
+                .unwrap(),
+            hash
+        );
+        assert_eq!(
+            state_trie
+                .get_storage_u256(contract, U256::from(3))
+                .unwrap(),
+            U256::MAX
+        );
+
+        // unset slots read as zero values.
+        assert!(!state_trie
+            .get_storage_bool(contract, U256::from(9))
+            .unwrap());
+        assert_eq!(
+            state_trie
+                .get_storage_address(contract, U256::from(9))
+                .unwrap(),
+            Address::zero()
+        );
+
+        // a full word doesn't fit an address or a bool.
+        assert!(state_trie
+            .get_storage_address(contract, U256::from(3))
+            .is_err());
+        assert!(state_trie
+            .get_storage_bool(contract, U256::from(3))
+            .is_err());
+
+        // uint64 at offset 0 and a bool at offset 8 of slot 4.
+        state_trie
+            .set_storage_packed(contract, U256::from(4), 0, 8, U256::from(1000))
+            .unwrap();
+        state_trie
+            .set_storage_packed(contract, U256::from(4), 8, 1, U256::one())
+            .unwrap();
+        assert_eq!(
+            state_trie
+                .get_storage_packed(contract, U256::from(4), 0, 8)
+                .unwrap(),
+            U256::from(1000)
+        );
+        assert_eq!(
+            state_trie.get_storage_at(contract, U256::from(4)).unwrap(),
+            (U256::one() << 64) + 1000
+        );
+
+        // setting false clears the slot.
+        state_trie
+            .set_storage_bool(contract, U256::one(), false)
+            .unwrap();
+        assert_eq!(
+            state_trie.get_storage_at(contract, U256::one()).unwrap(),
+            U256::zero()
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use ethers_core::types::{Address, H256, U256};
+
+use super::StateTrie;
+use crate::{utils::nibbles_to_hash, Error};
+
+// what happened between two commits which changed the root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RootChange {
+    pub old_root: H256,
+    pub new_root: H256,
+    // accounts written since the last commit by address hash, with the address if it's known.
+    pub accounts: Vec<(H256, Option<Address>)>,
+    // slots written with set_storage_value (or the typed setters) since the last commit.
+    pub storage: Vec<(Address, U256)>,
+}
+
+// subscribers and the last committed root. a clone of the trie starts without subscribers,
+// and they don't count for equality.
+#[derive(Default)]
+pub(super) struct RootWatch {
+    committed_root: Option<H256>,
+    storage: BTreeSet<(Address, U256)>,
+    senders: Vec<Sender<RootChange>>,
+}
+
+impl RootWatch {
+    pub(super) fn touch_storage(&mut self, address: Address, slot: U256) {
+        if !self.senders.is_empty() {
+            self.storage.insert((address, slot));
+        }
+    }
+}
+
+impl Clone for RootWatch {
+    fn clone(&self) -> Self {
+        RootWatch {
+            committed_root: self.committed_root,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Debug for RootWatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RootWatch")
+            .field("committed_root", &self.committed_root)
+            .field("subscribers", &self.senders.len())
+            .finish()
+    }
+}
+
+impl PartialEq for RootWatch {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for RootWatch {}
+
+impl StateTrie {
+    // gets a RootChange on every commit which changes the root. a receiver which is dropped
+    // is forgotten on the next commit.
+    pub fn subscribe(&mut self) -> Receiver<RootChange> {
+        if self.watch.committed_root.is_none() {
+            self.watch.committed_root = self.root();
+            self.account_trie.take_touched();
+        }
+        let (sender, receiver) = channel();
+        self.watch.senders.push(sender);
+        receiver
+    }
+
+    // marks the current root as committed and notifies the subscribers if it changed since
+    // the last commit (or since the first subscribe). the first commit without either only
+    // records the root.
+    pub fn commit(&mut self) -> Result<Option<RootChange>, Error> {
+        let new_root = self.root().ok_or(Error::Usage("root not set"))?;
+        let touched = self.account_trie.take_touched();
+        let storage = std::mem::take(&mut self.watch.storage);
+        let old_root = match self.watch.committed_root.replace(new_root) {
+            Some(old_root) if old_root != new_root => old_root,
+            _ => return Ok(None),
+        };
+
+        let accounts = touched
+            .iter()
+            .map(|path| {
+                let hash = nibbles_to_hash(path)?;
+                Ok((hash, self.addresses.get(&hash).copied()))
+            })
+            .collect::<Result<_, Error>>()?;
+        let change = RootChange {
+            old_root,
+            new_root,
+            accounts,
+            storage: storage.into_iter().collect(),
+        };
+        self.watch
+            .senders
+            .retain(|sender| sender.send(change.clone()).is_ok());
+        Ok(Some(change))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StateTrie;
+    use ethers_core::{
+        types::{Address, H256, U256},
+        utils::keccak256,
+    };
+
+    #[test]
+    pub fn test_root_change_notifications() {
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut state_trie = StateTrie::new();
+        let empty_root = state_trie.root().unwrap();
+        let receiver = state_trie.subscribe();
+
+        state_trie.account_trie.set_balance(a, U256::one()).unwrap();
+        state_trie
+            .set_storage_value(b, U256::from(3), U256::from(4))
+            .unwrap();
+        let change = state_trie.commit().unwrap().unwrap();
+        assert_eq!(receiver.try_recv().unwrap(), change);
+        assert_eq!(change.old_root, empty_root);
+        assert_eq!(change.new_root, state_trie.root().unwrap());
+        // a was only written through the account trie, so just its hash is known.
+        let mut expected = vec![
+            (H256::from(keccak256(a)), None),
+            (H256::from(keccak256(b)), Some(b)),
+        ];
+        expected.sort();
+        assert_eq!(change.accounts, expected);
+        assert_eq!(change.storage, vec![(b, U256::from(3))]);
+
+        // nothing changed.
+        assert!(state_trie.commit().unwrap().is_none());
+        assert!(receiver.try_recv().is_err());
+
+        // writing and reverting before the commit leaves the root as it was.
+        state_trie
+            .account_trie
+            .set_balance(a, U256::from(9))
+            .unwrap();
+        state_trie.account_trie.set_balance(a, U256::one()).unwrap();
+        assert!(state_trie.commit().unwrap().is_none());
+
+        drop(receiver);
+        state_trie
+            .account_trie
+            .set_balance(a, U256::from(2))
+            .unwrap();
+        let change = state_trie.commit().unwrap().unwrap();
+        assert_eq!(change.accounts, vec![(H256::from(keccak256(a)), None)]);
+        assert!(change.storage.is_empty());
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethers_core::types::{Address, Block, Transaction, H256, U256};
+use revm::primitives::{
+    AccessListItem, BlobExcessGasAndPrice, BlockEnv, Env, SpecId, TxEnv, TxKind, B256,
+};
+
+use crate::{
+    beacon_root_slots, receipts_root,
+    revm_db::{from_address, from_u256},
+    transactions::other_field,
+    AccountData, Error, ReceiptData, StateTrie, Witness, BEACON_ROOTS_ADDRESS, BLOB_TX_TYPE,
+    SET_CODE_TX_TYPE,
+};
+
+// a header field the block doesn't agree with, what the header says against what running the
+// block gave.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockMismatch {
+    StateRoot { expected: H256, computed: H256 },
+    ReceiptsRoot { expected: H256, computed: H256 },
+    GasUsed { expected: U256, computed: U256 },
+}
+
+// what running a block gave, and where it differs from the header.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockReport {
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub gas_used: U256,
+    pub receipts: Vec<ReceiptData>,
+    pub mismatches: Vec<BlockMismatch>,
+}
+
+impl BlockReport {
+    pub fn is_valid(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+fn block_env(block: &Block<Transaction>) -> Result<BlockEnv, Error> {
+    Ok(BlockEnv {
+        number: from_u256(
+            block
+                .number
+                .ok_or(Error::InternalError("block has no number"))?
+                .as_u64()
+                .into(),
+        ),
+        coinbase: from_address(block.author.unwrap_or_default()),
+        timestamp: from_u256(block.timestamp),
+        gas_limit: from_u256(block.gas_limit),
+        basefee: from_u256(block.base_fee_per_gas.unwrap_or_default()),
+        difficulty: from_u256(block.difficulty),
+        prevrandao: block.mix_hash.map(|mix_hash| B256::from(mix_hash.0)),
+        blob_excess_gas_and_price: block
+            .excess_blob_gas
+            .map(|excess_blob_gas| BlobExcessGasAndPrice::new(excess_blob_gas.as_u64())),
+    })
+}
+
+fn tx_env(tx: &Transaction) -> Result<TxEnv, Error> {
+    let tx_type = tx.transaction_type.unwrap_or_default().as_u64();
+    if tx_type == SET_CODE_TX_TYPE as u64 {
+        return Err(Error::InternalError(
+            "set code transactions can't be executed yet",
+        ));
+    }
+    // since eip-1559 the gas price is the cap, what is paid comes out of the base fee.
+    let (gas_price, gas_priority_fee) = match tx.max_fee_per_gas {
+        Some(max_fee_per_gas) => (max_fee_per_gas, tx.max_priority_fee_per_gas),
+        None => (tx.gas_price.unwrap_or_default(), None),
+    };
+    let (blob_hashes, max_fee_per_blob_gas) = if tx_type == BLOB_TX_TYPE as u64 {
+        (
+            other_field::<Vec<H256>>(tx, "blobVersionedHashes")?
+                .into_iter()
+                .map(|hash| B256::from(hash.0))
+                .collect(),
+            Some(from_u256(other_field::<U256>(tx, "maxFeePerBlobGas")?)),
+        )
+    } else {
+        (Vec::new(), None)
+    };
+    Ok(TxEnv {
+        caller: from_address(tx.from),
+        gas_limit: tx.gas.as_u64(),
+        gas_price: from_u256(gas_price),
+        transact_to: match tx.to {
+            Some(to) => TxKind::Call(from_address(to)),
+            None => TxKind::Create,
+        },
+        value: from_u256(tx.value),
+        data: tx.input.0.clone().into(),
+        nonce: Some(tx.nonce.as_u64()),
+        chain_id: tx.chain_id.map(|chain_id| chain_id.as_u64()),
+        access_list: tx
+            .access_list
+            .clone()
+            .unwrap_or_default()
+            .0
+            .into_iter()
+            .map(|item| AccessListItem {
+                address: from_address(item.address),
+                storage_keys: item
+                    .storage_keys
+                    .into_iter()
+                    .map(|key| B256::from(key.0))
+                    .collect(),
+            })
+            .collect(),
+        gas_priority_fee: gas_priority_fee.map(from_u256),
+        blob_hashes,
+        max_fee_per_blob_gas,
+        ..Default::default()
+    })
+}
+
+impl StateTrie {
+    // runs a whole block on the trie under cancun rules and checks the result against its
+    // header: the beacon root goes into its contract first, then the transactions, then the
+    // withdrawals. the block needs its full transactions. mismatches are in the report, the
+    // trie has the block applied either way.
+    pub fn apply_block(&mut self, block: &Block<Transaction>) -> Result<BlockReport, Error> {
+        self.apply_block_with_spec(SpecId::CANCUN, block)
+    }
+
+    pub fn apply_block_with_spec(
+        &mut self,
+        spec_id: SpecId,
+        block: &Block<Transaction>,
+    ) -> Result<BlockReport, Error> {
+        self.execute_block(spec_id, block, &mut BTreeMap::new())
+    }
+
+    // apply_block_with_spec, adding the accounts and slots the block writes to `touched`.
+    pub(crate) fn execute_block(
+        &mut self,
+        spec_id: SpecId,
+        block: &Block<Transaction>,
+        touched: &mut BTreeMap<Address, BTreeSet<U256>>,
+    ) -> Result<BlockReport, Error> {
+        let mut env = Env {
+            block: block_env(block)?,
+            ..Default::default()
+        };
+        if let Some(chain_id) = block.transactions.iter().find_map(|tx| tx.chain_id) {
+            env.cfg.chain_id = chain_id.as_u64();
+        }
+
+        // the system call of eip-4788, written directly. only once the contract is deployed.
+        if let Some(beacon_root) = block.parent_beacon_block_root {
+            if self.account_trie.get(BEACON_ROOTS_ADDRESS)?.code_hash
+                != AccountData::default().code_hash
+            {
+                let (timestamp_slot, root_slot) = beacon_root_slots(block.timestamp.as_u64());
+                touched
+                    .entry(BEACON_ROOTS_ADDRESS)
+                    .or_default()
+                    .extend([timestamp_slot, root_slot]);
+                self.set_storage_value(BEACON_ROOTS_ADDRESS, timestamp_slot, block.timestamp)?;
+                self.set_storage_value(
+                    BEACON_ROOTS_ADDRESS,
+                    root_slot,
+                    U256::from_big_endian(beacon_root.as_bytes()),
+                )?;
+            }
+        }
+
+        let mut report = BlockReport::default();
+        for tx in block.transactions.iter() {
+            let outcome = self.execute(spec_id, env.clone(), tx_env(tx)?, touched)?;
+            let tx_type = tx.transaction_type.unwrap_or_default().as_u64() as u8;
+            report
+                .receipts
+                .push(outcome.receipt(tx_type, report.gas_used));
+            report.gas_used += U256::from(outcome.gas_used);
+        }
+
+        // amounts are in gwei.
+        for withdrawal in block.withdrawals.iter().flatten() {
+            if withdrawal.amount.is_zero() {
+                continue;
+            }
+            let mut account = self.account_trie.get(withdrawal.address)?;
+            account.balance += withdrawal.amount * U256::exp10(9);
+            self.account_trie.set(withdrawal.address, account)?;
+            touched.entry(withdrawal.address).or_default();
+        }
+
+        report.state_root = self.root().ok_or(Error::Usage("root not set"))?;
+        report.receipts_root = receipts_root(&report.receipts)?;
+        if report.state_root != block.state_root {
+            report.mismatches.push(BlockMismatch::StateRoot {
+                expected: block.state_root,
+                computed: report.state_root,
+            });
+        }
+        if report.receipts_root != block.receipts_root {
+            report.mismatches.push(BlockMismatch::ReceiptsRoot {
+                expected: block.receipts_root,
+                computed: report.receipts_root,
+            });
+        }
+        if report.gas_used != block.gas_used {
+            report.mismatches.push(BlockMismatch::GasUsed {
+                expected: block.gas_used,
+                computed: report.gas_used,
+            });
+        }
+        Ok(report)
+    }
+}
+
+// checks a block against the state before it, with nothing but the witness: loads it, runs
+// the block and compares the roots and the gas with the header. an error means the block
+// couldn't be run, e.g. because the witness is missing a node or code, or a transaction is
+// invalid.
+pub fn validate_block_stateless(
+    parent_witness: &Witness,
+    block: &Block<Transaction>,
+) -> Result<BlockReport, Error> {
+    StateTrie::from_witness(parent_witness)?.apply_block(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_block_stateless, BlockMismatch};
+    use crate::{beacon_root_slots, StateTrie, BEACON_ROOTS_ADDRESS};
+    use ethers_core::types::{Address, Block, Bytes, Transaction, Withdrawal, H256, U256, U64};
+
+    #[test]
+    pub fn test_validate_block_stateless() {
+        let sender = Address::from_low_u64_be(0x1000);
+        let contract = Address::from_low_u64_be(0x2000);
+        let validator = Address::from_low_u64_be(0x3000);
+        let mut parent = StateTrie::new();
+        parent
+            .account_trie
+            .set_balance(sender, U256::exp10(18))
+            .unwrap();
+        // sstore(1, 42)
+        parent
+            .set_code(
+                contract,
+                Bytes::from(vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00]),
+            )
+            .unwrap();
+        // anything but empty code for the beacon roots contract.
+        parent
+            .set_code(BEACON_ROOTS_ADDRESS, Bytes::from(vec![0x00]))
+            .unwrap();
+        let witness = parent.witness().unwrap();
+
+        let mut block = Block::<Transaction> {
+            number: Some(U64::from(20_000_000)),
+            timestamp: U256::from(1_720_000_000),
+            gas_limit: U256::from(30_000_000),
+            base_fee_per_gas: Some(U256::zero()),
+            mix_hash: Some(H256::zero()),
+            excess_blob_gas: Some(U256::zero()),
+            parent_beacon_block_root: Some(H256::repeat_byte(0xbb)),
+            transactions: vec![Transaction {
+                from: sender,
+                to: Some(contract),
+                value: U256::from(5),
+                gas: U256::from(100_000),
+                gas_price: Some(U256::zero()),
+                transaction_type: Some(U64::zero()),
+                ..Default::default()
+            }],
+            withdrawals: Some(vec![Withdrawal {
+                address: validator,
+                amount: U256::from(3),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let report = validate_block_stateless(&witness, &block).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.receipts.len(), 1);
+        assert_eq!(report.receipts[0].status, Some(true));
+        assert_eq!(
+            report.mismatches,
+            vec![
+                BlockMismatch::StateRoot {
+                    expected: H256::zero(),
+                    computed: report.state_root
+                },
+                BlockMismatch::ReceiptsRoot {
+                    expected: H256::zero(),
+                    computed: report.receipts_root
+                },
+                BlockMismatch::GasUsed {
+                    expected: U256::zero(),
+                    computed: report.gas_used
+                },
+            ]
+        );
+
+        block.state_root = report.state_root;
+        block.receipts_root = report.receipts_root;
+        block.gas_used = report.gas_used;
+        assert!(validate_block_stateless(&witness, &block)
+            .unwrap()
+            .is_valid());
+
+        let mut post = StateTrie::from_witness(&witness).unwrap();
+        post.apply_block(&block).unwrap();
+        assert_eq!(
+            post.get_storage_at(contract, U256::one()).unwrap(),
+            U256::from(42)
+        );
+        assert_eq!(
+            post.account_trie.get(validator).unwrap().balance,
+            U256::from(3) * U256::exp10(9)
+        );
+        let (_, root_slot) = beacon_root_slots(1_720_000_000);
+        assert_eq!(
+            post.get_storage_at(BEACON_ROOTS_ADDRESS, root_slot)
+                .unwrap(),
+            U256::from_big_endian(&[0xbb; 32])
+        );
+
+        // the witness has to have every account the block touches.
+        let mut pruned = StateTrie::from_root(parent.root().unwrap());
+        pruned
+            .load_proof(parent.get_proof(sender, vec![]).unwrap())
+            .unwrap();
+        assert!(validate_block_stateless(&pruned.witness().unwrap(), &block).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+// counters and histograms through the `metrics` facade, for whatever recorder the service
+// installs (e.g. metrics-exporter-prometheus). without the metrics feature these are no-ops.
+
+#[cfg(feature = "metrics")]
+pub(crate) fn node_loaded() {
+    ::metrics::counter!("partial_mpt_nodes_loaded_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn hashed() {
+    ::metrics::counter!("partial_mpt_keccak_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn missing_node() {
+    ::metrics::counter!("partial_mpt_missing_node_errors_total").increment(1);
+}
+
+// time spent updating the nodes up to the root for one write.
+#[cfg(feature = "metrics")]
+pub(crate) fn time_root_update<T>(f: impl FnOnce() -> T) -> T {
+    let start = std::time::Instant::now();
+    let result = f();
+    ::metrics::histogram!("partial_mpt_root_update_seconds").record(start.elapsed().as_secs_f64());
+    result
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn node_loaded() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn hashed() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn missing_node() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn time_root_update<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use ::metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+    use ethers_core::types::{Address, U256};
+
+    use crate::{state_trie::StorageTrie, StateTrie};
+
+    #[derive(Default)]
+    struct Count(AtomicU64);
+
+    impl CounterFn for Count {
+        fn increment(&self, value: u64) {
+            self.0.fetch_add(value, Ordering::Relaxed);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0.store(value, Ordering::Relaxed);
+        }
+    }
+
+    impl HistogramFn for Count {
+        fn record(&self, _: f64) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // counts every counter increment and histogram sample by metric name.
+    #[derive(Default)]
+    struct TestRecorder(Mutex<HashMap<String, Arc<Count>>>);
+
+    impl TestRecorder {
+        fn count(&self, name: &str) -> Arc<Count> {
+            let mut counts = self.0.lock().unwrap();
+            counts.entry(name.to_string()).or_default().clone()
+        }
+
+        fn get(&self, name: &str) -> u64 {
+            self.count(name).0.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.count(key.name()))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.count(key.name()))
+        }
+    }
+
+    #[test]
+    pub fn test_metrics() {
+        let recorder = TestRecorder::default();
+        ::metrics::with_local_recorder(&recorder, || {
+            let address = Address::from_low_u64_be(1);
+            let mut full = StateTrie::new();
+            for i in 1..10u64 {
+                full.account_trie
+                    .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                    .unwrap();
+            }
+            assert_eq!(recorder.get("partial_mpt_root_update_seconds"), 9);
+            assert!(recorder.get("partial_mpt_keccak_total") > 0);
+
+            let proof = full.get_proof(address, vec![]).unwrap();
+            let nodes = proof.account_proof.len() as u64;
+            let mut partial = StateTrie::from_root(full.root().unwrap());
+            partial.load_proof(proof).unwrap();
+            assert_eq!(recorder.get("partial_mpt_nodes_loaded_total"), nodes);
+
+            assert!(partial
+                .account_trie
+                .get(Address::from_low_u64_be(2))
+                .is_err());
+            assert!(StorageTrie::from_root(full.root().unwrap())
+                .get(U256::one())
+                .is_err());
+            assert_eq!(recorder.get("partial_mpt_missing_node_errors_total"), 2);
+        });
+    }
+}
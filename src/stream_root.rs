@@ -0,0 +1,246 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::{Bytes, H256};
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nibbles::Nibbles,
+    nodes::{LeafValue, NodeData},
+    trie::MptKey,
+    Error,
+};
+
+type NodeSink<'a> = Box<dyn FnMut(H256, Bytes) -> Result<(), Error> + 'a>;
+
+// a branch on the path of the last key whose children aren't all known yet, its children
+// differ at nibble `depth`.
+struct OpenBranch {
+    depth: usize,
+    children: [Option<H256>; 17],
+}
+
+// computes the root of a trie from its leaves in path order (by hashed key for the state
+// tries) without keeping the trie: only the branches on the path of the last leaf are open,
+// at most one per nibble, and every node is handed to the sink as soon as it's complete,
+// children before their parents. for tries too big for memory, e.g. the whole storage of a
+// large contract from a snapshot, with the sink writing the nodes to a database.
+pub struct StreamingRoot<'a, K: MptKey, V: LeafValue, H: TrieHasher = KeccakHasher> {
+    stack: Vec<OpenBranch>,
+    // the last leaf, its node key depends on the next one.
+    pending: Option<(Vec<u8>, V)>,
+    sink: NodeSink<'a>,
+    _marker: PhantomData<(K, H)>,
+}
+
+impl<'a, K: MptKey, V: LeafValue, H: TrieHasher> StreamingRoot<'a, K, V, H> {
+    pub fn new(sink: impl FnMut(H256, Bytes) -> Result<(), Error> + 'a) -> Self {
+        StreamingRoot {
+            stack: Vec::new(),
+            pending: None,
+            sink: Box::new(sink),
+            _marker: PhantomData,
+        }
+    }
+
+    // only the root, the nodes are dropped.
+    pub fn root_only() -> Self {
+        Self::new(|_, _| Ok(()))
+    }
+
+    pub fn push(&mut self, key: K, value: V) -> Result<(), Error> {
+        self.push_path(key.to_nibbles()?.to_u4_vec(), value)
+    }
+
+    // push for keys which are hashed already, e.g. snapshot entries.
+    pub fn push_hashed(&mut self, hash: H256, value: V) -> Result<(), Error> {
+        self.push_path(
+            Nibbles::from_raw_path(Bytes::from(hash.as_bytes().to_vec())).to_u4_vec(),
+            value,
+        )
+    }
+
+    fn push_path(&mut self, path: Vec<u8>, value: V) -> Result<(), Error> {
+        // default values mean the key is not in the trie, like for from_entries.
+        if value == V::default() {
+            return Ok(());
+        }
+        let (last, last_value) = match self.pending.take() {
+            Some(pending) => pending,
+            None => {
+                self.pending = Some((path, value));
+                return Ok(());
+            }
+        };
+        if last >= path {
+            return Err(Error::InternalError("entries are not sorted by path"));
+        }
+        let common = last
+            .iter()
+            .zip(path.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common == last.len() {
+            return Err(Error::InternalError("a key is a prefix of another key"));
+        }
+
+        // the last leaf and every branch below where it splits from the new key are complete.
+        if self.stack.last().is_none_or(|top| top.depth < common) {
+            self.open(common);
+        }
+        self.close_leaf(&last, last_value)?;
+        while self.stack.last().is_some_and(|top| top.depth > common) {
+            let branch = self.stack.pop().unwrap();
+            if self.stack.last().is_none_or(|top| top.depth < common) {
+                self.open(common);
+            }
+            self.close_branch(&last, branch)?;
+        }
+        self.pending = Some((path, value));
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<H256, Error> {
+        let (last, last_value) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(H::empty_root()),
+        };
+        if self.stack.is_empty() {
+            return self.emit(NodeData::Leaf {
+                key: Nibbles::from_u4_vec(last)?,
+                value: last_value,
+            });
+        }
+        self.close_leaf(&last, last_value)?;
+        while self.stack.len() > 1 {
+            let branch = self.stack.pop().unwrap();
+            self.close_branch(&last, branch)?;
+        }
+
+        let root = self.stack.pop().unwrap();
+        let (depth, hash) = (root.depth, self.emit(NodeData::Branch(root.children))?);
+        if depth == 0 {
+            return Ok(hash);
+        }
+        self.emit(NodeData::Extension {
+            key: Nibbles::from_u4_vec(last[..depth].to_vec())?,
+            node: hash,
+        })
+    }
+
+    fn open(&mut self, depth: usize) {
+        self.stack.push(OpenBranch {
+            depth,
+            children: [None; 17],
+        });
+    }
+
+    fn emit(&mut self, node_data: NodeData<V>) -> Result<H256, Error> {
+        let raw = node_data.to_raw_rlp()?;
+        let hash = H::hash(&raw);
+        (self.sink)(hash, raw)?;
+        Ok(hash)
+    }
+
+    // the leaf for `path` under the deepest open branch.
+    fn close_leaf(&mut self, path: &[u8], value: V) -> Result<(), Error> {
+        let depth = self.stack.last().unwrap().depth;
+        let hash = self.emit(NodeData::Leaf {
+            key: Nibbles::from_u4_vec(path[depth + 1..].to_vec())?,
+            value,
+        })?;
+        self.stack.last_mut().unwrap().children[path[depth] as usize] = Some(hash);
+        Ok(())
+    }
+
+    // a complete branch into the open branch above it, through an extension if there are
+    // nibbles between the two.
+    fn close_branch(&mut self, path: &[u8], branch: OpenBranch) -> Result<(), Error> {
+        let parent = self.stack.last().unwrap().depth;
+        let mut hash = self.emit(NodeData::Branch(branch.children))?;
+        if branch.depth > parent + 1 {
+            hash = self.emit(NodeData::Extension {
+                key: Nibbles::from_u4_vec(path[parent + 1..branch.depth].to_vec())?,
+                node: hash,
+            })?;
+        }
+        self.stack.last_mut().unwrap().children[path[parent] as usize] = Some(hash);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamingRoot;
+    use crate::state_trie::StorageTrie;
+    use ethers_core::{
+        types::{BigEndianHash, Bytes, H256, U256},
+        utils::keccak256,
+    };
+    use std::collections::HashMap;
+
+    fn sorted(count: u64) -> Vec<(H256, U256)> {
+        let mut entries: Vec<(H256, U256)> = (1..=count)
+            .map(|i| {
+                (
+                    H256::from(keccak256(H256::from_uint(&U256::from(i)))),
+                    U256::from(i * 7),
+                )
+            })
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    pub fn test_streaming_root() {
+        for count in [0, 1, 2, 3, 17, 300] {
+            let expected =
+                StorageTrie::from_entries((1..=count).map(|i| (U256::from(i), U256::from(i * 7))))
+                    .unwrap();
+            let mut nodes: HashMap<H256, Bytes> = HashMap::new();
+            let mut stream = StreamingRoot::<U256, U256>::new(|hash, raw| {
+                nodes.insert(hash, raw);
+                Ok(())
+            });
+            for (hash, value) in sorted(count) {
+                stream.push_hashed(hash, value).unwrap();
+            }
+            let root = stream.finish().unwrap();
+            assert_eq!(Some(root), expected.root());
+
+            // the sink got the whole trie.
+            let mut trie = StorageTrie::from_root(root);
+            trie.load_raw_nodes(&nodes).unwrap();
+            for i in 1..=count {
+                assert_eq!(trie.get(U256::from(i)).unwrap(), U256::from(i * 7));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_streaming_root_order() {
+        // by key, which hashes in order only if the caller sorted by hash.
+        let mut stream = StreamingRoot::<U256, U256>::root_only();
+        let mut keys: Vec<U256> = (1..=5u64).map(U256::from).collect();
+        keys.sort_by_key(|key| keccak256(H256::from_uint(key)));
+        for key in keys.iter() {
+            stream.push(*key, U256::one()).unwrap();
+        }
+        // empty values are skipped.
+        stream.push(U256::from(100), U256::zero()).unwrap();
+        assert_eq!(
+            Some(stream.finish().unwrap()),
+            StorageTrie::from_entries(keys.iter().map(|key| (*key, U256::one())))
+                .unwrap()
+                .root()
+        );
+
+        let entries = sorted(3);
+        let mut stream = StreamingRoot::<U256, U256>::root_only();
+        stream.push_hashed(entries[1].0, entries[1].1).unwrap();
+        assert!(stream.push_hashed(entries[0].0, entries[0].1).is_err());
+        let mut stream = StreamingRoot::<U256, U256>::root_only();
+        stream.push_hashed(entries[1].0, entries[1].1).unwrap();
+        assert!(stream.push_hashed(entries[1].0, U256::from(3)).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use ethers_core::{
+    types::{Address, Bytes, EIP1186ProofResponse, H256, U256},
+    utils::keccak256,
+};
+
+use crate::{
+    state_trie::{AccountData, StorageTrie},
+    Error, StateTrie,
+};
+
+#[cfg(feature = "proptest")]
+pub mod strategies;
+
+// deterministic pseudo random values, keccak of the seed and a counter. only for tests.
+#[derive(Clone, Debug)]
+pub struct TestRng {
+    seed: u64,
+    counter: u64,
+}
+
+impl TestRng {
+    pub fn new(seed: u64) -> Self {
+        TestRng { seed, counter: 0 }
+    }
+
+    pub fn next_h256(&mut self) -> H256 {
+        let mut input = [0u8; 16];
+        input[..8].copy_from_slice(&self.seed.to_be_bytes());
+        input[8..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter += 1;
+        H256::from(keccak256(input))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.next_h256().to_low_u64_be()
+    }
+
+    pub fn next_u256(&mut self) -> U256 {
+        U256::from_big_endian(self.next_h256().as_bytes())
+    }
+
+    pub fn next_address(&mut self) -> Address {
+        Address::from(self.next_h256())
+    }
+
+    // uniform enough in 0..n for tests, n has to be positive.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// a complete state trie and what was put in it.
+#[derive(Clone, Debug)]
+pub struct RandomState {
+    pub state_trie: StateTrie,
+    // every account with its slots, all slots have a non zero value.
+    pub accounts: Vec<(Address, Vec<U256>)>,
+}
+
+impl RandomState {
+    // proof for the i-th account and all of its slots.
+    pub fn proof(&self, i: usize) -> Result<EIP1186ProofResponse, Error> {
+        let (address, slots) = &self.accounts[i];
+        get_proof(&self.state_trie, *address, slots)
+    }
+}
+
+// `accounts` accounts with random balances and nonces, each with up to `max_slots` slots.
+// the same seed always gives the same trie.
+pub fn random_state_trie(
+    seed: u64,
+    accounts: usize,
+    max_slots: usize,
+) -> Result<RandomState, Error> {
+    let mut rng = TestRng::new(seed);
+    let mut account_entries = Vec::with_capacity(accounts);
+    let mut storage = Vec::new();
+    let mut keys = Vec::with_capacity(accounts);
+    for _ in 0..accounts {
+        let address = rng.next_address();
+        account_entries.push((
+            address,
+            AccountData {
+                nonce: U256::from(rng.next_u64() % 1000),
+                balance: U256::from(rng.next_u64()),
+                ..Default::default()
+            },
+        ));
+
+        let slot_count = if max_slots == 0 {
+            0
+        } else {
+            rng.below(max_slots + 1)
+        };
+        let mut slots = Vec::with_capacity(slot_count);
+        for _ in 0..slot_count {
+            // small slots like solidity layouts, and hashed ones like mappings.
+            let slot = if rng.below(2) == 0 {
+                U256::from(rng.below(64))
+            } else {
+                rng.next_u256()
+            };
+            if slots.contains(&slot) {
+                continue;
+            }
+            storage.push((address, slot, U256::from(rng.next_u64() | 1)));
+            slots.push(slot);
+        }
+        keys.push((address, slots));
+    }
+
+    Ok(RandomState {
+        state_trie: StateTrie::from_flat_state(account_entries, storage)?,
+        accounts: keys,
+    })
+}
+
+// storage trie with `slots` random non zero entries.
+pub fn random_storage_trie(
+    seed: u64,
+    slots: usize,
+) -> Result<(StorageTrie, Vec<(U256, U256)>), Error> {
+    let mut rng = TestRng::new(seed);
+    let entries: Vec<(U256, U256)> = (0..slots)
+        .map(|_| (rng.next_u256(), U256::from(rng.next_u64() | 1)))
+        .collect();
+    Ok((StorageTrie::from_entries(entries.clone())?, entries))
+}
+
+// eth_getProof for any address and slots, absent ones give exclusion proofs.
+pub fn get_proof(
+    state_trie: &StateTrie,
+    address: Address,
+    slots: &[U256],
+) -> Result<EIP1186ProofResponse, Error> {
+    let slots = slots
+        .iter()
+        .map(|slot| {
+            let mut raw = [0u8; 32];
+            slot.to_big_endian(&mut raw);
+            H256::from(raw)
+        })
+        .collect();
+    state_trie.get_proof(address, slots)
+}
+
+// removes the node at `index`, a proof missing a node can't be loaded.
+pub fn drop_node(proof: &mut Vec<Bytes>, index: usize) {
+    proof.remove(index);
+}
+
+// flips every bit of one byte of one node, which changes the node's hash.
+pub fn flip_byte(proof: &mut [Bytes], node: usize, byte: usize) {
+    let mut raw = proof[node].to_vec();
+    raw[byte] ^= 0xff;
+    proof[node] = raw.into();
+}
+
+// one of drop_node or flip_byte at a random place, proof can't be empty.
+pub fn corrupt(rng: &mut TestRng, proof: &mut Vec<Bytes>) {
+    let node = rng.below(proof.len());
+    if rng.below(2) == 0 {
+        drop_node(proof, node);
+    } else {
+        let byte = rng.below(proof[node].len());
+        flip_byte(proof, node, byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{corrupt, flip_byte, get_proof, random_state_trie, random_storage_trie, TestRng};
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, U256};
+
+    #[test]
+    pub fn test_random_state_trie_deterministic() {
+        let a = random_state_trie(1, 50, 5).unwrap();
+        let b = random_state_trie(1, 50, 5).unwrap();
+        let c = random_state_trie(2, 50, 5).unwrap();
+        assert_eq!(a.state_trie.root(), b.state_trie.root());
+        assert_ne!(a.state_trie.root(), c.state_trie.root());
+        assert_eq!(a.accounts.len(), 50);
+
+        let (storage_trie, entries) = random_storage_trie(3, 100).unwrap();
+        assert_eq!(
+            storage_trie.root(),
+            StorageTrie::from_entries(entries).unwrap().root()
+        );
+    }
+
+    #[test]
+    pub fn test_random_proofs_load() {
+        let random = random_state_trie(7, 100, 8).unwrap();
+        let root = random.state_trie.root().unwrap();
+        let mut partial = StateTrie::from_root(root);
+        for i in 0..random.accounts.len() {
+            partial.load_proof(random.proof(i).unwrap()).unwrap();
+        }
+        let (address, slots) = &random.accounts[0];
+        let mut full = random.state_trie.clone();
+        for slot in slots {
+            assert_eq!(
+                partial.get_storage_at(*address, *slot).unwrap(),
+                full.get_storage_at(*address, *slot).unwrap()
+            );
+        }
+
+        // absent account and slot.
+        let absent = Address::repeat_byte(0xaa);
+        let proof = get_proof(&random.state_trie, absent, &[]).unwrap();
+        let mut partial = StateTrie::from_root(root);
+        partial.load_proof(proof).unwrap();
+        assert_eq!(
+            partial.account_trie.get(absent).unwrap().balance,
+            U256::zero()
+        );
+    }
+
+    #[test]
+    pub fn test_corrupted_proofs_fail() {
+        let random = random_state_trie(11, 100, 0).unwrap();
+        let root = random.state_trie.root().unwrap();
+        let mut rng = TestRng::new(11);
+        for i in 0..20 {
+            let mut proof = random.proof(i).unwrap();
+            corrupt(&mut rng, &mut proof.account_proof);
+            assert!(StateTrie::from_root(root).load_proof(proof).is_err());
+        }
+
+        let mut proof = random.proof(0).unwrap();
+        flip_byte(&mut proof.account_proof, 0, 5);
+        assert!(StateTrie::from_root(root).load_proof(proof).is_err());
+    }
+}
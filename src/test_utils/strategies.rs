@@ -0,0 +1,171 @@
+use proptest::{collection, prelude::*};
+
+use ethers_core::types::{Address, EIP1186ProofResponse, U256};
+
+use super::{get_proof, RandomState};
+use crate::{
+    state_trie::{AccountData, StorageTrie},
+    StateTrie,
+};
+
+// proptest strategies over realistic trie inputs. tries are built from generated entries
+// rather than a seed, so failing cases shrink to small tries.
+
+pub fn u256() -> impl Strategy<Value = U256> {
+    any::<[u8; 32]>().prop_map(|bytes| U256::from_big_endian(&bytes))
+}
+
+pub fn address() -> impl Strategy<Value = Address> {
+    any::<[u8; 20]>().prop_map(Address::from)
+}
+
+// slots like solidity layouts (small) and mappings (hashed), about half each.
+pub fn slot() -> impl Strategy<Value = U256> {
+    prop_oneof![(0u64..64).prop_map(U256::from), u256()]
+}
+
+// up to `max` distinct slots, sorted.
+pub fn key_set(max: usize) -> impl Strategy<Value = Vec<U256>> {
+    collection::btree_set(slot(), 0..=max).prop_map(|keys| keys.into_iter().collect())
+}
+
+// up to `max` slots with non zero values, sorted by slot.
+pub fn storage_entries(max: usize) -> impl Strategy<Value = Vec<(U256, U256)>> {
+    collection::btree_map(
+        slot(),
+        u256().prop_filter("non zero", |v| !v.is_zero()),
+        0..=max,
+    )
+    .prop_map(|entries| entries.into_iter().collect())
+}
+
+// a complete storage trie with what's in it, its root is the one of those entries.
+pub fn storage_trie(max: usize) -> impl Strategy<Value = (StorageTrie, Vec<(U256, U256)>)> {
+    storage_entries(max).prop_map(|entries| {
+        let trie = StorageTrie::from_entries(entries.clone()).expect("storage trie");
+        (trie, entries)
+    })
+}
+
+fn account() -> impl Strategy<Value = AccountData> {
+    (0u64..1000, any::<u64>()).prop_map(|(nonce, balance)| AccountData {
+        nonce: U256::from(nonce),
+        balance: U256::from(balance),
+        ..Default::default()
+    })
+}
+
+// a complete state trie with 1 to `max_accounts` accounts of up to `max_slots` slots each.
+pub fn state_trie(max_accounts: usize, max_slots: usize) -> impl Strategy<Value = RandomState> {
+    collection::btree_map(
+        address(),
+        (account(), storage_entries(max_slots)),
+        1..=max_accounts.max(1),
+    )
+    .prop_map(|accounts| {
+        let storage = accounts
+            .iter()
+            .flat_map(|(address, (_, entries))| {
+                entries
+                    .iter()
+                    .map(move |(slot, value)| (*address, *slot, *value))
+            })
+            .collect::<Vec<_>>();
+        let state_trie = StateTrie::from_flat_state(
+            accounts
+                .iter()
+                .map(|(address, (account, _))| (*address, account.to_owned())),
+            storage,
+        )
+        .expect("state trie");
+        RandomState {
+            state_trie,
+            accounts: accounts
+                .into_iter()
+                .map(|(address, (_, entries))| {
+                    (address, entries.into_iter().map(|(slot, _)| slot).collect())
+                })
+                .collect(),
+        }
+    })
+}
+
+// a state trie and a valid eth_getProof for one of its accounts with all of its slots,
+// which loads into StateTrie::from_root(state.state_trie.root()).
+pub fn proof(
+    max_accounts: usize,
+    max_slots: usize,
+) -> impl Strategy<Value = (RandomState, EIP1186ProofResponse)> {
+    state_trie(max_accounts, max_slots)
+        .prop_flat_map(|state| {
+            let accounts = state.accounts.len();
+            (Just(state), 0..accounts)
+        })
+        .prop_map(|(state, i)| {
+            let proof = state.proof(i).expect("proof");
+            (state, proof)
+        })
+}
+
+// like proof, for an account and slots which aren't in the trie (exclusion proofs).
+pub fn absent_proof(
+    max_accounts: usize,
+    max_slots: usize,
+) -> impl Strategy<Value = (RandomState, EIP1186ProofResponse)> {
+    (state_trie(max_accounts, max_slots), address(), key_set(4))
+        .prop_filter("address is in the trie", |(state, address, _)| {
+            state.accounts.iter().all(|(known, _)| known != address)
+        })
+        .prop_map(|(state, address, slots)| {
+            let proof = get_proof(&state.state_trie, address, &slots).expect("proof");
+            (state, proof)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{absent_proof, proof, storage_trie};
+    use crate::{state_trie::StorageTrie, StateTrie};
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_storage_trie_strategy((trie, entries) in storage_trie(20)) {
+            for (slot, value) in entries.iter() {
+                prop_assert_eq!(trie.get(*slot).unwrap(), *value);
+            }
+            prop_assert_eq!(trie.root(), StorageTrie::from_entries(entries).unwrap().root());
+        }
+
+        #[test]
+        fn test_proof_strategy((state, proof) in proof(10, 5)) {
+            let root = state.state_trie.root().unwrap();
+            let mut full = state.state_trie.clone();
+            let address = proof.address;
+            let slots = proof
+                .storage_proof
+                .iter()
+                .map(|storage| storage.key)
+                .collect::<Vec<_>>();
+            let mut partial = StateTrie::from_root(root);
+            partial.load_proof(proof).unwrap();
+            for slot in slots {
+                prop_assert_eq!(
+                    partial.get_storage_at(address, slot).unwrap(),
+                    full.get_storage_at(address, slot).unwrap()
+                );
+            }
+        }
+
+        #[test]
+        fn test_absent_proof_strategy((state, proof) in absent_proof(10, 3)) {
+            let address = proof.address;
+            let mut partial = StateTrie::from_root(state.state_trie.root().unwrap());
+            partial.load_proof(proof).unwrap();
+            prop_assert!(partial.account_trie.get(address).unwrap().balance.is_zero());
+        }
+    }
+}
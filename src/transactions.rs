@@ -0,0 +1,282 @@
+use ethers_core::{
+    types::{Address, Bytes, Transaction, H256, U256, U64},
+    utils::rlp::{Rlp, RlpStream},
+};
+use serde::Deserialize;
+
+use crate::{index_trie::ordered_trie_root, Error};
+
+pub const BLOB_TX_TYPE: u8 = 0x03;
+pub const SET_CODE_TX_TYPE: u8 = 0x04;
+
+// an eip-7702 authorization, as eth_getBlockByNumber returns it. older nodes give v
+// instead of yParity.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Authorization {
+    chain_id: U256,
+    address: Address,
+    nonce: U64,
+    y_parity: Option<U64>,
+    v: Option<U64>,
+    r: U256,
+    s: U256,
+}
+
+pub(crate) fn other_field<T: serde::de::DeserializeOwned>(
+    tx: &Transaction,
+    name: &str,
+) -> Result<T, Error> {
+    tx.other
+        .get_deserialized(name)
+        .ok_or(Error::InternalError("transaction field missing"))?
+        .map_err(Error::from)
+}
+
+// the fields every typed transaction since eip-1559 starts with.
+fn append_1559_fields(stream: &mut RlpStream, tx: &Transaction) -> Result<(), Error> {
+    let chain_id = tx
+        .chain_id
+        .ok_or(Error::InternalError("typed transaction without chain id"))?;
+    let to = tx.to.ok_or(Error::InternalError(
+        "blob and set code transactions can't create contracts",
+    ))?;
+    stream.append(&chain_id);
+    stream.append(&tx.nonce);
+    stream.append(&tx.max_priority_fee_per_gas.unwrap_or_default());
+    stream.append(&tx.max_fee_per_gas.unwrap_or_default());
+    stream.append(&tx.gas);
+    stream.append(&to);
+    stream.append(&tx.value);
+    stream.append(&tx.input.as_ref());
+    stream.append(&tx.access_list.clone().unwrap_or_default());
+    Ok(())
+}
+
+fn append_signature(stream: &mut RlpStream, tx: &Transaction) {
+    stream.append(&tx.v);
+    stream.append(&tx.r);
+    stream.append(&tx.s);
+}
+
+// the consensus encoding of a transaction from json-rpc, which is what the transactions root
+// commits to: rlp for legacy ones, the type byte followed by rlp for typed ones. ethers
+// doesn't know types 3 and 4, their extra fields are read from the extra json fields.
+pub fn encode_transaction(tx: &Transaction) -> Result<Bytes, Error> {
+    let tx_type = tx.transaction_type.unwrap_or_default().as_u64();
+    match tx_type {
+        0..=2 => Ok(tx.rlp()),
+        3 => {
+            let mut stream = RlpStream::new_list(14);
+            append_1559_fields(&mut stream, tx)?;
+            stream.append(&other_field::<U256>(tx, "maxFeePerBlobGas")?);
+            stream.append_list(&other_field::<Vec<H256>>(tx, "blobVersionedHashes")?);
+            append_signature(&mut stream, tx);
+            Ok([&[BLOB_TX_TYPE], &stream.out()[..]].concat().into())
+        }
+        4 => {
+            let authorizations = other_field::<Vec<Authorization>>(tx, "authorizationList")?;
+            let mut stream = RlpStream::new_list(13);
+            append_1559_fields(&mut stream, tx)?;
+            stream.begin_list(authorizations.len());
+            for authorization in authorizations {
+                let y_parity = authorization
+                    .y_parity
+                    .or(authorization.v)
+                    .ok_or(Error::InternalError("authorization without y parity"))?;
+                stream.begin_list(6);
+                stream.append(&authorization.chain_id);
+                stream.append(&authorization.address);
+                stream.append(&authorization.nonce);
+                stream.append(&y_parity);
+                stream.append(&authorization.r);
+                stream.append(&authorization.s);
+            }
+            append_signature(&mut stream, tx);
+            Ok([&[SET_CODE_TX_TYPE], &stream.out()[..]].concat().into())
+        }
+        _ => Err(Error::InternalError("unsupported transaction type")),
+    }
+}
+
+// raw transactions as eth_sendRawTransaction takes them to their consensus encoding. only
+// blob transactions differ, on the network they are wrapped as
+// 0x03 || rlp([tx, blobs, commitments, proofs]) and the trie only has 0x03 || rlp(tx).
+pub fn consensus_transaction(raw: &[u8]) -> Result<Bytes, Error> {
+    match raw.first() {
+        Some(&BLOB_TX_TYPE) => {
+            let rlp = Rlp::new(&raw[1..]);
+            if !rlp.is_list() {
+                return Err(Error::InternalError("blob transaction is not a list"));
+            }
+            let first = rlp.at(0)?;
+            if !first.is_list() {
+                // the tx itself, starting with the chain id.
+                return Ok(raw.to_vec().into());
+            }
+            Ok([&[BLOB_TX_TYPE], first.as_raw()].concat().into())
+        }
+        Some(_) => Ok(raw.to_vec().into()),
+        None => Err(Error::InternalError("empty transaction")),
+    }
+}
+
+// transactionsRoot of a block with these transactions, in block order.
+pub fn transactions_root(transactions: &[Transaction]) -> Result<H256, Error> {
+    ordered_trie_root(
+        transactions
+            .iter()
+            .map(encode_transaction)
+            .collect::<Result<Vec<_>, _>>()?,
+    )
+}
+
+// same from raw transactions, in either encoding.
+pub fn transactions_root_from_raw(transactions: &[Bytes]) -> Result<H256, Error> {
+    ordered_trie_root(
+        transactions
+            .iter()
+            .map(|raw| consensus_transaction(raw))
+            .collect::<Result<Vec<_>, _>>()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        consensus_transaction, encode_transaction, transactions_root, transactions_root_from_raw,
+        BLOB_TX_TYPE, SET_CODE_TX_TYPE,
+    };
+    use ethers_core::{
+        types::{Address, Bytes, Transaction, H256, U256, U64},
+        utils::rlp::RlpStream,
+    };
+    use serde_json::json;
+
+    fn transaction(tx_type: u64) -> Transaction {
+        let mut tx = Transaction {
+            transaction_type: Some(U64::from(tx_type)),
+            chain_id: Some(U256::one()),
+            nonce: U256::from(5),
+            max_priority_fee_per_gas: Some(U256::from(1_000_000_000u64)),
+            max_fee_per_gas: Some(U256::from(30_000_000_000u64)),
+            gas_price: Some(U256::from(30_000_000_000u64)),
+            gas: U256::from(100_000),
+            to: Some(Address::repeat_byte(0x11)),
+            value: U256::from(7),
+            input: Bytes::from(vec![0xca, 0xfe]),
+            access_list: Some(Default::default()),
+            v: U64::one(),
+            r: U256::from(0x1234),
+            s: U256::from(0x5678),
+            ..Default::default()
+        };
+        tx.other
+            .insert("maxFeePerBlobGas".to_string(), json!("0x3b9aca00"));
+        tx.other.insert(
+            "blobVersionedHashes".to_string(),
+            json!([H256::repeat_byte(0x01)]),
+        );
+        tx.other.insert(
+            "authorizationList".to_string(),
+            json!([{
+                "chainId": "0x1",
+                "address": Address::repeat_byte(0x22),
+                "nonce": "0x9",
+                "yParity": "0x0",
+                "r": "0xaa",
+                "s": "0xbb"
+            }]),
+        );
+        tx
+    }
+
+    fn start_1559_fields(stream: &mut RlpStream) {
+        stream.append(&U256::one());
+        stream.append(&U256::from(5));
+        stream.append(&U256::from(1_000_000_000u64));
+        stream.append(&U256::from(30_000_000_000u64));
+        stream.append(&U256::from(100_000));
+        stream.append(&Address::repeat_byte(0x11));
+        stream.append(&U256::from(7));
+        stream.append(&vec![0xcau8, 0xfe]);
+        stream.begin_list(0);
+    }
+
+    fn end_signature(stream: &mut RlpStream) {
+        stream.append(&1u8);
+        stream.append(&U256::from(0x1234));
+        stream.append(&U256::from(0x5678));
+    }
+
+    #[test]
+    pub fn test_blob_transaction() {
+        let mut expected = RlpStream::new_list(14);
+        start_1559_fields(&mut expected);
+        expected.append(&U256::from(1_000_000_000u64));
+        expected.append_list(&[H256::repeat_byte(0x01)]);
+        end_signature(&mut expected);
+        let expected: Bytes = [&[BLOB_TX_TYPE], &expected.out()[..]].concat().into();
+        assert_eq!(encode_transaction(&transaction(3)).unwrap(), expected);
+
+        // the network form carries the blobs, the trie doesn't.
+        let mut network = RlpStream::new_list(4);
+        network.append_raw(&expected[1..], 1);
+        network.append_list::<Vec<u8>, _>(&[vec![0u8; 64]]);
+        network.append_list::<Vec<u8>, _>(&[vec![1u8; 48]]);
+        network.append_list::<Vec<u8>, _>(&[vec![2u8; 48]]);
+        let network: Bytes = [&[BLOB_TX_TYPE], &network.out()[..]].concat().into();
+        assert_eq!(consensus_transaction(&network).unwrap(), expected);
+        assert_eq!(consensus_transaction(&expected).unwrap(), expected);
+        assert_eq!(
+            transactions_root_from_raw(&[network]).unwrap(),
+            transactions_root(&[transaction(3)]).unwrap()
+        );
+
+        // blob transactions can't create contracts.
+        let mut creation = transaction(3);
+        creation.to = None;
+        assert!(encode_transaction(&creation).is_err());
+    }
+
+    #[test]
+    pub fn test_set_code_transaction() {
+        let mut expected = RlpStream::new_list(13);
+        start_1559_fields(&mut expected);
+        expected.begin_list(1);
+        expected.begin_list(6);
+        expected.append(&U256::one());
+        expected.append(&Address::repeat_byte(0x22));
+        expected.append(&9u64);
+        expected.append(&0u8);
+        expected.append(&U256::from(0xaa));
+        expected.append(&U256::from(0xbb));
+        end_signature(&mut expected);
+        let expected: Bytes = [&[SET_CODE_TX_TYPE], &expected.out()[..]].concat().into();
+        assert_eq!(encode_transaction(&transaction(4)).unwrap(), expected);
+        assert_eq!(consensus_transaction(&expected).unwrap(), expected);
+
+        let mut tx = transaction(4);
+        tx.other.remove("authorizationList");
+        assert!(encode_transaction(&tx).is_err());
+    }
+
+    #[test]
+    pub fn test_transactions_root() {
+        // older types are encoded by ethers.
+        let legacy = transaction(0);
+        let dynamic_fee = transaction(2);
+        assert_eq!(encode_transaction(&dynamic_fee).unwrap(), dynamic_fee.rlp());
+
+        let block = [legacy, dynamic_fee, transaction(3), transaction(4)];
+        let raw = block
+            .iter()
+            .map(|tx| encode_transaction(tx).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transactions_root(&block).unwrap(),
+            transactions_root_from_raw(&raw).unwrap()
+        );
+        assert!(encode_transaction(&transaction(0x7f)).is_err());
+    }
+}
@@ -1,68 +1,279 @@
 use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    heatmap::HeatmapRecorder,
+    instrument::{self, Operation},
     nibbles::Nibbles,
-    nodes::{LeafValue, NodeData, Nodes},
-    utils::ConsecutiveList,
-    Error,
+    nodes::{LeafValue, NodeData, Nodes, ValueInterner},
+    stats,
+    witness::{ProofSizeEstimate, Witness},
+    Error, ErrorContext,
 };
-use ethers::{
-    types::{Bytes, H256},
-    utils::keccak256,
+use ethers_core::types::{Bytes, H256};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Debug,
+    marker::PhantomData,
 };
-use std::{fmt::Debug, marker::PhantomData, str::FromStr};
-
-const EMPTY_ROOT_STR: &str = "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
 
 pub trait MptKey: Clone + Debug + PartialEq {
     fn to_nibbles(&self) -> Result<Nibbles, Error>;
+
+    // what goes into the context of errors for this key.
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext::default()
+    }
+}
+
+pub(crate) struct LoadedNode<V: LeafValue> {
+    pub hash: H256,
+    // nibbles from the root to this node.
+    pub path: Vec<u8>,
+    // raw nodes from the root down to and including this node.
+    pub proof: Vec<Bytes>,
+    pub node_data: NodeData<V>,
+}
+
+// how insert_raw_node checks a node before storing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NodeVerification {
+    // the node has to be the root or a child of a loaded node which isn't loaded yet, and its
+    // bytes have to be the canonical encoding, so it is known to be part of the trie.
+    #[default]
+    Verify,
+    // stored under its hash as it is, e.g. for nodes from a trusted source in any order. a
+    // node the trie doesn't point to is never read, but takes memory and goes into witnesses.
+    Trust,
 }
 
-#[derive(Clone, Default, Debug, PartialEq)]
-pub struct Trie<K: MptKey, V: LeafValue> {
+#[derive(Clone, Default, Debug)]
+pub struct Trie<K: MptKey, V: LeafValue, H: TrieHasher = KeccakHasher> {
     root: Option<H256>,
-    nodes: Nodes<V>,
+    nodes: Nodes<NodeData<V>, H>,
+    // paths of the keys written since the last take_touched, for root change notifications.
+    touched: BTreeSet<Vec<u8>>,
+    // (key, old value, new value) of every write, in order, once start_changelog was called.
+    changelog: Option<Vec<(K, V, V)>>,
+    // the committed roots, once start_history was called. replaced nodes are kept meanwhile.
+    history: Option<Vec<H256>>,
+    // while set_all runs, what was under the hash of every node its writes stored or removed,
+    // oldest first, to undo them.
+    journal: Option<Vec<(H256, Option<NodeData<V>>)>>,
+    // shares the byte strings of loaded values once intern_values was called.
+    interner: Option<ValueInterner>,
+    pub(crate) heatmap: HeatmapRecorder,
     _marker: PhantomData<K>,
 }
 
-impl<K: MptKey, V: LeafValue> Trie<K, V> {
+// the same root and nodes, whatever was written to get there.
+impl<K: MptKey, V: LeafValue, H: TrieHasher> PartialEq for Trie<K, V, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root && self.nodes == other.nodes
+    }
+}
+
+impl<K: MptKey, V: LeafValue + Eq, H: TrieHasher + Eq> Eq for Trie<K, V, H> {}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
     pub fn from_root(root: H256) -> Self {
         Trie {
             root: Some(root),
             nodes: Nodes::default(),
+            touched: BTreeSet::new(),
+            changelog: None,
+            history: None,
+            journal: None,
+            interner: None,
+            heatmap: HeatmapRecorder::default(),
             _marker: PhantomData,
         }
     }
 
     pub fn empty() -> Self {
-        Self::from_root(H256::from_str(EMPTY_ROOT_STR).unwrap())
+        Self::from_root(H::empty_root())
+    }
+
+    // a trie over nodes from elsewhere, e.g. Nodes::try_from a node stream.
+    pub fn from_nodes(root: H256, nodes: Nodes<NodeData<V>, H>) -> Self {
+        Trie {
+            nodes,
+            ..Self::from_root(root)
+        }
     }
 
+    // from now on the values of loaded leaves share their bytes with equal values loaded
+    // before, which shrinks large witnesses with many equal byte string values. only for
+    // values kept as bytes, e.g. Bytes and LazyAccount, see LeafValue::intern.
+    pub fn intern_values(&mut self) {
+        self.interner.get_or_insert_with(ValueInterner::default);
+    }
+
+    // the distinct values seen since intern_values.
+    pub fn interned_values(&self) -> usize {
+        self.interner.as_ref().map_or(0, ValueInterner::len)
+    }
+
+    fn decode_node(&mut self, raw: Bytes) -> Result<NodeData<V>, Error> {
+        let node_data = NodeData::from_raw_rlp(raw)?;
+        Ok(match &mut self.interner {
+            Some(interner) => node_data.intern_value(interner),
+            None => node_data,
+        })
+    }
+
+    // writes hash the changed nodes as they go, so the root is always current and reading it
+    // needs neither &mut nor any hashing. None only for a default trie without a root.
     pub fn root(&self) -> Option<H256> {
         self.root
     }
 
-    pub fn get(&self, key: K) -> Result<V, Error> {
-        if self.root.is_none() {
-            return Err(Error::InternalError("root not set"));
+    // builds a complete trie in one pass, instead of inserting the entries one by one.
+    pub fn from_entries(entries: impl IntoIterator<Item = (K, V)>) -> Result<Self, Error> {
+        let mut paths = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key.to_nibbles()?.to_u4_vec(), value)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        paths.sort_by(|(path_a, _), (path_b, _)| path_a.cmp(path_b));
+        Self::build_sorted(paths)
+    }
+
+    // same as from_entries, for entries already sorted by their path (i.e. by hashed key for
+    // the state tries) which saves the sort on large exports.
+    pub fn from_sorted_entries(entries: impl IntoIterator<Item = (K, V)>) -> Result<Self, Error> {
+        let paths = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key.to_nibbles()?.to_u4_vec(), value)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        if paths.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+            return Err(Error::InternalError("entries are not sorted by path"));
+        }
+        Self::build_sorted(paths)
+    }
+
+    // from_entries for keys which are hashed already, e.g. from a snapshot which only has
+    // the hashes. the trie can still be read by K, which hashes to the same paths.
+    pub(crate) fn from_hashed_entries(
+        entries: impl IntoIterator<Item = (H256, V)>,
+    ) -> Result<Self, Error> {
+        let mut paths = entries
+            .into_iter()
+            .map(|(hash, value)| {
+                (
+                    Nibbles::from_raw_path(Bytes::from(hash.as_bytes().to_vec())).to_u4_vec(),
+                    value,
+                )
+            })
+            .collect::<Vec<_>>();
+        paths.sort_by(|(path_a, _), (path_b, _)| path_a.cmp(path_b));
+        Self::build_sorted(paths)
+    }
+
+    fn build_sorted(mut paths: Vec<(Vec<u8>, V)>) -> Result<Self, Error> {
+        // default values mean the key is not in the trie.
+        paths.retain(|(_, value)| *value != V::default());
+        if paths.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::InternalError("duplicate key in entries"));
+        }
+
+        let mut trie = Self::empty();
+        if !paths.is_empty() {
+            trie.root = Some(trie.build_subtrie(&paths, 0)?);
+        }
+        Ok(trie)
+    }
+
+    // entries are sorted and share the first `depth` nibbles.
+    pub(crate) fn build_subtrie(
+        &mut self,
+        entries: &[(Vec<u8>, V)],
+        depth: usize,
+    ) -> Result<H256, Error> {
+        if let [(path, value)] = entries {
+            return self.nodes.create_leaf(
+                Nibbles::from_u4_vec(path[depth..].to_vec())?,
+                value.to_owned(),
+            );
+        }
+
+        // sorted, so the prefix shared by the first and last entry is shared by all of them.
+        let first = &entries[0].0;
+        let last = &entries[entries.len() - 1].0;
+        let common = first[depth..]
+            .iter()
+            .zip(last[depth..].iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        // sorted, a key which is a prefix of another one comes first and ends here.
+        if depth + common >= first.len() {
+            return Err(Error::InternalError("a key is a prefix of another key"));
         }
 
-        let path = key.to_nibbles()?;
-        let mut hash_current = self.root.unwrap();
+        let mut branch_node_arr: [Option<H256>; 17] = [None; 17];
+        let mut start = 0;
+        while start < entries.len() {
+            let nibble = entries[start].0[depth + common];
+            let end = start
+                + entries[start..]
+                    .iter()
+                    .take_while(|(path, _)| path[depth + common] == nibble)
+                    .count();
+            branch_node_arr[nibble as usize] =
+                Some(self.build_subtrie(&entries[start..end], depth + common + 1)?);
+            start = end;
+        }
+        let (branch_hash, _) = self.nodes.insert(NodeData::Branch(branch_node_arr))?;
+
+        if common == 0 {
+            return Ok(branch_hash);
+        }
+        let (extension_hash, _) = self.nodes.insert(NodeData::Extension {
+            key: Nibbles::from_u4_vec(first[depth..depth + common].to_vec())?,
+            node: branch_hash,
+        })?;
+        Ok(extension_hash)
+    }
+
+    pub fn get(&self, key: K) -> Result<V, Error> {
+        self.get_path(key.to_nibbles()?)
+            .map_err(|err| err.with_context(key.error_context()))
+    }
+
+    // get by the (hashed) path of a key, for callers which only have the hash.
+    pub(crate) fn get_path(&self, path: Nibbles) -> Result<V, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        self.get_path_from(root, path)
+    }
+
+    fn get_path_from(&self, root: H256, path: Nibbles) -> Result<V, Error> {
+        let mut hash_current = root;
         let mut i = 0;
+        self.heatmap.record(|heatmap| heatmap.walks += 1);
         loop {
-            if hash_current == EMPTY_ROOT_STR.parse().unwrap() {
+            if hash_current == H::empty_root() {
                 // we got to an empty hash, means everything under this is empty.
                 return Ok(V::default());
             }
 
-            let node_data = self
-                .nodes
-                .get(&hash_current)
-                .ok_or(Error::InternalError("node not present, please add a proof"))?;
+            let node_data = self.nodes.get(&hash_current).ok_or_else(|| {
+                node_not_present().with_context(ErrorContext {
+                    path: Some(path.to_u4_vec()[..i].to_vec()),
+                    node: Some(hash_current),
+                    ..Default::default()
+                })
+            })?;
+            self.heatmap.record(|heatmap| {
+                *heatmap
+                    .nodes
+                    .entry(path.to_u4_vec()[..i].to_vec())
+                    .or_default() += 1
+            });
 
             match node_data {
                 NodeData::Leaf { key, value } => {
                     if key.clone() == path.slice(i)? {
                         // path exactly matches the leaf, it means we have found the value.
+                        self.heatmap.record(|heatmap| {
+                            *heatmap.keys.entry(path.to_u4_vec()).or_default() += 1
+                        });
                         return Ok(value.to_owned());
                     } else {
                         // path doesn't match with the leaf, it means value is not set for the key.
@@ -83,6 +294,10 @@ impl<K: MptKey, V: LeafValue> Trie<K, V> {
                     }
                 }
                 NodeData::Extension { key, node } => {
+                    if !path.slice(i)?.as_slice().starts_with(key.as_slice()) {
+                        // path diverges from the extension, key is not in the trie.
+                        return Ok(V::default());
+                    }
                     // consume extension key nibbles from path.
                     i += key.len();
                     // get hash of next branch node from the extension to walk further.
@@ -93,250 +308,516 @@ impl<K: MptKey, V: LeafValue> Trie<K, V> {
     }
 
     pub fn set(&mut self, key: K, new_value: V) -> Result<(), Error> {
-        if self.root.is_none() {
-            return Err(Error::InternalError("root not set"));
+        let context = key.error_context();
+        self.set_value(key, new_value)
+            .map_err(|err| err.with_context(context))
+    }
+
+    // sets all the entries, or none of them: when a write fails, e.g. a removal whose sibling
+    // isn't loaded, the trie goes back to how it was before the first one.
+    pub fn set_all(&mut self, entries: impl IntoIterator<Item = (K, V)>) -> Result<(), Error> {
+        let root = self.root;
+        let touched = self.touched.clone();
+        let changelog_len = self.changelog.as_ref().map(Vec::len);
+        self.journal = Some(Vec::new());
+        let result = entries
+            .into_iter()
+            .try_for_each(|(key, value)| self.set(key, value));
+        let journal = self.journal.take().unwrap_or_default();
+        if result.is_err() {
+            for (hash, node_data) in journal.into_iter().rev() {
+                match node_data {
+                    Some(node_data) => {
+                        self.nodes.insert(node_data)?;
+                    }
+                    None => {
+                        self.nodes.remove(&hash);
+                    }
+                }
+            }
+            self.root = root;
+            self.touched = touched;
+            if let (Some(changelog), Some(len)) = (&mut self.changelog, changelog_len) {
+                changelog.truncate(len);
+            }
         }
+        result
+    }
+
+    fn set_value(&mut self, key: K, new_value: V) -> Result<(), Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
 
-        if self.get(key.clone()).unwrap() == (new_value) {
+        let old_value = self.get(key.clone())?;
+        if old_value == new_value {
             // value is unchanged, do nothing.
             return Ok(());
         }
+        let logged = self
+            .changelog
+            .is_some()
+            .then(|| (key.clone(), old_value, new_value.clone()));
+
+        let path = key.to_nibbles()?.to_u4_vec();
+        let root_updated = stats::time_root_update(|| {
+            if new_value == V::default() {
+                // a removal can collapse the lowest branch into its other child, which has to be
+                // loaded. checked before anything changes, so a failed removal leaves the trie as is.
+                self.check_sibling_loaded(key)?;
+                self.remove_at(root, &path)
+            } else {
+                self.insert_at(root, &path, new_value)
+            }
+        })?;
+        self.root = Some(root_updated);
+        self.touched.insert(path);
+        if let (Some(changelog), Some(entry)) = (&mut self.changelog, logged) {
+            changelog.push(entry);
+        }
+        Ok(())
+    }
 
-        let path = key.to_nibbles()?;
-        let mut hash_items = ConsecutiveList::new(self.root.unwrap());
+    pub(crate) fn start_changelog(&mut self) {
+        self.changelog.get_or_insert_with(Vec::new);
+    }
 
-        if hash_items.current() == EMPTY_ROOT_STR.parse().unwrap() {
-            // root is empty, simply assign a leaf to the root.
-            self.root = Some(self.nodes.create_leaf(path, new_value)?);
-            return Ok(());
+    pub(crate) fn changelog_len(&self) -> usize {
+        self.changelog.as_ref().map_or(0, Vec::len)
+    }
+
+    // the writes logged so far, the log keeps recording.
+    pub(crate) fn take_changelog(&mut self) -> Vec<(K, V, V)> {
+        self.changelog
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // hashed keys written since the last call, as nibble paths.
+    pub(crate) fn take_touched(&mut self) -> BTreeSet<Vec<u8>> {
+        std::mem::take(&mut self.touched)
+    }
+
+    // from now on the nodes replaced by writes are kept, so every root marked with
+    // commit_version stays readable with get_at. unchanged subtrees are shared between the
+    // versions, as nodes are stored by hash.
+    pub fn start_history(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    pub(crate) fn records_history(&self) -> bool {
+        self.history.is_some()
+    }
+
+    // marks the current root as a version to keep reading, returns it.
+    pub fn commit_version(&mut self) -> Result<H256, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let history = self
+            .history
+            .as_mut()
+            .ok_or(Error::Usage("history not started, see start_history"))?;
+        if history.last() != Some(&root) {
+            history.push(root);
+        }
+        Ok(root)
+    }
+
+    // the committed roots, oldest first.
+    pub fn versions(&self) -> &[H256] {
+        self.history.as_deref().unwrap_or_default()
+    }
+
+    fn check_version(&self, root: H256) -> Result<(), Error> {
+        if Some(root) != self.root && !self.versions().contains(&root) {
+            return Err(Error::Usage("root is not a committed version"));
         }
+        Ok(())
+    }
+
+    // the value at a committed root, or the current one.
+    pub fn get_at(&self, root: H256, key: K) -> Result<V, Error> {
+        self.check_version(root)?;
+        self.get_from(root, key)
+    }
+
+    // get_proof against a committed root, or the current one.
+    pub fn get_proof_at(&self, root: H256, key: K) -> Result<Vec<Bytes>, Error> {
+        self.check_version(root)?;
+        self.get_proof_from(root, key)
+    }
+
+    // get and get_proof under any root the nodes have, see TrieStore.
+    pub(crate) fn get_from(&self, root: H256, key: K) -> Result<V, Error> {
+        self.get_path_from(root, key.to_nibbles()?)
+    }
+
+    pub(crate) fn get_proof_from(&self, root: H256, key: K) -> Result<Vec<Bytes>, Error> {
+        match self.walk_path_from(root, key.to_nibbles()?)? {
+            (proof, None) => proof
+                .iter()
+                .map(|node_data| node_data.to_raw_rlp())
+                .collect(),
+            (_, Some(_)) => Err(node_not_present()),
+        }
+    }
+
+    // writes replace the nodes on their path, the old ones stay while recording history.
+    fn take_node(&mut self, hash: &H256) -> Option<NodeData<V>> {
+        match self.history {
+            Some(_) => self.nodes.get(hash).cloned(),
+            None => {
+                let node_data = self.nodes.remove(hash);
+                if let (Some(journal), Some(node_data)) = (&mut self.journal, &node_data) {
+                    journal.push((*hash, Some(node_data.clone())));
+                }
+                node_data
+            }
+        }
+    }
+
+    // the nodes written by set go through here, so set_all can undo them.
+    fn store_node(&mut self, node_data: NodeData<V>) -> Result<H256, Error> {
+        let (hash, previous) = self.nodes.insert(node_data)?;
+        if let Some(journal) = &mut self.journal {
+            journal.push((hash, previous));
+        }
+        Ok(hash)
+    }
+
+    fn store_leaf(&mut self, key: Nibbles, value: V) -> Result<H256, Error> {
+        self.store_node(NodeData::Leaf { key, value })
+    }
+
+    // sets the value under the node, returns the hash of the updated node. nodes on the path
+    // are replaced, so they are taken out of the map as we go down.
+    fn insert_at(&mut self, hash: H256, path: &[u8], new_value: V) -> Result<H256, Error> {
+        if hash == H::empty_root() {
+            return self.store_leaf(Nibbles::from_u4_vec(path.to_vec())?, new_value);
+        }
+
+        let node_data = self.take_node(&hash).ok_or_else(node_not_present)?;
+        let node_updated = match node_data {
+            NodeData::Leaf { key, value } => {
+                if key.as_slice() == path {
+                    // path exactly matches, simply update value.
+                    NodeData::Leaf {
+                        key,
+                        value: new_value,
+                    }
+                } else {
+                    // otherwise both leaves go under a new branch.
+                    let key = key.to_u4_vec();
+                    let common = diverging_at(&key, path)?;
+                    let leaf_hash =
+                        self.store_leaf(Nibbles::from_u4_vec(key[common + 1..].to_vec())?, value)?;
+                    return self.split(&key, leaf_hash, path, common, new_value);
+                }
+            }
+            NodeData::Branch(mut arr) => {
+                let nibble = *path
+                    .first()
+                    .ok_or(Error::InternalError("key ends at a branch"))?
+                    as usize;
+                let child = arr[nibble].unwrap_or_else(H::empty_root);
+                arr[nibble] = Some(self.insert_at(child, &path[1..], new_value)?);
+                NodeData::Branch(arr)
+            }
+            NodeData::Extension { key, node } => {
+                let key = key.to_u4_vec();
+                if path.starts_with(&key) {
+                    NodeData::Extension {
+                        key: Nibbles::from_u4_vec(key.clone())?,
+                        node: self.insert_at(node, &path[key.len()..], new_value)?,
+                    }
+                } else {
+                    // path leaves the extension, what is left of it goes under a new branch.
+                    let common = diverging_at(&key, path)?;
+                    let rest = key[common + 1..].to_vec();
+                    let child_hash = if rest.is_empty() {
+                        node
+                    } else {
+                        self.store_node(NodeData::Extension {
+                            key: Nibbles::from_u4_vec(rest)?,
+                            node,
+                        })?
+                    };
+                    return self.split(&key, child_hash, path, common, new_value);
+                }
+            }
+        };
+        self.store_node(node_updated)
+    }
 
-        // keep traversing down the trie until we get the final node and update it.
+    // branch at `common` with the existing child (of a leaf or extension with `key`) and a new
+    // leaf for the rest of the path, under an extension for the shared nibbles if any.
+    fn split(
+        &mut self,
+        key: &[u8],
+        child_hash: H256,
+        path: &[u8],
+        common: usize,
+        new_value: V,
+    ) -> Result<H256, Error> {
+        let mut arr: [Option<H256>; 17] = [None; 17];
+        arr[key[common] as usize] = Some(child_hash);
+        arr[path[common] as usize] = Some(self.store_leaf(
+            Nibbles::from_u4_vec(path[common + 1..].to_vec())?,
+            new_value,
+        )?);
+        let branch_hash = self.store_node(NodeData::Branch(arr))?;
+        if common == 0 {
+            return Ok(branch_hash);
+        }
+        let extension_hash = self.store_node(NodeData::Extension {
+            key: Nibbles::from_u4_vec(path[..common].to_vec())?,
+            node: branch_hash,
+        })?;
+        Ok(extension_hash)
+    }
+
+    // removes the key, which has to be in the trie, under the node. returns the hash of the
+    // updated node, or the empty root if nothing is left under it.
+    fn remove_at(&mut self, hash: H256, path: &[u8]) -> Result<H256, Error> {
+        let node_data = self.take_node(&hash).ok_or_else(node_not_present)?;
+        match node_data {
+            NodeData::Leaf { .. } => Ok(H::empty_root()),
+            NodeData::Branch(mut arr) => {
+                let nibble = path[0] as usize;
+                let child = self.remove_at(
+                    arr[nibble].ok_or(Error::Usage("key is not in the trie"))?,
+                    &path[1..],
+                )?;
+                arr[nibble] = (child != H::empty_root()).then_some(child);
+
+                let children: Vec<usize> = (0..16).filter(|i| arr[*i].is_some()).collect();
+                if let [keep_index] = children[..] {
+                    // a branch can't have a single child, it becomes that child.
+                    self.prepend_to(vec![keep_index as u8], arr[keep_index].unwrap())
+                } else {
+                    self.store_node(NodeData::Branch(arr))
+                }
+            }
+            NodeData::Extension { key, node } => {
+                let key = key.to_u4_vec();
+                let child = self.remove_at(node, &path[key.len()..])?;
+                // the branch under the extension may have collapsed.
+                self.prepend_to(key, child)
+            }
+        }
+    }
+
+    // the node with `nibbles` in front of its path: merged into a leaf or extension child,
+    // or as an extension pointing to a branch child.
+    fn prepend_to(&mut self, nibbles: Vec<u8>, hash: H256) -> Result<H256, Error> {
+        let node_updated = match self.nodes.get(&hash) {
+            Some(NodeData::Leaf { key, value }) => NodeData::Leaf {
+                key: key.prepend_nibbles(nibbles)?,
+                value: value.to_owned(),
+            },
+            Some(NodeData::Extension { key, node }) => NodeData::Extension {
+                key: key.prepend_nibbles(nibbles)?,
+                node: node.to_owned(),
+            },
+            Some(NodeData::Branch(_)) => {
+                return self.store_node(NodeData::Extension {
+                    key: Nibbles::from_u4_vec(nibbles)?,
+                    node: hash,
+                })
+            }
+            None => return Err(node_not_present()),
+        };
+        self.take_node(&hash);
+        self.store_node(node_updated)
+    }
+
+    // only the lowest branch on the path can collapse on a removal, and only when the key's
+    // sibling is its one other child.
+    fn check_sibling_loaded(&self, key: K) -> Result<(), Error> {
+        let (proof, _) = self.walk(key)?;
+        if let Some(NodeData::Branch(arr)) = proof
+            .iter()
+            .rev()
+            .find(|node_data| matches!(node_data, NodeData::Branch(_)))
+        {
+            let children: Vec<&H256> = arr[..16].iter().flatten().collect();
+            if children.len() == 2 && children.iter().any(|hash| !self.nodes.contains(hash)) {
+                stats::missing_node();
+                return Err(Error::MissingData(
+                    "sibling node not present, please add a proof for it",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // root of the trie of the keys under the nibble `prefix`, with the prefix cut off their
+    // paths: the hash of the node at the prefix, or of the part below it of an extension or a
+    // leaf the prefix ends in, and the empty root for no keys. only the nodes above it have to
+    // be loaded. e.g. for checking a shard of the keys against its part of the root, or for
+    // putting a root together from the subtree roots of several workers.
+    pub fn subtree_root(&self, prefix: &[u8]) -> Result<H256, Error> {
+        if prefix.iter().any(|nibble| *nibble > 0xf) {
+            return Err(Error::Usage("cannot be more than 4 bits"));
+        }
+        let mut hash = self.root.ok_or(Error::Usage("root not set"))?;
         let mut i = 0;
-        let mut hash_updated: H256;
-        loop {
-            // temporarily remove node from the map, so we can insert updated node into the map.
-            let current_node = self
-                .nodes
-                .remove(&hash_items.current())
-                .ok_or(Error::InternalError("node not present, please add a proof"))?;
-
-            // update current node if necessary.
-            let current_node_updated = match current_node {
+        while i < prefix.len() {
+            if hash == H::empty_root() {
+                break;
+            }
+            let rest = &prefix[i..];
+            match self.nodes.get(&hash).ok_or_else(node_not_present)? {
                 NodeData::Leaf { key, value } => {
-                    let path_slice = path.slice(i)?;
-                    // consume leaf key nibbles from path.
-                    i += key.len();
-
-                    if key == path_slice {
-                        // path exactly matches, simply update value.
-                        NodeData::Leaf {
-                            key,
-                            value: new_value.clone(),
+                    if !key.as_slice().starts_with(rest) {
+                        return Ok(H::empty_root());
+                    }
+                    return NodeData::Leaf {
+                        key: Nibbles::from_u4_vec(key.as_slice()[rest.len()..].to_vec())?,
+                        value: value.to_owned(),
+                    }
+                    .hash::<H>();
+                }
+                NodeData::Branch(arr) => match arr[prefix[i] as usize] {
+                    Some(child) => {
+                        hash = child;
+                        i += 1;
+                    }
+                    None => return Ok(H::empty_root()),
+                },
+                NodeData::Extension { key, node } => {
+                    if rest.starts_with(key.as_slice()) {
+                        hash = node.to_owned();
+                        i += key.len();
+                    } else if key.as_slice().starts_with(rest) {
+                        return NodeData::<V>::Extension {
+                            key: Nibbles::from_u4_vec(key.as_slice()[rest.len()..].to_vec())?,
+                            node: node.to_owned(),
                         }
+                        .hash::<H>();
                     } else {
-                        // otherwise to insert a leaf here, we have to hook both leaves under a branch.
-                        self.nodes.create_branch_or_extension(
-                            key,
-                            value,
-                            path_slice,
-                            new_value.clone(),
-                        )?
+                        return Ok(H::empty_root());
                     }
                 }
-                NodeData::Branch(mut arr) => {
-                    let nibble = path.nibble_at(i)?;
-                    // consume 1 nibble from path.
-                    i += 1;
+            }
+        }
+        Ok(hash)
+    }
 
-                    if arr[nibble].is_some() {
-                        // set next hash for traversing down the branch.
-                        hash_items.set_next(arr[nibble].unwrap())
-                    } else {
-                        // create a leaf and assign to the branch.
-                        let path_slice = path.slice(i)?;
-                        let leaf_hash = self
-                            .nodes
-                            .create_leaf(path_slice.clone(), new_value.clone())?;
-                        arr[nibble] = Some(leaf_hash);
-                        // consume newly created leaf key nibbles from path.
-                        i += path_slice.len();
-                    }
-                    NodeData::Branch(arr)
+    // takes the subtree at nibble `path` out of the trie, as if all its keys were removed,
+    // and returns its root hash. only the nodes above it have to be loaded, and the sibling
+    // when the lowest branch on the path collapses. the path has to end at a node.
+    pub(crate) fn remove_subtree(&mut self, path: &[u8]) -> Result<H256, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut subtree = root;
+        let mut lowest_branch = None;
+        let mut i = 0;
+        while i < path.len() {
+            match self.nodes.get(&subtree).ok_or_else(node_not_present)? {
+                NodeData::Leaf { .. } => {
+                    return Err(Error::Usage("path ends inside a leaf"));
+                }
+                NodeData::Branch(arr) => {
+                    lowest_branch = Some(arr);
+                    subtree = arr[path[i] as usize]
+                        .ok_or(Error::Usage("nothing in the trie at this path"))?;
+                    i += 1;
                 }
                 NodeData::Extension { key, node } => {
-                    // set next hash for traversing down the extension.
-                    hash_items.set_next(node.to_owned());
-                    // consume extension key nibbles from path.
+                    if !path[i..].starts_with(key.as_slice()) {
+                        return Err(Error::Usage("path ends inside an extension"));
+                    }
                     i += key.len();
-                    NodeData::Extension { key, node }
+                    subtree = node.to_owned();
                 }
-            };
+            }
+        }
+        if subtree == H::empty_root() {
+            return Err(Error::Usage("nothing in the trie at this path"));
+        }
+        // checked before anything changes, like for a removal.
+        if let Some(arr) = lowest_branch {
+            let children: Vec<&H256> = arr[..16].iter().flatten().collect();
+            if children.len() == 2 && children.iter().any(|hash| !self.nodes.contains(hash)) {
+                stats::missing_node();
+                return Err(Error::MissingData(
+                    "sibling node not present, please add a proof for it",
+                ));
+            }
+        }
 
-            // insert updated node into the map.
-            (hash_updated, _) = self.nodes.insert(current_node_updated)?;
+        let root_updated = stats::time_root_update(|| self.cut_at(root, path))?;
+        self.root = Some(root_updated);
+        self.touched.insert(path.to_vec());
 
-            if !hash_items.go_next() {
-                // we got nothing to traverse further, exit the loop.
-                assert_eq!(i, path.len(), "path will be traversed completely");
-                break;
+        // the subtree's nodes go too, unless the rest of the trie has the same node somewhere.
+        let kept: HashSet<H256> = self.loaded_nodes()?.iter().map(|node| node.hash).collect();
+        let mut pending = vec![subtree];
+        while let Some(hash) = pending.pop() {
+            if kept.contains(&hash) {
+                continue;
+            }
+            match self.take_node(&hash) {
+                Some(NodeData::Branch(arr)) => pending.extend(arr.iter().flatten()),
+                Some(NodeData::Extension { node, .. }) => pending.push(node),
+                _ => {}
             }
         }
+        Ok(subtree)
+    }
 
-        if new_value == V::default() {
-            // since we are removing the node, re-arrange the trie.
-            if let Some(branch_hash) = hash_items.prev() {
-                // we have a branch above us, so let us update that branch.
-                let mut branch_node = self.nodes.remove(branch_hash).ok_or(
-                    Error::InternalError("branch found but still got None somehow"),
+    // like remove_at, for a path ending at a node rather than at a leaf.
+    fn cut_at(&mut self, hash: H256, path: &[u8]) -> Result<H256, Error> {
+        if path.is_empty() {
+            return Ok(H::empty_root());
+        }
+        match self.take_node(&hash).ok_or_else(node_not_present)? {
+            NodeData::Leaf { .. } => Err(Error::Usage("path ends inside a leaf")),
+            NodeData::Branch(mut arr) => {
+                let nibble = path[0] as usize;
+                let child = self.cut_at(
+                    arr[nibble].ok_or(Error::Usage("nothing in the trie at this path"))?,
+                    &path[1..],
                 )?;
-                assert!(branch_node.is_branch());
-                let mut arr = branch_node.get_branch_arr().unwrap();
-                let num_of_nodes_on_branch =
-                    arr.iter().fold(
-                        0,
-                        |sum: i32, hash| {
-                            if hash.is_some() {
-                                sum + 1
-                            } else {
-                                sum
-                            }
-                        },
-                    );
-                if arr.iter().fold(
-                    0,
-                    |sum: i32, hash| {
-                        if hash.is_some() {
-                            sum + 1
-                        } else {
-                            sum
-                        }
-                    },
-                ) > 2
-                {
-                    // we can simply remove the leaf and stop here.
-                    let removal_index = arr
-                        .iter()
-                        .position(|el| el.is_some() && el.unwrap() == hash_items.current())
-                        .ok_or({
-                            Error::InternalError(
-                                "hash not found in parent node, this should ideally not happen",
-                            )
-                        })?;
-
-                    // update the branch node and save it.
-                    arr[removal_index] = None;
-                    branch_node = NodeData::Branch(arr);
-                    (hash_updated, _) = self.nodes.insert(branch_node)?;
-                    hash_items.go_back();
-                } else if num_of_nodes_on_branch == 2 {
-                    // we cannot simply remove leaf since that will cause branch contain just single node.
-                    let keep_index = arr
-                        .iter()
-                        .position(|el| el.is_some() && el.unwrap() != hash_items.current())
-                        .ok_or(Error::InternalError(
-                            "failed to find another element, this should ideally not happen",
-                        ))?;
-
-                    // we want to remove the branch and put the keep_node in it's place.
-                    let keep_hash = arr[keep_index].unwrap();
-                    let keep_node = self.nodes.remove(&keep_hash).ok_or({
-                        Error::InternalError("keep node not present, please load_proof for key, TODO display key here")
-                    })?;
-
-                    // making necessary changes to the keep_node.
-                    let keep_node_updated = match keep_node {
-                        NodeData::Leaf { key, value } => {
-                            // insert nibble at begining of key.
-                            NodeData::Leaf {
-                                key: key.prepend_nibbles(vec![keep_index as u8])?,
-                                value,
-                            }
-                        }
-                        NodeData::Branch(arr) => {
-                            // insert the branch back as it is.
-                            self.nodes.insert(NodeData::Branch(arr))?;
-                            // create an extension node which points to the branch.
-                            NodeData::<V>::Extension {
-                                key: Nibbles::from_u4_vec(vec![keep_index as u8])?,
-                                node: keep_hash,
-                            }
-                        }
-                        NodeData::Extension { key, node } => {
-                            // edit the key of this extension node and add nibble at begining of key.
-                            NodeData::Extension {
-                                key: key.prepend_nibbles(vec![keep_index as u8])?,
-                                node,
-                            }
-                        }
-                    };
-                    // store in hash_updated so that the next loop will update all parent upto the root.
-                    (hash_updated, _) = self.nodes.insert(keep_node_updated)?;
-                    hash_items.go_back();
+                arr[nibble] = (child != H::empty_root()).then_some(child);
+
+                let children: Vec<usize> = (0..16).filter(|i| arr[*i].is_some()).collect();
+                if let [keep_index] = children[..] {
+                    self.prepend_to(vec![keep_index as u8], arr[keep_index].unwrap())
                 } else {
-                    panic!("there were less than two nodes on a branch, this can't happen");
+                    Ok(self.nodes.insert(NodeData::Branch(arr))?.0)
                 }
-            } else {
-                // leaf is directly on the root, removing it makes the trie empty.
-                hash_updated = EMPTY_ROOT_STR.parse().unwrap();
             }
-        }
-
-        // keep traversing up the trie while updating the hashes until we get to the root.
-        loop {
-            if let Some(hash_old_parent) = hash_items.prev() {
-                // we have a parent, so let us update that parent.
-                let parent_node =
-                    self.nodes
-                        .remove(hash_old_parent)
-                        .ok_or(Error::InternalError(
-                            "parent found but still got None somehow",
-                        ))?;
-                let parent_node_updated = match parent_node {
-                    NodeData::Leaf { key: _, value: _ } => {
-                        // leaf cannot appear as a parent.
-                        unreachable!()
-                    }
-                    NodeData::Branch(mut arr) => {
-                        // update the hash at correct location in parent branch.
-                        let index = arr
-                            .iter()
-                            .position(|el| el.is_some() && el.unwrap() == hash_items.current())
-                            .ok_or(Error::InternalError(
-                                "hash not found in parent node, this should ideally not happen",
-                            ))?;
-                        arr[index] = Some(hash_updated);
-                        NodeData::<V>::Branch(arr)
-                    }
-                    NodeData::Extension { key, node: _ } => NodeData::Extension {
-                        key,
-                        node: hash_updated,
-                    },
-                };
-                // store in hash_updated so that the next iteration will use this in parent.
-                (hash_updated, _) = self.nodes.insert(parent_node_updated)?;
-                hash_items.go_back();
-            } else {
-                // finally we reached the root! what a hell of a journey this was 😌.
-                self.root = Some(hash_updated);
-                break;
+            NodeData::Extension { key, node } => {
+                let key = key.to_u4_vec();
+                match self.cut_at(node, &path[key.len()..])? {
+                    // the whole branch under the extension went.
+                    child if child == H::empty_root() => Ok(child),
+                    child => self.prepend_to(key, child),
+                }
             }
         }
-
-        Ok(())
     }
 
     pub fn remove(&mut self, key: K) -> Result<(), Error> {
         self.set(key, V::default())
     }
 
+    // load_proof for sources which only have the nodes (databases, other proof systems, test
+    // vectors), the value is read from the proof and returned. the default value when the
+    // proof shows the key isn't there.
+    pub fn load_proof_nodes(&mut self, key: K, proof: Vec<Bytes>) -> Result<V, Error> {
+        let value: V = proof_value(&key, &proof)?;
+        self.load_proof(key, value.clone(), proof)?;
+        Ok(value)
+    }
+
     #[allow(clippy::manual_flatten)]
     pub fn load_proof(&mut self, key: K, value: V, proof: Vec<Bytes>) -> Result<(), Error> {
+        let _span = instrument::span(Operation::VerifyProof);
         if proof.is_empty() {
-            if self.root.is_some() {
-                if self.root.unwrap() != EMPTY_ROOT_STR.parse().unwrap() {
+            if let Some(root) = self.root {
+                if root != H::empty_root() {
                     // enforce proof to be empty.
-                    return Err(Error::InternalError(
+                    return Err(Error::MissingData(
                         "Root is not empty, hence some proof is needed",
                     ));
                 } else if value != V::default() {
@@ -353,14 +834,16 @@ impl<K: MptKey, V: LeafValue> Trie<K, V> {
         if self.root.is_none() {
             // use first element in proof to calculate root.
             let proof_root = proof[0].clone();
-            self.root = Some(H256::from(keccak256(proof_root)));
+            self.root = Some(H::hash(&proof_root));
         }
 
         let mut root = self.root.unwrap();
         let mut key_current = key.clone().to_nibbles()?;
+        // each entry is hashed once, as the child of the previous one and as the current node.
+        let hashes: Vec<H256> = proof.iter().map(|entry| H::hash(entry)).collect();
 
         for (i, proof_entry) in proof.iter().enumerate() {
-            let hash_node_data = H256::from(keccak256(proof_entry.clone()));
+            let hash_node_data = hashes[i];
 
             // check if node data is preimage of root.
             if hash_node_data != root {
@@ -369,13 +852,16 @@ impl<K: MptKey, V: LeafValue> Trie<K, V> {
                 ));
             }
 
-            // decode the node.
-            let node_data = NodeData::from_raw_rlp(proof_entry.to_owned())?;
-
-            let some_node_data_stored = self.nodes.get(&hash_node_data);
-            if some_node_data_stored.is_none() {
-                self.nodes.insert(node_data.clone())?;
-            }
+            // nodes near the root are shared by proofs of other keys, no need to decode them again.
+            let node_data = match self.nodes.get(&hash_node_data) {
+                Some(node_data) => node_data.clone(),
+                None => {
+                    let node_data = self.decode_node(proof_entry.to_owned())?;
+                    self.nodes.insert(node_data.clone())?;
+                    stats::node_loaded();
+                    node_data
+                }
+            };
 
             // if this is a leaf node (the last one), enforce key and value to be proper.
             if let NodeData::Leaf {
@@ -396,50 +882,497 @@ impl<K: MptKey, V: LeafValue> Trie<K, V> {
                 }
             }
 
+            let last = proof.len() <= i + 1;
             match node_data {
                 NodeData::Extension { key, node } => {
+                    if !key_current.as_slice().starts_with(key.as_slice()) {
+                        // the key leaves the extension, so it isn't in the trie.
+                        return absent(&value, last);
+                    }
                     root = node;
                     // skip nibbles already included in extension key in the current key.
                     key_current = key_current.slice(key.len())?;
                 }
                 NodeData::Branch(arr) => {
-                    if proof.len() <= i + 1 {
+                    match arr[first_nibble(&key_current)?] {
                         // child does not exist, so the value must be null
-                        if value == V::default() {
-                            return Ok(());
-                        } else {
-                            return Err(Error::InternalError(
-                                "child does not exist and value is not null",
-                            ));
-                        }
-                    } else {
-                        // find the child node in branch which matches hash_child.
-                        let hash_child = H256::from(keccak256(proof[i + 1].clone()));
-                        for some_child in arr {
-                            if let Some(child) = some_child {
-                                if child == hash_child {
-                                    root = child;
-                                    // skip one nibble in the current key for branch nodes.
-                                    key_current = key_current.slice(1)?;
-                                    break;
-                                }
-                            }
+                        None => return absent(&value, last),
+                        Some(child) => {
+                            root = child;
+                            // skip one nibble in the current key for branch nodes.
+                            key_current = key_current.slice(1)?;
                         }
                     }
                 }
                 _ => return Ok(()),
             };
+            // the key goes on below the last node, the proof is cut short.
+            if last {
+                return Err(Error::InternalError("proof ends above the key"));
+            }
         }
 
         Ok(())
     }
+
+    pub fn witness(&self) -> Result<Witness<H>, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        Ok(Witness::with_hasher(root, self.raw_nodes()?))
+    }
+
+    // raw nodes from the root down to the key, same shape as the proof given to load_proof.
+    pub fn get_proof(&self, key: K) -> Result<Vec<Bytes>, Error> {
+        let context = key.error_context();
+        self.proof_nodes(key)
+            .and_then(|proof| {
+                proof
+                    .iter()
+                    .map(|node_data| node_data.to_raw_rlp())
+                    .collect()
+            })
+            .map_err(|err| err.with_context(context))
+    }
+
+    pub(crate) fn node(&self, hash: &H256) -> Option<&NodeData<V>> {
+        self.nodes.get(hash)
+    }
+
+    pub fn get_raw_node(&self, hash: H256) -> Result<Option<Bytes>, Error> {
+        self.nodes
+            .get(&hash)
+            .map(|node_data| node_data.to_raw_rlp())
+            .transpose()
+    }
+
+    // adds one raw node to the store, e.g. from a database or another source of nodes than
+    // proofs, and returns its hash. Verify looks for the hash at the frontier, which is a walk
+    // over the loaded nodes, so for many nodes Trust or a Witness is faster.
+    pub fn insert_raw_node(
+        &mut self,
+        raw: Bytes,
+        verification: NodeVerification,
+    ) -> Result<H256, Error> {
+        let node_data = self.decode_node(raw.clone())?;
+        let hash = node_data.hash::<H>()?;
+        if verification == NodeVerification::Verify {
+            if hash != H::hash(&raw) {
+                return Err(Error::InternalError("node does not match its hash"));
+            }
+            if !self
+                .unresolved_nodes()?
+                .iter()
+                .any(|(_, unresolved)| *unresolved == hash)
+            {
+                return Err(Error::InternalError("node is not referenced by the trie"));
+            }
+        }
+        self.nodes.insert(node_data)?;
+        stats::node_loaded();
+        Ok(hash)
+    }
+
+    pub fn estimate_proof_size(&self, keys: &[K]) -> Result<ProofSizeEstimate, Error> {
+        let mut estimate = ProofSizeEstimate::default();
+        let mut seen = HashSet::new();
+        for key in keys {
+            estimate.add_key(&mut seen, self.proof_node_sizes(key.to_owned())?);
+        }
+        Ok(estimate)
+    }
+
+    pub(crate) fn proof_node_sizes(&self, key: K) -> Result<Vec<(H256, usize)>, Error> {
+        self.proof_nodes(key)?
+            .iter()
+            .map(|node_data| {
+                let raw = node_data.to_raw_rlp()?;
+                Ok((H::hash(&raw), raw.len()))
+            })
+            .collect()
+    }
+
+    // get_proof by the (hashed) path of a key.
+    pub(crate) fn get_proof_path(&self, path: Nibbles) -> Result<Vec<Bytes>, Error> {
+        match self.walk_path(path)? {
+            (proof, None) => proof
+                .iter()
+                .map(|node_data| node_data.to_raw_rlp())
+                .collect(),
+            (_, Some(_)) => Err(node_not_present()),
+        }
+    }
+
+    fn proof_nodes(&self, key: K) -> Result<Vec<&NodeData<V>>, Error> {
+        match self.walk(key)? {
+            (proof, None) => Ok(proof),
+            (_, Some(hash)) => Err(node_not_present().with_context(ErrorContext {
+                node: Some(hash),
+                ..Default::default()
+            })),
+        }
+    }
+
+    // first node on the path to the key which is not loaded yet, if any.
+    pub(crate) fn missing_node(&self, key: K) -> Result<Option<H256>, Error> {
+        Ok(self.walk(key)?.1)
+    }
+
+    // nodes on the path to the key, stops early at the first node which is not loaded.
+    fn walk(&self, key: K) -> Result<(Vec<&NodeData<V>>, Option<H256>), Error> {
+        self.walk_path(key.to_nibbles()?)
+    }
+
+    fn walk_path(&self, path: Nibbles) -> Result<(Vec<&NodeData<V>>, Option<H256>), Error> {
+        self.walk_path_from(self.root.ok_or(Error::Usage("root not set"))?, path)
+    }
+
+    fn walk_path_from(
+        &self,
+        root: H256,
+        path: Nibbles,
+    ) -> Result<(Vec<&NodeData<V>>, Option<H256>), Error> {
+        let mut hash_current = root;
+        let mut proof = Vec::new();
+        let mut i = 0;
+        while hash_current != H::empty_root() {
+            let node_data = match self.nodes.get(&hash_current) {
+                Some(node_data) => node_data,
+                None => return Ok((proof, Some(hash_current))),
+            };
+            proof.push(node_data);
+
+            match node_data {
+                NodeData::Leaf { .. } => break,
+                NodeData::Branch(arr) => {
+                    match arr[path.nibble_at(i)?] {
+                        Some(hash_next) => hash_current = hash_next,
+                        // key resolves to empty at this branch.
+                        None => break,
+                    }
+                    i += 1;
+                }
+                NodeData::Extension { key, node } => {
+                    if !path.slice(i)?.as_slice().starts_with(key.as_slice()) {
+                        // path diverges from the extension, key is not in the trie.
+                        break;
+                    }
+                    i += key.len();
+                    hash_current = node.to_owned();
+                }
+            }
+        }
+        Ok((proof, None))
+    }
+
+    // stores a node fetched by its hash, after checking that it hashes to it.
+    pub(crate) fn insert_fetched_node(&mut self, hash: H256, raw: Bytes) -> Result<(), Error> {
+        if H::hash(&raw) != hash {
+            return Err(Error::InternalError("fetched node does not match its hash"));
+        }
+        let node_data = self.decode_node(raw)?;
+        self.nodes.insert(node_data)?;
+        Ok(())
+    }
+
+    // stores a node built outside of the writes, e.g. while checking a range proof.
+    pub(crate) fn insert_node(&mut self, node_data: NodeData<V>) -> Result<H256, Error> {
+        Ok(self.nodes.insert(node_data)?.0)
+    }
+
+    // takes the nodes of a trie with the same root, which were checked against it.
+    pub(crate) fn merge_nodes(&mut self, other: Self) -> Result<(), Error> {
+        for _ in other.nodes.iter() {
+            stats::node_loaded();
+        }
+        self.merge_loaded_nodes(other);
+        Ok(())
+    }
+
+    // the root writes and proofs go to next, see TrieStore.
+    pub(crate) fn set_root(&mut self, root: H256) {
+        self.root = Some(root);
+    }
+
+    // merge_nodes for nodes which were counted when they were loaded, e.g. from proofs into
+    // a trie of their own.
+    pub(crate) fn merge_loaded_nodes(&mut self, other: Self) {
+        self.nodes.extend(other.nodes);
+    }
+
+    pub(crate) fn raw_nodes(&self) -> Result<Vec<Bytes>, Error> {
+        self.nodes
+            .iter()
+            .map(|(_, node_data)| node_data.to_raw_rlp())
+            .collect()
+    }
+
+    // every loaded node reachable from the root, along with its path and the proof to it.
+    pub(crate) fn loaded_nodes(&self) -> Result<Vec<LoadedNode<V>>, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut loaded = Vec::new();
+        let mut pending = vec![(root, Vec::new(), Vec::new())];
+        while let Some((hash, path, mut proof)) = pending.pop() {
+            let node_data = match self.nodes.get(&hash) {
+                Some(node_data) if hash != H::empty_root() => node_data,
+                _ => continue,
+            };
+            proof.push(node_data.to_raw_rlp()?);
+
+            match node_data {
+                NodeData::Leaf { .. } => {}
+                NodeData::Branch(arr) => {
+                    for (nibble, child) in arr.iter().enumerate() {
+                        if let Some(child) = child {
+                            let mut child_path = path.clone();
+                            child_path.push(nibble as u8);
+                            pending.push((*child, child_path, proof.clone()));
+                        }
+                    }
+                }
+                NodeData::Extension { key, node } => {
+                    let mut child_path = path.clone();
+                    child_path.extend(key.to_u4_vec());
+                    pending.push((*node, child_path, proof.clone()));
+                }
+            }
+            loaded.push(LoadedNode {
+                hash,
+                path,
+                proof,
+                node_data: node_data.to_owned(),
+            });
+        }
+        Ok(loaded)
+    }
+
+    // every hash the loaded nodes point to whose node isn't loaded, with its nibble path,
+    // sorted by path. the boundary of the partial trie, where further proofs have to go.
+    pub fn frontier(&self) -> Result<Vec<(Vec<u8>, H256)>, Error> {
+        self.unresolved_nodes()
+    }
+
+    // hashes of the stored nodes which neither the root nor a committed version reaches, i.e.
+    // what prune would remove. sorted.
+    pub fn prune_dry_run(&self) -> Result<Vec<H256>, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut roots = vec![root];
+        roots.extend(self.versions());
+        Ok(self.unreachable_from(roots))
+    }
+
+    // drops the nodes nothing can read anymore, e.g. trusted inserts the trie never pointed
+    // to, and returns their hashes. the nodes of committed versions stay.
+    pub fn prune(&mut self) -> Result<Vec<H256>, Error> {
+        let unreachable = self.prune_dry_run()?;
+        self.remove_nodes(&unreachable);
+        Ok(unreachable)
+    }
+
+    pub(crate) fn remove_nodes(&mut self, hashes: &[H256]) {
+        for hash in hashes {
+            self.nodes.remove(hash);
+        }
+    }
+
+    // stored nodes which aren't under any of the roots, all of them for no roots.
+    pub(crate) fn unreachable_from(&self, roots: Vec<H256>) -> Vec<H256> {
+        let reachable = self.reachable_from(roots);
+        let mut unreachable: Vec<H256> = self
+            .nodes
+            .iter()
+            .map(|(hash, _)| *hash)
+            .filter(|hash| !reachable.contains(hash))
+            .collect();
+        unreachable.sort();
+        unreachable
+    }
+
+    // hashes of the stored nodes under the roots, and of the roots.
+    pub(crate) fn reachable_from(&self, roots: Vec<H256>) -> HashSet<H256> {
+        let mut reachable = HashSet::new();
+        let mut pending = roots;
+        while let Some(hash) = pending.pop() {
+            if !reachable.insert(hash) {
+                continue;
+            }
+            match self.nodes.get(&hash) {
+                Some(NodeData::Branch(arr)) => pending.extend(arr.iter().flatten()),
+                Some(NodeData::Extension { key: _, node }) => pending.push(*node),
+                _ => {}
+            }
+        }
+        reachable
+    }
+
+    // nodes referenced by the loaded part of the trie which are not loaded, with their paths.
+    pub(crate) fn unresolved_nodes(&self) -> Result<Vec<(Vec<u8>, H256)>, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let is_unresolved = |hash: &H256| *hash != H::empty_root() && !self.nodes.contains(hash);
+        if is_unresolved(&root) {
+            return Ok(vec![(vec![], root)]);
+        }
+
+        let mut unresolved = Vec::new();
+        for loaded in self.loaded_nodes()? {
+            match loaded.node_data {
+                NodeData::Leaf { .. } => {}
+                NodeData::Branch(arr) => {
+                    for (nibble, child) in arr.iter().enumerate() {
+                        match child {
+                            Some(child) if is_unresolved(child) => {
+                                let mut path = loaded.path.clone();
+                                path.push(nibble as u8);
+                                unresolved.push((path, *child));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                NodeData::Extension { key, node } => {
+                    if is_unresolved(&node) {
+                        let mut path = loaded.path;
+                        path.extend(key.to_u4_vec());
+                        unresolved.push((path, node));
+                    }
+                }
+            }
+        }
+        unresolved.sort();
+        Ok(unresolved)
+    }
+
+    // decodes and stores every node reachable from the root which is present in raw_nodes,
+    // returns the values of all the leaves that were found on the way.
+    pub(crate) fn load_raw_nodes(
+        &mut self,
+        raw_nodes: &HashMap<H256, Bytes>,
+    ) -> Result<Vec<V>, Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut values = Vec::new();
+        let mut pending = vec![root];
+        while let Some(hash) = pending.pop() {
+            if hash == H::empty_root() {
+                continue;
+            }
+            let raw = match raw_nodes.get(&hash) {
+                Some(raw) => raw.to_owned(),
+                // node was not included, so this part of the trie stays unresolved.
+                None => continue,
+            };
+
+            let node_data = self.decode_node(raw)?;
+            match &node_data {
+                NodeData::Leaf { key: _, value } => values.push(value.to_owned()),
+                NodeData::Branch(arr) => pending.extend(arr.iter().flatten()),
+                NodeData::Extension { key: _, node } => pending.push(node.to_owned()),
+            }
+            self.nodes.insert(node_data)?;
+            stats::node_loaded();
+        }
+        Ok(values)
+    }
+}
+
+// complete tries as one-liners, e.g. `let trie: AccountTrie = accounts.into_iter().collect()`.
+// unlike from_entries a repeated key isn't an error, the last value wins like for a HashMap.
+// panics on a key without a path or one which is a prefix of another, which the keys of the
+// state can't be. use from_entries for keys from elsewhere, it returns the error instead.
+impl<K: MptKey, V: LeafValue, H: TrieHasher> FromIterator<(K, V)> for Trie<K, V, H> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut paths = entries
+            .into_iter()
+            .map(|(key, value)| (key.to_nibbles().expect("key to nibbles").to_u4_vec(), value))
+            .collect::<Vec<_>>();
+        // stable, so the values of a key stay in order and the last one is kept.
+        paths.sort_by(|(path_a, _), (path_b, _)| path_a.cmp(path_b));
+        paths.reverse();
+        paths.dedup_by(|a, b| a.0 == b.0);
+        paths.reverse();
+        Self::build_sorted(paths).expect("trie from entries")
+    }
+}
+
+// the value a proof shows for the key, following the key's path through its nodes. the
+// hashes aren't checked here, load_proof does that.
+pub(crate) fn proof_value<K: MptKey, V: LeafValue>(key: &K, proof: &[Bytes]) -> Result<V, Error> {
+    let mut path = key.to_nibbles()?;
+    if proof.is_empty() {
+        // only the empty root has no nodes, load_proof checks it is that one.
+        return Ok(V::default());
+    }
+    for entry in proof {
+        match NodeData::<V>::from_raw_rlp(entry.clone())? {
+            NodeData::Leaf { key, value } => {
+                return Ok(if key == path { value } else { V::default() });
+            }
+            NodeData::Extension { key, .. } => {
+                if !path.as_slice().starts_with(key.as_slice()) {
+                    return Ok(V::default());
+                }
+                path = path.slice(key.len())?;
+            }
+            NodeData::Branch(arr) => {
+                if arr[first_nibble(&path)?].is_none() {
+                    return Ok(V::default());
+                }
+                path = path.slice(1)?;
+            }
+        }
+    }
+    // the key goes on below the last node. load_proof rejects such a proof too.
+    Err(Error::InternalError("proof ends above the key"))
+}
+
+fn first_nibble(path: &Nibbles) -> Result<usize, Error> {
+    path.as_slice()
+        .first()
+        .map(|nibble| *nibble as usize)
+        .ok_or(Error::InternalError("key ends at a branch"))
+}
+
+// where a proof shows there is nothing for the key, the value has to be the default one and
+// no nodes may follow.
+fn absent<V: LeafValue>(value: &V, last: bool) -> Result<(), Error> {
+    if !last {
+        return Err(Error::InternalError(
+            "proof goes on past the end of the key",
+        ));
+    }
+    if *value != V::default() {
+        return Err(Error::InternalError(
+            "child does not exist and value is not null",
+        ));
+    }
+    Ok(())
+}
+
+fn node_not_present() -> Error {
+    stats::missing_node();
+    Error::MissingData("node not present, please add a proof")
+}
+
+// first nibble where the two paths differ.
+fn diverging_at(key: &[u8], path: &[u8]) -> Result<usize, Error> {
+    let common = key
+        .iter()
+        .zip(path.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common >= key.len() || common >= path.len() {
+        return Err(Error::InternalError(
+            "key is a prefix of another key, values in branches are not supported",
+        ));
+    }
+    Ok(common)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{MptKey, Nibbles, NodeData, Trie, EMPTY_ROOT_STR};
-    use ethers::{
-        types::{BigEndianHash, Bytes, H256, U256},
+    use super::{
+        KeccakHasher, MptKey, Nibbles, NodeData, NodeVerification, Trie, TrieHasher, Witness,
+    };
+    use crate::state_trie::{AccountData, AccountTrie, StorageTrie};
+    use ethers_core::{
+        types::{Address, BigEndianHash, Bytes, H256, U256},
         utils::{hex, keccak256},
     };
 
@@ -857,6 +1790,48 @@ mod tests {
         assert_eq!(trie.get(5).unwrap(), 0);
     }
 
+    #[test]
+    pub fn test_trie_load_proof_forged_absence() {
+        let mut full = StorageTrie::empty();
+        for i in 1..20u64 {
+            full.set(U256::from(i), U256::from(i)).unwrap();
+        }
+        let root = full.root().unwrap();
+        let proof = full.get_proof(U256::from(5)).unwrap();
+        assert!(proof.len() > 1);
+
+        // cut at the root branch, whose slot for the key has a child.
+        let truncated = vec![proof[0].clone()];
+        assert!(StorageTrie::from_root(root)
+            .load_proof(U256::from(5), U256::zero(), truncated.clone())
+            .is_err());
+        assert!(StorageTrie::from_root(root)
+            .load_proof_nodes(U256::from(5), truncated)
+            .is_err());
+
+        // the proof of a key under another child of the root.
+        let key_nibble = |key: u64| U256::from(key).to_nibbles().unwrap().as_slice()[0];
+        let sibling = (6..20u64)
+            .find(|key| key_nibble(*key) != key_nibble(5))
+            .unwrap();
+        let wrong = full.get_proof(U256::from(sibling)).unwrap();
+        assert!(StorageTrie::from_root(root)
+            .load_proof(U256::from(5), U256::zero(), wrong.clone())
+            .is_err());
+        assert!(StorageTrie::from_root(root)
+            .load_proof_nodes(U256::from(5), wrong)
+            .is_err());
+
+        // a real proof of absence still loads.
+        let absent = full.get_proof(U256::from(100)).unwrap();
+        assert_eq!(
+            StorageTrie::from_root(root)
+                .load_proof_nodes(U256::from(100), absent)
+                .unwrap(),
+            U256::zero()
+        );
+    }
+
     #[test]
     pub fn test_trie_get_1() {
         let mut trie = Trie::<Nibbles, u64>::default();
@@ -978,6 +1953,36 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_trie_get_5_diverging_extension() {
+        // both keys start with f, so the root is an extension.
+        let full = Trie::<Nibbles, u64>::from_entries(vec![
+            (
+                Nibbles::from_raw_path_str(
+                    "0xf13b9e45cff25a1c1f71a308d9e909a90d33e20ee6f1e6d3b8f99990718b127f",
+                ),
+                1,
+            ),
+            (
+                Nibbles::from_raw_path_str(
+                    "0xfa4f1d3132b65d1f1e32b686399ae190c72e7f99d61abf1b1f43d5ea343fdd31",
+                ),
+                2,
+            ),
+        ])
+        .unwrap();
+        let absent = Nibbles::from_raw_path_str(
+            "0x017994e8ab5fb54676dc8f56083acfc89d41296a017994e8ab5fb54676dc8f56",
+        );
+        let proof = full.get_proof(absent.clone()).unwrap();
+        assert_eq!(proof.len(), 1);
+
+        // the extension alone proves the key is not there.
+        let mut partial = Trie::<Nibbles, u64>::from_root(full.root().unwrap());
+        partial.load_proof(absent.clone(), 0, proof).unwrap();
+        assert_eq!(partial.get(absent).unwrap(), 0);
+    }
+
     #[test]
     pub fn test_trie_set_1() {
         let mut trie = Trie::<Nibbles, u64>::default();
@@ -1163,7 +2168,7 @@ mod tests {
         );
 
         trie.remove(2).unwrap();
-        assert_eq!(hex::encode(trie.root.unwrap()), EMPTY_ROOT_STR);
+        assert_eq!(trie.root.unwrap(), KeccakHasher::empty_root());
     }
 
     #[test]
@@ -1253,4 +2258,390 @@ mod tests {
         trie.remove(2).unwrap();
         assert_eq!(trie.root.unwrap(), root_before);
     }
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct PrefixedKeccakHasher;
+
+    impl TrieHasher for PrefixedKeccakHasher {
+        fn hash(data: &[u8]) -> H256 {
+            H256::from(keccak256([&[0x01], data].concat()))
+        }
+    }
+
+    #[test]
+    pub fn test_trie_from_entries() {
+        let entries = (0..200u64)
+            .map(|i| (U256::from(i * 7), U256::from(i + 1)))
+            .collect::<Vec<_>>();
+
+        // root computed with an independent mpt implementation (parity's triehash).
+        let built = StorageTrie::from_entries(entries).unwrap();
+        assert_eq!(
+            hex::encode(built.root().unwrap()),
+            "4a471dd34284d305e14104bdc5ea296873950a0506c298ed823455aecb7a03cb"
+        );
+        assert_eq!(built.get(U256::from(7)).unwrap(), U256::from(2));
+        assert_eq!(built.get(U256::from(8)).unwrap(), U256::zero());
+
+        // storage trie from the geth dev state tests.
+        assert_eq!(
+            hex::encode(
+                StorageTrie::from_entries(vec![
+                    (U256::from(1), U256::from(2)),
+                    (U256::from(2), U256::from(4))
+                ])
+                .unwrap()
+                .root()
+                .unwrap()
+            ),
+            "29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+        );
+
+        assert_eq!(
+            StorageTrie::from_entries(vec![]).unwrap().root(),
+            StorageTrie::empty().root()
+        );
+        assert!(StorageTrie::from_entries(vec![
+            (U256::one(), U256::one()),
+            (U256::one(), U256::from(2))
+        ])
+        .is_err());
+
+        // keys of a custom MptKey which are prefixes of others have nowhere to go.
+        let nibbles = |u4: &[u8]| Nibbles::from_u4_vec(u4.to_vec()).unwrap();
+        for keys in [
+            vec![&[1u8, 2][..], &[1, 2, 3]],
+            vec![&[1, 2], &[1, 2, 3], &[1, 4]],
+        ] {
+            let err =
+                Trie::<Nibbles, u64>::from_entries(keys.into_iter().map(|u4| (nibbles(u4), 1)))
+                    .unwrap_err();
+            assert!(matches!(
+                err,
+                crate::Error::InternalError("a key is a prefix of another key")
+            ));
+        }
+    }
+
+    #[test]
+    pub fn test_trie_from_sorted_entries() {
+        let mut entries = (0..20u64)
+            .map(|i| (U256::from(i), U256::from(i + 1)))
+            .collect::<Vec<_>>();
+        assert!(StorageTrie::from_sorted_entries(entries.clone()).is_err());
+
+        entries.sort_by_key(|(key, _)| key.to_nibbles().unwrap().to_u4_vec());
+        assert_eq!(
+            StorageTrie::from_sorted_entries(entries.clone())
+                .unwrap()
+                .root(),
+            StorageTrie::from_entries(entries).unwrap().root()
+        );
+    }
+
+    #[test]
+    pub fn test_trie_root_from_shared_reference() {
+        let mut trie = StorageTrie::empty();
+        trie.set(U256::one(), U256::from(2)).unwrap();
+        let expected = StorageTrie::from_entries([(U256::one(), U256::from(2))])
+            .unwrap()
+            .root();
+
+        let trie = &trie;
+        std::thread::scope(|scope| {
+            let readers: Vec<_> = (0..4).map(|_| scope.spawn(move || trie.root())).collect();
+            for reader in readers {
+                assert_eq!(reader.join().unwrap(), expected);
+            }
+        });
+    }
+
+    #[test]
+    pub fn test_trie_from_iterator() {
+        let entries = (1..50u64)
+            .map(|i| (U256::from(i), U256::from(i * 3)))
+            .collect::<Vec<_>>();
+        let collected: StorageTrie = entries.iter().cloned().collect();
+        assert_eq!(
+            collected.root(),
+            StorageTrie::from_entries(entries.clone()).unwrap().root()
+        );
+
+        // the last value of a key wins.
+        let repeated: StorageTrie = [(U256::one(), U256::from(5)), (U256::one(), U256::from(6))]
+            .into_iter()
+            .collect();
+        assert_eq!(repeated.get(U256::one()).unwrap(), U256::from(6));
+
+        let accounts: AccountTrie = (1..10u64)
+            .map(|i| {
+                let account = AccountData {
+                    balance: U256::from(i),
+                    ..Default::default()
+                };
+                (Address::from_low_u64_be(i), account)
+            })
+            .collect();
+        assert_eq!(
+            accounts.get(Address::from_low_u64_be(4)).unwrap().balance,
+            U256::from(4)
+        );
+    }
+
+    #[test]
+    pub fn test_trie_set_remove_matches_from_entries() {
+        // large enough for leaves and extensions to be split and collapsed again.
+        let entries = (0..200u64)
+            .map(|i| (U256::from(i * 7), U256::from(i + 1)))
+            .collect::<Vec<_>>();
+        let expected = StorageTrie::from_entries(entries.clone()).unwrap();
+
+        let mut forward = StorageTrie::empty();
+        for (key, value) in entries.iter() {
+            forward.set(*key, *value).unwrap();
+        }
+        let mut backward = StorageTrie::empty();
+        for (key, value) in entries.iter().rev() {
+            backward.set(*key, *value).unwrap();
+        }
+        assert_eq!(forward.root(), expected.root());
+        assert_eq!(backward.root(), expected.root());
+
+        for (key, _) in entries.iter().step_by(2) {
+            forward.remove(*key).unwrap();
+        }
+        let rest = entries.iter().skip(1).step_by(2).cloned();
+        assert_eq!(
+            forward.root(),
+            StorageTrie::from_entries(rest).unwrap().root()
+        );
+        // removed nodes don't stay around.
+        assert_eq!(
+            forward.witness().unwrap(),
+            StorageTrie::from_entries(entries.iter().skip(1).step_by(2).cloned())
+                .unwrap()
+                .witness()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_trie_get_proof_through_extensions() {
+        // enough keys for extension nodes below the first branches.
+        let entries = (0..200u64)
+            .map(|i| (U256::from(i), U256::from(i + 1)))
+            .collect::<Vec<_>>();
+        let full = StorageTrie::from_entries(entries.clone()).unwrap();
+        assert!(full
+            .nodes
+            .iter()
+            .any(|(_, node_data)| matches!(node_data, NodeData::Extension { .. })));
+
+        let mut partial = StorageTrie::from_root(full.root().unwrap());
+        for (key, value) in entries {
+            partial
+                .load_proof(key, value, full.get_proof(key).unwrap())
+                .unwrap();
+        }
+        assert_eq!(partial.get(U256::from(7)).unwrap(), U256::from(8));
+        assert!(full.get_proof(U256::from(1000)).is_ok());
+    }
+
+    #[test]
+    pub fn test_trie_subtree_root() {
+        let entries = (0..300u64)
+            .map(|i| (U256::from(i), U256::from(i + 1)))
+            .collect::<Vec<_>>();
+        let full = StorageTrie::from_entries(entries.clone()).unwrap();
+        let mut paths = entries
+            .iter()
+            .map(|(key, value)| (key.to_nibbles().unwrap().to_u4_vec(), *value))
+            .collect::<Vec<_>>();
+        paths.sort();
+        // the root of a trie over the keys under the prefix, with the prefix cut off.
+        let expected = |prefix: &[u8]| {
+            let stripped = paths
+                .iter()
+                .filter(|(path, _)| path.starts_with(prefix))
+                .map(|(path, value)| (path[prefix.len()..].to_vec(), *value))
+                .collect::<Vec<_>>();
+            StorageTrie::build_sorted(stripped).unwrap().root().unwrap()
+        };
+
+        let key_path = entries[42].0.to_nibbles().unwrap().to_u4_vec();
+        for len in [0, 1, 2, 3, 5, 10, 64] {
+            assert_eq!(
+                full.subtree_root(&key_path[..len]).unwrap(),
+                expected(&key_path[..len])
+            );
+        }
+        assert_eq!(full.subtree_root(&[]).unwrap(), full.root().unwrap());
+        let mut missing = key_path[..10].to_vec();
+        missing[9] = (missing[9] + 1) % 16;
+        assert_eq!(full.subtree_root(&missing).unwrap(), expected(&missing));
+        assert!(full.subtree_root(&[16]).is_err());
+
+        // a root from the first nibble's subtrees, like 16 workers would compute them.
+        let mut arr = [None; 17];
+        for nibble in 0..16u8 {
+            let root = full.subtree_root(&[nibble]).unwrap();
+            assert_eq!(root, expected(&[nibble]));
+            arr[nibble as usize] = Some(root);
+        }
+        assert_eq!(
+            NodeData::<U256>::Branch(arr)
+                .hash::<KeccakHasher>()
+                .unwrap(),
+            full.root().unwrap()
+        );
+
+        // only the nodes on the path are needed.
+        let mut partial = StorageTrie::from_root(full.root().unwrap());
+        partial
+            .load_proof(
+                entries[42].0,
+                entries[42].1,
+                full.get_proof(entries[42].0).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            partial.subtree_root(&key_path[..3]).unwrap(),
+            expected(&key_path[..3])
+        );
+    }
+
+    #[test]
+    pub fn test_trie_insert_raw_node() {
+        let entries = (0..50u64)
+            .map(|i| (U256::from(i), U256::from(i + 1)))
+            .collect::<Vec<_>>();
+        let full = StorageTrie::from_entries(entries).unwrap();
+        let proof = full.get_proof(U256::from(7)).unwrap();
+
+        // from the root down, every node is a child of the one before.
+        let mut partial = StorageTrie::from_root(full.root().unwrap());
+        for raw in proof.iter() {
+            let hash = partial
+                .insert_raw_node(raw.clone(), NodeVerification::Verify)
+                .unwrap();
+            assert_eq!(hash, H256::from(keccak256(raw)));
+        }
+        assert_eq!(partial.get(U256::from(7)).unwrap(), U256::from(8));
+
+        // a leaf without its branch can only be trusted.
+        let mut partial = StorageTrie::from_root(full.root().unwrap());
+        let leaf = proof.last().unwrap().clone();
+        assert!(partial
+            .insert_raw_node(leaf.clone(), NodeVerification::Verify)
+            .is_err());
+        partial
+            .insert_raw_node(leaf, NodeVerification::Trust)
+            .unwrap();
+        assert!(partial.get(U256::from(7)).is_err());
+        for raw in proof.iter().rev().skip(1) {
+            partial
+                .insert_raw_node(raw.clone(), NodeVerification::Trust)
+                .unwrap();
+        }
+        assert_eq!(partial.get(U256::from(7)).unwrap(), U256::from(8));
+    }
+
+    #[test]
+    pub fn test_trie_custom_hasher() {
+        let mut trie = Trie::<u64, u64, PrefixedKeccakHasher>::empty();
+        let mut keccak_trie = Trie::<u64, u64>::empty();
+        assert_eq!(trie.root.unwrap(), PrefixedKeccakHasher::empty_root());
+
+        for key in [2, 5, 7] {
+            trie.set(key, key).unwrap();
+            keccak_trie.set(key, key).unwrap();
+        }
+        assert_ne!(trie.root, keccak_trie.root);
+        assert_eq!(trie.get(5).unwrap(), 5);
+
+        // proofs are verified using the same hasher.
+        let mut loaded = Trie::<u64, u64, PrefixedKeccakHasher>::from_root(trie.root.unwrap());
+        let root_node = trie
+            .nodes
+            .get(&trie.root.unwrap())
+            .unwrap()
+            .to_raw_rlp()
+            .unwrap();
+        loaded.load_proof(9, 0, vec![root_node]).unwrap();
+
+        // and so are the nodes of its witness.
+        let witness =
+            Witness::<PrefixedKeccakHasher>::decode_with_hasher(trie.witness().unwrap().encode())
+                .unwrap();
+        let mut loaded = Trie::<u64, u64, PrefixedKeccakHasher>::from_root(trie.root.unwrap());
+        loaded.load_raw_nodes(&witness.node_map()).unwrap();
+        assert_eq!(loaded.get(7).unwrap(), 7);
+
+        for key in [2, 5, 7] {
+            trie.remove(key).unwrap();
+        }
+        assert_eq!(trie.root.unwrap(), PrefixedKeccakHasher::empty_root());
+    }
+
+    #[test]
+    pub fn test_intern_values() {
+        let mut full = Trie::<u64, Bytes>::empty();
+        for i in 1..=100u64 {
+            let value = Bytes::from(vec![(i % 3) as u8 + 1; 40]);
+            full.set(i, value).unwrap();
+        }
+        let raw_nodes: std::collections::HashMap<H256, Bytes> = full
+            .raw_nodes()
+            .unwrap()
+            .into_iter()
+            .map(|raw| (H256::from(keccak256(&raw)), raw))
+            .collect();
+
+        let mut trie = Trie::<u64, Bytes>::from_root(full.root().unwrap());
+        trie.intern_values();
+        trie.load_raw_nodes(&raw_nodes).unwrap();
+        assert_eq!(trie.interned_values(), 3);
+        // one allocation for equal values.
+        assert_eq!(trie.get(3).unwrap().as_ptr(), trie.get(6).unwrap().as_ptr());
+        assert_ne!(trie.get(3).unwrap().as_ptr(), trie.get(4).unwrap().as_ptr());
+        assert_eq!(trie.get(4).unwrap(), full.get(4).unwrap());
+        assert_eq!(Trie::<u64, Bytes>::empty().interned_values(), 0);
+    }
+
+    #[test]
+    pub fn test_trie_history() {
+        let mut trie =
+            StorageTrie::from_entries((1..50u64).map(|i| (U256::from(i), U256::from(i)))).unwrap();
+        assert!(trie.commit_version().is_err());
+        trie.start_history();
+        let first = trie.commit_version().unwrap();
+
+        trie.set(U256::from(7), U256::from(700)).unwrap();
+        trie.remove(U256::from(8)).unwrap();
+        let second = trie.commit_version().unwrap();
+        trie.set(U256::from(7), U256::from(7000)).unwrap();
+        trie.set(U256::from(100), U256::one()).unwrap();
+        assert_eq!(trie.versions(), &[first, second]);
+
+        assert_eq!(trie.get_at(first, U256::from(7)).unwrap(), U256::from(7));
+        assert_eq!(trie.get_at(first, U256::from(8)).unwrap(), U256::from(8));
+        assert_eq!(trie.get_at(second, U256::from(7)).unwrap(), U256::from(700));
+        assert_eq!(trie.get_at(second, U256::from(8)).unwrap(), U256::zero());
+        assert_eq!(trie.get(U256::from(7)).unwrap(), U256::from(7000));
+        assert_eq!(
+            trie.get_at(trie.root().unwrap(), U256::from(100)).unwrap(),
+            U256::one()
+        );
+        assert!(trie.get_at(H256::repeat_byte(1), U256::from(7)).is_err());
+
+        // proofs against an old root verify as they did then.
+        let mut old = StorageTrie::from_root(first);
+        old.load_proof(
+            U256::from(8),
+            U256::from(8),
+            trie.get_proof_at(first, U256::from(8)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(old.get(U256::from(8)).unwrap(), U256::from(8));
+    }
 }
@@ -0,0 +1,136 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::H256;
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nodes::LeafValue,
+    trie::{MptKey, Trie},
+    Error,
+};
+
+// one place for the ways to start a trie, instead of a constructor for every combination.
+// keys are hashed or not depending on K's MptKey (Address and U256 are), so there's no
+// secure flag, and nodes only live in memory so there's no backend to choose either.
+#[derive(Clone, Debug)]
+pub struct TrieBuilder<K: MptKey, V: LeafValue, H: TrieHasher = KeccakHasher> {
+    root: Option<H256>,
+    entries: Option<Vec<(K, V)>>,
+    _hasher: PhantomData<H>,
+}
+
+impl<K: MptKey, V: LeafValue> TrieBuilder<K, V> {
+    pub fn new() -> Self {
+        TrieBuilder {
+            root: None,
+            entries: None,
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<K: MptKey, V: LeafValue> Default for TrieBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> TrieBuilder<K, V, H> {
+    // keccak unless set, everything else set so far is kept.
+    pub fn hasher<H2: TrieHasher>(self) -> TrieBuilder<K, V, H2> {
+        TrieBuilder {
+            root: self.root,
+            entries: self.entries,
+            _hasher: PhantomData,
+        }
+    }
+
+    // root of a trie to load proofs into. with entries, the root they have to hash to.
+    pub fn root(mut self, root: H256) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    // builds the complete trie from these entries, see Trie::from_entries.
+    pub fn entries(mut self, entries: impl IntoIterator<Item = (K, V)>) -> Self {
+        self.entries = Some(entries.into_iter().collect());
+        self
+    }
+
+    // an empty trie if neither a root nor entries are set.
+    pub fn build(self) -> Result<Trie<K, V, H>, Error> {
+        match (self.entries, self.root) {
+            (Some(entries), root) => {
+                let trie = Trie::from_entries(entries)?;
+                if root.is_some() && trie.root() != root {
+                    return Err(Error::InternalError(
+                        "entries don't match the expected root",
+                    ));
+                }
+                Ok(trie)
+            }
+            (None, Some(root)) => Ok(Trie::from_root(root)),
+            (None, None) => Ok(Trie::empty()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrieBuilder;
+    use crate::state_trie::StorageTrie;
+    use ethers_core::types::{H256, U256};
+
+    #[test]
+    pub fn test_trie_builder() {
+        let entries: Vec<(U256, U256)> = (1..20u64)
+            .map(|i| (U256::from(i), U256::from(i * 2)))
+            .collect();
+        let expected = StorageTrie::from_entries(entries.clone()).unwrap();
+
+        let trie = TrieBuilder::<U256, U256>::new()
+            .entries(entries.clone())
+            .build()
+            .unwrap();
+        assert_eq!(trie.root(), expected.root());
+        assert_eq!(
+            TrieBuilder::<U256, U256>::new().build().unwrap().root(),
+            StorageTrie::empty().root()
+        );
+
+        let root = expected.root().unwrap();
+        let from_root = TrieBuilder::<U256, U256>::new().root(root).build().unwrap();
+        assert_eq!(from_root, StorageTrie::from_root(root));
+
+        // entries checked against the root.
+        assert!(TrieBuilder::<U256, U256>::new()
+            .entries(entries.clone())
+            .root(root)
+            .build()
+            .is_ok());
+        assert!(TrieBuilder::<U256, U256>::new()
+            .entries(entries)
+            .root(H256::repeat_byte(1))
+            .build()
+            .is_err());
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    pub fn test_trie_builder_hasher() {
+        use crate::{trie::Trie, Blake3Hasher};
+
+        let entries = vec![(U256::one(), U256::from(2))];
+        let trie = TrieBuilder::<U256, U256>::new()
+            .hasher::<Blake3Hasher>()
+            .entries(entries.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            trie.root(),
+            Trie::<U256, U256, Blake3Hasher>::from_entries(entries)
+                .unwrap()
+                .root()
+        );
+    }
+}
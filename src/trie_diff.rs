@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use ethers_core::types::{Bytes, H256};
+
+use crate::{
+    hasher::TrieHasher,
+    nibbles::Nibbles,
+    nodes::{LeafValue, NodeData},
+    trie::{MptKey, Trie},
+    Error,
+};
+
+// a leaf which is different between two tries. keys are the raw paths, i.e. the hashed key
+// for account and storage tries.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrieChange<V> {
+    Added { key: Bytes, value: V },
+    Removed { key: Bytes, value: V },
+    Changed { key: Bytes, old: V, new: V },
+}
+
+impl<V> TrieChange<V> {
+    pub fn key(&self) -> &Bytes {
+        match self {
+            TrieChange::Added { key, .. } => key,
+            TrieChange::Removed { key, .. } => key,
+            TrieChange::Changed { key, .. } => key,
+        }
+    }
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Trie<K, V, H> {
+    fn diff_node(&self, hash: &H256) -> Result<Option<&NodeData<V>>, Error> {
+        if *hash == H::empty_root() {
+            return Ok(None);
+        }
+        self.node(hash)
+            .map(Some)
+            .ok_or(Error::MissingData("diff needs a node which is not loaded"))
+    }
+
+    // every leaf below `hash`, by full nibble path.
+    fn diff_leaves(
+        &self,
+        hash: H256,
+        path: Vec<u8>,
+        leaves: &mut BTreeMap<Vec<u8>, V>,
+    ) -> Result<(), Error> {
+        match self.diff_node(&hash)? {
+            None => {}
+            Some(NodeData::Leaf { key, value }) => {
+                let mut full = path;
+                full.extend(key.to_u4_vec());
+                leaves.insert(full, value.to_owned());
+            }
+            Some(NodeData::Branch(children)) => {
+                for (nibble, child) in children.iter().enumerate() {
+                    if let Some(child) = child {
+                        let mut child_path = path.clone();
+                        child_path.push(nibble as u8);
+                        self.diff_leaves(*child, child_path, leaves)?;
+                    }
+                }
+            }
+            Some(NodeData::Extension { key, node }) => {
+                let mut child_path = path;
+                child_path.extend(key.to_u4_vec());
+                self.diff_leaves(*node, child_path, leaves)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn diff_from(
+        &self,
+        original: &Self,
+        ours: H256,
+        theirs: H256,
+        path: Vec<u8>,
+        changes: &mut Vec<TrieChange<V>>,
+    ) -> Result<(), Error> {
+        // same hash, same everything below, no need to look.
+        if ours == theirs {
+            return Ok(());
+        }
+        match (self.diff_node(&ours)?, original.diff_node(&theirs)?) {
+            (Some(NodeData::Branch(our_children)), Some(NodeData::Branch(their_children))) => {
+                for nibble in 0..16 {
+                    let our_child = our_children[nibble].unwrap_or(H::empty_root());
+                    let their_child = their_children[nibble].unwrap_or(H::empty_root());
+                    let mut child_path = path.clone();
+                    child_path.push(nibble as u8);
+                    self.diff_from(original, our_child, their_child, child_path, changes)?;
+                }
+                Ok(())
+            }
+            (
+                Some(NodeData::Extension {
+                    key: our_key,
+                    node: our_child,
+                }),
+                Some(NodeData::Extension {
+                    key: their_key,
+                    node: their_child,
+                }),
+            ) if our_key == their_key => {
+                let mut child_path = path;
+                child_path.extend(our_key.to_u4_vec());
+                self.diff_from(original, *our_child, *their_child, child_path, changes)
+            }
+            // the shapes differ here, compare the leaves of both sides. that's only the
+            // subtree which changed, e.g. a leaf which became a branch.
+            _ => {
+                let mut our_leaves = BTreeMap::new();
+                self.diff_leaves(ours, path.clone(), &mut our_leaves)?;
+                let mut their_leaves = BTreeMap::new();
+                original.diff_leaves(theirs, path, &mut their_leaves)?;
+
+                let mut subtree_changes = Vec::new();
+                for (full, value) in our_leaves.iter() {
+                    let key = Nibbles::from_u4_vec(full.to_owned())?.to_raw_path();
+                    match their_leaves.get(full) {
+                        None => subtree_changes.push(TrieChange::Added {
+                            key,
+                            value: value.to_owned(),
+                        }),
+                        Some(old) if old != value => subtree_changes.push(TrieChange::Changed {
+                            key,
+                            old: old.to_owned(),
+                            new: value.to_owned(),
+                        }),
+                        _ => {}
+                    }
+                }
+                for (full, value) in their_leaves {
+                    if !our_leaves.contains_key(&full) {
+                        subtree_changes.push(TrieChange::Removed {
+                            key: Nibbles::from_u4_vec(full)?.to_raw_path(),
+                            value,
+                        });
+                    }
+                }
+                subtree_changes.sort_by(|a, b| a.key().cmp(b.key()));
+                changes.extend(subtree_changes);
+                Ok(())
+            }
+        }
+    }
+
+    // leaves that differ from original, sorted by key. descends only where the child hashes
+    // differ, so two witnesses of consecutive blocks cost about the size of the change and
+    // the unchanged parts don't even need to be loaded. the changed parts do, on both sides.
+    pub fn diff(&self, original: &Self) -> Result<Vec<TrieChange<V>>, Error> {
+        let ours = self.root().ok_or(Error::Usage("root not set"))?;
+        let theirs = original.root().ok_or(Error::Usage("root not set"))?;
+        let mut changes = Vec::new();
+        self.diff_from(original, ours, theirs, vec![], &mut changes)?;
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrieChange;
+    use crate::{
+        state_trie::{AccountData, AccountTrie},
+        StateTrie,
+    };
+    use ethers_core::{
+        types::{Address, Bytes, U256},
+        utils::keccak256,
+    };
+
+    fn key(address: Address) -> Bytes {
+        Bytes::from(keccak256(address).to_vec())
+    }
+
+    #[test]
+    pub fn test_trie_diff() {
+        let mut original = StateTrie::new();
+        for i in 1..200u64 {
+            original
+                .account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        let mut updated = original.account_trie.clone();
+        assert!(updated.diff(&original.account_trie).unwrap().is_empty());
+
+        let changed = Address::from_low_u64_be(5);
+        let added = Address::from_low_u64_be(500);
+        let removed = Address::from_low_u64_be(7);
+        updated.set_balance(changed, U256::from(50)).unwrap();
+        updated.set_balance(added, U256::one()).unwrap();
+        updated.remove(removed).unwrap();
+
+        let mut expected = vec![
+            TrieChange::Changed {
+                key: key(changed),
+                old: AccountData {
+                    balance: U256::from(5),
+                    ..Default::default()
+                },
+                new: AccountData {
+                    balance: U256::from(50),
+                    ..Default::default()
+                },
+            },
+            TrieChange::Added {
+                key: key(added),
+                value: AccountData {
+                    balance: U256::one(),
+                    ..Default::default()
+                },
+            },
+            TrieChange::Removed {
+                key: key(removed),
+                value: AccountData {
+                    balance: U256::from(7),
+                    ..Default::default()
+                },
+            },
+        ];
+        expected.sort_by(|a, b| a.key().cmp(b.key()));
+        assert_eq!(updated.diff(&original.account_trie).unwrap(), expected);
+    }
+
+    #[test]
+    pub fn test_trie_diff_skips_unchanged_subtrees() {
+        let mut full = StateTrie::new();
+        for i in 1..200u64 {
+            full.account_trie
+                .set_balance(Address::from_low_u64_be(i), U256::from(i))
+                .unwrap();
+        }
+        // two partial tries with only the path to one account, the rest isn't loaded.
+        let address = Address::from_low_u64_be(3);
+        let root = full.root().unwrap();
+        let proof = full.account_trie.get_proof(address).unwrap();
+        let mut before = AccountTrie::from_root(root);
+        before
+            .load_proof(address, full.account_trie.get(address).unwrap(), proof)
+            .unwrap();
+        let mut after = before.clone();
+        after.set_nonce(address, U256::one()).unwrap();
+
+        let changes = after.diff(&before).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].key(), &key(address));
+
+        // a change below an unloaded node can't be diffed.
+        let mut other = full.account_trie.clone();
+        other
+            .set_balance(Address::from_low_u64_be(300), U256::one())
+            .unwrap();
+        assert!(other.diff(&before).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use bytes::BytesMut;
+use ethers_core::{
+    types::{Bytes, H256, U256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    nodes::{LeafValue, NodeData},
+    trie::{MptKey, Trie},
+    witness::sorted_by_hash,
+    Error, StateTrie, Witness,
+};
+
+// tries of the same key and value types over one node store, each under its own root in a
+// namespace, like the storage tries of the accounts by their hashed address. a node many of
+// them have is stored once. writes never remove nodes, another namespace can still have them,
+// see prune.
+#[derive(Clone, Debug)]
+pub struct TrieStore<K: MptKey, V: LeafValue, H: TrieHasher = KeccakHasher> {
+    // the nodes of every namespace. its root is the one of the last write or proof.
+    trie: Trie<K, V, H>,
+    roots: BTreeMap<H256, H256>,
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> Default for TrieStore<K, V, H> {
+    fn default() -> Self {
+        let mut trie = Trie::empty();
+        // replaced nodes are kept, like for the versions of one trie.
+        trie.start_history();
+        TrieStore {
+            trie,
+            roots: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: MptKey, V: LeafValue, H: TrieHasher> TrieStore<K, V, H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // starts a namespace at the root, or moves it there. nothing of it is loaded until a proof
+    // for it is, unless the store has the nodes already.
+    pub fn set_root(&mut self, namespace: H256, root: H256) {
+        self.roots.insert(namespace, root);
+    }
+
+    // a namespace nothing was written to or loaded for is the empty trie.
+    pub fn root(&self, namespace: H256) -> H256 {
+        self.roots
+            .get(&namespace)
+            .copied()
+            .unwrap_or_else(H::empty_root)
+    }
+
+    // namespaces with their roots, ordered by namespace.
+    pub fn roots(&self) -> &BTreeMap<H256, H256> {
+        &self.roots
+    }
+
+    pub fn get(&self, namespace: H256, key: K) -> Result<V, Error> {
+        self.trie.get_from(self.root(namespace), key)
+    }
+
+    pub fn get_proof(&self, namespace: H256, key: K) -> Result<Vec<Bytes>, Error> {
+        self.trie.get_proof_from(self.root(namespace), key)
+    }
+
+    pub fn set(&mut self, namespace: H256, key: K, value: V) -> Result<(), Error> {
+        self.with_namespace(namespace, |trie| trie.set(key, value))
+    }
+
+    pub fn load_proof(
+        &mut self,
+        namespace: H256,
+        key: K,
+        value: V,
+        proof: Vec<Bytes>,
+    ) -> Result<(), Error> {
+        self.with_namespace(namespace, |trie| trie.load_proof(key, value, proof))
+    }
+
+    // runs f on the trie at the namespace's root and keeps the root it ends at.
+    fn with_namespace(
+        &mut self,
+        namespace: H256,
+        f: impl FnOnce(&mut Trie<K, V, H>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.trie.set_root(self.root(namespace));
+        f(&mut self.trie)?;
+        self.trie.take_touched();
+        let root = self.trie.root().ok_or(Error::Usage("root not set"))?;
+        self.roots.insert(namespace, root);
+        Ok(())
+    }
+
+    // hashes of the nodes no namespace reaches anymore, e.g. the ones writes replaced.
+    pub fn prune_dry_run(&self) -> Vec<H256> {
+        self.trie
+            .unreachable_from(self.roots.values().copied().collect())
+    }
+
+    pub fn prune(&mut self) -> Vec<H256> {
+        let unreachable = self.prune_dry_run();
+        self.trie.remove_nodes(&unreachable);
+        unreachable
+    }
+
+    // the loaded nodes of one namespace.
+    pub fn witness(&self, namespace: H256) -> Result<Witness<H>, Error> {
+        let root = self.root(namespace);
+        Ok(Witness::with_hasher(
+            root,
+            self.reachable_nodes(vec![root])?,
+        ))
+    }
+
+    fn reachable_nodes(&self, roots: Vec<H256>) -> Result<Vec<Bytes>, Error> {
+        self.trie
+            .reachable_from(roots)
+            .iter()
+            .filter_map(|hash| self.trie.node(hash))
+            .map(|node_data| node_data.to_raw_rlp())
+            .collect()
+    }
+
+    // every namespace in one file, rlp([[[namespace, root], ...], [node_0, ...]]), with the
+    // nodes of all of them de-duplicated and sorted by hash like in a witness.
+    pub fn encode(&self) -> Result<Bytes, Error> {
+        let nodes =
+            sorted_by_hash::<H>(self.reachable_nodes(self.roots.values().copied().collect())?);
+        let mut rlp_stream = RlpStream::new_list(2);
+        rlp_stream.begin_list(self.roots.len());
+        for (namespace, root) in self.roots.iter() {
+            rlp_stream.begin_list(2);
+            rlp_stream.append(namespace);
+            rlp_stream.append(root);
+        }
+        rlp_stream.begin_list(nodes.len());
+        for node in nodes.iter() {
+            rlp_stream.append(&BytesMut::from(node.to_vec().as_slice()));
+        }
+        Ok(Bytes::from(rlp_stream.out().to_vec()))
+    }
+
+    // nodes have to be canonical and match their hashes, the roots are taken as they are.
+    pub fn decode(raw: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(raw);
+        if rlp.item_count()? != 2 {
+            return Err(Error::InternalError("trie store should have two fields"));
+        }
+        let mut store = Self::new();
+        for item in rlp.at(0)?.iter() {
+            store.set_root(item.val_at(0)?, item.val_at(1)?);
+        }
+        for item in rlp.at(1)?.iter() {
+            let raw_node = item.data()?;
+            let hash = store
+                .trie
+                .insert_node(NodeData::from_raw_rlp(Bytes::from(raw_node.to_vec()))?)?;
+            if hash != H::hash(raw_node) {
+                return Err(Error::InternalError("node does not match its hash"));
+            }
+        }
+        Ok(store)
+    }
+}
+
+impl TrieStore<U256, U256> {
+    // the loaded storage tries of the state in one store, each under its hashed address.
+    pub fn from_storage(state_trie: &StateTrie) -> Result<Self, Error> {
+        let mut store = Self::new();
+        for (address_hash, storage_trie) in state_trie.storage_tries_by_hash()? {
+            store.trie.merge_loaded_nodes(storage_trie.to_owned());
+            store.set_root(
+                address_hash,
+                storage_trie.root().ok_or(Error::Usage("root not set"))?,
+            );
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrieStore;
+    use crate::{
+        hasher::{KeccakHasher, TrieHasher},
+        key_cache::hashed_address,
+        state_trie::StorageTrie,
+        StateTrie,
+    };
+    use ethers_core::{
+        types::{Address, H256, U256},
+        utils::rlp::Rlp,
+    };
+
+    #[test]
+    pub fn test_trie_store() {
+        let (a, b) = (H256::repeat_byte(0xa), H256::repeat_byte(0xb));
+        let mut store = TrieStore::<U256, U256>::new();
+        assert_eq!(store.root(a), KeccakHasher::empty_root());
+        for slot in 1..=10u64 {
+            store.set(a, U256::from(slot), U256::from(slot)).unwrap();
+            store.set(b, U256::from(slot), U256::from(slot)).unwrap();
+        }
+        // the same data in both, the nodes are shared.
+        assert_eq!(store.root(a), store.root(b));
+        let pruned = store.prune();
+        assert!(!pruned.is_empty());
+
+        // a write to one namespace leaves the other as it was.
+        let root_b = store.root(b);
+        store.set(a, U256::from(3), U256::from(33)).unwrap();
+        store.set(a, U256::from(11), U256::from(11)).unwrap();
+        assert_eq!(store.get(a, U256::from(3)).unwrap(), U256::from(33));
+        assert_eq!(store.get(b, U256::from(3)).unwrap(), U256::from(3));
+        assert_eq!(store.root(b), root_b);
+        store.prune();
+        assert_eq!(store.get(b, U256::from(7)).unwrap(), U256::from(7));
+
+        let mut expected = StorageTrie::empty();
+        for slot in 1..=11u64 {
+            let value = if slot == 3 { 33 } else { slot };
+            expected.set(U256::from(slot), U256::from(value)).unwrap();
+        }
+        assert_eq!(Some(store.root(a)), expected.root());
+        assert_eq!(
+            store.get_proof(a, U256::from(5)).unwrap(),
+            expected.get_proof(U256::from(5)).unwrap()
+        );
+
+        // one file for both, fewer nodes than their two witnesses.
+        let encoded = store.encode().unwrap();
+        let decoded = TrieStore::<U256, U256>::decode(&encoded).unwrap();
+        assert_eq!(decoded.roots(), store.roots());
+        assert_eq!(decoded.encode().unwrap(), encoded);
+        assert_eq!(decoded.get(a, U256::from(11)).unwrap(), U256::from(11));
+        let witness_nodes = store.witness(a).unwrap().len() + store.witness(b).unwrap().len();
+        assert!(Rlp::new(&encoded).at(1).unwrap().item_count().unwrap() < witness_nodes);
+
+        // proofs go to their namespace.
+        let mut partial = TrieStore::<U256, U256>::new();
+        partial.set_root(b, root_b);
+        partial
+            .load_proof(
+                b,
+                U256::from(2),
+                U256::from(2),
+                store.get_proof(b, U256::from(2)).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(partial.get(b, U256::from(2)).unwrap(), U256::from(2));
+        assert!(partial.get(b, U256::from(4)).is_err());
+        assert_eq!(partial.root(b), root_b);
+    }
+
+    #[test]
+    pub fn test_trie_store_from_storage() {
+        let mut state_trie = StateTrie::new();
+        for i in 1..=3u64 {
+            state_trie
+                .set_storage_value(Address::from_low_u64_be(i), U256::one(), U256::from(i))
+                .unwrap();
+        }
+        let store = TrieStore::from_storage(&state_trie).unwrap();
+        assert_eq!(store.roots().len(), 3);
+        for i in 1..=3u64 {
+            let address = Address::from_low_u64_be(i);
+            assert_eq!(
+                store.root(hashed_address(address)),
+                state_trie.get_storage_root(address).unwrap()
+            );
+            assert_eq!(
+                store.get(hashed_address(address), U256::one()).unwrap(),
+                U256::from(i)
+            );
+        }
+    }
+}
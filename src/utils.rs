@@ -1,57 +1,23 @@
-#[derive(Clone, Debug)]
-pub struct ConsecutiveList<T> {
-    current_index: usize,
-    list: Vec<T>,
-}
-
-impl<T: Clone> ConsecutiveList<T> {
-    pub fn new(initial_value: T) -> Self {
-        Self {
-            current_index: 0,
-            list: vec![initial_value],
-        }
-    }
-
-    pub fn current(&self) -> T {
-        self.list[self.current_index].clone()
-    }
-
-    pub fn set_next(&mut self, val: T) {
-        self.list.push(val);
-    }
-
-    pub fn go_next(&mut self) -> bool {
-        if self.list.len() == self.current_index + 1 {
-            false
-        } else {
-            self.current_index += 1;
-            true
-        }
-    }
+use ethers_core::types::H256;
 
-    pub fn go_back(&mut self) -> bool {
-        if self.list.len() == 1 {
-            false
-        } else {
-            self.current_index -= 1;
-            true
-        }
-    }
+use crate::Error;
 
-    #[allow(dead_code)]
-    pub fn next(&self) -> Option<&T> {
-        if self.list.len() < self.current_index + 1 {
-            None
-        } else {
-            Some(&self.list[self.current_index])
-        }
-    }
+pub(crate) fn hex_nibbles(nibbles: &[u8]) -> String {
+    nibbles
+        .iter()
+        .map(|nibble| format!("{:x}", nibble))
+        .collect()
+}
 
-    pub fn prev(&self) -> Option<&T> {
-        if self.current_index == 0 {
-            None
-        } else {
-            Some(&self.list[self.current_index - 1])
-        }
+// full 64 nibble path of a leaf back to the hashed key.
+pub(crate) fn nibbles_to_hash(nibbles: &[u8]) -> Result<H256, Error> {
+    if nibbles.len() != 64 {
+        return Err(Error::InternalError("leaf path should be 64 nibbles"));
     }
+    Ok(H256::from_slice(
+        &nibbles
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect::<Vec<_>>(),
+    ))
 }
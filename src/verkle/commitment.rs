@@ -0,0 +1,32 @@
+use std::fmt::Debug;
+
+use ethers_core::{types::H256, utils::keccak256};
+
+// commits to a vector of 32 byte values. real verkle trees use pedersen commitments over
+// banderwagon with ipa proofs, this trait is where such a scheme plugs in.
+pub trait VerkleCommitter: Clone + Debug + Default + PartialEq {
+    fn commit(values: &[H256]) -> H256;
+
+    // hash used to derive tree keys (stems) from addresses and tree indexes.
+    fn key_hash(data: &[u8]) -> H256;
+}
+
+// hash based stand-in for the vector commitment. it is NOT a vector commitment (no
+// openings, no aggregation) and roots will not match any real verkle network, it only
+// gives the tree correct structure so the api can be prototyped against.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HashCommitter;
+
+impl VerkleCommitter for HashCommitter {
+    fn commit(values: &[H256]) -> H256 {
+        let mut preimage = Vec::with_capacity(values.len() * 32);
+        for value in values {
+            preimage.extend_from_slice(value.as_bytes());
+        }
+        H256::from(keccak256(preimage))
+    }
+
+    fn key_hash(data: &[u8]) -> H256 {
+        H256::from(keccak256(data))
+    }
+}
@@ -0,0 +1,216 @@
+// experimental verkle (eip-6800) state, kept next to the mpt so the same partial loading
+// model can be tried on it. the commitment scheme is pluggable, see VerkleCommitter.
+mod commitment;
+mod overlay;
+mod tree;
+
+use ethers_core::types::{Address, Bytes, H256, U256};
+
+use crate::Error;
+
+pub use commitment::{HashCommitter, VerkleCommitter};
+pub use overlay::OverlayState;
+pub use tree::{Stem, VerkleNode, VerkleTrie, STEM_LEN, VERKLE_WIDTH};
+
+// sub indexes of the account header stem.
+pub const VERSION_LEAF_KEY: u8 = 0;
+pub const BALANCE_LEAF_KEY: u8 = 1;
+pub const NONCE_LEAF_KEY: u8 = 2;
+pub const CODE_KECCAK_LEAF_KEY: u8 = 3;
+pub const CODE_SIZE_LEAF_KEY: u8 = 4;
+
+// the first 64 storage slots live in the account header stem.
+const HEADER_STORAGE_OFFSET: u64 = 64;
+const CODE_OFFSET: u64 = 128;
+
+pub fn get_tree_key<C: VerkleCommitter>(address: Address, tree_index: U256, sub_index: u8) -> H256 {
+    let mut input = [0u8; 64];
+    input[12..32].copy_from_slice(address.as_bytes());
+    tree_index.to_little_endian(&mut input[32..]);
+
+    let mut key = C::key_hash(&input).to_fixed_bytes();
+    key[STEM_LEN] = sub_index;
+    H256::from(key)
+}
+
+pub fn get_storage_tree_key<C: VerkleCommitter>(address: Address, slot: U256) -> H256 {
+    if slot < U256::from(CODE_OFFSET - HEADER_STORAGE_OFFSET) {
+        get_tree_key::<C>(
+            address,
+            U256::zero(),
+            (HEADER_STORAGE_OFFSET + slot.as_u64()) as u8,
+        )
+    } else {
+        // main storage starts at 256^31 leaves, i.e. tree index 256^30.
+        let tree_index = (U256::one() << 240) + (slot >> 8);
+        get_tree_key::<C>(address, tree_index, (slot.low_u64() & 0xff) as u8)
+    }
+}
+
+// leaf values are little endian in verkle.
+fn u256_to_leaf(value: U256) -> H256 {
+    let mut bytes = [0u8; 32];
+    value.to_little_endian(&mut bytes);
+    H256::from(bytes)
+}
+
+fn leaf_to_u256(leaf: Option<H256>) -> U256 {
+    leaf.map(|leaf| U256::from_little_endian(leaf.as_bytes()))
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct VerkleState<C: VerkleCommitter = HashCommitter> {
+    pub tree: VerkleTrie<C>,
+}
+
+impl<C: VerkleCommitter> VerkleState<C> {
+    pub fn from_commitment(root: H256) -> Self {
+        VerkleState {
+            tree: VerkleTrie::from_commitment(root),
+        }
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.tree.root()
+    }
+
+    pub fn load_proof(
+        &mut self,
+        key: H256,
+        value: Option<H256>,
+        proof: Vec<Bytes>,
+    ) -> Result<(), Error> {
+        self.tree.load_proof(key, value, proof)
+    }
+
+    pub fn get_balance(&self, address: Address) -> Result<U256, Error> {
+        self.get_header_value(address, BALANCE_LEAF_KEY)
+    }
+
+    pub fn set_balance(&mut self, address: Address, new_balance: U256) -> Result<(), Error> {
+        self.set_header_value(address, BALANCE_LEAF_KEY, new_balance)
+    }
+
+    pub fn get_nonce(&self, address: Address) -> Result<U256, Error> {
+        self.get_header_value(address, NONCE_LEAF_KEY)
+    }
+
+    pub fn set_nonce(&mut self, address: Address, new_nonce: U256) -> Result<(), Error> {
+        self.set_header_value(address, NONCE_LEAF_KEY, new_nonce)
+    }
+
+    // code hash is stored as is, it is a hash and not a number.
+    pub fn get_code_hash(&self, address: Address) -> Result<Option<H256>, Error> {
+        self.tree.get(get_tree_key::<C>(
+            address,
+            U256::zero(),
+            CODE_KECCAK_LEAF_KEY,
+        ))
+    }
+
+    pub fn set_code_hash(&mut self, address: Address, new_code_hash: H256) -> Result<(), Error> {
+        self.tree.set(
+            get_tree_key::<C>(address, U256::zero(), CODE_KECCAK_LEAF_KEY),
+            new_code_hash,
+        )
+    }
+
+    pub fn get_storage_at(&self, address: Address, slot: U256) -> Result<U256, Error> {
+        Ok(leaf_to_u256(
+            self.tree.get(get_storage_tree_key::<C>(address, slot))?,
+        ))
+    }
+
+    pub fn set_storage_value(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: U256,
+    ) -> Result<(), Error> {
+        self.tree.set(
+            get_storage_tree_key::<C>(address, slot),
+            u256_to_leaf(value),
+        )
+    }
+
+    fn get_header_value(&self, address: Address, sub_index: u8) -> Result<U256, Error> {
+        Ok(leaf_to_u256(self.tree.get(get_tree_key::<C>(
+            address,
+            U256::zero(),
+            sub_index,
+        ))?))
+    }
+
+    fn set_header_value(
+        &mut self,
+        address: Address,
+        sub_index: u8,
+        value: U256,
+    ) -> Result<(), Error> {
+        self.tree.set(
+            get_tree_key::<C>(address, U256::zero(), sub_index),
+            u256_to_leaf(value),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_storage_tree_key, get_tree_key, HashCommitter, VerkleState, BALANCE_LEAF_KEY, STEM_LEN,
+    };
+    use ethers_core::types::{Address, H256, U256};
+
+    fn address() -> Address {
+        "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    pub fn test_verkle_tree_key_layout() {
+        let header = get_tree_key::<HashCommitter>(address(), U256::zero(), BALANCE_LEAF_KEY);
+        let slot_0 = get_storage_tree_key::<HashCommitter>(address(), U256::zero());
+        let slot_big = get_storage_tree_key::<HashCommitter>(address(), U256::from(1000));
+
+        // first storage slots share the stem with the account header.
+        assert_eq!(header[..STEM_LEN], slot_0[..STEM_LEN]);
+        assert_eq!(slot_0[STEM_LEN], 64);
+        assert_ne!(header[..STEM_LEN], slot_big[..STEM_LEN]);
+        assert_eq!(slot_big[STEM_LEN], (1000 % 256) as u8);
+    }
+
+    #[test]
+    pub fn test_verkle_state_partial_update() {
+        let mut full = VerkleState::<HashCommitter>::from_commitment(H256::zero());
+        full.set_balance(address(), U256::from(10)).unwrap();
+        full.set_nonce(address(), U256::from(1)).unwrap();
+        full.set_storage_value(address(), U256::from(1000), U256::from(7))
+            .unwrap();
+        full.set_balance(Address::zero(), U256::from(3)).unwrap();
+
+        let balance_key = get_tree_key::<HashCommitter>(address(), U256::zero(), BALANCE_LEAF_KEY);
+        let mut partial = VerkleState::<HashCommitter>::from_commitment(full.root().unwrap());
+        partial
+            .load_proof(
+                balance_key,
+                full.tree.get(balance_key).unwrap(),
+                full.tree.get_proof(balance_key).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(partial.get_balance(address()).unwrap(), U256::from(10));
+        assert_eq!(partial.get_nonce(address()).unwrap(), U256::from(1));
+
+        full.set_balance(address(), U256::from(20)).unwrap();
+        partial.set_balance(address(), U256::from(20)).unwrap();
+        assert_eq!(partial.root(), full.root());
+
+        // storage slot was not loaded.
+        assert!(partial.get_storage_at(address(), U256::from(1000)).is_err());
+        assert_eq!(
+            full.get_storage_at(address(), U256::from(1000)).unwrap(),
+            U256::from(7)
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use ethers_core::types::{Address, H256, U256};
+
+use super::{
+    get_storage_tree_key, get_tree_key, leaf_to_u256, HashCommitter, VerkleCommitter, VerkleState,
+    BALANCE_LEAF_KEY, CODE_KECCAK_LEAF_KEY, NONCE_LEAF_KEY,
+};
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    state_trie::AccountData,
+    Error, StateTrie,
+};
+
+// eip-7612 style transition: the mpt is frozen at the fork and only read, every write goes to
+// the verkle overlay. reads look in the overlay first and fall back to the mpt, leaf by leaf,
+// and the state root is the overlay's. the mpt root stays what it was at the fork.
+#[derive(Clone, Debug)]
+pub struct OverlayState<C: VerkleCommitter = HashCommitter> {
+    pub base: StateTrie,
+    pub overlay: VerkleState<C>,
+}
+
+impl<C: VerkleCommitter> OverlayState<C> {
+    // starts the transition with an empty overlay.
+    pub fn new(base: StateTrie) -> Self {
+        OverlayState {
+            base,
+            overlay: VerkleState::from_commitment(H256::zero()),
+        }
+    }
+
+    // continues a transition whose overlay is partially loaded.
+    pub fn with_overlay(base: StateTrie, overlay: VerkleState<C>) -> Self {
+        OverlayState { base, overlay }
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.overlay.root()
+    }
+
+    pub fn base_root(&self) -> Option<H256> {
+        self.base.root()
+    }
+
+    fn base_account(&self, address: Address) -> Result<AccountData, Error> {
+        self.base.account_trie.get(address)
+    }
+
+    fn header_leaf(&self, address: Address, sub_index: u8) -> Result<Option<H256>, Error> {
+        self.overlay
+            .tree
+            .get(get_tree_key::<C>(address, U256::zero(), sub_index))
+    }
+
+    pub fn get_balance(&self, address: Address) -> Result<U256, Error> {
+        match self.header_leaf(address, BALANCE_LEAF_KEY)? {
+            Some(leaf) => Ok(leaf_to_u256(Some(leaf))),
+            None => Ok(self.base_account(address)?.balance),
+        }
+    }
+
+    pub fn get_nonce(&self, address: Address) -> Result<U256, Error> {
+        match self.header_leaf(address, NONCE_LEAF_KEY)? {
+            Some(leaf) => Ok(leaf_to_u256(Some(leaf))),
+            None => Ok(self.base_account(address)?.nonce),
+        }
+    }
+
+    // None for accounts which exist on neither side.
+    pub fn get_code_hash(&self, address: Address) -> Result<Option<H256>, Error> {
+        if let Some(code_hash) = self.header_leaf(address, CODE_KECCAK_LEAF_KEY)? {
+            return Ok(Some(code_hash));
+        }
+        let account = self.base_account(address)?;
+        Ok((account != AccountData::default()).then_some(account.code_hash))
+    }
+
+    pub fn get_storage_at(&self, address: Address, slot: U256) -> Result<U256, Error> {
+        if let Some(leaf) = self
+            .overlay
+            .tree
+            .get(get_storage_tree_key::<C>(address, slot))?
+        {
+            return Ok(leaf_to_u256(Some(leaf)));
+        }
+        let storage_root = self.base_account(address)?.storage_root;
+        if storage_root == KeccakHasher::empty_root() {
+            return Ok(U256::zero());
+        }
+        self.base
+            .loaded_storage_trie(&storage_root)
+            .ok_or(Error::MissingData("storage trie not present"))?
+            .get(slot)
+    }
+
+    pub fn set_balance(&mut self, address: Address, new_balance: U256) -> Result<(), Error> {
+        self.overlay.set_balance(address, new_balance)
+    }
+
+    pub fn set_nonce(&mut self, address: Address, new_nonce: U256) -> Result<(), Error> {
+        self.overlay.set_nonce(address, new_nonce)
+    }
+
+    pub fn set_code_hash(&mut self, address: Address, new_code_hash: H256) -> Result<(), Error> {
+        self.overlay.set_code_hash(address, new_code_hash)
+    }
+
+    pub fn set_storage_value(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: U256,
+    ) -> Result<(), Error> {
+        self.overlay.set_storage_value(address, slot, value)
+    }
+
+    // what the conversion sweep does for one account: copies its header and the given slots
+    // from the mpt into the overlay, unless the overlay already has a newer value. reads
+    // don't change, the root does.
+    pub fn migrate_account(&mut self, address: Address, slots: &[U256]) -> Result<(), Error> {
+        let account = self.base_account(address)?;
+        if account == AccountData::default() {
+            return Ok(());
+        }
+        if self.header_leaf(address, BALANCE_LEAF_KEY)?.is_none() {
+            self.overlay.set_balance(address, account.balance)?;
+        }
+        if self.header_leaf(address, NONCE_LEAF_KEY)?.is_none() {
+            self.overlay.set_nonce(address, account.nonce)?;
+        }
+        if self.header_leaf(address, CODE_KECCAK_LEAF_KEY)?.is_none() {
+            self.overlay.set_code_hash(address, account.code_hash)?;
+        }
+        for slot in slots {
+            let key = get_storage_tree_key::<C>(address, *slot);
+            if self.overlay.tree.get(key)?.is_some() {
+                continue;
+            }
+            let value = self.get_storage_at(address, *slot)?;
+            if !value.is_zero() {
+                self.overlay.set_storage_value(address, *slot, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OverlayState;
+    use crate::{verkle::HashCommitter, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    fn base() -> StateTrie {
+        let mut state_trie = StateTrie::new();
+        for i in 1..10u64 {
+            let address = Address::from_low_u64_be(i);
+            state_trie
+                .account_trie
+                .set_balance(address, U256::from(i * 100))
+                .unwrap();
+            state_trie
+                .account_trie
+                .set_nonce(address, U256::from(i))
+                .unwrap();
+            state_trie
+                .set_storage_value(address, U256::from(i), U256::from(i * 2))
+                .unwrap();
+        }
+        state_trie
+    }
+
+    #[test]
+    pub fn test_overlay_reads_and_writes() {
+        let base = base();
+        let base_root = base.root();
+        let mut state = OverlayState::<HashCommitter>::new(base);
+        let address = Address::from_low_u64_be(3);
+
+        // everything comes from the mpt at first.
+        assert_eq!(state.get_balance(address).unwrap(), U256::from(300));
+        assert_eq!(
+            state.get_storage_at(address, U256::from(3)).unwrap(),
+            U256::from(6)
+        );
+        assert_eq!(
+            state.get_code_hash(Address::repeat_byte(0xee)).unwrap(),
+            None
+        );
+        assert!(state.get_code_hash(address).unwrap().is_some());
+
+        // writes land in the overlay and shadow the mpt, the mpt doesn't change.
+        state.set_balance(address, U256::from(1)).unwrap();
+        state
+            .set_storage_value(address, U256::from(3), U256::zero())
+            .unwrap();
+        assert_eq!(state.get_balance(address).unwrap(), U256::one());
+        assert_eq!(state.get_nonce(address).unwrap(), U256::from(3));
+        assert_eq!(
+            state.get_storage_at(address, U256::from(3)).unwrap(),
+            U256::zero()
+        );
+        assert_eq!(state.base_root(), base_root);
+        assert_ne!(state.root(), Some(H256::zero()));
+    }
+
+    #[test]
+    pub fn test_overlay_migration() {
+        let mut state = OverlayState::<HashCommitter>::new(base());
+        let address = Address::from_low_u64_be(5);
+        state.set_nonce(address, U256::from(50)).unwrap();
+        state.migrate_account(address, &[U256::from(5)]).unwrap();
+
+        // the newer nonce is kept, the rest is copied over.
+        assert_eq!(state.get_nonce(address).unwrap(), U256::from(50));
+        assert_eq!(state.get_balance(address).unwrap(), U256::from(500));
+        assert_eq!(
+            state.get_storage_at(address, U256::from(5)).unwrap(),
+            U256::from(10)
+        );
+        let migrated = state.overlay.clone();
+
+        // a fresh overlay with the same writes doesn't see the mpt, so its root differs until
+        // the same values are migrated.
+        let mut direct = OverlayState::<HashCommitter>::new(StateTrie::new());
+        direct.set_nonce(address, U256::from(50)).unwrap();
+        assert_ne!(direct.root(), migrated.root());
+        direct.set_balance(address, U256::from(500)).unwrap();
+        direct
+            .set_code_hash(address, state.get_code_hash(address).unwrap().unwrap())
+            .unwrap();
+        direct
+            .set_storage_value(address, U256::from(5), U256::from(10))
+            .unwrap();
+        assert_eq!(direct.root(), migrated.root());
+
+        // accounts which don't exist aren't created.
+        let before = state.root();
+        state
+            .migrate_account(Address::repeat_byte(0xee), &[U256::one()])
+            .unwrap();
+        assert_eq!(state.root(), before);
+    }
+}
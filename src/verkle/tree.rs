@@ -0,0 +1,556 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use bytes::BytesMut;
+use ethers_core::{
+    types::{Bytes, H256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+use super::commitment::{HashCommitter, VerkleCommitter};
+use crate::Error;
+
+pub const VERKLE_WIDTH: usize = 256;
+pub const STEM_LEN: usize = 31;
+
+pub type Stem = [u8; STEM_LEN];
+
+type Children = Box<[Option<H256>; VERKLE_WIDTH]>;
+
+fn stem_of(key: &[u8; 32]) -> Stem {
+    let mut stem = [0u8; STEM_LEN];
+    stem.copy_from_slice(&key[..STEM_LEN]);
+    stem
+}
+
+// byte of the stem an internal node at depth branches on. a stem is only 31 bytes, so deeper
+// internal nodes can't be, e.g. a proof which keeps going.
+fn child_index(key: &[u8; 32], depth: usize) -> Result<usize, Error> {
+    if depth >= STEM_LEN {
+        return Err(Error::InternalError(
+            "internal node is deeper than the stem length",
+        ));
+    }
+    Ok(key[depth] as usize)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerkleNode {
+    // child commitments indexed by one byte of the stem.
+    Internal(Children),
+    // all 256 values that share the same 31 byte stem, indexed by the last key byte.
+    Stem { stem: Stem, values: Children },
+}
+
+fn commit_values<C: VerkleCommitter>(values: &[Option<H256>]) -> H256 {
+    // every value is committed along with a marker, so a zero value differs from no value.
+    let mut inputs = Vec::with_capacity(values.len() * 2);
+    for value in values.iter() {
+        match value {
+            Some(value) => {
+                inputs.push(H256::from_low_u64_be(1));
+                inputs.push(*value);
+            }
+            None => {
+                inputs.push(H256::zero());
+                inputs.push(H256::zero());
+            }
+        }
+    }
+    C::commit(&inputs)
+}
+
+fn append_optional_hash(rlp_stream: &mut RlpStream, entry: &Option<H256>) {
+    let bm = match entry {
+        Some(hash) => BytesMut::from(hash.as_bytes()),
+        None => BytesMut::new(),
+    };
+    rlp_stream.append(&bm);
+}
+
+fn decode_children(rlp: &Rlp) -> Result<Children, Error> {
+    let mut children: Children = Box::new([None; VERKLE_WIDTH]);
+    for (i, child) in children.iter_mut().enumerate() {
+        let value = rlp.at(i)?.data()?.to_owned();
+        *child = match value.len() {
+            32 => Some(H256::from_slice(value.as_slice())),
+            0 => None,
+            _ => {
+                return Err(Error::InternalError(
+                    "invalid commitment length in verkle node",
+                ))
+            }
+        };
+    }
+    Ok(children)
+}
+
+impl VerkleNode {
+    pub fn commitment<C: VerkleCommitter>(&self) -> H256 {
+        match self {
+            VerkleNode::Internal(children) => C::commit(
+                &children
+                    .iter()
+                    .map(|child| child.unwrap_or_default())
+                    .collect::<Vec<_>>(),
+            ),
+            VerkleNode::Stem { stem, values } => {
+                let mut stem_value = [0u8; 32];
+                stem_value[..STEM_LEN].copy_from_slice(stem);
+                // C = commit(1, stem, C1, C2), C1 and C2 commit to lower and upper halves.
+                C::commit(&[
+                    H256::from_low_u64_be(1),
+                    H256::from(stem_value),
+                    commit_values::<C>(&values[..VERKLE_WIDTH / 2]),
+                    commit_values::<C>(&values[VERKLE_WIDTH / 2..]),
+                ])
+            }
+        }
+    }
+
+    pub fn from_raw_rlp(raw: Bytes) -> Result<Self, Error> {
+        let rlp = Rlp::new(&raw);
+        match rlp.item_count()? {
+            VERKLE_WIDTH => Ok(VerkleNode::Internal(decode_children(&rlp)?)),
+            2 => {
+                let stem_bytes = rlp.at(0)?.data()?.to_owned();
+                if stem_bytes.len() != STEM_LEN {
+                    return Err(Error::InternalError("invalid stem length in verkle node"));
+                }
+                let values_rlp = rlp.at(1)?;
+                if values_rlp.item_count()? != VERKLE_WIDTH {
+                    return Err(Error::InternalError("stem node should have 256 values"));
+                }
+                let mut stem = [0u8; STEM_LEN];
+                stem.copy_from_slice(&stem_bytes);
+                Ok(VerkleNode::Stem {
+                    stem,
+                    values: decode_children(&values_rlp)?,
+                })
+            }
+            _ => Err(Error::InternalError("Unknown num_items in verkle node")),
+        }
+    }
+
+    pub fn to_raw_rlp(&self) -> Bytes {
+        let mut rlp_stream = RlpStream::new();
+        match self {
+            VerkleNode::Internal(children) => {
+                rlp_stream.begin_list(VERKLE_WIDTH);
+                for child in children.iter() {
+                    append_optional_hash(&mut rlp_stream, child);
+                }
+            }
+            VerkleNode::Stem { stem, values } => {
+                rlp_stream.begin_list(2);
+                rlp_stream.append(&BytesMut::from(stem.as_slice()));
+                rlp_stream.begin_list(VERKLE_WIDTH);
+                for value in values.iter() {
+                    append_optional_hash(&mut rlp_stream, value);
+                }
+            }
+        }
+        Bytes::from(rlp_stream.out().to_vec())
+    }
+}
+
+// 256-ary verkle tree with 32 byte keys (31 byte stem + 1 byte suffix) and 32 byte values.
+// like the mpt, only the nodes that were loaded through proofs are known and the
+// commitments of everything else are taken as given.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct VerkleTrie<C: VerkleCommitter = HashCommitter> {
+    root: Option<H256>,
+    nodes: HashMap<H256, VerkleNode>,
+    _marker: PhantomData<C>,
+}
+
+impl<C: VerkleCommitter> VerkleTrie<C> {
+    pub fn from_commitment(root: H256) -> Self {
+        VerkleTrie {
+            root: Some(root),
+            nodes: HashMap::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    // commitment of the empty tree is the identity, which serializes to zero.
+    pub fn empty() -> Self {
+        Self::from_commitment(H256::zero())
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.root
+    }
+
+    pub fn get(&self, key: H256) -> Result<Option<H256>, Error> {
+        let key = key.to_fixed_bytes();
+        let mut current = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut depth = 0;
+        loop {
+            if current.is_zero() {
+                return Ok(None);
+            }
+            let node = self
+                .nodes
+                .get(&current)
+                .ok_or(Error::MissingData("node not present, please add a proof"))?;
+            match node {
+                VerkleNode::Internal(children) => match children[child_index(&key, depth)?] {
+                    Some(child) => {
+                        current = child;
+                        depth += 1;
+                    }
+                    None => return Ok(None),
+                },
+                VerkleNode::Stem { stem, values } => {
+                    if *stem == stem_of(&key) {
+                        return Ok(values[key[STEM_LEN] as usize]);
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    pub fn set(&mut self, key: H256, value: H256) -> Result<(), Error> {
+        self.update(key, Some(value))
+    }
+
+    pub fn remove(&mut self, key: H256) -> Result<(), Error> {
+        self.update(key, None)
+    }
+
+    fn update(&mut self, key: H256, value: Option<H256>) -> Result<(), Error> {
+        let root = self.root.ok_or(Error::Usage("root not set"))?;
+        let current = if root.is_zero() { None } else { Some(root) };
+        let new_root = self.update_at(current, 0, &key.to_fixed_bytes(), value)?;
+        self.root = Some(new_root.unwrap_or_default());
+        Ok(())
+    }
+
+    // updates the subtree at commitment and returns its new commitment, None if it is empty now.
+    fn update_at(
+        &mut self,
+        commitment: Option<H256>,
+        depth: usize,
+        key: &[u8; 32],
+        value: Option<H256>,
+    ) -> Result<Option<H256>, Error> {
+        let commitment = match commitment {
+            Some(commitment) => commitment,
+            None => {
+                // empty spot, so a new stem node goes here.
+                return match value {
+                    Some(value) => {
+                        let mut values: Children = Box::new([None; VERKLE_WIDTH]);
+                        values[key[STEM_LEN] as usize] = Some(value);
+                        Ok(Some(self.store(VerkleNode::Stem {
+                            stem: stem_of(key),
+                            values,
+                        })))
+                    }
+                    None => Ok(None),
+                };
+            }
+        };
+
+        let node = self
+            .nodes
+            .get(&commitment)
+            .ok_or(Error::MissingData("node not present, please add a proof"))?
+            .to_owned();
+
+        match node {
+            VerkleNode::Internal(mut children) => {
+                let index = child_index(key, depth)?;
+                children[index] = self.update_at(children[index], depth + 1, key, value)?;
+                self.nodes.remove(&commitment);
+                self.store_internal(children)
+            }
+            VerkleNode::Stem { stem, mut values } => {
+                if stem == stem_of(key) {
+                    values[key[STEM_LEN] as usize] = value;
+                    self.nodes.remove(&commitment);
+                    if values.iter().all(Option::is_none) {
+                        return Ok(None);
+                    }
+                    Ok(Some(self.store(VerkleNode::Stem { stem, values })))
+                } else if value.is_none() {
+                    // key is not in the tree, nothing to remove.
+                    Ok(Some(commitment))
+                } else {
+                    // some other stem lives here, push it one level down under an internal node
+                    // and retry. this repeats until both stems get different bytes.
+                    let mut children: Children = Box::new([None; VERKLE_WIDTH]);
+                    children[stem[depth] as usize] = Some(commitment);
+                    let internal = self.store(VerkleNode::Internal(children));
+                    self.update_at(Some(internal), depth, key, value)
+                }
+            }
+        }
+    }
+
+    fn store_internal(&mut self, children: Children) -> Result<Option<H256>, Error> {
+        let mut present = children.iter().flatten();
+        match (present.next(), present.next()) {
+            (None, _) => Ok(None),
+            (Some(only_child), None) => {
+                // an internal node with a single stem below it collapses into that stem.
+                let only_child = *only_child;
+                match self.nodes.get(&only_child) {
+                    Some(VerkleNode::Stem { .. }) => Ok(Some(only_child)),
+                    Some(VerkleNode::Internal(_)) => {
+                        Ok(Some(self.store(VerkleNode::Internal(children))))
+                    }
+                    None => Err(Error::MissingData(
+                        "sibling node not present, please add a proof for a neighbouring key",
+                    )),
+                }
+            }
+            _ => Ok(Some(self.store(VerkleNode::Internal(children)))),
+        }
+    }
+
+    fn store(&mut self, node: VerkleNode) -> H256 {
+        let commitment = node.commitment::<C>();
+        self.nodes.insert(commitment, node);
+        commitment
+    }
+
+    // proof is the list of serialized nodes from the root down to the key's stem (or to the
+    // point where the key is shown to be absent).
+    pub fn load_proof(
+        &mut self,
+        key: H256,
+        value: Option<H256>,
+        proof: Vec<Bytes>,
+    ) -> Result<(), Error> {
+        if proof.is_empty() {
+            if self.root.is_some() && self.root != Some(H256::zero()) {
+                return Err(Error::MissingData(
+                    "Root is not empty, hence some proof is needed",
+                ));
+            }
+            if value.is_some() {
+                return Err(Error::InternalError(
+                    "Value should be empty, since root is empty",
+                ));
+            }
+            return Ok(());
+        }
+
+        let key = key.to_fixed_bytes();
+        let mut expected = match self.root {
+            Some(root) => root,
+            None => {
+                let root = VerkleNode::from_raw_rlp(proof[0].clone())?.commitment::<C>();
+                self.root = Some(root);
+                root
+            }
+        };
+
+        for (depth, proof_entry) in proof.iter().enumerate() {
+            let node = VerkleNode::from_raw_rlp(proof_entry.to_owned())?;
+            if node.commitment::<C>() != expected {
+                return Err(Error::InternalError(
+                    "proof entry commitment does not match the node commitment",
+                ));
+            }
+            self.nodes.entry(expected).or_insert_with(|| node.clone());
+
+            match node {
+                VerkleNode::Internal(children) => match children[child_index(&key, depth)?] {
+                    Some(child) => expected = child,
+                    None => {
+                        if value.is_some() {
+                            return Err(Error::InternalError(
+                                "child does not exist and value is not null",
+                            ));
+                        }
+                        return Ok(());
+                    }
+                },
+                VerkleNode::Stem { stem, values } => {
+                    let found = if stem == stem_of(&key) {
+                        values[key[STEM_LEN] as usize]
+                    } else {
+                        None
+                    };
+                    if found != value {
+                        return Err(Error::InternalError("value in stem does not match input"));
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(Error::InternalError(
+            "proof ended before reaching a stem or an empty child",
+        ))
+    }
+
+    pub fn get_proof(&self, key: H256) -> Result<Vec<Bytes>, Error> {
+        let key = key.to_fixed_bytes();
+        let mut current = self.root.ok_or(Error::Usage("root not set"))?;
+        let mut proof = Vec::new();
+        let mut depth = 0;
+        while !current.is_zero() {
+            let node = self
+                .nodes
+                .get(&current)
+                .ok_or(Error::MissingData("node not present, please add a proof"))?;
+            proof.push(node.to_raw_rlp());
+            match node {
+                VerkleNode::Internal(children) => match children[child_index(&key, depth)?] {
+                    Some(child) => {
+                        current = child;
+                        depth += 1;
+                    }
+                    None => break,
+                },
+                VerkleNode::Stem { .. } => break,
+            }
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashCommitter, VerkleNode, VerkleTrie, VERKLE_WIDTH};
+    use ethers_core::types::H256;
+
+    fn key(prefix: &[u8], suffix: u8) -> H256 {
+        let mut key = [0u8; 32];
+        key[..prefix.len()].copy_from_slice(prefix);
+        key[31] = suffix;
+        H256::from(key)
+    }
+
+    #[test]
+    pub fn test_verkle_set_get() {
+        let mut trie = VerkleTrie::<HashCommitter>::empty();
+        trie.set(key(&[1], 0), H256::from_low_u64_be(10)).unwrap();
+        trie.set(key(&[1], 1), H256::from_low_u64_be(11)).unwrap();
+        trie.set(key(&[2], 0), H256::from_low_u64_be(20)).unwrap();
+
+        assert_eq!(
+            trie.get(key(&[1], 1)).unwrap(),
+            Some(H256::from_low_u64_be(11))
+        );
+        assert_eq!(
+            trie.get(key(&[2], 0)).unwrap(),
+            Some(H256::from_low_u64_be(20))
+        );
+        assert_eq!(trie.get(key(&[2], 1)).unwrap(), None);
+        assert_eq!(trie.get(key(&[3], 0)).unwrap(), None);
+    }
+
+    #[test]
+    pub fn test_verkle_root_independent_of_order() {
+        let entries = [
+            (key(&[1], 0), 1),
+            (key(&[1, 2, 3], 5), 2),
+            (key(&[1, 2, 4], 5), 3),
+            (key(&[9], 7), 4),
+        ];
+
+        let mut trie_a = VerkleTrie::<HashCommitter>::empty();
+        for (k, v) in entries.iter() {
+            trie_a.set(*k, H256::from_low_u64_be(*v)).unwrap();
+        }
+        let mut trie_b = VerkleTrie::<HashCommitter>::empty();
+        for (k, v) in entries.iter().rev() {
+            trie_b.set(*k, H256::from_low_u64_be(*v)).unwrap();
+        }
+        assert_eq!(trie_a.root(), trie_b.root());
+    }
+
+    #[test]
+    pub fn test_verkle_remove() {
+        let mut trie = VerkleTrie::<HashCommitter>::empty();
+        trie.set(key(&[1, 2, 3], 0), H256::from_low_u64_be(1))
+            .unwrap();
+        let root_one = trie.root();
+
+        trie.set(key(&[1, 2, 4], 0), H256::from_low_u64_be(2))
+            .unwrap();
+        assert_ne!(trie.root(), root_one);
+
+        // removing the second key collapses the internal nodes back into one stem.
+        trie.remove(key(&[1, 2, 4], 0)).unwrap();
+        assert_eq!(trie.root(), root_one);
+
+        trie.remove(key(&[1, 2, 3], 0)).unwrap();
+        assert_eq!(trie.root(), Some(H256::zero()));
+    }
+
+    #[test]
+    pub fn test_verkle_load_proof_and_update() {
+        let mut full = VerkleTrie::<HashCommitter>::empty();
+        for i in 0..20u8 {
+            full.set(key(&[i, i], i), H256::from_low_u64_be(i as u64))
+                .unwrap();
+        }
+
+        let target = key(&[5, 5], 5);
+        let mut partial = VerkleTrie::<HashCommitter>::from_commitment(full.root().unwrap());
+        partial
+            .load_proof(
+                target,
+                Some(H256::from_low_u64_be(5)),
+                full.get_proof(target).unwrap(),
+            )
+            .unwrap();
+
+        // absence proof for a key under a missing child.
+        let absent = key(&[200], 0);
+        partial
+            .load_proof(absent, None, full.get_proof(absent).unwrap())
+            .unwrap();
+        assert_eq!(partial.get(absent).unwrap(), None);
+
+        // a wrong value is rejected.
+        assert!(partial
+            .load_proof(
+                target,
+                Some(H256::from_low_u64_be(6)),
+                full.get_proof(target).unwrap()
+            )
+            .is_err());
+
+        full.set(target, H256::from_low_u64_be(500)).unwrap();
+        partial.set(target, H256::from_low_u64_be(500)).unwrap();
+        assert_eq!(partial.root(), full.root());
+
+        // keys whose nodes were not loaded cannot be read.
+        assert!(partial.get(key(&[6, 6], 6)).is_err());
+    }
+
+    #[test]
+    pub fn test_verkle_load_proof_too_deep() {
+        // internal nodes all the way down, more of them than the key has bytes.
+        let mut proof = Vec::new();
+        let mut child = H256::repeat_byte(1);
+        for _ in 0..40 {
+            let mut children = Box::new([None; VERKLE_WIDTH]);
+            children[0] = Some(child);
+            let node = VerkleNode::Internal(children);
+            child = node.commitment::<HashCommitter>();
+            proof.insert(0, node.to_raw_rlp());
+        }
+        let mut trie = VerkleTrie::<HashCommitter>::from_commitment(child);
+        assert!(trie.load_proof(H256::zero(), None, proof).is_err());
+    }
+
+    #[test]
+    pub fn test_verkle_node_rlp_roundtrip() {
+        let mut trie = VerkleTrie::<HashCommitter>::empty();
+        trie.set(key(&[1], 0), H256::from_low_u64_be(10)).unwrap();
+        trie.set(key(&[2], 3), H256::from_low_u64_be(20)).unwrap();
+
+        for raw in trie.get_proof(key(&[2], 3)).unwrap() {
+            let node = VerkleNode::from_raw_rlp(raw.clone()).unwrap();
+            assert_eq!(node.to_raw_rlp(), raw);
+        }
+    }
+}
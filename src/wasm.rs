@@ -0,0 +1,181 @@
+use ethers_core::types::{Address, H256, U256};
+use wasm_bindgen::prelude::*;
+
+use crate::{Error, StateTrie, Witness};
+
+fn js_error(err: Error) -> JsError {
+    JsError::new(&format!("{:?}", err))
+}
+
+fn parse_h256(value: &str) -> Result<H256, JsError> {
+    value
+        .parse()
+        .map_err(|_| js_error(Error::InternalError("invalid 32 byte hex")))
+}
+
+fn parse_address(value: &str) -> Result<Address, JsError> {
+    value
+        .parse()
+        .map_err(|_| js_error(Error::InternalError("invalid address")))
+}
+
+// decimal, or hex with 0x. big numbers are passed as strings so nothing is lost in a js number.
+fn parse_u256(value: &str) -> Result<U256, JsError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(value).ok(),
+    }
+    .ok_or_else(|| js_error(Error::InternalError("invalid number")))
+}
+
+fn hex_u256(value: U256) -> String {
+    format!("{:#x}", value)
+}
+
+// js facing StateTrie. hashes, addresses and numbers go in and out as hex strings.
+#[wasm_bindgen(js_name = StateTrie)]
+pub struct WasmStateTrie {
+    inner: StateTrie,
+}
+
+#[wasm_bindgen(js_class = StateTrie)]
+impl WasmStateTrie {
+    #[wasm_bindgen(constructor)]
+    pub fn new(root: &str) -> Result<WasmStateTrie, JsError> {
+        Ok(WasmStateTrie {
+            inner: StateTrie::from_root(parse_h256(root)?),
+        })
+    }
+
+    #[wasm_bindgen(js_name = fromWitness)]
+    pub fn from_witness(witness: &[u8]) -> Result<WasmStateTrie, JsError> {
+        let witness = Witness::decode(witness.to_vec().into()).map_err(js_error)?;
+        Ok(WasmStateTrie {
+            inner: StateTrie::from_witness(&witness).map_err(js_error)?,
+        })
+    }
+
+    // result of eth_getProof as json, or the whole rpc response.
+    #[wasm_bindgen(js_name = loadProof)]
+    pub fn load_proof(&mut self, proof: &str) -> Result<(), JsError> {
+        self.inner.load_proof_json_str(proof).map_err(js_error)
+    }
+
+    pub fn root(&self) -> Option<String> {
+        self.inner.root().map(|root| format!("{:?}", root))
+    }
+
+    #[wasm_bindgen(js_name = getBalance)]
+    pub fn get_balance(&self, address: &str) -> Result<String, JsError> {
+        let account = self
+            .inner
+            .account_trie
+            .get(parse_address(address)?)
+            .map_err(js_error)?;
+        Ok(hex_u256(account.balance))
+    }
+
+    #[wasm_bindgen(js_name = getNonce)]
+    pub fn get_nonce(&self, address: &str) -> Result<String, JsError> {
+        let account = self
+            .inner
+            .account_trie
+            .get(parse_address(address)?)
+            .map_err(js_error)?;
+        Ok(hex_u256(account.nonce))
+    }
+
+    #[wasm_bindgen(js_name = getStorage)]
+    pub fn get_storage(&mut self, address: &str, slot: &str) -> Result<String, JsError> {
+        let value = self
+            .inner
+            .get_storage_at(parse_address(address)?, parse_u256(slot)?)
+            .map_err(js_error)?;
+        Ok(hex_u256(value))
+    }
+
+    #[wasm_bindgen(js_name = setBalance)]
+    pub fn set_balance(&mut self, address: &str, balance: &str) -> Result<(), JsError> {
+        self.inner
+            .account_trie
+            .set_balance(parse_address(address)?, parse_u256(balance)?)
+            .map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = setNonce)]
+    pub fn set_nonce(&mut self, address: &str, nonce: &str) -> Result<(), JsError> {
+        self.inner
+            .account_trie
+            .set_nonce(parse_address(address)?, parse_u256(nonce)?)
+            .map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = setStorage)]
+    pub fn set_storage(&mut self, address: &str, slot: &str, value: &str) -> Result<(), JsError> {
+        self.inner
+            .set_storage_value(
+                parse_address(address)?,
+                parse_u256(slot)?,
+                parse_u256(value)?,
+            )
+            .map_err(js_error)
+    }
+
+    // canonical witness encoding of everything that is loaded.
+    pub fn witness(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.witness().map_err(js_error)?.encode().to_vec())
+    }
+
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> Result<String, JsError> {
+        Ok(self.inner.to_json().map_err(js_error)?.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WasmStateTrie;
+    use crate::{state_trie::StorageTrie, StateTrie};
+    use ethers_core::types::{Address, H256, U256};
+
+    // only the happy paths, JsError can't be created outside of wasm.
+    #[test]
+    pub fn test_wasm_state_trie() {
+        let address = Address::from_low_u64_be(1);
+        let mut full = StateTrie::from_root(StorageTrie::empty().root().unwrap());
+        full.account_trie
+            .set_balance(address, U256::from(100))
+            .unwrap();
+        full.set_storage_value(address, U256::one(), U256::from(7))
+            .unwrap();
+        let proof = full
+            .get_proof(address, vec![H256::from_low_u64_be(1)])
+            .unwrap();
+
+        let root = format!("{:?}", full.root().unwrap());
+        let mut trie = WasmStateTrie::new(&root).unwrap();
+        trie.load_proof(&serde_json::to_string(&proof).unwrap())
+            .unwrap();
+        assert_eq!(trie.root(), Some(root.clone()));
+
+        let address = "0x0000000000000000000000000000000000000001";
+        assert_eq!(trie.get_balance(address).unwrap(), "0x64");
+        assert_eq!(trie.get_storage(address, "1").unwrap(), "0x7");
+
+        trie.set_balance(address, "1000000000000000000").unwrap();
+        trie.set_storage(address, "0x1", "0x8").unwrap();
+        full.account_trie
+            .set_balance(Address::from_low_u64_be(1), U256::exp10(18))
+            .unwrap();
+        full.set_storage_value(Address::from_low_u64_be(1), U256::one(), U256::from(8))
+            .unwrap();
+        assert_eq!(trie.root(), Some(format!("{:?}", full.root().unwrap())));
+
+        let from_witness = WasmStateTrie::from_witness(&trie.witness().unwrap()).unwrap();
+        assert_eq!(from_witness.root(), trie.root());
+        assert_eq!(
+            from_witness.get_balance(address).unwrap(),
+            "0xde0b6b3a7640000"
+        );
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+use bytes::BytesMut;
+use ethers_core::{
+    types::{Bytes, H256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+use crate::{
+    hasher::{KeccakHasher, TrieHasher},
+    BlockHashes, Error,
+};
+
+// A witness is the set of trie nodes (raw rlp) needed to work with some keys under a root.
+//
+// The encoding is canonical: nodes are de-duplicated and sorted by their hash, and the
+// fields are always encoded in the same order as rlp([root, [node_0, node_1, ...]]), so
+// two parties generating a witness for the same keys end up with identical bytes.
+//
+// The hashes of the blocks before, for BLOCKHASH, can come along as a third field:
+// rlp([root, [node_0, ...], [newest_number, [hash_newest, ...]]]). Without them the encoding
+// is the two field one.
+//
+// The code of the contracts, for executing a block on the witness alone, is a fourth field,
+// sorted by code hash like the nodes: rlp([root, [node_0, ...], block_hashes, [code_0, ...]]),
+// with an empty list in place of missing block hashes.
+//
+// Nodes are sorted by, and referenced with, the hash of the trie they come from, keccak
+// unless the trie has another hasher.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Witness<H: TrieHasher = KeccakHasher> {
+    root: H256,
+    nodes: Vec<Bytes>,
+    block_hashes: Option<BlockHashes>,
+    codes: Vec<Bytes>,
+    hasher: PhantomData<H>,
+}
+
+// de-duplicated and sorted by hash.
+pub(crate) fn sorted_by_hash<H: TrieHasher>(items: impl IntoIterator<Item = Bytes>) -> Vec<Bytes> {
+    let mut hashed_items = items
+        .into_iter()
+        .map(|item| (H::hash(&item), item))
+        .collect::<Vec<_>>();
+    hashed_items.sort_by_key(|(hash, _)| *hash);
+    hashed_items.dedup_by(|(hash_a, _), (hash_b, _)| hash_a == hash_b);
+    hashed_items.into_iter().map(|(_, item)| item).collect()
+}
+
+impl Witness {
+    pub fn new(root: H256, nodes: impl IntoIterator<Item = Bytes>) -> Self {
+        Self::with_hasher(root, nodes)
+    }
+
+    pub fn decode(raw: Bytes) -> Result<Self, Error> {
+        Self::decode_with_hasher(raw)
+    }
+}
+
+impl<H: TrieHasher> Witness<H> {
+    // new for the nodes of a trie with another hasher.
+    pub fn with_hasher(root: H256, nodes: impl IntoIterator<Item = Bytes>) -> Self {
+        Self {
+            root,
+            nodes: sorted_by_hash::<H>(nodes),
+            block_hashes: None,
+            codes: Vec::new(),
+            hasher: PhantomData,
+        }
+    }
+
+    // empty ones are left out, so they encode like a witness without them.
+    pub fn with_block_hashes(mut self, block_hashes: BlockHashes) -> Self {
+        self.block_hashes = (!block_hashes.is_empty()).then_some(block_hashes);
+        self
+    }
+
+    pub fn block_hashes(&self) -> Option<&BlockHashes> {
+        self.block_hashes.as_ref()
+    }
+
+    // the empty code is left out, it is always known.
+    pub fn with_codes(mut self, codes: impl IntoIterator<Item = Bytes>) -> Self {
+        // code hashes are keccak whatever the trie hashes with.
+        self.codes =
+            sorted_by_hash::<KeccakHasher>(codes.into_iter().filter(|code| !code.is_empty()));
+        self
+    }
+
+    pub fn codes(&self) -> &[Bytes] {
+        &self.codes
+    }
+
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    pub fn nodes(&self) -> &[Bytes] {
+        &self.nodes
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn encode(&self) -> Bytes {
+        let fields = match (&self.block_hashes, self.codes.is_empty()) {
+            (_, false) => 4,
+            (Some(_), true) => 3,
+            (None, true) => 2,
+        };
+        let mut rlp_stream = RlpStream::new_list(fields);
+        rlp_stream.append(&self.root);
+        rlp_stream.begin_list(self.nodes.len());
+        for node in self.nodes.iter() {
+            rlp_stream.append(&BytesMut::from(node.to_vec().as_slice()));
+        }
+        match &self.block_hashes {
+            Some(block_hashes) => block_hashes.append_to(&mut rlp_stream),
+            None if fields == 4 => {
+                rlp_stream.begin_list(0);
+            }
+            None => {}
+        }
+        if fields == 4 {
+            rlp_stream.begin_list(self.codes.len());
+            for code in self.codes.iter() {
+                rlp_stream.append(&BytesMut::from(code.to_vec().as_slice()));
+            }
+        }
+        Bytes::from(rlp_stream.out().to_vec())
+    }
+
+    pub fn decode_with_hasher(raw: Bytes) -> Result<Self, Error> {
+        let rlp = Rlp::new(&raw);
+        let fields = rlp.item_count()?;
+        if !(2..=4).contains(&fields) {
+            return Err(Error::InternalError(
+                "witness should have two to four fields",
+            ));
+        }
+
+        let root = rlp.val_at::<H256>(0)?;
+        let mut nodes = Vec::new();
+        for item in rlp.at(1)?.iter() {
+            nodes.push(Bytes::from(item.data()?.to_owned()));
+        }
+
+        // only accept canonical encodings, otherwise same witness could have many encodings.
+        let mut witness = Self::with_hasher(root, nodes.clone());
+        if witness.nodes != nodes {
+            return Err(Error::InternalError(
+                "witness nodes are not sorted by hash or contain duplicates",
+            ));
+        }
+        if fields >= 3 {
+            let block_hashes = rlp.at(2)?;
+            // the empty list only stands in for them before the codes.
+            if fields == 3 || block_hashes.item_count()? != 0 {
+                witness.block_hashes = Some(BlockHashes::decode(&block_hashes)?);
+            }
+        }
+        if fields == 4 {
+            let mut codes = Vec::new();
+            for item in rlp.at(3)?.iter() {
+                codes.push(Bytes::from(item.data()?.to_owned()));
+            }
+            witness = witness.with_codes(codes.clone());
+            if codes.is_empty() || witness.codes != codes {
+                return Err(Error::InternalError(
+                    "witness codes are empty, not sorted by hash or contain duplicates",
+                ));
+            }
+        }
+        Ok(witness)
+    }
+
+    // commitment to the witness, equal for everyone who has the same set of nodes and root.
+    pub fn content_hash(&self) -> H256 {
+        H::hash(&self.encode())
+    }
+
+    pub(crate) fn node_map(&self) -> HashMap<H256, Bytes> {
+        self.nodes
+            .iter()
+            .map(|node| (H::hash(node), node.to_owned()))
+            .collect()
+    }
+}
+
+// size of the witness needed for a set of keys, without building it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProofSizeEstimate {
+    // distinct nodes needed by all the keys.
+    pub node_count: usize,
+    // raw bytes of those nodes, i.e. what eth_getProof style proofs would carry once deduped.
+    pub node_bytes: usize,
+    // extra node bytes each key adds on top of the keys before it, in input order.
+    pub marginal_bytes: Vec<usize>,
+    // nodes rlp encoded as strings inside the witness node list.
+    encoded_nodes_len: usize,
+}
+
+impl ProofSizeEstimate {
+    // length of Witness::encode() for exactly these nodes.
+    pub fn witness_bytes(&self) -> usize {
+        rlp_list_len(33 + rlp_list_len(self.encoded_nodes_len))
+    }
+
+    // record the nodes needed by one key (or a group of keys), as (hash, rlp length) pairs.
+    pub(crate) fn add_key(
+        &mut self,
+        seen: &mut HashSet<H256>,
+        nodes: impl IntoIterator<Item = (H256, usize)>,
+    ) {
+        let mut marginal = 0;
+        for (hash, len) in nodes {
+            if seen.insert(hash) {
+                self.node_count += 1;
+                self.node_bytes += len;
+                self.encoded_nodes_len += rlp_header_len(len) + len;
+                marginal += len;
+            }
+        }
+        self.marginal_bytes.push(marginal);
+    }
+}
+
+// trie nodes are never a single byte, so they always get a string header.
+fn rlp_header_len(payload_len: usize) -> usize {
+    if payload_len <= 55 {
+        1
+    } else {
+        1 + (usize::BITS - payload_len.leading_zeros()).div_ceil(8) as usize
+    }
+}
+
+fn rlp_list_len(payload_len: usize) -> usize {
+    rlp_header_len(payload_len) + payload_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Witness;
+    use crate::{state_trie::StorageTrie, BlockHashes, StateTrie};
+    use ethers_core::{
+        types::{Address, Bytes, H256, U256},
+        utils::rlp::RlpStream,
+    };
+
+    fn storage_trie_with(slots: &[u64]) -> StorageTrie {
+        let mut trie = StorageTrie::empty();
+        for slot in slots {
+            trie.set(U256::from(*slot), U256::from(*slot + 100))
+                .unwrap();
+        }
+        trie
+    }
+
+    #[test]
+    pub fn test_witness_same_for_insertion_order() {
+        let trie_a = storage_trie_with(&[1, 2, 3, 4, 5, 6, 7]);
+        let trie_b = storage_trie_with(&[7, 3, 5, 1, 6, 2, 4]);
+
+        let witness_a = trie_a.witness().unwrap();
+        let witness_b = trie_b.witness().unwrap();
+
+        assert_eq!(witness_a.root(), witness_b.root());
+        assert_eq!(witness_a.encode(), witness_b.encode());
+        assert_eq!(witness_a.content_hash(), witness_b.content_hash());
+    }
+
+    #[test]
+    pub fn test_witness_dedup_and_sort() {
+        let node_a: Bytes =
+            "0xe2a0305787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace04"
+                .parse()
+                .unwrap();
+        let node_b: Bytes =
+            "0xe2a032575a0e9e593c00f959f8c92f12db2869c3395a3b0502d05e2516446f71f85b09"
+                .parse()
+                .unwrap();
+
+        let witness_1 = Witness::new(
+            Default::default(),
+            vec![node_a.clone(), node_b.clone(), node_a.clone()],
+        );
+        let witness_2 = Witness::new(Default::default(), vec![node_b, node_a]);
+
+        assert_eq!(witness_1.len(), 2);
+        assert_eq!(witness_1, witness_2);
+        assert_eq!(witness_1.content_hash(), witness_2.content_hash());
+    }
+
+    #[test]
+    pub fn test_witness_encode_decode() {
+        let witness = storage_trie_with(&[1, 2, 3]).witness().unwrap();
+        let decoded = Witness::decode(witness.encode()).unwrap();
+        assert_eq!(decoded, witness);
+    }
+
+    #[test]
+    pub fn test_witness_block_hashes() {
+        let mut state_trie = StateTrie::new();
+        state_trie
+            .account_trie
+            .set_balance(Address::from_low_u64_be(1), U256::one())
+            .unwrap();
+        let without = state_trie.witness().unwrap();
+        let block_hashes =
+            BlockHashes::new(99, (0..10).map(H256::from_low_u64_be).collect()).unwrap();
+        state_trie.set_block_hashes(block_hashes.clone());
+
+        let witness = state_trie.witness().unwrap();
+        assert_ne!(witness.encode(), without.encode());
+        let decoded = Witness::decode(witness.encode()).unwrap();
+        assert_eq!(decoded.block_hashes(), Some(&block_hashes));
+        let loaded = StateTrie::from_witness(&decoded).unwrap();
+        assert_eq!(
+            loaded.block_hashes().blockhash(100, 97).unwrap(),
+            H256::from_low_u64_be(2)
+        );
+
+        // empty ones encode like none.
+        let empty = without.clone().with_block_hashes(BlockHashes::default());
+        assert_eq!(empty.encode(), without.encode());
+        assert_eq!(
+            Witness::decode(without.encode()).unwrap().block_hashes(),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_witness_codes() {
+        let address = Address::from_low_u64_be(1);
+        let mut state_trie = StateTrie::new();
+        let code = Bytes::from(vec![0x60, 0x00]);
+        state_trie.set_code(address, code.clone()).unwrap();
+        let witness = state_trie.witness().unwrap();
+        assert_eq!(witness.codes().to_vec(), vec![code.clone()]);
+        let decoded = Witness::decode(witness.encode()).unwrap();
+        assert_eq!(decoded, witness);
+        assert_eq!(decoded.block_hashes(), None);
+        assert_eq!(
+            StateTrie::from_witness(&decoded)
+                .unwrap()
+                .get_code(address)
+                .unwrap(),
+            Some(code.clone())
+        );
+
+        // with block hashes too, and the empty code is left out.
+        let block_hashes = BlockHashes::new(9, vec![H256::zero()]).unwrap();
+        let both = witness
+            .clone()
+            .with_block_hashes(block_hashes.clone())
+            .with_codes([code, Bytes::new()]);
+        assert_eq!(both.codes().len(), 1);
+        let decoded = Witness::decode(both.encode()).unwrap();
+        assert_eq!(decoded.block_hashes(), Some(&block_hashes));
+        assert_eq!(decoded, both);
+
+        // an empty code list has no fourth field.
+        let mut rlp_stream = RlpStream::new_list(4);
+        rlp_stream.append(&witness.root());
+        rlp_stream.begin_list(0);
+        rlp_stream.begin_list(0);
+        rlp_stream.begin_list(0);
+        assert!(Witness::decode(rlp_stream.out().freeze().into()).is_err());
+    }
+
+    #[test]
+    pub fn test_witness_decode_rejects_non_canonical() {
+        let witness = storage_trie_with(&[1, 2, 3]).witness().unwrap();
+
+        // put the nodes in reverse order, which is not canonical.
+        let mut reversed = witness.clone();
+        reversed.nodes.reverse();
+        assert!(Witness::decode(reversed.encode()).is_err());
+    }
+
+    #[test]
+    pub fn test_estimate_proof_size_matches_witness() {
+        let trie = storage_trie_with(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        let keys = [U256::from(3), U256::from(7), U256::from(3)];
+        let estimate = trie.estimate_proof_size(&keys).unwrap();
+
+        let mut proof_nodes = Vec::new();
+        for key in keys.iter() {
+            proof_nodes.extend(trie.get_proof(*key).unwrap());
+        }
+        let witness = Witness::new(trie.root().unwrap(), proof_nodes);
+
+        assert_eq!(estimate.node_count, witness.len());
+        assert_eq!(
+            estimate.node_bytes,
+            witness.nodes().iter().map(|node| node.len()).sum::<usize>()
+        );
+        assert_eq!(estimate.witness_bytes(), witness.encode().len());
+        // a repeated key costs nothing extra.
+        assert_eq!(estimate.marginal_bytes.len(), 3);
+        assert_eq!(estimate.marginal_bytes[2], 0);
+        assert_eq!(
+            estimate.marginal_bytes.iter().sum::<usize>(),
+            estimate.node_bytes
+        );
+    }
+
+    #[test]
+    pub fn test_state_trie_witness_roundtrip() {
+        let mut state_trie = StateTrie::from_root(
+            "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+                .parse()
+                .unwrap(),
+        );
+        let address = "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+            .parse()
+            .unwrap();
+        state_trie
+            .account_trie
+            .set_balance(address, U256::from(10))
+            .unwrap();
+
+        let witness = state_trie.witness().unwrap();
+        let restored = StateTrie::from_witness(&witness).unwrap();
+
+        assert_eq!(restored.root(), state_trie.root());
+        assert_eq!(
+            restored.account_trie.get(address).unwrap().balance,
+            U256::from(10)
+        );
+        assert_eq!(restored.witness().unwrap(), witness);
+    }
+}